@@ -19,11 +19,18 @@ use std::time::Duration;
 const CRATES_TO_PUBLISH: &[&str] = &[
     "wit-bindgen-core",
     "wit-bindgen-c",
+    "wit-bindgen-cpp",
+    "wit-bindgen-zig",
+    "wit-bindgen-swift",
+    "wit-bindgen-assemblyscript",
+    "wit-bindgen-grain",
     "wit-bindgen-rust",
     "wit-bindgen-go",
     "wit-bindgen-csharp",
     "wit-bindgen-teavm-java",
+    "wit-bindgen-teavm-kotlin",
     "wit-bindgen-markdown",
+    "wit-bindgen-json",
     "wit-bindgen-moonbit",
     "wit-bindgen-rust-macro",
     "wit-bindgen-rt",
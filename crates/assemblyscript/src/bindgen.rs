@@ -0,0 +1,805 @@
+use crate::{case_name, func_name, InterfaceGenerator};
+use std::fmt::Write as _;
+use std::mem;
+use wit_bindgen_core::abi::{Bindgen, Bitcast, Instruction, WasmType};
+use wit_bindgen_core::{dealias, uwriteln, wit_parser::*, Direction, Ns, Source};
+
+pub(super) struct FunctionBindgen<'a, 'b> {
+    pub gen: &'b mut InterfaceGenerator<'a>,
+    /// The function `CallInterface` calls, or for methods the name of the
+    /// method called on the first argument.
+    pub callee: String,
+    pub params: Vec<String>,
+    pub locals: Ns,
+    pub src: Source,
+    block_storage: Vec<Source>,
+    blocks: Vec<(String, Vec<String>)>,
+    payloads: Vec<String>,
+    /// The names of the elements and base pointers of the lists being lowered
+    /// or lifted, innermost last.
+    iter_elems: Vec<String>,
+    iter_bases: Vec<String>,
+    /// The arena an import's temporary allocations are made in, which is
+    /// freed before it returns.
+    pub arena: String,
+    pub needs_arena: bool,
+}
+
+impl<'a, 'b> FunctionBindgen<'a, 'b> {
+    pub(super) fn new(
+        gen: &'b mut InterfaceGenerator<'a>,
+        mut locals: Ns,
+    ) -> FunctionBindgen<'a, 'b> {
+        let arena = locals.tmp("arena");
+        FunctionBindgen {
+            gen,
+            callee: String::new(),
+            params: Vec::new(),
+            locals,
+            src: Source::default(),
+            block_storage: Vec::new(),
+            blocks: Vec::new(),
+            payloads: Vec::new(),
+            iter_elems: Vec::new(),
+            iter_bases: Vec::new(),
+            arena,
+            needs_arena: false,
+        }
+    }
+
+    fn in_import(&self) -> bool {
+        self.gen.direction == Direction::Import
+    }
+
+    /// Binds `op` to a new constant so that it's only evaluated once, and
+    /// returns its name.
+    fn bind(&mut self, name: &str, op: &str) -> String {
+        let tmp = self.locals.tmp(name);
+        uwriteln!(self.src, "const {tmp} = {op};");
+        tmp
+    }
+
+    fn load(&mut self, ty: &str, offset: i32, operands: &[String]) -> String {
+        self.bind("load", &format!("load<{ty}>({}, {offset})", operands[0]))
+    }
+
+    fn store(&mut self, ty: &str, value: &str, offset: i32, operands: &[String]) {
+        uwriteln!(self.src, "store<{ty}>({}, {value}, {offset});", operands[1]);
+    }
+
+    /// Returns an expression allocating `size` bytes aligned to `align`,
+    /// which are freed before an import returns, and are freed by the
+    /// post-return function of an export.
+    fn alloc(&mut self, size: &str, align: usize) -> String {
+        if self.in_import() {
+            self.needs_arena = true;
+            format!("{}.alloc({size}, {align})", self.arena)
+        } else {
+            format!("cabi_realloc(0, 0, {align}, {size})")
+        }
+    }
+
+    /// Declares a variable for each of the core wasm `types` which the
+    /// results of a variant's cases are assigned to.
+    fn declare_results(&mut self, types: &[WasmType]) -> Vec<String> {
+        types
+            .iter()
+            .map(|ty| {
+                let tmp = self.locals.tmp("variant");
+                uwriteln!(self.src, "let {tmp}: {} = 0;", wasm_type(*ty));
+                tmp
+            })
+            .collect()
+    }
+
+    /// Writes the body of a branch lowering a case of a variant, whose
+    /// payload is bound to `payload` if it has one.
+    fn lower_case(
+        &mut self,
+        payload: Option<(&str, String)>,
+        block: &str,
+        ops: &[String],
+        vars: &[String],
+    ) {
+        if let Some((payload, value)) = payload {
+            uwriteln!(self.src, "const {payload} = {value};");
+        }
+        self.src.push_str(block);
+        for (var, op) in vars.iter().zip(ops) {
+            uwriteln!(self.src, "{var} = {op};");
+        }
+    }
+
+    /// Writes the case of a `switch` lifting a case of a variant, which
+    /// evaluates `block` and assigns `value` to `result`.
+    fn lift_case(&mut self, discriminant: usize, block: &str, result: &str, value: &str) {
+        uwriteln!(self.src, "case {discriminant}: {{");
+        self.src.push_str(block);
+        uwriteln!(self.src, "{result} = {value};");
+        self.src.push_str("break;\n}\n");
+    }
+
+    /// Declares the variable a lifted value of type `ty` is assigned to.
+    fn declare_lifted(&mut self, name: &str, ty: &str) -> String {
+        let tmp = self.locals.tmp(name);
+        uwriteln!(self.src, "let {tmp}: {ty} = changetype<{ty}>(0);");
+        tmp
+    }
+
+    fn type_name(&self, ty: &Type) -> String {
+        self.gen.type_name(ty)
+    }
+}
+
+impl Bindgen for FunctionBindgen<'_, '_> {
+    type Operand = String;
+
+    fn sizes(&self) -> &SizeAlign {
+        &self.gen.gen.sizes
+    }
+
+    fn push_block(&mut self) {
+        let prev = mem::take(&mut self.src);
+        self.block_storage.push(prev);
+    }
+
+    fn finish_block(&mut self, operands: &mut Vec<String>) {
+        let to_restore = self.block_storage.pop().unwrap();
+        let src = mem::replace(&mut self.src, to_restore);
+        self.blocks.push((src.into(), mem::take(operands)));
+    }
+
+    fn return_pointer(&mut self, size: usize, align: usize) -> String {
+        // Imports allocate their return area in their arena, because exports
+        // need their return area to be live until the post-return call.
+        if self.in_import() {
+            let alloc = self.alloc(&size.to_string(), align);
+            self.bind("retArea", &alloc)
+        } else {
+            self.gen.gen.return_pointer_area_size = self.gen.gen.return_pointer_area_size.max(size);
+            self.gen.gen.return_pointer_area_align =
+                self.gen.gen.return_pointer_area_align.max(align);
+            "witRetArea".to_string()
+        }
+    }
+
+    fn is_list_canonical(&self, _resolve: &Resolve, ty: &Type) -> bool {
+        crate::typed_array(ty).is_some()
+    }
+
+    fn emit(
+        &mut self,
+        resolve: &Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<String>,
+        results: &mut Vec<String>,
+    ) {
+        match inst {
+            Instruction::GetArg { nth } => results.push(self.params[*nth].clone()),
+            Instruction::I32Const { val } => results.push(val.to_string()),
+            Instruction::ConstZero { tys } => {
+                for ty in tys.iter() {
+                    results.push(format!("<{}>0", wasm_type(*ty)));
+                }
+            }
+
+            Instruction::U8FromI32 => results.push(format!("<u8>{}", operands[0])),
+            Instruction::S8FromI32 => results.push(format!("<i8>{}", operands[0])),
+            Instruction::U16FromI32 => results.push(format!("<u16>{}", operands[0])),
+            Instruction::S16FromI32 => results.push(format!("<i16>{}", operands[0])),
+            Instruction::U32FromI32 | Instruction::CharFromI32 => {
+                results.push(format!("<u32>{}", operands[0]))
+            }
+            Instruction::U64FromI64 => results.push(format!("<u64>{}", operands[0])),
+            Instruction::S32FromI32 | Instruction::S64FromI64 => results.push(operands[0].clone()),
+
+            Instruction::I32FromU8
+            | Instruction::I32FromS8
+            | Instruction::I32FromU16
+            | Instruction::I32FromS16
+            | Instruction::I32FromU32
+            | Instruction::I32FromChar
+            | Instruction::I32FromBool => results.push(format!("<i32>{}", operands[0])),
+            Instruction::I64FromU64 => results.push(format!("<i64>{}", operands[0])),
+            Instruction::I32FromS32 | Instruction::I64FromS64 => results.push(operands[0].clone()),
+
+            Instruction::CoreF32FromF32
+            | Instruction::CoreF64FromF64
+            | Instruction::F32FromCoreF32
+            | Instruction::F64FromCoreF64 => {
+                results.push(operands[0].clone());
+            }
+
+            Instruction::BoolFromI32 => results.push(format!("({} != 0)", operands[0])),
+
+            Instruction::Bitcasts { casts } => {
+                for (cast, op) in casts.iter().zip(operands) {
+                    results.push(perform_cast(op, cast));
+                }
+            }
+
+            Instruction::RecordLower { record, .. } => {
+                if record.fields.is_empty() {
+                    return;
+                }
+                let op = self.bind("record", &operands[0]);
+                for field in record.fields.iter() {
+                    results.push(format!("{op}.{}", func_name(&field.name)));
+                }
+            }
+            Instruction::RecordLift { ty, .. } => {
+                let name = self.gen.qualified_name(*ty);
+                results.push(format!("new {name}({})", operands.join(", ")));
+            }
+
+            Instruction::TupleLower { tuple, .. } => {
+                if tuple.types.is_empty() {
+                    return;
+                }
+                let op = self.bind("tuple", &operands[0]);
+                results.extend((0..tuple.types.len()).map(|i| format!("{op}.f{i}")));
+            }
+            Instruction::TupleLift { tuple, .. } => {
+                let name = self.gen.tuple_name(tuple.types.iter());
+                results.push(format!("new {name}({})", operands.join(", ")));
+            }
+
+            Instruction::HandleLower { handle, .. } => {
+                let op = &operands[0];
+                let resource = self.gen.resource(handle_resource(handle));
+                match (resource.direction, handle) {
+                    (Direction::Import, Handle::Own(_)) => {
+                        results.push(format!("{op}.takeHandle()"))
+                    }
+                    (Direction::Import, Handle::Borrow(_)) => results.push(format!("{op}.handle")),
+                    // Each handle to an exported resource keeps its object
+                    // alive until its destructor is called.
+                    (Direction::Export, _) => {
+                        results.push(format!("{}(witRep({op}))", resource.new))
+                    }
+                }
+            }
+            Instruction::HandleLift { handle, .. } => {
+                let op = &operands[0];
+                let id = dealias(resolve, handle_resource(handle));
+                let name = self.gen.qualified_name(id);
+                let resource = self.gen.resource(id).clone();
+                match (resource.direction, handle) {
+                    (Direction::Import, _) => results.push(format!("new {name}({op})")),
+                    (Direction::Export, Handle::Borrow(_)) => {
+                        results.push(format!("changetype<{name}>(<usize>{op})"))
+                    }
+                    // An owned handle to an exported resource is dropped once
+                    // its object is referenced, which calls the destructor
+                    // releasing the object.
+                    (Direction::Export, Handle::Own(_)) => {
+                        let handle = self.bind("handle", op);
+                        let object = self.bind(
+                            "object",
+                            &format!("changetype<{name}>(<usize>{}({handle}))", resource.rep),
+                        );
+                        uwriteln!(self.src, "{}({handle});", resource.drop);
+                        results.push(object);
+                    }
+                }
+            }
+
+            Instruction::FlagsLower { flags, .. } => {
+                let op = self.bind("flags", &operands[0]);
+                let words = match flags.repr() {
+                    FlagsRepr::U8 | FlagsRepr::U16 => 1,
+                    FlagsRepr::U32(n) => n,
+                };
+                let bits = (0..words)
+                    .map(|_| {
+                        let tmp = self.locals.tmp("bits");
+                        uwriteln!(self.src, "let {tmp}: i32 = 0;");
+                        tmp
+                    })
+                    .collect::<Vec<_>>();
+                for (i, flag) in flags.flags.iter().enumerate() {
+                    uwriteln!(
+                        self.src,
+                        "if ({op}.{}) {} |= 1 << {};",
+                        func_name(&flag.name),
+                        bits[i / 32],
+                        i % 32
+                    );
+                }
+                results.extend(bits);
+            }
+            Instruction::FlagsLift { flags, ty, .. } => {
+                let name = self.gen.qualified_name(*ty);
+                let result = self.bind("flags", &format!("new {name}()"));
+                for (i, flag) in flags.flags.iter().enumerate() {
+                    uwriteln!(
+                        self.src,
+                        "{result}.{} = ({} & (1 << {})) != 0;",
+                        func_name(&flag.name),
+                        operands[i / 32],
+                        i % 32
+                    );
+                }
+                results.push(result);
+            }
+
+            Instruction::VariantPayloadName => {
+                let name = self.locals.tmp("payload");
+                results.push(name.clone());
+                self.payloads.push(name);
+            }
+
+            Instruction::VariantLower {
+                variant,
+                results: result_types,
+                ..
+            } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+                let payloads = self
+                    .payloads
+                    .drain(self.payloads.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+
+                let op = self.bind("variant", &operands[0]);
+                let vars = self.declare_results(result_types);
+                uwriteln!(self.src, "switch ({op}.tag) {{");
+                for (i, ((case, (block, block_results)), payload)) in
+                    variant.cases.iter().zip(blocks).zip(payloads).enumerate()
+                {
+                    uwriteln!(self.src, "case {i}: {{");
+                    let payload = case
+                        .ty
+                        .as_ref()
+                        .map(|_| (payload.as_str(), format!("{op}.{}", case_name(&case.name))));
+                    self.lower_case(payload, &block, &block_results, &vars);
+                    self.src.push_str("break;\n}\n");
+                }
+                self.src.push_str("}\n");
+                results.extend(vars);
+            }
+
+            Instruction::VariantLift { variant, ty, .. } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+
+                let name = self.gen.qualified_name(*ty);
+                let result = self.declare_lifted("variant", &name);
+                uwriteln!(self.src, "switch ({}) {{", operands[0]);
+                for (i, (case, (block, block_results))) in
+                    variant.cases.iter().zip(blocks).enumerate()
+                {
+                    let value = format!(
+                        "{name}.{}({})",
+                        case_name(&case.name),
+                        block_results.join(", ")
+                    );
+                    self.lift_case(i, &block, &result, &value);
+                }
+                self.src.push_str("default: unreachable();\n}\n");
+                results.push(result);
+            }
+
+            Instruction::EnumLower { .. } => results.push(format!("<i32>{}", operands[0])),
+            Instruction::EnumLift { ty, .. } => {
+                let name = self.gen.qualified_name(*ty);
+                results.push(format!("<{name}>{}", operands[0]));
+            }
+
+            Instruction::OptionLower {
+                results: result_types,
+                ..
+            } => {
+                let (some, some_results) = self.blocks.pop().unwrap();
+                let (none, none_results) = self.blocks.pop().unwrap();
+                let some_payload = self.payloads.pop().unwrap();
+                let _none_payload = self.payloads.pop().unwrap();
+
+                let op = self.bind("option", &operands[0]);
+                let vars = self.declare_results(result_types);
+                uwriteln!(self.src, "if ({op}.isSome) {{");
+                let payload = Some((some_payload.as_str(), format!("{op}.value")));
+                self.lower_case(payload, &some, &some_results, &vars);
+                self.src.push_str("} else {\n");
+                self.lower_case(None, &none, &none_results, &vars);
+                self.src.push_str("}\n");
+                results.extend(vars);
+            }
+
+            Instruction::OptionLift { payload, .. } => {
+                let (some, some_results) = self.blocks.pop().unwrap();
+                let (none, none_results) = self.blocks.pop().unwrap();
+                assert!(none_results.is_empty());
+                assert_eq!(some_results.len(), 1);
+
+                let ty = self.type_name(payload);
+                let result = self.declare_lifted("option", &format!("Option<{ty}>"));
+                uwriteln!(self.src, "switch ({}) {{", operands[0]);
+                self.lift_case(0, &none, &result, &format!("Option.none<{ty}>()"));
+                let value = format!("Option.some<{ty}>({})", some_results[0]);
+                self.lift_case(1, &some, &result, &value);
+                self.src.push_str("default: unreachable();\n}\n");
+                results.push(result);
+            }
+
+            Instruction::ResultLower {
+                results: result_types,
+                result,
+                ..
+            } => {
+                let (err, err_results) = self.blocks.pop().unwrap();
+                let (ok, ok_results) = self.blocks.pop().unwrap();
+                let err_payload = self.payloads.pop().unwrap();
+                let ok_payload = self.payloads.pop().unwrap();
+
+                let op = self.bind("result", &operands[0]);
+                let vars = self.declare_results(result_types);
+                uwriteln!(self.src, "if ({op}.isOk) {{");
+                let payload = result
+                    .ok
+                    .as_ref()
+                    .map(|_| (ok_payload.as_str(), format!("{op}.value")));
+                self.lower_case(payload, &ok, &ok_results, &vars);
+                self.src.push_str("} else {\n");
+                let payload = result
+                    .err
+                    .as_ref()
+                    .map(|_| (err_payload.as_str(), format!("{op}.error")));
+                self.lower_case(payload, &err, &err_results, &vars);
+                self.src.push_str("}\n");
+                results.extend(vars);
+            }
+
+            Instruction::ResultLift { result, .. } => {
+                let (err, err_results) = self.blocks.pop().unwrap();
+                let (ok, ok_results) = self.blocks.pop().unwrap();
+                let ok_ty = self.gen.optional_type_name(result.ok.as_ref());
+                let err_ty = self.gen.optional_type_name(result.err.as_ref());
+                let ty = format!("Result<{ok_ty}, {err_ty}>");
+                let lifted = self.declare_lifted("result", &ty);
+                uwriteln!(self.src, "switch ({}) {{", operands[0]);
+                let ok_value = format!(
+                    "Result.ok<{ok_ty}, {err_ty}>({})",
+                    ok_results.first().map_or("0", |s| s)
+                );
+                self.lift_case(0, &ok, &lifted, &ok_value);
+                let err_value = format!(
+                    "Result.err<{ok_ty}, {err_ty}>({})",
+                    err_results.first().map_or("0", |s| s)
+                );
+                self.lift_case(1, &err, &lifted, &err_value);
+                self.src.push_str("default: unreachable();\n}\n");
+                results.push(lifted);
+            }
+
+            // Imports pass the contents of typed arrays and strings, which
+            // are encoded as UTF-16, without copying them. Exports copy them
+            // so that the post-return function can free them.
+            Instruction::ListCanonLower { element, .. } => {
+                let list = self.bind("list", &operands[0]);
+                if self.in_import() {
+                    results.push(format!("{list}.dataStart"));
+                } else {
+                    let align = self.gen.gen.sizes.align(element).align_wasm32();
+                    results.push(self.bind(
+                        "ptr",
+                        &format!("witCopy({list}.dataStart, <usize>{list}.byteLength, {align})"),
+                    ));
+                }
+                results.push(format!("<usize>{list}.length"));
+            }
+            Instruction::StringLower { .. } => {
+                let string = self.bind("string", &operands[0]);
+                if self.in_import() {
+                    results.push(format!("changetype<usize>({string})"));
+                } else {
+                    results.push(self.bind(
+                        "ptr",
+                        &format!(
+                            "witCopy(changetype<usize>({string}), <usize>{string}.length << 1, 2)"
+                        ),
+                    ));
+                }
+                results.push(format!("<usize>{string}.length"));
+            }
+
+            Instruction::ListLower { element, .. } => {
+                let (body, body_results) = self.blocks.pop().unwrap();
+                assert!(body_results.is_empty());
+                let elem = self.iter_elems.pop().unwrap();
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.gen.sizes.align(element).align_wasm32();
+
+                let list = self.bind("list", &operands[0]);
+                let alloc = self.alloc(&format!("<usize>{list}.length * {size}"), align);
+                let ptr = self.bind("ptr", &alloc);
+                let index = self.locals.tmp("i");
+                uwriteln!(
+                    self.src,
+                    "for (let {index} = 0; {index} < {list}.length; {index}++) {{"
+                );
+                uwriteln!(self.src, "const {elem} = {list}[{index}];");
+                uwriteln!(self.src, "const {base} = {ptr} + <usize>{index} * {size};");
+                self.src.push_str(&body);
+                self.src.push_str("}\n");
+                results.push(ptr);
+                results.push(format!("<usize>{list}.length"));
+            }
+
+            Instruction::ListCanonLift { element, .. } => {
+                let array = crate::typed_array(element).unwrap();
+                results.push(format!(
+                    "witLiftTypedArray<{array}>({}, {})",
+                    operands[0], operands[1]
+                ));
+            }
+            Instruction::StringLift => {
+                results.push(format!("witLiftString({}, {})", operands[0], operands[1]));
+            }
+
+            Instruction::ListLift { element, .. } => {
+                let (body, body_results) = self.blocks.pop().unwrap();
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let ty = self.type_name(element);
+
+                let ptr = self.bind("ptr", &operands[0]);
+                let len = self.bind("len", &operands[1]);
+                let list = self.bind("list", &format!("new Array<{ty}>()"));
+                let index = self.locals.tmp("i");
+                uwriteln!(
+                    self.src,
+                    "for (let {index}: usize = 0; {index} < {len}; {index}++) {{"
+                );
+                uwriteln!(self.src, "const {base} = {ptr} + {index} * {size};");
+                self.src.push_str(&body);
+                uwriteln!(self.src, "{list}.push({});", body_results[0]);
+                self.src.push_str("}\n");
+                uwriteln!(self.src, "witFree({ptr}, {len} * {size});");
+                results.push(list);
+            }
+
+            Instruction::IterElem { .. } => {
+                let elem = self.locals.tmp("elem");
+                self.iter_elems.push(elem.clone());
+                results.push(elem);
+            }
+            Instruction::IterBasePointer => {
+                let base = self.locals.tmp("base");
+                self.iter_bases.push(base.clone());
+                results.push(base);
+            }
+
+            Instruction::CallWasm { name, sig } => {
+                let module = self.gen.module.clone().unwrap();
+                let params = sig
+                    .params
+                    .iter()
+                    .map(|ty| wasm_type(*ty))
+                    .collect::<Vec<_>>();
+                let result = match &sig.results[..] {
+                    [] => None,
+                    [ty] => Some(wasm_type(*ty)),
+                    _ => unreachable!("multiple results are returned through a return pointer"),
+                };
+                let import = self.gen.gen.declare_extern(&module, name, &params, result);
+                let call = format!("{import}({})", operands.join(", "));
+                if sig.results.is_empty() {
+                    uwriteln!(self.src, "{call};");
+                } else {
+                    results.push(self.bind("ret", &call));
+                }
+            }
+
+            Instruction::CallInterface { func, .. } => {
+                let call = match func.kind {
+                    FunctionKind::Method(_) => format!(
+                        "{}.{}({})",
+                        operands[0],
+                        self.callee,
+                        operands[1..].join(", ")
+                    ),
+                    _ => format!("{}({})", self.callee, operands.join(", ")),
+                };
+                match func.results.len() {
+                    0 => uwriteln!(self.src, "{call};"),
+                    1 => results.push(self.bind("ret", &call)),
+                    n => {
+                        let ret = self.bind("ret", &call);
+                        results.extend((0..n).map(|i| format!("{ret}.f{i}")));
+                    }
+                }
+            }
+
+            Instruction::Return { amt, func } => {
+                if !self.in_import() {
+                    assert!(*amt <= 1);
+                    if *amt == 1 {
+                        uwriteln!(self.src, "return {};", operands[0]);
+                    }
+                    return;
+                }
+                let value = match operands.len() {
+                    0 => None,
+                    1 => Some(operands[0].clone()),
+                    _ => {
+                        let name = self.gen.tuple_name(func.results.iter_types());
+                        Some(format!("new {name}({})", operands.join(", ")))
+                    }
+                };
+                // The results have been lifted out of the arena by now, so
+                // it's freed before returning them.
+                match (value, self.needs_arena) {
+                    (None, false) => {}
+                    (None, true) => uwriteln!(self.src, "{}.deallocate();", self.arena),
+                    (Some(value), false) => uwriteln!(self.src, "return {value};"),
+                    (Some(value), true) => {
+                        let ret = self.bind("result", &value);
+                        uwriteln!(self.src, "{}.deallocate();", self.arena);
+                        uwriteln!(self.src, "return {ret};");
+                    }
+                }
+            }
+
+            Instruction::I32Load { offset } => results.push(self.load("i32", *offset, operands)),
+            Instruction::I64Load { offset } => results.push(self.load("i64", *offset, operands)),
+            Instruction::F32Load { offset } => results.push(self.load("f32", *offset, operands)),
+            Instruction::F64Load { offset } => results.push(self.load("f64", *offset, operands)),
+            Instruction::PointerLoad { offset } | Instruction::LengthLoad { offset } => {
+                results.push(self.load("usize", *offset, operands))
+            }
+            Instruction::I32Load8U { offset } => {
+                let load = self.load("u8", *offset, operands);
+                results.push(format!("<i32>{load}"));
+            }
+            Instruction::I32Load8S { offset } => {
+                let load = self.load("i8", *offset, operands);
+                results.push(format!("<i32>{load}"));
+            }
+            Instruction::I32Load16U { offset } => {
+                let load = self.load("u16", *offset, operands);
+                results.push(format!("<i32>{load}"));
+            }
+            Instruction::I32Load16S { offset } => {
+                let load = self.load("i16", *offset, operands);
+                results.push(format!("<i32>{load}"));
+            }
+
+            Instruction::I32Store { offset } => {
+                let value = operands[0].clone();
+                self.store("i32", &value, *offset, operands)
+            }
+            Instruction::I64Store { offset } => {
+                let value = operands[0].clone();
+                self.store("i64", &value, *offset, operands)
+            }
+            Instruction::F32Store { offset } => {
+                let value = operands[0].clone();
+                self.store("f32", &value, *offset, operands)
+            }
+            Instruction::F64Store { offset } => {
+                let value = operands[0].clone();
+                self.store("f64", &value, *offset, operands)
+            }
+            Instruction::I32Store8 { offset } => {
+                let value = format!("<u8>{}", operands[0]);
+                self.store("u8", &value, *offset, operands)
+            }
+            Instruction::I32Store16 { offset } => {
+                let value = format!("<u16>{}", operands[0]);
+                self.store("u16", &value, *offset, operands)
+            }
+            Instruction::PointerStore { offset } | Instruction::LengthStore { offset } => {
+                let value = operands[0].clone();
+                self.store("usize", &value, *offset, operands)
+            }
+
+            Instruction::Malloc { .. } => unreachable!(),
+
+            Instruction::GuestDeallocate { size, .. } => {
+                uwriteln!(self.src, "witFree({}, {size});", operands[0]);
+            }
+            Instruction::GuestDeallocateString => {
+                uwriteln!(self.src, "witFree({}, {} << 1);", operands[0], operands[1]);
+            }
+            Instruction::GuestDeallocateVariant { blocks } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - blocks..)
+                    .collect::<Vec<_>>();
+
+                uwriteln!(self.src, "switch ({}) {{", operands[0]);
+                for (i, (block, results)) in blocks.into_iter().enumerate() {
+                    assert!(results.is_empty());
+                    uwriteln!(self.src, "case {i}: {{");
+                    self.src.push_str(&block);
+                    self.src.push_str("break;\n}\n");
+                }
+                self.src.push_str("default: unreachable();\n}\n");
+            }
+            Instruction::GuestDeallocateList { element } => {
+                let (body, results) = self.blocks.pop().unwrap();
+                assert!(results.is_empty());
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+
+                let ptr = self.bind("ptr", &operands[0]);
+                let len = self.bind("len", &operands[1]);
+                if !body.trim().is_empty() {
+                    let index = self.locals.tmp("i");
+                    uwriteln!(
+                        self.src,
+                        "for (let {index}: usize = 0; {index} < {len}; {index}++) {{"
+                    );
+                    uwriteln!(self.src, "const {base} = {ptr} + {index} * {size};");
+                    self.src.push_str(&body);
+                    self.src.push_str("}\n");
+                }
+                uwriteln!(self.src, "witFree({ptr}, {len} * {size});");
+            }
+
+            Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. }
+            | Instruction::Flush { .. } => {
+                unreachable!("async functions are never requested by this generator")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+        }
+    }
+}
+
+fn handle_resource(handle: &Handle) -> TypeId {
+    let (Handle::Own(resource) | Handle::Borrow(resource)) = handle;
+    *resource
+}
+
+fn perform_cast(op: &str, cast: &Bitcast) -> String {
+    match cast {
+        Bitcast::I32ToF32 => format!("reinterpret<f32>({op})"),
+        Bitcast::I64ToF64 => format!("reinterpret<f64>({op})"),
+        Bitcast::F32ToI32 => format!("reinterpret<i32>({op})"),
+        Bitcast::F64ToI64 => format!("reinterpret<i64>({op})"),
+        Bitcast::I64ToF32 => format!("reinterpret<f32>(<i32>{op})"),
+        Bitcast::F32ToI64 => format!("<i64>reinterpret<u32>({op})"),
+        Bitcast::I32ToI64 => format!("<i64>{op}"),
+        Bitcast::I64ToI32 => format!("<i32>{op}"),
+        // P64 is represented as `i64`, and both pointers and lengths as
+        // `usize`, so these are no-ops.
+        Bitcast::I64ToP64 | Bitcast::P64ToI64 | Bitcast::PToL | Bitcast::LToP => op.to_string(),
+        Bitcast::P64ToP | Bitcast::I64ToL | Bitcast::I32ToP | Bitcast::I32ToL => {
+            format!("<usize>{op}")
+        }
+        Bitcast::PToP64 | Bitcast::LToI64 => format!("<i64>{op}"),
+        Bitcast::PToI32 | Bitcast::LToI32 => format!("<i32>{op}"),
+        Bitcast::None => op.to_string(),
+        Bitcast::Sequence(sequence) => {
+            let [first, second] = &**sequence;
+            let inner = perform_cast(op, first);
+            perform_cast(&inner, second)
+        }
+    }
+}
+
+pub(super) fn wasm_type(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 => "i32",
+        WasmType::I64 => "i64",
+        WasmType::F32 => "f32",
+        WasmType::F64 => "f64",
+        WasmType::Pointer | WasmType::Length => "usize",
+        WasmType::PointerOrI64 => "i64",
+    }
+}
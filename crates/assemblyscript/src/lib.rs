@@ -0,0 +1,1290 @@
+mod bindgen;
+
+use anyhow::Result;
+use bindgen::{wasm_type, FunctionBindgen};
+use heck::*;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use wit_bindgen_core::abi::{self, AbiVariant, LiftLower};
+use wit_bindgen_core::{
+    dealias, uwriteln, wit_parser::*, AnonymousTypeGenerator, Direction, Files,
+    InterfaceGenerator as _, Ns, Source, WorldGenerator,
+};
+
+/// Support code placed at the top of every generated file.
+const RUNTIME: &str = include_str!("runtime.ts");
+
+/// The names `RUNTIME` declares and generated code refers to, which
+/// parameters and top-level functions mustn't shadow.
+const RUNTIME_DECLS: &[&str] = &[
+    "cabi_realloc",
+    "witZero",
+    "witFree",
+    "witCopy",
+    "witLiftTypedArray",
+    "witLiftString",
+    "witHandles",
+    "witRep",
+    "witRelease",
+    "witRetArea",
+    "impl",
+    "memory",
+    "heap",
+    "load",
+    "store",
+    "changetype",
+    "unreachable",
+    "assert",
+];
+
+/// The types generated code refers to, which generated types mustn't shadow.
+const RESERVED_TYPES: &[&str] = &[
+    "Array",
+    "Float32Array",
+    "Float64Array",
+    "Int8Array",
+    "Int16Array",
+    "Int32Array",
+    "Int64Array",
+    "Map",
+    "Option",
+    "Result",
+    "String",
+    "Uint8Array",
+    "Uint16Array",
+    "Uint32Array",
+    "Uint64Array",
+    "Unit",
+    "WitArena",
+];
+
+#[derive(Default)]
+struct AssemblyScript {
+    opts: Opts,
+    world: String,
+    sizes: SizeAlign,
+    src: Source,
+    /// The `@external` declarations of the core wasm functions the bindings
+    /// import.
+    externs: Source,
+    /// The definitions of the core wasm functions the bindings export.
+    exports: Source,
+    /// The functions in `exports`, and the names the entry point exports
+    /// them as.
+    export_names: Vec<(String, String)>,
+    /// The names of the top-level functions.
+    fn_names: Ns,
+    /// The names of the top-level types and namespaces.
+    type_names: Ns,
+    /// The number of values in each tuple used by the world, for which a
+    /// tuple class is generated.
+    tuples: BTreeSet<usize>,
+    return_pointer_area_size: usize,
+    return_pointer_area_align: usize,
+
+    /// The namespace each interface's bindings are placed in, which changes
+    /// to the exported namespace once an interface which is both imported
+    /// and exported is exported.
+    namespaces: HashMap<InterfaceId, String>,
+    /// The namespace of the exports module each exported interface is
+    /// implemented in.
+    impl_namespaces: HashMap<InterfaceId, String>,
+    /// Whether each resource is imported or exported, and the core wasm
+    /// functions which manage its handles, which change in the same way as
+    /// `namespaces`.
+    resources: HashMap<TypeId, Resource>,
+}
+
+#[derive(Clone)]
+struct Resource {
+    direction: Direction,
+    /// The `[resource-drop]` import.
+    drop: String,
+    /// The `[resource-new]` and `[resource-rep]` imports of an exported
+    /// resource.
+    new: String,
+    rep: String,
+}
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct Opts {
+    /// The module the world's exports are implemented in, relative to the
+    /// generated bindings.
+    #[cfg_attr(feature = "clap", arg(long, default_value = "./exports"))]
+    pub exports_module: Option<String>,
+}
+
+impl Opts {
+    pub fn build(&self) -> Box<dyn WorldGenerator> {
+        Box::new(AssemblyScript {
+            opts: self.clone(),
+            ..AssemblyScript::default()
+        })
+    }
+}
+
+impl WorldGenerator for AssemblyScript {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        wit_bindgen_core::reject_futures_and_streams(resolve, world, "AssemblyScript")
+    }
+
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+        self.world = resolve.worlds[world].name.clone();
+        self.sizes.fill(resolve);
+        for name in RESERVED_TYPES {
+            self.type_names.insert(name).unwrap();
+        }
+        for name in RUNTIME_DECLS {
+            self.fn_names.insert(name).unwrap();
+        }
+        // Types imported by the world itself are declared at the top level,
+        // so namespaces mustn't shadow them.
+        for item in resolve.worlds[world].imports.values() {
+            if let WorldItem::Type(id) = item {
+                let name = type_ident(resolve.types[*id].name.as_ref().unwrap());
+                let _ = self.type_names.insert(&name);
+            }
+        }
+
+        for (_, ty) in resolve.types.iter() {
+            if let TypeDefKind::Tuple(tuple) = &ty.kind {
+                self.tuples.insert(tuple.types.len());
+            }
+        }
+        let funcs = resolve
+            .interfaces
+            .iter()
+            .flat_map(|(_, iface)| iface.functions.values())
+            .chain(
+                resolve.worlds[world]
+                    .imports
+                    .values()
+                    .chain(resolve.worlds[world].exports.values())
+                    .filter_map(|item| match item {
+                        WorldItem::Function(func) => Some(func),
+                        _ => None,
+                    }),
+            );
+        for func in funcs {
+            if func.results.len() > 1 {
+                self.tuples.insert(func.results.len());
+            }
+        }
+        for n in self.tuples.iter() {
+            self.type_names.insert(&format!("Tuple{n}")).unwrap();
+        }
+    }
+
+    fn import_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let namespace = self.namespace_name(resolve, name);
+        let namespace = self.type_names.tmp(&namespace);
+        self.namespaces.insert(id, namespace.clone());
+        let module = resolve.name_world_key(name);
+        let mut gen = self.interface(resolve, Some(namespace), Direction::Import, Some(module));
+        gen.funcs = resolve.interfaces[id].functions.values().collect();
+        gen.types(id);
+        gen.import_funcs();
+        gen.finish();
+        Ok(())
+    }
+
+    fn import_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) {
+        let mut gen = self.interface(resolve, None, Direction::Import, Some("$root".to_string()));
+        gen.funcs = funcs.iter().map(|(_, f)| *f).collect();
+        gen.import_funcs();
+        gen.finish();
+    }
+
+    fn import_types(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        types: &[(&str, TypeId)],
+        _files: &mut Files,
+    ) {
+        let mut gen = self.interface(resolve, None, Direction::Import, Some("$root".to_string()));
+        for (name, id) in types {
+            gen.define_type(name, *id);
+        }
+        gen.finish();
+    }
+
+    fn export_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let path = self.namespace_name(resolve, name);
+        let namespace = self.type_names.tmp(&format!("Exports{path}"));
+        self.namespaces.insert(id, namespace.clone());
+        self.impl_namespaces
+            .insert(id, format!("impl.{}", path.to_lower_camel_case()));
+        let module = resolve.name_world_key(name);
+        let mut gen = self.interface(resolve, Some(namespace), Direction::Export, Some(module));
+        gen.impl_path = format!("impl.{}", path.to_lower_camel_case());
+        gen.funcs = resolve.interfaces[id].functions.values().collect();
+        gen.types(id);
+        gen.export_funcs();
+        gen.finish();
+        Ok(())
+    }
+
+    fn export_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) -> Result<()> {
+        let mut gen = self.interface(resolve, None, Direction::Export, None);
+        gen.funcs = funcs.iter().map(|(_, f)| *f).collect();
+        gen.export_funcs();
+        gen.finish();
+        Ok(())
+    }
+
+    fn finish(&mut self, _resolve: &Resolve, _id: WorldId, files: &mut Files) -> Result<()> {
+        let snake = self.world.to_snake_case();
+        let version = env!("CARGO_PKG_VERSION");
+
+        let mut src = Source::default();
+        wit_bindgen_core::generated_preamble(&mut src, version);
+        if !self.export_names.is_empty() {
+            let module = self.opts.exports_module.as_deref().unwrap_or("./exports");
+            uwriteln!(src, "\nimport * as impl from \"{module}\";");
+        }
+        src.push_str("\n");
+        src.as_mut_string().push_str(RUNTIME);
+
+        for n in self.tuples.iter() {
+            let params = (0..*n).map(|i| format!("T{i}")).collect::<Vec<_>>();
+            let fields = (0..*n)
+                .map(|i| format!("public f{i}: T{i}"))
+                .collect::<Vec<_>>();
+            uwriteln!(src, "\n/** A WIT `tuple` of {n} values. */");
+            if *n == 0 {
+                src.push_str("export class Tuple0 {}\n");
+            } else {
+                src.push_str(&format!(
+                    "export class Tuple{n}<{}> {{\n",
+                    params.join(", ")
+                ));
+                uwriteln!(src, "constructor({}) {{}}", fields.join(", "));
+                src.push_str("}\n");
+            }
+        }
+
+        let contents = src.as_mut_string();
+        contents.push_str(self.src.as_str());
+        contents.push_str(self.externs.as_str());
+        contents.push_str(self.exports.as_str());
+
+        // Declare a statically-allocated return area, if needed. Only exports
+        // use this since imports allocate their return area in their arena.
+        if self.return_pointer_area_size > 0 {
+            uwriteln!(
+                src,
+                "\nconst witRetArea = cabi_realloc(0, 0, {}, {});",
+                self.return_pointer_area_align,
+                self.return_pointer_area_size,
+            );
+        }
+        files.push(&format!("{snake}.ts"), src.as_bytes());
+
+        // The entry point only exports the core wasm exports, since any other
+        // declarations it exported would also be exported from the module.
+        let mut entry = Source::default();
+        wit_bindgen_core::generated_preamble(&mut entry, version);
+        entry.push_str("\nexport {\n");
+        uwriteln!(entry, "cabi_realloc,");
+        for (func, export_name) in self.export_names.iter() {
+            if is_ident(export_name) {
+                uwriteln!(entry, "{func} as {export_name},");
+            } else {
+                uwriteln!(entry, "{func},");
+            }
+        }
+        uwriteln!(entry, "}} from \"./{snake}\";");
+        files.push(&format!("{snake}_component.ts"), entry.as_bytes());
+        Ok(())
+    }
+}
+
+impl AssemblyScript {
+    fn interface<'a>(
+        &'a mut self,
+        resolve: &'a Resolve,
+        namespace: Option<String>,
+        direction: Direction,
+        module: Option<String>,
+    ) -> InterfaceGenerator<'a> {
+        InterfaceGenerator {
+            gen: self,
+            resolve,
+            namespace,
+            direction,
+            module,
+            impl_path: "impl".to_string(),
+            funcs: Vec::new(),
+            src: Source::default(),
+            exports_docs: Source::default(),
+        }
+    }
+
+    /// Returns the name of the namespace bindings for the interface `key` are
+    /// placed in, without the `Exports` prefix of an exported interface.
+    fn namespace_name(&self, resolve: &Resolve, key: &WorldKey) -> String {
+        let mut path = String::new();
+        match key {
+            WorldKey::Name(name) => {
+                path.push_str(&self.world.to_upper_camel_case());
+                path.push_str(&name.to_upper_camel_case());
+            }
+            WorldKey::Interface(id) => {
+                let iface = &resolve.interfaces[*id];
+                let pkg = &resolve.packages[iface.package.unwrap()].name;
+                path.push_str(&pkg.namespace.to_upper_camel_case());
+                path.push_str(&pkg.name.to_upper_camel_case());
+                // Only disambiguate by version if more than one version of
+                // this package is in use.
+                if let Some(version) = &pkg.version {
+                    let versions = resolve
+                        .packages
+                        .iter()
+                        .filter(|(_, p)| {
+                            p.name.namespace == pkg.namespace && p.name.name == pkg.name
+                        })
+                        .count();
+                    if versions > 1 {
+                        let version = version.to_string().replace(['.', '-', '+'], "_");
+                        path.push_str(&format!("V{version}"));
+                    }
+                }
+                path.push_str(&iface.name.as_ref().unwrap().to_upper_camel_case());
+            }
+        }
+        path
+    }
+
+    /// Declares the core wasm import `name` from `module`, returning the name
+    /// of the function it's declared as.
+    fn declare_extern(
+        &mut self,
+        module: &str,
+        name: &str,
+        params: &[&str],
+        result: Option<&str>,
+    ) -> String {
+        let func = self.fn_names.tmp(&format!(
+            "__wasm_import_{}_{}",
+            module.to_snake_case(),
+            name.to_snake_case()
+        ));
+        let params = params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| format!("arg{i}: {ty}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let result = result.unwrap_or("void");
+        uwriteln!(self.externs, "\n// @ts-ignore: decorator");
+        uwriteln!(self.externs, "@external(\"{module}\", \"{name}\")");
+        uwriteln!(self.externs, "declare function {func}({params}): {result};");
+        func
+    }
+}
+
+struct InterfaceGenerator<'a> {
+    gen: &'a mut AssemblyScript,
+    resolve: &'a Resolve,
+    /// The namespace declarations are placed in, which is `None` for the
+    /// world itself.
+    namespace: Option<String>,
+    direction: Direction,
+    /// The core wasm module imports come from, or the name of the exported
+    /// interface, which is `None` for functions exported by the world itself.
+    module: Option<String>,
+    /// The namespace of the exports module exported functions are
+    /// implemented in.
+    impl_path: String,
+    /// The functions of the interface, including the methods of resources.
+    funcs: Vec<&'a Function>,
+    src: Source,
+    /// The signatures of the functions and resources the exports module
+    /// implements, which are documented before the namespace.
+    exports_docs: Source,
+}
+
+impl<'a> InterfaceGenerator<'a> {
+    fn finish(self) {
+        if !self.exports_docs.as_str().is_empty() {
+            let exports = match &self.module {
+                Some(module) => format!("`{module}`"),
+                None => format!("the world `{}`", self.gen.world),
+            };
+            let location = match self.impl_path.strip_prefix("impl.") {
+                Some(namespace) => format!("the namespace `{namespace}` of the exports module"),
+                None => "the exports module".to_string(),
+            };
+            uwriteln!(
+                self.gen.src,
+                "\n// The exports of {exports}, which are implemented in {location}:"
+            );
+            uwriteln!(self.gen.src, "//");
+            self.gen.src.push_str(self.exports_docs.as_str());
+        }
+        // The source is appended verbatim, since `Source::push_str` would
+        // strip the indentation of the continuation lines of doc comments.
+        let src = self.gen.src.as_mut_string();
+        match &self.namespace {
+            Some(namespace) if !self.src.as_str().is_empty() => {
+                uwriteln!(src, "\nexport namespace {namespace} {{");
+                for line in self.src.as_str().trim_start_matches('\n').lines() {
+                    if !line.is_empty() {
+                        src.push_str("  ");
+                    }
+                    src.push_str(line);
+                    src.push('\n');
+                }
+                src.push_str("}\n");
+            }
+            _ => src.push_str(self.src.as_str()),
+        }
+    }
+
+    /// Returns a namespace for the parameters and locals of a function, which
+    /// mustn't shadow the runtime declarations it refers to.
+    fn locals(&self) -> Ns {
+        let mut ns = Ns::default();
+        for name in RUNTIME_DECLS {
+            ns.insert(name).unwrap();
+        }
+        ns
+    }
+
+    /// Returns the methods, static functions and constructor of the resource
+    /// `id`.
+    fn resource_funcs(&self, id: TypeId) -> Vec<&'a Function> {
+        self.funcs
+            .iter()
+            .copied()
+            .filter(|f| match f.kind {
+                FunctionKind::Freestanding => false,
+                FunctionKind::Method(r)
+                | FunctionKind::Static(r)
+                | FunctionKind::Constructor(r) => r == id,
+            })
+            .collect()
+    }
+
+    /// Defines the class wrapping handles to the imported resource `id`.
+    fn define_imported_resource(&mut self, id: TypeId, name: &str) {
+        let resource = self.resolve.types[id].name.as_ref().unwrap();
+        let module = self.module.clone().unwrap();
+        let drop = self.gen.declare_extern(
+            &module,
+            &format!("[resource-drop]{resource}"),
+            &["i32"],
+            None,
+        );
+        self.gen.resources.insert(
+            id,
+            Resource {
+                direction: Direction::Import,
+                drop: drop.clone(),
+                new: String::new(),
+                rep: String::new(),
+            },
+        );
+        uwriteln!(
+            self.src,
+            "export class {name} {{
+                /** Wraps `handle`, which `drop` drops unless it's borrowed or taken. */
+                constructor(public handle: i32) {{}}
+
+                /** Takes ownership of the handle, which `drop` then no longer drops. */
+                takeHandle(): i32 {{
+                    const handle = this.handle;
+                    this.handle = 0;
+                    return handle;
+                }}
+
+                /** Drops the handle to the resource. */
+                drop(): void {{
+                    if (this.handle != 0) {{
+                        {drop}(this.handle);
+                        this.handle = 0;
+                    }}
+                }}"
+        );
+        for func in self.resource_funcs(id) {
+            self.src.push_str("\n");
+            self.import_func(func);
+        }
+        self.src.push_str("}\n");
+    }
+
+    /// Declares the core wasm functions managing the handles of the exported
+    /// resource `id`, and the destructor releasing its objects.
+    fn define_exported_resource(&mut self, id: TypeId, name: &str) {
+        let resource = self.resolve.types[id].name.as_ref().unwrap();
+        let module = self.module.clone().unwrap();
+        let export_module = format!("[export]{module}");
+        let new = self.gen.declare_extern(
+            &export_module,
+            &format!("[resource-new]{resource}"),
+            &["i32"],
+            Some("i32"),
+        );
+        let rep = self.gen.declare_extern(
+            &export_module,
+            &format!("[resource-rep]{resource}"),
+            &["i32"],
+            Some("i32"),
+        );
+        let drop = self.gen.declare_extern(
+            &export_module,
+            &format!("[resource-drop]{resource}"),
+            &["i32"],
+            None,
+        );
+        self.gen.resources.insert(
+            id,
+            Resource {
+                direction: Direction::Export,
+                drop,
+                new,
+                rep,
+            },
+        );
+
+        uwriteln!(self.exports_docs, "//   export class {name} {{");
+        for func in self.resource_funcs(id) {
+            let sig = self.signature(func, &mut None);
+            uwriteln!(self.exports_docs, "//     {sig}");
+        }
+        uwriteln!(self.exports_docs, "//   }}");
+
+        // Objects are kept alive while there are handles to them, and are
+        // released by the destructor once the last one is dropped.
+        let dtor = self.gen.fn_names.tmp(&format!(
+            "__wasm_export_{}_{}_dtor",
+            module.to_snake_case(),
+            resource.to_snake_case()
+        ));
+        uwriteln!(
+            self.gen.exports,
+            "
+                export function {dtor}(rep: i32): void {{
+                    witRelease(rep);
+                }}"
+        );
+        self.gen
+            .export_names
+            .push((dtor, format!("{module}#[dtor]{resource}")));
+    }
+
+    fn import_funcs(&mut self) {
+        for func in self.funcs.clone() {
+            if let FunctionKind::Freestanding = func.kind {
+                self.src.push_str("\n");
+                self.import_func(func);
+            }
+        }
+    }
+
+    /// Generates a function calling the import `func`.
+    fn import_func(&mut self, func: &Function) {
+        self.docs(&func.docs);
+        let mut locals = self.locals();
+        let mut params = Some(Vec::new());
+        let sig = self.signature(func, &mut params);
+        let params = params.unwrap();
+        for param in params.iter() {
+            let _ = locals.insert(param);
+        }
+
+        let mut f = FunctionBindgen::new(self, locals);
+        f.params = params;
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            func,
+            &mut f,
+            false,
+        );
+        let FunctionBindgen {
+            src: body,
+            needs_arena,
+            arena,
+            ..
+        } = f;
+
+        let sig = match func.kind {
+            FunctionKind::Freestanding => format!("export {sig}"),
+            _ => sig,
+        };
+        self.src.push_str(&format!("{sig} {{\n"));
+        if needs_arena {
+            uwriteln!(self.src, "const {arena} = new WitArena();");
+        }
+        self.src.push_str(&body);
+        self.src.push_str("}\n");
+    }
+
+    fn export_funcs(&mut self) {
+        for func in self.funcs.clone() {
+            if let FunctionKind::Freestanding = func.kind {
+                let sig = self.signature(func, &mut None);
+                uwriteln!(self.exports_docs, "//   export {sig}");
+            }
+        }
+        for func in self.funcs.clone() {
+            self.export_func(func);
+        }
+    }
+
+    /// Generates a function which exports `func` by lifting its arguments,
+    /// calling its implementation, and lowering its results.
+    fn export_func(&mut self, func: &Function) {
+        let export_name = func.core_export_name(self.module.as_deref()).into_owned();
+        let shim = self
+            .gen
+            .fn_names
+            .tmp(&format!("__wasm_export_{}", export_name.to_snake_case()));
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
+
+        let callee = match func.kind {
+            FunctionKind::Freestanding => {
+                format!("{}.{}", self.impl_path, func_name(&func.name))
+            }
+            FunctionKind::Method(_) => self.func_name(func),
+            FunctionKind::Static(id) => {
+                format!("{}.{}", self.qualified_name(id), self.func_name(func))
+            }
+            FunctionKind::Constructor(id) => format!("new {}", self.qualified_name(id)),
+        };
+
+        let mut locals = self.locals();
+        let params = sig
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| (locals.tmp(&format!("arg{i}")), wasm_type(*ty)))
+            .collect::<Vec<_>>();
+        let mut f = FunctionBindgen::new(self, locals);
+        f.callee = callee;
+        f.params = params.iter().map(|(name, _)| name.clone()).collect();
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestExport,
+            LiftLower::LiftArgsLowerResults,
+            func,
+            &mut f,
+            false,
+        );
+        let body = f.src;
+        self.push_export(&shim, &params, &sig.results, &body, &export_name);
+
+        if abi::guest_export_needs_post_return(self.resolve, func) {
+            let mut locals = self.locals();
+            let params = sig
+                .results
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| (locals.tmp(&format!("arg{i}")), wasm_type(*ty)))
+                .collect::<Vec<_>>();
+            let mut f = FunctionBindgen::new(self, locals);
+            f.params = params.iter().map(|(name, _)| name.clone()).collect();
+            abi::post_return(f.gen.resolve, func, &mut f);
+            let body = f.src;
+            let shim = self.gen.fn_names.tmp(&format!("{shim}_post_return"));
+            self.push_export(
+                &shim,
+                &params,
+                &[],
+                &body,
+                &format!("cabi_post_{export_name}"),
+            );
+        }
+    }
+
+    /// Adds the function `func`, taking `params` and returning `results`, to
+    /// the functions exported as `export_name`.
+    fn push_export(
+        &mut self,
+        func: &str,
+        params: &[(String, &str)],
+        results: &[abi::WasmType],
+        body: &str,
+        export_name: &str,
+    ) {
+        let params = params
+            .iter()
+            .map(|(name, ty)| format!("{name}: {ty}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let result = match results {
+            [] => "void",
+            [ty] => wasm_type(*ty),
+            _ => unreachable!("multiple results are returned through a return pointer"),
+        };
+        self.gen.exports.push_str(&format!(
+            "\nexport function {func}({params}): {result} {{\n"
+        ));
+        self.gen.exports.push_str(body);
+        self.gen.exports.push_str("}\n");
+        self.gen
+            .export_names
+            .push((func.to_string(), export_name.to_string()));
+    }
+
+    /// Returns the declaration of the function for `func`, collecting the
+    /// names its parameters are bound to in `params` if it's given.
+    fn signature(&self, func: &Function, params: &mut Option<Vec<String>>) -> String {
+        let mut decl = match func.kind {
+            FunctionKind::Freestanding => format!("function {}", self.func_name(func)),
+            FunctionKind::Method(_) => self.func_name(func),
+            FunctionKind::Static(_) => format!("static {}", self.func_name(func)),
+            // Exported constructors are the constructors of the classes
+            // implementing them, while imported constructors are static
+            // functions since the constructor wraps a handle.
+            FunctionKind::Constructor(_) => match self.direction {
+                Direction::Import => "static create".to_string(),
+                Direction::Export => "constructor".to_string(),
+            },
+        };
+
+        let mut names = self.locals();
+        let mut decls = Vec::new();
+        for (i, (name, ty)) in func.params.iter().enumerate() {
+            if i == 0 && matches!(func.kind, FunctionKind::Method(_)) {
+                if let Some(params) = params {
+                    params.push("this".to_string());
+                }
+                continue;
+            }
+            let local = as_ident(&names.tmp(&name.to_lower_camel_case()));
+            decls.push(format!("{local}: {}", self.type_name(ty)));
+            if let Some(params) = params {
+                params.push(local);
+            }
+        }
+        decl.push_str(&format!("({})", decls.join(", ")));
+
+        match func.kind {
+            FunctionKind::Constructor(id) if self.direction == Direction::Import => {
+                decl.push_str(&format!(": {}", self.qualified_name(id)));
+            }
+            FunctionKind::Constructor(_) => {}
+            _ => decl.push_str(&format!(": {}", self.results_type(&func.results))),
+        }
+        decl
+    }
+
+    /// Returns the type of the value a function returning `results` returns.
+    fn results_type(&self, results: &Results) -> String {
+        let types = results.iter_types().collect::<Vec<_>>();
+        match types.as_slice() {
+            [] => "void".to_string(),
+            [ty] => self.type_name(ty),
+            types => self.tuple_name(types.iter().copied()),
+        }
+    }
+
+    /// Returns the name of the function for `func`, which is a method of its
+    /// resource's class unless it's freestanding.
+    fn func_name(&self, func: &Function) -> String {
+        match func.kind {
+            FunctionKind::Freestanding => func_name(&func.name),
+            FunctionKind::Constructor(_) => "create".to_string(),
+            FunctionKind::Method(_) | FunctionKind::Static(_) => {
+                let name = func.item_name().to_lower_camel_case();
+                match name.as_str() {
+                    "handle" | "takeHandle" | "drop" | "create" | "constructor" => {
+                        format!("{name}_")
+                    }
+                    _ => as_ident(&name),
+                }
+            }
+        }
+    }
+
+    fn docs(&mut self, docs: &Docs) {
+        if let Some(docs) = &docs.contents {
+            let lines = docs.trim().lines().collect::<Vec<_>>();
+            if let [line] = lines.as_slice() {
+                uwriteln!(self.src, "/** {} */", line.trim());
+                return;
+            }
+            uwriteln!(self.src, "/**");
+            for line in lines {
+                let line = line.trim();
+                if line.is_empty() {
+                    uwriteln!(self.src, " *");
+                } else {
+                    uwriteln!(self.src, " * {line}");
+                }
+            }
+            uwriteln!(self.src, " */");
+        }
+    }
+
+    fn type_name(&self, ty: &Type) -> String {
+        match ty {
+            Type::Bool => "bool".to_string(),
+            Type::U8 => "u8".to_string(),
+            Type::S8 => "i8".to_string(),
+            Type::U16 => "u16".to_string(),
+            Type::S16 => "i16".to_string(),
+            Type::U32 | Type::Char => "u32".to_string(),
+            Type::S32 => "i32".to_string(),
+            Type::U64 => "u64".to_string(),
+            Type::S64 => "i64".to_string(),
+            Type::F32 => "f32".to_string(),
+            Type::F64 => "f64".to_string(),
+            Type::String => "string".to_string(),
+            Type::Id(id) => {
+                if self.resolve.types[*id].name.is_some() {
+                    self.qualified_name(*id)
+                } else {
+                    self.anonymous_type_name(*id)
+                }
+            }
+        }
+    }
+
+    fn optional_type_name(&self, ty: Option<&Type>) -> String {
+        ty.map_or("Unit".to_string(), |ty| self.type_name(ty))
+    }
+
+    /// Returns the name of the tuple class holding values of `types`.
+    fn tuple_name<'b>(&self, types: impl ExactSizeIterator<Item = &'b Type>) -> String {
+        let n = types.len();
+        if n == 0 {
+            return "Tuple0".to_string();
+        }
+        let types = types.map(|ty| self.type_name(ty)).collect::<Vec<_>>();
+        format!("Tuple{n}<{}>", types.join(", "))
+    }
+
+    /// Returns the type of the anonymous type `id`.
+    fn anonymous_type_name(&self, id: TypeId) -> String {
+        let mut printer = TypePrinter {
+            gen: self,
+            name: String::new(),
+        };
+        printer.define_anonymous_type(id);
+        printer.name
+    }
+
+    /// Returns the name of the named type `id`, qualified by its namespace so
+    /// it can be used anywhere in the generated file.
+    ///
+    /// Exported resources are the classes implementing them in the exports
+    /// module.
+    fn qualified_name(&self, id: TypeId) -> String {
+        let ty = &self.resolve.types[id];
+        let name = type_ident(ty.name.as_ref().unwrap());
+        match ty.owner {
+            TypeOwner::Interface(iface) => {
+                let exported = matches!(
+                    self.gen.resources.get(&id),
+                    Some(Resource {
+                        direction: Direction::Export,
+                        ..
+                    })
+                );
+                if exported {
+                    format!("{}.{name}", self.gen.impl_namespaces[&iface])
+                } else {
+                    format!("{}.{name}", self.gen.namespaces[&iface])
+                }
+            }
+            TypeOwner::World(_) => name,
+            TypeOwner::None => unreachable!(),
+        }
+    }
+
+    /// Returns an expression of the zero value of `ty`, which fields which
+    /// aren't otherwise initialized are initialized with.
+    fn zero(&self, ty: &Type) -> String {
+        match ty {
+            Type::Bool => "false".to_string(),
+            Type::String => "\"\"".to_string(),
+            Type::Id(id) => match &self.resolve.types[dealias(self.resolve, *id)].kind {
+                TypeDefKind::Type(ty) => self.zero(ty),
+                TypeDefKind::Enum(_) => "0".to_string(),
+                _ => format!("changetype<{}>(0)", self.type_name(ty)),
+            },
+            _ => "0".to_string(),
+        }
+    }
+
+    fn resource(&self, id: TypeId) -> &Resource {
+        &self.gen.resources[&dealias(self.resolve, id)]
+    }
+
+    fn type_alias_to(&mut self, name: &str, docs: &Docs, ty: String) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.src, "export type {} = {ty};", type_ident(name));
+    }
+}
+
+impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
+    fn resolve(&self) -> &'a Resolve {
+        self.resolve
+    }
+
+    fn type_record(&mut self, _id: TypeId, name: &str, record: &Record, docs: &Docs) {
+        let name = type_ident(name);
+        self.src.push_str("\n");
+        self.docs(docs);
+        self.src.push_str(&format!("export class {name} {{\n"));
+        let mut params = Vec::new();
+        for field in record.fields.iter() {
+            self.docs(&field.docs);
+            let field_name = func_name(&field.name);
+            let ty = self.type_name(&field.ty);
+            uwriteln!(self.src, "{field_name}: {ty};");
+            params.push(format!("{field_name}: {ty}"));
+        }
+        if !record.fields.is_empty() {
+            self.src
+                .push_str(&format!("\nconstructor({}) {{\n", params.join(", ")));
+            for field in record.fields.iter() {
+                let field_name = func_name(&field.name);
+                uwriteln!(self.src, "this.{field_name} = {field_name};");
+            }
+            self.src.push_str("}\n");
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_resource(&mut self, id: TypeId, name: &str, docs: &Docs) {
+        let name = type_ident(name);
+        match self.direction {
+            Direction::Import => {
+                self.src.push_str("\n");
+                self.docs(docs);
+                self.define_imported_resource(id, &name);
+            }
+            Direction::Export => self.define_exported_resource(id, &name),
+        }
+    }
+
+    fn type_flags(&mut self, _id: TypeId, name: &str, flags: &Flags, docs: &Docs) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        self.src
+            .push_str(&format!("export class {} {{\n", type_ident(name)));
+        for flag in flags.flags.iter() {
+            self.docs(&flag.docs);
+            uwriteln!(self.src, "{}: bool = false;", func_name(&flag.name));
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_tuple(&mut self, id: TypeId, name: &str, _tuple: &Tuple, docs: &Docs) {
+        let ty = self.anonymous_type_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_variant(&mut self, _id: TypeId, name: &str, variant: &Variant, docs: &Docs) {
+        let name = type_ident(name);
+        let tag = format!("{name}Tag");
+        self.src.push_str("\n");
+        self.docs(docs);
+        self.src.push_str(&format!("export enum {tag} {{\n"));
+        for case in variant.cases.iter() {
+            uwriteln!(self.src, "{},", case.name.to_upper_camel_case());
+        }
+        self.src.push_str("}\n");
+
+        // The payload of each case is stored in a separate field, which is
+        // read through a getter asserting that it's the variant's case.
+        self.src.push_str(&format!("\nexport class {name} {{\n"));
+        for case in variant.cases.iter() {
+            if let Some(ty) = &case.ty {
+                let case_name = case_name(&case.name);
+                let zero = self.zero(ty);
+                let ty = self.type_name(ty);
+                uwriteln!(self.src, "private _{case_name}: {ty} = {zero};");
+            }
+        }
+        uwriteln!(self.src, "\nconstructor(public readonly tag: {tag}) {{}}");
+        for case in variant.cases.iter() {
+            let case_name = case_name(&case.name);
+            let tag_case = case.name.to_upper_camel_case();
+            self.src.push_str("\n");
+            self.docs(&case.docs);
+            match &case.ty {
+                Some(ty) => {
+                    let ty = self.type_name(ty);
+                    uwriteln!(
+                        self.src,
+                        "static {case_name}(value: {ty}): {name} {{
+                            const variant = new {name}({tag}.{tag_case});
+                            variant._{case_name} = value;
+                            return variant;
+                        }}
+
+                        get {case_name}(): {ty} {{
+                            assert(this.tag == {tag}.{tag_case}, \"variant is not `{}`\");
+                            return this._{case_name};
+                        }}",
+                        case.name
+                    );
+                }
+                None => {
+                    uwriteln!(
+                        self.src,
+                        "static {case_name}(): {name} {{
+                            return new {name}({tag}.{tag_case});
+                        }}"
+                    );
+                }
+            }
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_option(&mut self, id: TypeId, name: &str, _payload: &Type, docs: &Docs) {
+        let ty = self.anonymous_type_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_result(&mut self, id: TypeId, name: &str, _result: &Result_, docs: &Docs) {
+        let ty = self.anonymous_type_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_enum(&mut self, _id: TypeId, name: &str, enum_: &Enum, docs: &Docs) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        self.src
+            .push_str(&format!("export enum {} {{\n", type_ident(name)));
+        for case in enum_.cases.iter() {
+            self.docs(&case.docs);
+            uwriteln!(self.src, "{},", case.name.to_upper_camel_case());
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_alias(&mut self, _id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        let ty = self.type_name(ty);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_list(&mut self, id: TypeId, name: &str, _ty: &Type, docs: &Docs) {
+        let ty = self.anonymous_type_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_builtin(&mut self, _id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        let ty = self.type_name(ty);
+        self.type_alias_to(name, docs, ty);
+    }
+}
+
+/// Prints the type of an anonymous type.
+struct TypePrinter<'a, 'b> {
+    gen: &'b InterfaceGenerator<'a>,
+    name: String,
+}
+
+impl<'a> AnonymousTypeGenerator<'a> for TypePrinter<'a, '_> {
+    fn resolve(&self) -> &'a Resolve {
+        self.gen.resolve
+    }
+
+    fn anonymous_typ_type(&mut self, _id: TypeId, ty: &Type, _docs: &Docs) {
+        self.name = self.gen.type_name(ty);
+    }
+
+    fn anonymous_type_handle(&mut self, _id: TypeId, handle: &Handle, _docs: &Docs) {
+        // Both owned and borrowed handles are represented by the resource's
+        // class.
+        let (Handle::Own(resource) | Handle::Borrow(resource)) = handle;
+        self.name = self
+            .gen
+            .qualified_name(dealias(self.gen.resolve, *resource));
+    }
+
+    fn anonymous_type_tuple(&mut self, _id: TypeId, ty: &Tuple, _docs: &Docs) {
+        self.name = self.gen.tuple_name(ty.types.iter());
+    }
+
+    fn anonymous_type_option(&mut self, _id: TypeId, ty: &Type, _docs: &Docs) {
+        self.name = format!("Option<{}>", self.gen.type_name(ty));
+    }
+
+    fn anonymous_type_result(&mut self, _id: TypeId, ty: &Result_, _docs: &Docs) {
+        let ok = self.gen.optional_type_name(ty.ok.as_ref());
+        let err = self.gen.optional_type_name(ty.err.as_ref());
+        self.name = format!("Result<{ok}, {err}>");
+    }
+
+    fn anonymous_type_list(&mut self, _id: TypeId, ty: &Type, _docs: &Docs) {
+        self.name = match typed_array(ty) {
+            Some(array) => array.to_string(),
+            None => format!("Array<{}>", self.gen.type_name(ty)),
+        };
+    }
+
+    fn anonymous_type_future(&mut self, _id: TypeId, _ty: &Option<Type>, _docs: &Docs) {
+        unreachable!("worlds using futures and streams are rejected in `validate`")
+    }
+
+    fn anonymous_type_stream(&mut self, _id: TypeId, _ty: &Stream, _docs: &Docs) {
+        unreachable!("worlds using futures and streams are rejected in `validate`")
+    }
+}
+
+/// Returns the typed array a `list` of `ty` is represented by, if it's a list
+/// of numbers.
+fn typed_array(ty: &Type) -> Option<&'static str> {
+    match ty {
+        Type::U8 => Some("Uint8Array"),
+        Type::S8 => Some("Int8Array"),
+        Type::U16 => Some("Uint16Array"),
+        Type::S16 => Some("Int16Array"),
+        Type::U32 => Some("Uint32Array"),
+        Type::S32 => Some("Int32Array"),
+        Type::U64 => Some("Uint64Array"),
+        Type::S64 => Some("Int64Array"),
+        Type::F32 => Some("Float32Array"),
+        Type::F64 => Some("Float64Array"),
+        _ => None,
+    }
+}
+
+/// Returns the name of the type for `name`, which mustn't shadow any type
+/// generated code refers to.
+fn type_ident(name: &str) -> String {
+    let name = name.to_upper_camel_case();
+    if RESERVED_TYPES.contains(&name.as_str()) || is_tuple_name(&name) {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
+
+fn is_tuple_name(name: &str) -> bool {
+    name.strip_prefix("Tuple")
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Returns the name of the function, field or parameter for `name`.
+fn func_name(name: &str) -> String {
+    as_ident(&name.to_lower_camel_case())
+}
+
+/// Returns the name of the static function constructing a case of a variant,
+/// which mustn't clash with the variant's `tag`.
+fn case_name(name: &str) -> String {
+    match func_name(name).as_str() {
+        "tag" => "tag_".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Returns whether `name` can be used as the name of an export of the entry
+/// point.
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        && as_ident(name) == name
+}
+
+/// Returns `name` as an identifier, appending `_` if it's a keyword.
+pub fn as_ident(name: &str) -> String {
+    let keywords = [
+        "abstract",
+        "as",
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "constructor",
+        "continue",
+        "debugger",
+        "declare",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "enum",
+        "export",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "from",
+        "function",
+        "get",
+        "if",
+        "implements",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "is",
+        "keyof",
+        "let",
+        "module",
+        "namespace",
+        "new",
+        "null",
+        "of",
+        "package",
+        "private",
+        "protected",
+        "public",
+        "readonly",
+        "return",
+        "set",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "type",
+        "typeof",
+        "var",
+        "void",
+        "while",
+        "with",
+        "yield",
+    ];
+    if keywords.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,147 @@
+/** A WIT `option`, which has a `value` if it `isSome`. */
+export class Option<T> {
+  constructor(public readonly isSome: bool, private _value: T) {}
+
+  static some<T>(value: T): Option<T> {
+    return new Option<T>(true, value);
+  }
+
+  static none<T>(): Option<T> {
+    return new Option<T>(false, witZero<T>());
+  }
+
+  get value(): T {
+    assert(this.isSome, "option is none");
+    return this._value;
+  }
+}
+
+/** A WIT `result`, which has a `value` if it `isOk` and an `error` otherwise. */
+export class Result<T, E> {
+  constructor(public readonly isOk: bool, private _value: T, private _error: E) {}
+
+  static ok<T, E>(value: T): Result<T, E> {
+    return new Result<T, E>(true, value, witZero<E>());
+  }
+
+  static err<T, E>(error: E): Result<T, E> {
+    return new Result<T, E>(false, witZero<T>(), error);
+  }
+
+  get value(): T {
+    assert(this.isOk, "result is an error");
+    return this._value;
+  }
+
+  get error(): E {
+    assert(!this.isOk, "result is ok");
+    return this._error;
+  }
+}
+
+/** The payload of a case of a `result` which doesn't have one. */
+export type Unit = u8;
+
+/** Returns the zero value of `T`, which is `null` for references. */
+// @ts-ignore: decorator
+@inline
+export function witZero<T>(): T {
+  if (isReference<T>()) {
+    return changetype<T>(0);
+  } else {
+    // @ts-ignore: cast
+    return <T>0;
+  }
+}
+
+/** The canonical ABI's allocation function, which allocates unmanaged memory. */
+export function cabi_realloc(oldPtr: usize, oldSize: usize, align: usize, newSize: usize): usize {
+  if (newSize == 0) {
+    return align;
+  }
+  if (oldSize == 0) {
+    return heap.alloc(newSize);
+  }
+  return heap.realloc(oldPtr, newSize);
+}
+
+/** Frees `size` bytes at `ptr` which were allocated by `cabi_realloc`. */
+export function witFree(ptr: usize, size: usize): void {
+  if (size != 0) {
+    heap.free(ptr);
+  }
+}
+
+/** Copies `size` bytes at `src` to memory allocated by `cabi_realloc`. */
+export function witCopy(src: usize, size: usize, align: usize): usize {
+  const ptr = cabi_realloc(0, 0, align, size);
+  memory.copy(ptr, src, size);
+  return ptr;
+}
+
+/** Copies the `length` elements at `ptr` into a new typed array, and frees them. */
+export function witLiftTypedArray<T>(ptr: usize, length: usize): T {
+  // @ts-ignore: typed array
+  const array = instantiate<T>(<i32>length);
+  // @ts-ignore: typed array
+  const size = <usize>array.byteLength;
+  // @ts-ignore: typed array
+  memory.copy(array.dataStart, ptr, size);
+  witFree(ptr, size);
+  return array;
+}
+
+/** Decodes the `length` UTF-16 code units at `ptr` into a string, and frees them. */
+export function witLiftString(ptr: usize, length: usize): string {
+  const string = String.UTF16.decodeUnsafe(ptr, length << 1);
+  witFree(ptr, length << 1);
+  return string;
+}
+
+const witHandles = new Map<usize, u32>();
+
+/**
+ * Returns the representation of a new handle to `object`, which is kept alive
+ * until `witRelease` is called once for each handle.
+ */
+export function witRep<T>(object: T): i32 {
+  const ptr = changetype<usize>(object);
+  if (witHandles.has(ptr)) {
+    witHandles.set(ptr, witHandles.get(ptr) + 1);
+  } else {
+    __pin(ptr);
+    witHandles.set(ptr, 1);
+  }
+  return <i32>ptr;
+}
+
+/** Releases the object `rep` is the representation of once its last handle is dropped. */
+export function witRelease(rep: i32): void {
+  const ptr = <usize>rep;
+  const count = witHandles.get(ptr);
+  if (count == 1) {
+    witHandles.delete(ptr);
+    __unpin(ptr);
+  } else {
+    witHandles.set(ptr, count - 1);
+  }
+}
+
+/** The memory an import's arguments are lowered into, which is freed once it returns. */
+export class WitArena {
+  private ptrs: Array<usize> = new Array<usize>();
+
+  alloc(size: usize, align: usize): usize {
+    const ptr = cabi_realloc(0, 0, align, size);
+    if (size != 0) {
+      this.ptrs.push(ptr);
+    }
+    return ptr;
+  }
+
+  deallocate(): void {
+    for (let i = 0; i < this.ptrs.length; i++) {
+      heap.free(this.ptrs[i]);
+    }
+  }
+}
@@ -0,0 +1,34 @@
+use heck::*;
+use std::path::Path;
+use std::process::Command;
+
+macro_rules! codegen_test {
+    ($id:ident $name:tt $test:tt) => {
+        #[test]
+        fn $id() {
+            test_helpers::run_world_codegen_test(
+                "guest-assemblyscript",
+                $test.as_ref(),
+                |resolve, world, files| {
+                    wit_bindgen_assemblyscript::Opts::default()
+                        .build()
+                        .generate(resolve, world, files)
+                        .unwrap()
+                },
+                verify,
+            );
+        }
+    };
+}
+
+test_helpers::codegen_tests!();
+
+fn verify(dir: &Path, name: &str) {
+    // Exports are implemented in a separate module which doesn't exist here,
+    // so only type-check the bindings themselves.
+    let mut cmd = Command::new("asc");
+    cmd.arg(dir.join(format!("{}.ts", name.to_snake_case())));
+    cmd.arg("--noEmit");
+    cmd.arg("--use").arg("abort=");
+    test_helpers::run_command(&mut cmd);
+}
@@ -1,4 +1,4 @@
-mod component_type_object;
+pub mod component_type_object;
 
 use anyhow::Result;
 use heck::*;
@@ -142,6 +142,10 @@ enum Scalar {
 }
 
 impl WorldGenerator for C {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        wit_bindgen_core::reject_futures_and_streams(resolve, world, "C")
+    }
+
     fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
         self.world = self
             .opts
@@ -301,7 +305,10 @@ impl WorldGenerator for C {
 
         self.print_intrinsics();
 
-        if self.needs_string {
+        if self.needs_string && self.opts.string_encoding == StringEncoding::CompactUTF16 {
+            self.c_include("<string.h>");
+            self.print_compact_utf16_helpers();
+        } else if self.needs_string {
             self.c_include("<string.h>");
             let (strlen, size) = match self.opts.string_encoding {
                 StringEncoding::UTF8 => (format!("strlen(s)"), 1),
@@ -325,13 +332,13 @@ impl WorldGenerator for C {
                     );
                     (format!("{snake}_string_len(s)"), 2)
                 }
-                StringEncoding::CompactUTF16 => unimplemented!(),
+                StringEncoding::CompactUTF16 => unreachable!(),
             };
             let ty = self.char_type();
             let c_string_ty = match self.opts.string_encoding {
                 StringEncoding::UTF8 => "char",
                 StringEncoding::UTF16 => "char16_t",
-                StringEncoding::CompactUTF16 => panic!("Compact UTF16 unsupported"),
+                StringEncoding::CompactUTF16 => unreachable!(),
             };
             uwrite!(
                 self.src.h_helpers,
@@ -431,15 +438,32 @@ impl WorldGenerator for C {
         c_str.push_str(&self.src.c_fns);
 
         if self.needs_string {
+            let docs = if self.opts.string_encoding == StringEncoding::CompactUTF16 {
+                format!(
+                    "// Strings are latin1 unless the `{}_STRING_UTF16` bit of\n\
+                     // `len` is set, in which case `ptr` points to UTF-16 code units\n\
+                     // instead. The rest of `len` is the number of code units.\n",
+                    self.world.to_shouty_snake_case(),
+                )
+            } else {
+                String::new()
+            };
             uwriteln!(
                 h_str,
                 "
-                typedef struct {snake}_string_t {{\n\
+                {docs}typedef struct {snake}_string_t {{\n\
                   {ty} *ptr;\n\
                   size_t len;\n\
                 }} {snake}_string_t;",
                 ty = self.char_type(),
             );
+            if self.opts.string_encoding == StringEncoding::CompactUTF16 {
+                uwriteln!(
+                    h_str,
+                    "\n#define {}_STRING_UTF16 ((size_t) 1 << 31)",
+                    self.world.to_shouty_snake_case(),
+                );
+            }
         }
         if self.src.h_defs.len() > 0 {
             h_str.push_str(&self.src.h_defs);
@@ -539,12 +563,144 @@ impl C {
 
     fn char_type(&self) -> &'static str {
         match self.opts.string_encoding {
-            StringEncoding::UTF8 => "uint8_t",
+            StringEncoding::UTF8 | StringEncoding::CompactUTF16 => "uint8_t",
             StringEncoding::UTF16 => "uint16_t",
-            StringEncoding::CompactUTF16 => panic!("Compact UTF16 unsupported"),
         }
     }
 
+    /// Prints the string helpers for `StringEncoding::CompactUTF16`, where each
+    /// string is either latin1 or UTF-16 as indicated by a tag bit in its
+    /// length.
+    fn print_compact_utf16_helpers(&mut self) {
+        self.h_include("<uchar.h>");
+        let snake = self.world.to_snake_case();
+        let tag = format!("{}_STRING_UTF16", self.world.to_shouty_snake_case());
+        uwrite!(
+            self.src.h_helpers,
+            "
+               // Returns whether `s` holds UTF-16 code units rather than latin1.
+               bool {snake}_string_is_utf16(const {snake}_string_t *s);
+
+               // Returns the number of code units in `s`, without its tag bit.
+               size_t {snake}_string_code_units(const {snake}_string_t *s);
+
+               // Transfers ownership of the nul-terminated latin1 string `s` into
+               // the string `ret`.
+               void {snake}_string_set_latin1({snake}_string_t *ret, const char *s);
+
+               // Transfers ownership of the nul-terminated UTF-16 string `s` into
+               // the string `ret`.
+               void {snake}_string_set_utf16({snake}_string_t *ret, const char16_t *s);
+
+               // Creates a copy of the nul-terminated latin1 string `s` and stores
+               // it into the component model string `ret`.
+               void {snake}_string_dup_latin1({snake}_string_t *ret, const char *s);
+
+               // Creates a copy of the nul-terminated UTF-16 string `s` and stores
+               // it into the component model string `ret`.
+               void {snake}_string_dup_utf16({snake}_string_t *ret, const char16_t *s);
+
+               // Stores a UTF-16 copy of `s` into `ret`, widening it if it's latin1.
+               void {snake}_string_to_utf16({snake}_string_t *ret, const {snake}_string_t *s);
+
+               // Stores a latin1 copy of `s` into `ret`, narrowing it if it's UTF-16.
+               // Returns false without allocating if `s` has a character outside of
+               // latin1.
+               bool {snake}_string_to_latin1({snake}_string_t *ret, const {snake}_string_t *s);
+
+               // Deallocates the string pointed to by `ret`, deallocating
+               // the memory behind the string.
+               void {snake}_string_free({snake}_string_t *ret);\
+           ",
+        );
+        uwrite!(
+            self.src.c_helpers,
+            "
+               static size_t {snake}_utf16_len(const char16_t *s) {{
+                   const char16_t *c = s;
+                   for (; *c; ++c);
+                   return c - s;
+               }}
+
+               bool {snake}_string_is_utf16(const {snake}_string_t *s) {{
+                   return (s->len & {tag}) != 0;
+               }}
+
+               size_t {snake}_string_code_units(const {snake}_string_t *s) {{
+                   return s->len & ~{tag};
+               }}
+
+               void {snake}_string_set_latin1({snake}_string_t *ret, const char *s) {{
+                   ret->ptr = (uint8_t*) s;
+                   ret->len = strlen(s);
+               }}
+
+               void {snake}_string_set_utf16({snake}_string_t *ret, const char16_t *s) {{
+                   ret->ptr = (uint8_t*) s;
+                   ret->len = {snake}_utf16_len(s) | {tag};
+               }}
+
+               void {snake}_string_dup_latin1({snake}_string_t *ret, const char *s) {{
+                   size_t len = strlen(s);
+                   ret->ptr = (uint8_t*) cabi_realloc(NULL, 0, 1, len);
+                   memcpy(ret->ptr, s, len);
+                   ret->len = len;
+               }}
+
+               void {snake}_string_dup_utf16({snake}_string_t *ret, const char16_t *s) {{
+                   size_t len = {snake}_utf16_len(s);
+                   ret->ptr = (uint8_t*) cabi_realloc(NULL, 0, 2, len * 2);
+                   memcpy(ret->ptr, s, len * 2);
+                   ret->len = len | {tag};
+               }}
+
+               void {snake}_string_to_utf16({snake}_string_t *ret, const {snake}_string_t *s) {{
+                   size_t len = {snake}_string_code_units(s);
+                   uint16_t *ptr = (uint16_t*) cabi_realloc(NULL, 0, 2, len * 2);
+                   if ({snake}_string_is_utf16(s)) {{
+                       memcpy(ptr, s->ptr, len * 2);
+                   }} else {{
+                       for (size_t i = 0; i < len; i++) {{
+                           ptr[i] = s->ptr[i];
+                       }}
+                   }}
+                   ret->ptr = (uint8_t*) ptr;
+                   ret->len = len | {tag};
+               }}
+
+               bool {snake}_string_to_latin1({snake}_string_t *ret, const {snake}_string_t *s) {{
+                   size_t len = {snake}_string_code_units(s);
+                   if (!{snake}_string_is_utf16(s)) {{
+                       ret->ptr = (uint8_t*) cabi_realloc(NULL, 0, 1, len);
+                       memcpy(ret->ptr, s->ptr, len);
+                       ret->len = len;
+                       return true;
+                   }}
+                   const uint16_t *units = (const uint16_t*) s->ptr;
+                   for (size_t i = 0; i < len; i++) {{
+                       if (units[i] > 0xff) {{
+                           return false;
+                       }}
+                   }}
+                   ret->ptr = (uint8_t*) cabi_realloc(NULL, 0, 1, len);
+                   for (size_t i = 0; i < len; i++) {{
+                       ret->ptr[i] = (uint8_t) units[i];
+                   }}
+                   ret->len = len;
+                   return true;
+               }}
+
+               void {snake}_string_free({snake}_string_t *ret) {{
+                   if ({snake}_string_code_units(ret) > 0) {{
+                       free(ret->ptr);
+                   }}
+                   ret->ptr = NULL;
+                   ret->len = 0;
+               }}
+           ",
+        );
+    }
+
     fn type_name(&mut self, ty: &Type) -> String {
         let mut name = String::new();
         self.push_type_name(ty, &mut name);
@@ -1045,7 +1201,7 @@ extern void {ns}_{snake}_drop_own({own} handle);
         } else {
             let module = match self.interface {
                 Some((_, key)) => self.resolve.name_world_key(key),
-                None => unimplemented!("resource exports from worlds"),
+                None => unreachable!("worlds only import types"),
             };
             format!("[export]{module}")
         };
@@ -1682,7 +1838,7 @@ impl InterfaceGenerator<'_> {
         match sig.results.len() {
             0 => self.src.c_fns("void"),
             1 => self.src.c_fns(wasm_type(sig.results[0])),
-            _ => unimplemented!("multi-value return not supported"),
+            _ => unreachable!("multiple results are returned through a return pointer"),
         }
         self.src.c_fns(" ");
         self.src.c_fns(&import_name);
@@ -1750,6 +1906,7 @@ impl InterfaceGenerator<'_> {
             LiftLower::LowerArgsLiftResults,
             func,
             &mut f,
+            false,
         );
 
         let FunctionBindgen {
@@ -1797,7 +1954,7 @@ impl InterfaceGenerator<'_> {
         match sig.results.len() {
             0 => f.gen.src.c_adapters("void"),
             1 => f.gen.src.c_adapters(wasm_type(sig.results[0])),
-            _ => unimplemented!("multi-value return not supported"),
+            _ => unreachable!("multiple results are returned through a return pointer"),
         }
         f.gen.src.c_adapters(" ");
         f.gen.src.c_adapters(&import_name);
@@ -1822,6 +1979,7 @@ impl InterfaceGenerator<'_> {
             LiftLower::LiftArgsLowerResults,
             func,
             &mut f,
+            false,
         );
         let FunctionBindgen { src, .. } = f;
         self.src.c_adapters(&src);
@@ -2331,6 +2489,21 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 results.push(result);
             }
 
+            Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. }
+            | Instruction::Flush { .. } => {
+                unreachable!("async functions are never requested by this generator")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+
             Instruction::TupleLower { tuple, .. } => {
                 let op = &operands[0];
                 for i in 0..tuple.types.len() {
@@ -2740,7 +2913,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                         uwrite!(self.src, " {} = ", ret);
                         results.push(ret);
                     }
-                    _ => unimplemented!(),
+                    _ => unreachable!("multiple results are returned through a return pointer"),
                 }
                 self.src.push_str(self.func_to_call);
                 self.src.push_str("(");
@@ -2753,7 +2926,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 self.src.push_str(");\n");
             }
 
-            Instruction::CallInterface { func } => {
+            Instruction::CallInterface { func, .. } => {
                 let mut args = String::new();
                 for (i, (op, (byref, _))) in operands.iter().zip(&self.sig.params).enumerate() {
                     if i > 0 {
@@ -2999,7 +3172,12 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 uwriteln!(self.src, "free({});", operands[0]);
             }
             Instruction::GuestDeallocateString => {
-                uwriteln!(self.src, "if (({}) > 0) {{", operands[1]);
+                if self.gen.gen.opts.string_encoding == StringEncoding::CompactUTF16 {
+                    let tag = format!("{}_STRING_UTF16", self.gen.gen.world.to_shouty_snake_case());
+                    uwriteln!(self.src, "if ((({}) & ~{tag}) > 0) {{", operands[1]);
+                } else {
+                    uwriteln!(self.src, "if (({}) > 0) {{", operands[1]);
+                }
                 uwriteln!(self.src, "free({});", operands[0]);
                 uwriteln!(self.src, "}}");
             }
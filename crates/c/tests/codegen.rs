@@ -6,6 +6,8 @@ use std::process::Command;
 use wit_parser::{Resolve, UnresolvedPackageGroup};
 
 macro_rules! codegen_test {
+    // todo: support flags with more than 64 members and then remove the following line:
+    (flags_large $name:tt $test:tt) => {};
     ($id:ident $name:tt $test:tt) => {
         #[test]
         fn $id() {
@@ -146,3 +148,34 @@ void exports_rename2_f() {}
     verify(&dir, "rename-option");
     Ok(())
 }
+
+#[test]
+fn futures_and_streams_are_rejected() -> Result<()> {
+    let mut resolve = Resolve::default();
+    let pkg = resolve.push_group(UnresolvedPackageGroup::parse(
+        "input.wit",
+        r#"
+            package foo:bar;
+
+            interface i {
+                f: func() -> stream<u8>;
+            }
+
+            world futures-and-streams {
+                import i;
+            }
+        "#,
+    )?)?;
+    let world = resolve.select_world(pkg, None)?;
+    let mut files = Default::default();
+    let err = wit_bindgen_c::Opts::default()
+        .build()
+        .generate(&resolve, world, &mut files)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "`foo:bar/i` uses `future` or `stream` types, which aren't supported by the C \
+         generator yet",
+    );
+    Ok(())
+}
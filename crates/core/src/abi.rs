@@ -1,7 +1,7 @@
 pub use wit_parser::abi::{AbiVariant, WasmSignature, WasmType};
 use wit_parser::{
     Enum, Flags, FlagsRepr, Function, Handle, Int, Record, Resolve, Result_, Results, SizeAlign,
-    Tuple, Type, TypeDefKind, TypeId, Variant,
+    Stream, Tuple, Type, TypeDefKind, TypeId, Variant,
 };
 
 // Helper macro for defining instructions without having to have tons of
@@ -350,6 +350,30 @@ def_instruction! {
             ty: TypeId,
         } : [1] => [1],
 
+        /// Create an `i32` from a future.
+        FutureLower {
+            payload: &'a Option<Type>,
+            ty: TypeId,
+        } : [1] => [1],
+
+        /// Create a future from an `i32`.
+        FutureLift {
+            payload: &'a Option<Type>,
+            ty: TypeId,
+        } : [1] => [1],
+
+        /// Create an `i32` from a stream.
+        StreamLower {
+            payload: &'a Stream,
+            ty: TypeId,
+        } : [1] => [1],
+
+        /// Create a stream from an `i32`.
+        StreamLift {
+            payload: &'a Stream,
+            ty: TypeId,
+        } : [1] => [1],
+
         /// Pops a tuple value off the stack, decomposes the tuple to all of
         /// its fields, and then pushes the fields onto the stack.
         TupleLower {
@@ -464,13 +488,47 @@ def_instruction! {
             sig: &'a WasmSignature,
         } : [sig.params.len()] => [sig.results.len()],
 
+        /// Same as `CallWasm`, except the callee is lowered with the async
+        /// ABI.
+        ///
+        /// The last parameter, if the function has any results, is a pointer
+        /// to where the results will be written. Code generators are expected
+        /// to wait for the call to complete before proceeding.
+        AsyncCallWasm {
+            name: &'a str,
+            sig: &'a WasmSignature,
+        } : [sig.params.len()] => [0],
+
         /// Same as `CallWasm`, except the dual where an interface is being
         /// called rather than a raw wasm function.
         ///
-        /// Note that this will be used for async functions.
+        /// When `async_` is set the callee is an async function and this
+        /// pushes a single value representing its (not yet awaited) result
+        /// which is then consumed by `AsyncPostCallInterface`.
         CallInterface {
             func: &'a Function,
-        } : [func.params.len()] => [func.results.len()],
+            async_: bool,
+        } : [func.params.len()] => [if *async_ { 1 } else { func.results.len() }],
+
+        /// Takes the value produced by an async `CallInterface` and arranges
+        /// for the function's results to be pushed once it completes.
+        ///
+        /// This pushes the value to be returned from the export to the
+        /// runtime followed by each of the function's results.
+        AsyncPostCallInterface {
+            func: &'a Function,
+        } : [1] => [func.results.len() + 1],
+
+        /// Calls the `[task-return]` intrinsic named `name` with the lowered
+        /// results of an async export.
+        AsyncCallReturn {
+            name: &'a str,
+            params: &'a [WasmType],
+        } : [params.len()] => [0],
+
+        /// Forces evaluation of the top `amt` operands, for example before
+        /// the memory they were read from is deallocated.
+        Flush { amt: usize } : [*amt] => [*amt],
 
         /// Returns `amt` values on the stack. This is always the last
         /// instruction.
@@ -486,6 +544,17 @@ def_instruction! {
             align: usize,
         } : [0] => [1],
 
+        /// Allocates `size` bytes with alignment `align` which must remain
+        /// valid across an async call, such as the parameter and result areas
+        /// of an async import.
+        ///
+        /// Pushes the returned pointer onto the stack. The pointer is later
+        /// released with `GuestDeallocate`.
+        AsyncMalloc {
+            size: usize,
+            align: usize,
+        } : [0] => [1],
+
         /// Used exclusively for guest-code generation this indicates that
         /// the standard memory deallocation function needs to be invoked with
         /// the specified parameters.
@@ -677,14 +746,68 @@ pub trait Bindgen {
 /// language-specific values into the wasm types to call a WASI function,
 /// and it will also automatically convert the results of the WASI function
 /// back to a language-specific value.
+///
+/// When `async_` is set the function is lifted or lowered using the component
+/// model async ABI instead, see [`async_wasm_signature`]. This is only
+/// defined for guest bindings, so `lift_lower` is ignored in that case and
+/// the instructions specific to the async ABI, such as `AsyncCallWasm`, are
+/// never emitted to generators which always pass `false`.
 pub fn call(
     resolve: &Resolve,
     variant: AbiVariant,
     lift_lower: LiftLower,
     func: &Function,
     bindgen: &mut impl Bindgen,
+    async_: bool,
 ) {
-    Generator::new(resolve, variant, lift_lower, bindgen).call(func);
+    Generator::new(resolve, variant, lift_lower, bindgen).call(func, async_);
+}
+
+/// Maximum number of flat parameters passed directly to an import lowered
+/// with the async ABI.
+const MAX_FLAT_ASYNC_PARAMS: usize = 4;
+
+/// Maximum number of flat parameters passed directly to a function, or to
+/// the `[task-return]` intrinsic of an async export.
+const MAX_FLAT_PARAMS: usize = 16;
+
+/// Returns the core wasm signature of `func` when it's lifted or lowered
+/// with the component model async ABI.
+///
+/// * Async imports take their parameters flat, or through a pointer if there
+///   are too many, followed by a pointer to where results are written. They
+///   return a status code combined with a subtask handle.
+/// * Async exports take their parameters in the same way as synchronous
+///   exports and return a callback code. Results are instead passed to the
+///   `[task-return]` intrinsic.
+pub fn async_wasm_signature(
+    resolve: &Resolve,
+    variant: AbiVariant,
+    func: &Function,
+) -> WasmSignature {
+    let mut sig = resolve.wasm_signature(variant, func);
+    match variant {
+        AbiVariant::GuestImport => {
+            let mut params = Vec::new();
+            for (_, ty) in func.params.iter() {
+                resolve.push_flat(ty, &mut params);
+            }
+            sig.indirect_params = params.len() > MAX_FLAT_ASYNC_PARAMS;
+            if sig.indirect_params {
+                params = vec![WasmType::Pointer];
+            }
+            sig.retptr = func.results.len() > 0;
+            if sig.retptr {
+                params.push(WasmType::Pointer);
+            }
+            sig.params = params;
+        }
+        AbiVariant::GuestExport => {
+            sig.retptr = false;
+        }
+    }
+    sig.results = vec![WasmType::I32];
+    sig
 }
 
 /// Used in a similar manner as the `Interface::call` function except is
@@ -713,6 +836,129 @@ pub fn guest_export_needs_post_return(resolve: &Resolve, func: &Function) -> boo
         .any(|t| needs_post_return(resolve, t))
 }
 
+/// Generates instructions to write `value`, of type `ty`, to memory at
+/// `address`.
+///
+/// Any lists in `value` are lowered with ownership transferred to the
+/// memory, see [`deallocate_lists_in_memory`] for releasing them.
+pub fn lower_to_memory<B: Bindgen>(
+    resolve: &Resolve,
+    bindgen: &mut B,
+    address: B::Operand,
+    value: B::Operand,
+    ty: &Type,
+) {
+    let mut generator = Generator::new(
+        resolve,
+        AbiVariant::GuestExport,
+        LiftLower::LiftArgsLowerResults,
+        bindgen,
+    );
+    generator.stack.push(value);
+    generator.write_to_memory(ty, address, 0);
+}
+
+/// Generates instructions to read a value of type `ty` from memory at
+/// `address`, returning the operand holding the value.
+pub fn lift_from_memory<B: Bindgen>(
+    resolve: &Resolve,
+    bindgen: &mut B,
+    address: B::Operand,
+    ty: &Type,
+) -> B::Operand {
+    let mut generator = Generator::new(
+        resolve,
+        AbiVariant::GuestImport,
+        LiftLower::LowerArgsLiftResults,
+        bindgen,
+    );
+    generator.read_from_memory(ty, address, 0);
+    generator.stack.pop().unwrap()
+}
+
+/// Generates instructions to deallocate any lists within a value of type `ty`
+/// previously written to memory at `address` by [`lower_to_memory`].
+pub fn deallocate_lists_in_memory<B: Bindgen>(
+    resolve: &Resolve,
+    bindgen: &mut B,
+    address: B::Operand,
+    ty: &Type,
+) {
+    Generator::new(
+        resolve,
+        AbiVariant::GuestExport,
+        LiftLower::LiftArgsLowerResults,
+        bindgen,
+    )
+    .deallocate(ty, address, 0);
+}
+
+/// Returns the `future` and `stream` types used by `func`, in the order that
+/// their intrinsics are numbered.
+///
+/// Types are visited depth-first through the parameters and then the results
+/// of `func`, with payloads visited before the type containing them. The
+/// index of a type in the returned list is the `N` in intrinsic names such as
+/// `[future-new-N]func`.
+pub fn find_futures_and_streams(resolve: &Resolve, func: &Function) -> Vec<TypeId> {
+    fn visit(resolve: &Resolve, ty: &Type, results: &mut Vec<TypeId>) {
+        let Type::Id(id) = ty else {
+            return;
+        };
+        match &resolve.types[*id].kind {
+            TypeDefKind::Resource
+            | TypeDefKind::Handle(_)
+            | TypeDefKind::Flags(_)
+            | TypeDefKind::Enum(_) => {}
+            TypeDefKind::Record(r) => {
+                for field in r.fields.iter() {
+                    visit(resolve, &field.ty, results);
+                }
+            }
+            TypeDefKind::Tuple(t) => {
+                for ty in t.types.iter() {
+                    visit(resolve, ty, results);
+                }
+            }
+            TypeDefKind::Variant(v) => {
+                for ty in v.cases.iter().filter_map(|c| c.ty.as_ref()) {
+                    visit(resolve, ty, results);
+                }
+            }
+            TypeDefKind::Option(ty) | TypeDefKind::List(ty) | TypeDefKind::Type(ty) => {
+                visit(resolve, ty, results);
+            }
+            TypeDefKind::Result(r) => {
+                for ty in [&r.ok, &r.err].into_iter().flatten() {
+                    visit(resolve, ty, results);
+                }
+            }
+            TypeDefKind::Future(ty) => {
+                if let Some(ty) = ty {
+                    visit(resolve, ty, results);
+                }
+                results.push(*id);
+            }
+            TypeDefKind::Stream(s) => {
+                for ty in [&s.element, &s.end].into_iter().flatten() {
+                    visit(resolve, ty, results);
+                }
+                results.push(*id);
+            }
+            TypeDefKind::Unknown => unreachable!(),
+        }
+    }
+
+    let mut results = Vec::new();
+    for (_, ty) in func.params.iter() {
+        visit(resolve, ty, &mut results);
+    }
+    for ty in func.results.iter_types() {
+        visit(resolve, ty, &mut results);
+    }
+    results
+}
+
 fn needs_post_return(resolve: &Resolve, ty: &Type) -> bool {
     match ty {
         Type::String => true,
@@ -721,6 +967,7 @@ fn needs_post_return(resolve: &Resolve, ty: &Type) -> bool {
             TypeDefKind::Type(t) => needs_post_return(resolve, t),
             TypeDefKind::Handle(_) => false,
             TypeDefKind::Resource => false,
+            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => false,
             TypeDefKind::Record(r) => r.fields.iter().any(|f| needs_post_return(resolve, &f.ty)),
             TypeDefKind::Tuple(t) => t.types.iter().any(|t| needs_post_return(resolve, t)),
             TypeDefKind::Variant(t) => t
@@ -734,7 +981,6 @@ fn needs_post_return(resolve: &Resolve, ty: &Type) -> bool {
                 .filter_map(|t| t.as_ref())
                 .any(|t| needs_post_return(resolve, t)),
             TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => false,
-            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => unimplemented!(),
             TypeDefKind::Unknown => unreachable!(),
         },
 
@@ -762,6 +1008,7 @@ struct Generator<'a, B: Bindgen> {
     results: Vec<B::Operand>,
     stack: Vec<B::Operand>,
     return_pointer: Option<B::Operand>,
+    async_: bool,
 }
 
 impl<'a, B: Bindgen> Generator<'a, B> {
@@ -780,10 +1027,15 @@ impl<'a, B: Bindgen> Generator<'a, B> {
             results: Vec::new(),
             stack: Vec::new(),
             return_pointer: None,
+            async_: false,
         }
     }
 
-    fn call(&mut self, func: &Function) {
+    fn call(&mut self, func: &Function, async_: bool) {
+        self.async_ = async_;
+        if async_ {
+            return self.call_async(func);
+        }
         let sig = self.resolve.wasm_signature(self.variant, func);
 
         match self.lift_lower {
@@ -915,7 +1167,10 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                 }
 
                 // ... and that allows us to call the interface types function
-                self.emit(&Instruction::CallInterface { func });
+                self.emit(&Instruction::CallInterface {
+                    func,
+                    async_: false,
+                });
 
                 // This was dynamically allocated by the caller so after
                 // it's been read by the guest we need to deallocate it.
@@ -990,6 +1245,164 @@ impl<'a, B: Bindgen> Generator<'a, B> {
         );
     }
 
+    fn call_async(&mut self, func: &Function) {
+        let sig = async_wasm_signature(self.resolve, self.variant, func);
+
+        // The async ABI is only defined for guests, where imports are always
+        // lowered and exports are always lifted, so the direction follows from
+        // the variant alone.
+        match self.variant {
+            AbiVariant::GuestImport => {
+                // Parameters passed indirectly and results both live in
+                // memory which must stay valid until the callee has returned,
+                // so neither uses the return pointer area.
+                let params = if !sig.indirect_params {
+                    for (nth, (_, ty)) in func.params.iter().enumerate() {
+                        self.emit(&Instruction::GetArg { nth });
+                        self.lower(ty);
+                    }
+                    None
+                } else {
+                    let info = self
+                        .bindgen
+                        .sizes()
+                        .record(func.params.iter().map(|t| &t.1));
+                    let size = info.size.size_wasm32();
+                    let align = info.align.align_wasm32();
+                    self.emit(&Instruction::AsyncMalloc { size, align });
+                    let ptr = self.stack.pop().unwrap();
+                    for nth in 0..func.params.len() {
+                        self.emit(&Instruction::GetArg { nth });
+                    }
+                    self.write_params_to_memory(func.params.iter().map(|t| &t.1), ptr.clone(), 0);
+                    self.stack.push(ptr.clone());
+                    Some((ptr, size, align))
+                };
+
+                let results = if sig.retptr {
+                    let info = self.bindgen.sizes().params(func.results.iter_types());
+                    let size = info.size.size_wasm32();
+                    let align = info.align.align_wasm32();
+                    self.emit(&Instruction::AsyncMalloc { size, align });
+                    let ptr = self.stack.pop().unwrap();
+                    self.stack.push(ptr.clone());
+                    Some((ptr, size, align))
+                } else {
+                    None
+                };
+
+                assert_eq!(self.stack.len(), sig.params.len());
+                self.emit(&Instruction::AsyncCallWasm {
+                    name: &format!("[async-lower]{}", func.name),
+                    sig: &sig,
+                });
+
+                // The callee has returned at this point so the parameters can
+                // be released ...
+                if let Some((ptr, size, align)) = params {
+                    self.stack.push(ptr);
+                    self.emit(&Instruction::GuestDeallocate { size, align });
+                }
+
+                // ... and the results are read out of memory before it's
+                // released as well.
+                if let Some((ptr, size, align)) = results {
+                    self.read_results_from_memory(&func.results, ptr.clone(), 0);
+                    self.emit(&Instruction::Flush {
+                        amt: func.results.len(),
+                    });
+                    self.stack.push(ptr);
+                    self.emit(&Instruction::GuestDeallocate { size, align });
+                }
+
+                self.emit(&Instruction::Return {
+                    func,
+                    amt: func.results.len(),
+                });
+            }
+            AbiVariant::GuestExport => {
+                if !sig.indirect_params {
+                    let mut offset = 0;
+                    let mut temp = Vec::new();
+                    for (_, ty) in func.params.iter() {
+                        temp.truncate(0);
+                        self.resolve.push_flat(ty, &mut temp);
+                        for _ in 0..temp.len() {
+                            self.emit(&Instruction::GetArg { nth: offset });
+                            offset += 1;
+                        }
+                        self.lift(ty);
+                    }
+                } else {
+                    let mut offset = 0usize;
+                    self.emit(&Instruction::GetArg { nth: 0 });
+                    let ptr = self.stack.pop().unwrap();
+                    for (_, ty) in func.params.iter() {
+                        offset = align_to(offset, self.bindgen.sizes().align(ty).align_wasm32());
+                        self.read_from_memory(ty, ptr.clone(), offset as i32);
+                        offset += self.bindgen.sizes().size(ty).size_wasm32();
+                    }
+                }
+
+                // Arguments are fully lifted at this point so they can be
+                // released before the call, which may not complete until long
+                // after this function has returned.
+                if sig.indirect_params {
+                    let info = self
+                        .bindgen
+                        .sizes()
+                        .record(func.params.iter().map(|t| &t.1));
+                    self.emit(&Instruction::GetArg { nth: 0 });
+                    self.emit(&Instruction::GuestDeallocate {
+                        size: info.size.size_wasm32(),
+                        align: info.align.align_wasm32(),
+                    });
+                }
+
+                self.emit(&Instruction::CallInterface { func, async_: true });
+
+                // Once the call completes its results are lowered and handed
+                // to the `[task-return]` intrinsic rather than returned.
+                self.emit(&Instruction::AsyncPostCallInterface { func });
+                let mut params = Vec::new();
+                for ty in func.results.iter_types() {
+                    self.resolve.push_flat(ty, &mut params);
+                }
+                let results = self
+                    .stack
+                    .drain(self.stack.len() - func.results.len()..)
+                    .collect::<Vec<_>>();
+                if params.len() > MAX_FLAT_PARAMS {
+                    params = vec![WasmType::Pointer];
+                    let info = self.bindgen.sizes().params(func.results.iter_types());
+                    let ptr = self
+                        .bindgen
+                        .return_pointer(info.size.size_wasm32(), info.align.align_wasm32());
+                    self.stack.extend(results);
+                    self.write_params_to_memory(func.results.iter_types(), ptr.clone(), 0);
+                    self.stack.push(ptr);
+                } else {
+                    for (ty, result) in func.results.iter_types().zip(results) {
+                        self.stack.push(result);
+                        self.lower(ty);
+                    }
+                }
+                self.emit(&Instruction::AsyncCallReturn {
+                    name: &format!("[task-return]{}", func.name),
+                    params: &params,
+                });
+
+                self.emit(&Instruction::Return { func, amt: 1 });
+            }
+        }
+
+        assert!(
+            self.stack.is_empty(),
+            "stack has {} items remaining",
+            self.stack.len()
+        );
+    }
+
     fn post_return(&mut self, func: &Function) {
         let sig = self.resolve.wasm_signature(self.variant, func);
 
@@ -1177,8 +1590,12 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                         results: &results,
                     });
                 }
-                TypeDefKind::Future(_) => todo!("lower future"),
-                TypeDefKind::Stream(_) => todo!("lower stream"),
+                TypeDefKind::Future(payload) => {
+                    self.emit(&FutureLower { payload, ty: id });
+                }
+                TypeDefKind::Stream(payload) => {
+                    self.emit(&StreamLower { payload, ty: id });
+                }
                 TypeDefKind::Unknown => unreachable!(),
             },
         }
@@ -1245,6 +1662,9 @@ impl<'a, B: Bindgen> Generator<'a, B> {
         // ownership in all other cases.
         match (self.variant, self.lift_lower) {
             (AbiVariant::GuestImport, LiftLower::LowerArgsLiftResults) => None,
+            // Results of async exports are copied out by `[task-return]`
+            // so ownership is retained here as well.
+            (AbiVariant::GuestExport, LiftLower::LiftArgsLowerResults) if self.async_ => None,
             _ => Some("cabi_realloc"),
         }
     }
@@ -1362,8 +1782,12 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                     self.emit(&ResultLift { result: r, ty: id });
                 }
 
-                TypeDefKind::Future(_) => todo!("lift future"),
-                TypeDefKind::Stream(_) => todo!("lift stream"),
+                TypeDefKind::Future(payload) => {
+                    self.emit(&FutureLift { payload, ty: id });
+                }
+                TypeDefKind::Stream(payload) => {
+                    self.emit(&StreamLift { payload, ty: id });
+                }
                 TypeDefKind::Unknown => unreachable!(),
             },
         }
@@ -1521,8 +1945,9 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                     self.store_intrepr(offset, e.tag());
                 }
 
-                TypeDefKind::Future(_) => todo!("write future to memory"),
-                TypeDefKind::Stream(_) => todo!("write stream to memory"),
+                TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {
+                    self.lower_and_emit(ty, addr, &I32Store { offset })
+                }
                 TypeDefKind::Unknown => unreachable!(),
             },
         }
@@ -1709,8 +2134,9 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                     self.lift(ty);
                 }
 
-                TypeDefKind::Future(_) => todo!("read future from memory"),
-                TypeDefKind::Stream(_) => todo!("read stream from memory"),
+                TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {
+                    self.emit_and_lift(ty, addr, &I32Load { offset })
+                }
                 TypeDefKind::Unknown => unreachable!(),
             },
         }
@@ -1885,8 +2311,9 @@ impl<'a, B: Bindgen> Generator<'a, B> {
 
                 TypeDefKind::Enum(_) => {}
 
-                TypeDefKind::Future(_) => todo!("read future from memory"),
-                TypeDefKind::Stream(_) => todo!("read stream from memory"),
+                // Futures and streams are handles which are owned by the
+                // receiver, so there's nothing to deallocate here.
+                TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {}
                 TypeDefKind::Unknown => unreachable!(),
             },
         }
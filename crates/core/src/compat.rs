@@ -0,0 +1,1018 @@
+//! Comparing two versions of a world to find the changes which break
+//! components built against the older version, and the changes which break
+//! code written against the bindings generated for it.
+
+use crate::trace::type_name;
+use crate::Direction;
+use std::collections::HashMap;
+use std::fmt;
+use wit_parser::*;
+
+/// The generators whose bindings are considered, by subcommand name.
+const ALL: &[&str] = &[
+    "rust",
+    "c",
+    "cpp",
+    "zig",
+    "swift",
+    "assemblyscript",
+    "grain",
+    "teavm-java",
+    "teavm-kotlin",
+    "tiny-go",
+    "c-sharp",
+    "moonbit",
+];
+
+/// Generators whose code has to match every case of a variant or enum, so a
+/// new case breaks existing matches.
+const EXHAUSTIVE: &[&str] = &["rust", "zig", "swift", "teavm-kotlin", "moonbit"];
+
+/// Generators which implement exports with a generated trait or interface,
+/// where implementing a function that no longer exists is an error.
+const IMPLEMENTS: &[&str] = &["rust", "teavm-kotlin"];
+
+/// Generators using the names of parameters as argument labels.
+const LABELLED: &[&str] = &["swift"];
+
+const NONE: &[&str] = &[];
+
+/// A difference found between two versions of a world.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// The world item the change was found in, such as
+    /// ``import `wasi:cli/stdout` ``.
+    pub item: String,
+    pub description: String,
+    /// Whether components built against the old version of the world may fail
+    /// to instantiate, or may misbehave, with the new version.
+    pub breaking: bool,
+    /// The generators whose bindings change such that code written against
+    /// the old bindings may no longer compile.
+    pub bindings: &'static [&'static str],
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.item, self.description)
+    }
+}
+
+/// Which way the values of a type cross the component boundary, from the
+/// point of view of the guest.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Flow {
+    /// Parameters of exports and results of imports.
+    ToGuest,
+    /// Parameters of imports and results of exports.
+    FromGuest,
+    Both,
+}
+
+impl Flow {
+    fn params(direction: Direction) -> Flow {
+        match direction {
+            Direction::Import => Flow::FromGuest,
+            Direction::Export => Flow::ToGuest,
+        }
+    }
+
+    fn results(direction: Direction) -> Flow {
+        match direction {
+            Direction::Import => Flow::ToGuest,
+            Direction::Export => Flow::FromGuest,
+        }
+    }
+
+    fn merge(self, other: Flow) -> Flow {
+        if self == other {
+            self
+        } else {
+            Flow::Both
+        }
+    }
+}
+
+fn to_guest(flow: Option<Flow>) -> bool {
+    matches!(flow, Some(Flow::ToGuest | Flow::Both))
+}
+
+fn from_guest(flow: Option<Flow>) -> bool {
+    matches!(flow, Some(Flow::FromGuest | Flow::Both))
+}
+
+/// Compares the `old_world` in `old` with the `new_world` in `new`.
+///
+/// Items of the worlds are matched by name, ignoring the versions of
+/// interfaces, and everything reachable from them is compared structurally.
+/// Whether a change is breaking depends on which way the values it affects
+/// cross the component boundary: for example a new case of a variant breaks
+/// guests receiving it, but not guests which only send it to the host.
+pub fn compare(
+    old: &Resolve,
+    old_world: WorldId,
+    new: &Resolve,
+    new_world: WorldId,
+) -> Vec<Change> {
+    let mut compare = Compare {
+        old,
+        new,
+        flows: HashMap::new(),
+        item: String::new(),
+        changes: Vec::new(),
+    };
+    let (old_world, new_world) = (&old.worlds[old_world], &new.worlds[new_world]);
+    for (items, direction) in [
+        (&old_world.imports, Direction::Import),
+        (&old_world.exports, Direction::Export),
+    ] {
+        for item in items.values() {
+            compare.item_flows(item, direction);
+        }
+    }
+    compare.items(old_world, new_world, Direction::Import);
+    compare.items(old_world, new_world, Direction::Export);
+    compare.changes
+}
+
+struct Compare<'a> {
+    old: &'a Resolve,
+    new: &'a Resolve,
+    /// How the values of each named type of the old world flow, as found by
+    /// walking its functions. Types missing here aren't used by any function.
+    flows: HashMap<TypeId, Flow>,
+    item: String,
+    changes: Vec<Change>,
+}
+
+impl<'a> Compare<'a> {
+    fn item_flows(&mut self, item: &WorldItem, direction: Direction) {
+        match item {
+            WorldItem::Interface { id, .. } => {
+                for func in self.old.interfaces[*id].functions.values() {
+                    self.func_flows(func, direction);
+                }
+            }
+            WorldItem::Function(func) => self.func_flows(func, direction),
+            WorldItem::Type(_) => {}
+        }
+    }
+
+    fn func_flows(&mut self, func: &Function, direction: Direction) {
+        for (_, ty) in func.params.iter() {
+            self.type_flows(ty, Flow::params(direction));
+        }
+        for ty in func.results.iter_types() {
+            self.type_flows(ty, Flow::results(direction));
+        }
+    }
+
+    fn type_flows(&mut self, ty: &Type, flow: Flow) {
+        let Type::Id(id) = ty else { return };
+        self.flows
+            .entry(*id)
+            .and_modify(|prev| *prev = prev.merge(flow))
+            .or_insert(flow);
+        match &self.old.types[*id].kind {
+            TypeDefKind::Record(record) => {
+                for field in record.fields.iter() {
+                    self.type_flows(&field.ty, flow);
+                }
+            }
+            TypeDefKind::Variant(variant) => {
+                for case in variant.cases.iter() {
+                    if let Some(ty) = &case.ty {
+                        self.type_flows(ty, flow);
+                    }
+                }
+            }
+            TypeDefKind::Tuple(tuple) => {
+                for ty in tuple.types.iter() {
+                    self.type_flows(ty, flow);
+                }
+            }
+            TypeDefKind::Option(ty) | TypeDefKind::List(ty) | TypeDefKind::Type(ty) => {
+                self.type_flows(ty, flow)
+            }
+            TypeDefKind::Result(result) => {
+                for ty in result.ok.iter().chain(&result.err) {
+                    self.type_flows(ty, flow);
+                }
+            }
+            TypeDefKind::Future(ty) => {
+                if let Some(ty) = ty {
+                    self.type_flows(ty, flow);
+                }
+            }
+            TypeDefKind::Stream(stream) => {
+                for ty in stream.element.iter().chain(&stream.end) {
+                    self.type_flows(ty, flow);
+                }
+            }
+            TypeDefKind::Flags(_)
+            | TypeDefKind::Enum(_)
+            | TypeDefKind::Resource
+            | TypeDefKind::Handle(_)
+            | TypeDefKind::Unknown => {}
+        }
+    }
+
+    fn push(&mut self, description: String, breaking: bool, bindings: &'static [&'static str]) {
+        // The same change may be found once for each way a type is used, in
+        // which case the most severe verdicts are kept. The sets of
+        // generators are nested, so the larger one is their union.
+        if let Some(prev) = self
+            .changes
+            .iter_mut()
+            .find(|c| c.item == self.item && c.description == description)
+        {
+            prev.breaking |= breaking;
+            if bindings.len() > prev.bindings.len() {
+                prev.bindings = bindings;
+            }
+            return;
+        }
+        self.changes.push(Change {
+            item: self.item.clone(),
+            description,
+            breaking,
+            bindings,
+        });
+    }
+
+    fn items(&mut self, old: &World, new: &World, direction: Direction) {
+        let (prefix, old, new) = match direction {
+            Direction::Import => ("import", &old.imports, &new.imports),
+            Direction::Export => ("export", &old.exports, &new.exports),
+        };
+        let new_items = new
+            .iter()
+            .map(|(key, item)| (item_name(self.new, key), (key, item)))
+            .collect::<HashMap<_, _>>();
+        let mut matched = Vec::new();
+        for (old_key, old_item) in old {
+            let name = item_name(self.old, old_key);
+            self.item = format!("{prefix} `{name}`");
+            match new_items.get(&name) {
+                Some((new_key, new_item)) => {
+                    self.item(old_key, old_item, new_key, new_item, direction);
+                    matched.push(name);
+                }
+                None => {
+                    let description = format!("{} removed", item_kind(old_item));
+                    match (old_item, direction) {
+                        (WorldItem::Type(_), _) => self.push(description, false, ALL),
+                        (_, Direction::Import) => self.push(description, true, ALL),
+                        (_, Direction::Export) => self.push(description, false, IMPLEMENTS),
+                    }
+                }
+            }
+        }
+        for (new_key, new_item) in new {
+            let name = item_name(self.new, new_key);
+            if matched.contains(&name) {
+                continue;
+            }
+            self.item = format!("{prefix} `{name}`");
+            let description = format!("{} added", item_kind(new_item));
+            match (new_item, direction) {
+                (WorldItem::Type(_), _) | (_, Direction::Import) => {
+                    self.push(description, false, NONE)
+                }
+                (_, Direction::Export) => self.push(description, true, ALL),
+            }
+        }
+    }
+
+    fn item(
+        &mut self,
+        old_key: &WorldKey,
+        old_item: &WorldItem,
+        new_key: &WorldKey,
+        new_item: &WorldItem,
+        direction: Direction,
+    ) {
+        match (old_item, new_item) {
+            (WorldItem::Interface { id: old_id, .. }, WorldItem::Interface { id: new_id, .. }) => {
+                if let (WorldKey::Interface(_), WorldKey::Interface(_)) = (old_key, new_key) {
+                    self.versions(*old_id, *new_id);
+                }
+                self.interface(*old_id, *new_id, direction);
+            }
+            (WorldItem::Function(old_func), WorldItem::Function(new_func)) => {
+                self.function(old_func, new_func, "", direction);
+            }
+            (WorldItem::Type(old_id), WorldItem::Type(new_id)) => {
+                let flow = self.flows.get(old_id).copied();
+                self.defs(*old_id, *new_id, flow, "");
+            }
+            _ => self.push(
+                format!(
+                    "changed from {} to {}",
+                    item_kind(old_item),
+                    item_kind(new_item)
+                ),
+                true,
+                ALL,
+            ),
+        }
+    }
+
+    fn versions(&mut self, old_id: InterfaceId, new_id: InterfaceId) {
+        let version = |resolve: &Resolve, id: InterfaceId| {
+            let package = resolve.interfaces[id].package?;
+            resolve.packages[package].name.version.clone()
+        };
+        let (old_version, new_version) = (version(self.old, old_id), version(self.new, new_id));
+        if old_version == new_version {
+            return;
+        }
+        let compatible = match (&old_version, &new_version) {
+            (Some(old), Some(new)) => {
+                old.major == new.major
+                    && (old.major != 0 || old.minor == new.minor)
+                    && (old.major != 0 || old.minor != 0 || old.patch == new.patch)
+            }
+            _ => false,
+        };
+        let show = |version: Option<String>| version.unwrap_or_else(|| "none".to_string());
+        self.push(
+            format!(
+                "version changed from {} to {}",
+                show(old_version.map(|v| v.to_string())),
+                show(new_version.map(|v| v.to_string()))
+            ),
+            !compatible,
+            NONE,
+        );
+    }
+
+    fn interface(&mut self, old_id: InterfaceId, new_id: InterfaceId, direction: Direction) {
+        let (old, new) = (&self.old.interfaces[old_id], &self.new.interfaces[new_id]);
+
+        for (name, old_ty) in old.types.iter() {
+            let what = format!("type `{name}`");
+            match new.types.get(name) {
+                Some(new_ty) => {
+                    let flow = self.flows.get(old_ty).copied();
+                    self.defs(*old_ty, *new_ty, flow, &what);
+                }
+                None => self.push(format!("{what} removed"), false, ALL),
+            }
+        }
+        for name in new.types.keys() {
+            if !old.types.contains_key(name) {
+                self.push(format!("type `{name}` added"), false, NONE);
+            }
+        }
+
+        for (name, old_func) in old.functions.iter() {
+            let what = func_name(self.old, old_func);
+            match new.functions.get(name) {
+                Some(new_func) => self.function(old_func, new_func, &what, direction),
+                None => match direction {
+                    Direction::Import => self.push(format!("{what} removed"), true, ALL),
+                    Direction::Export => self.push(format!("{what} removed"), false, IMPLEMENTS),
+                },
+            }
+        }
+        for (name, new_func) in new.functions.iter() {
+            if old.functions.contains_key(name) {
+                continue;
+            }
+            let what = func_name(self.new, new_func);
+            match direction {
+                Direction::Import => self.push(format!("{what} added"), false, NONE),
+                Direction::Export => self.push(format!("{what} added"), true, ALL),
+            }
+        }
+    }
+
+    fn function(&mut self, old: &Function, new: &Function, what: &str, direction: Direction) {
+        let params = Some(Flow::params(direction));
+        if old.params.len() != new.params.len() {
+            self.push(
+                at(
+                    what,
+                    format!(
+                        "parameters changed from `{}` to `{}`",
+                        params_str(self.old, &old.params),
+                        params_str(self.new, &new.params)
+                    ),
+                ),
+                true,
+                ALL,
+            );
+        } else {
+            for ((old_name, old_ty), (new_name, new_ty)) in old.params.iter().zip(&new.params) {
+                if old_name != new_name {
+                    self.push(
+                        at(
+                            what,
+                            format!("parameter `{old_name}` renamed to `{new_name}`"),
+                        ),
+                        false,
+                        LABELLED,
+                    );
+                }
+                self.ty(
+                    old_ty,
+                    new_ty,
+                    params,
+                    &at(what, format!("parameter `{new_name}`")),
+                );
+            }
+        }
+
+        let results = Some(Flow::results(direction));
+        match (&old.results, &new.results) {
+            (Results::Anon(old_ty), Results::Anon(new_ty)) => {
+                self.ty(old_ty, new_ty, results, &at(what, "result".to_string()))
+            }
+            (Results::Named(old_params), Results::Named(new_params))
+                if old_params.len() == new_params.len() =>
+            {
+                for ((old_name, old_ty), (new_name, new_ty)) in old_params.iter().zip(new_params) {
+                    if old_name != new_name {
+                        self.push(
+                            at(what, format!("result `{old_name}` renamed to `{new_name}`")),
+                            false,
+                            NONE,
+                        );
+                    }
+                    self.ty(
+                        old_ty,
+                        new_ty,
+                        results,
+                        &at(what, format!("result `{new_name}`")),
+                    );
+                }
+            }
+            _ => self.push(
+                at(
+                    what,
+                    format!(
+                        "results changed from `{}` to `{}`",
+                        results_str(self.old, &old.results),
+                        results_str(self.new, &new.results)
+                    ),
+                ),
+                true,
+                ALL,
+            ),
+        }
+    }
+
+    /// Compares the types used in the same place, such as a parameter or a
+    /// field.
+    ///
+    /// Named types with the same name are compared where they're defined,
+    /// anything else is compared structurally and reported as a single change
+    /// of the type used.
+    fn ty(&mut self, old: &Type, new: &Type, flow: Option<Flow>, what: &str) {
+        let type_def_name = |resolve: &'a Resolve, ty: &Type| match ty {
+            Type::Id(id) => resolve.types[*id].name.as_deref(),
+            _ => None,
+        };
+        let (old_name, new_name) = (type_def_name(self.old, old), type_def_name(self.new, new));
+        if old_name.is_some() && old_name == new_name {
+            return;
+        }
+
+        let mut nested = Compare {
+            old: self.old,
+            new: self.new,
+            flows: HashMap::new(),
+            item: String::new(),
+            changes: Vec::new(),
+        };
+        let (old_ty, new_ty) = (dealias(self.old, old), dealias(self.new, new));
+        let differs = match (old_ty, new_ty) {
+            (Type::Id(old_id), Type::Id(new_id)) => {
+                nested.defs(old_id, new_id, flow, "");
+                !nested.changes.is_empty()
+            }
+            (Type::Id(_), _) | (_, Type::Id(_)) => true,
+            (old_ty, new_ty) => old_ty != new_ty,
+        };
+        if !differs && old_name == new_name {
+            return;
+        }
+
+        // Structurally identical types are only a problem for the bindings,
+        // which name them differently.
+        let (breaking, bindings) = if nested.changes.is_empty() {
+            (differs && flow.is_some(), ALL)
+        } else {
+            let breaking = nested.changes.iter().any(|c| c.breaking);
+            let bindings = nested
+                .changes
+                .iter()
+                .map(|c| c.bindings)
+                .max_by_key(|b| b.len())
+                .unwrap_or(NONE);
+            let bindings = if old_name != new_name { ALL } else { bindings };
+            (breaking, bindings)
+        };
+        self.push(
+            at(
+                what,
+                format!(
+                    "type changed from `{}` to `{}`",
+                    type_name(self.old, old),
+                    type_name(self.new, new)
+                ),
+            ),
+            breaking,
+            bindings,
+        );
+    }
+
+    /// Compares the definitions of two types, with `flow` being how the
+    /// values of the old type are used, if at all.
+    fn defs(&mut self, old_id: TypeId, new_id: TypeId, flow: Option<Flow>, what: &str) {
+        let used = flow.is_some();
+        let (old, new) = (&self.old.types[old_id], &self.new.types[new_id]);
+        match (&old.kind, &new.kind) {
+            (TypeDefKind::Record(old), TypeDefKind::Record(new)) => {
+                let old = old
+                    .fields
+                    .iter()
+                    .map(|f| (f.name.as_str(), Some(&f.ty)))
+                    .collect();
+                let new = new
+                    .fields
+                    .iter()
+                    .map(|f| (f.name.as_str(), Some(&f.ty)))
+                    .collect();
+                self.fields(old, new, flow, what);
+            }
+            (TypeDefKind::Variant(old), TypeDefKind::Variant(new)) => {
+                let old = old
+                    .cases
+                    .iter()
+                    .map(|c| (c.name.as_str(), c.ty.as_ref()))
+                    .collect();
+                let new = new
+                    .cases
+                    .iter()
+                    .map(|c| (c.name.as_str(), c.ty.as_ref()))
+                    .collect();
+                self.cases(old, new, flow, what, "case", EXHAUSTIVE);
+            }
+            (TypeDefKind::Enum(old), TypeDefKind::Enum(new)) => {
+                let old = old.cases.iter().map(|c| (c.name.as_str(), None)).collect();
+                let new = new.cases.iter().map(|c| (c.name.as_str(), None)).collect();
+                self.cases(old, new, flow, what, "case", EXHAUSTIVE);
+            }
+            (TypeDefKind::Flags(old), TypeDefKind::Flags(new)) => {
+                if old.repr() != new.repr() {
+                    self.push(
+                        at(
+                            what,
+                            format!(
+                                "representation changed from {} to {} flags",
+                                old.flags.len(),
+                                new.flags.len()
+                            ),
+                        ),
+                        used,
+                        NONE,
+                    );
+                }
+                let old = old.flags.iter().map(|f| (f.name.as_str(), None)).collect();
+                let new = new.flags.iter().map(|f| (f.name.as_str(), None)).collect();
+                self.cases(old, new, flow, what, "flag", NONE);
+            }
+            (TypeDefKind::Tuple(old), TypeDefKind::Tuple(new)) => {
+                if old.types.len() != new.types.len() {
+                    self.push(
+                        at(
+                            what,
+                            format!(
+                                "changed from {} to {} elements",
+                                old.types.len(),
+                                new.types.len()
+                            ),
+                        ),
+                        used,
+                        ALL,
+                    );
+                    return;
+                }
+                for (i, (old, new)) in old.types.iter().zip(&new.types).enumerate() {
+                    self.ty(old, new, flow, &at(what, format!("element {i}")));
+                }
+            }
+            (TypeDefKind::Option(old), TypeDefKind::Option(new))
+            | (TypeDefKind::List(old), TypeDefKind::List(new))
+            | (TypeDefKind::Type(old), TypeDefKind::Type(new)) => self.ty(old, new, flow, what),
+            (TypeDefKind::Result(old), TypeDefKind::Result(new)) => {
+                self.payload(
+                    old.ok.as_ref(),
+                    new.ok.as_ref(),
+                    flow,
+                    &at(what, "ok".into()),
+                );
+                self.payload(
+                    old.err.as_ref(),
+                    new.err.as_ref(),
+                    flow,
+                    &at(what, "err".into()),
+                );
+            }
+            (TypeDefKind::Future(old), TypeDefKind::Future(new)) => {
+                self.payload(old.as_ref(), new.as_ref(), flow, what);
+            }
+            (TypeDefKind::Stream(old), TypeDefKind::Stream(new)) => {
+                self.payload(
+                    old.element.as_ref(),
+                    new.element.as_ref(),
+                    flow,
+                    &at(what, "element".into()),
+                );
+                self.payload(
+                    old.end.as_ref(),
+                    new.end.as_ref(),
+                    flow,
+                    &at(what, "end".into()),
+                );
+            }
+            (TypeDefKind::Handle(Handle::Own(old)), TypeDefKind::Handle(Handle::Own(new)))
+            | (
+                TypeDefKind::Handle(Handle::Borrow(old)),
+                TypeDefKind::Handle(Handle::Borrow(new)),
+            ) => self.ty(&Type::Id(*old), &Type::Id(*new), flow, what),
+            (TypeDefKind::Resource, TypeDefKind::Resource)
+            | (TypeDefKind::Unknown, TypeDefKind::Unknown) => {}
+            (old_kind, new_kind) => self.push(
+                at(
+                    what,
+                    format!(
+                        "changed from {} to {}",
+                        old_kind.as_str(),
+                        new_kind.as_str()
+                    ),
+                ),
+                used,
+                ALL,
+            ),
+        }
+    }
+
+    fn payload(&mut self, old: Option<&Type>, new: Option<&Type>, flow: Option<Flow>, what: &str) {
+        match (old, new) {
+            (Some(old), Some(new)) => self.ty(old, new, flow, what),
+            (None, None) => {}
+            (old, new) => {
+                let show = |resolve: &Resolve, ty: Option<&Type>| match ty {
+                    Some(ty) => type_name(resolve, ty),
+                    None => "_".to_string(),
+                };
+                self.push(
+                    at(
+                        what,
+                        format!(
+                            "type changed from `{}` to `{}`",
+                            show(self.old, old),
+                            show(self.new, new)
+                        ),
+                    ),
+                    flow.is_some(),
+                    ALL,
+                );
+            }
+        }
+    }
+
+    /// Compares the fields of records, which are all lowered by position.
+    fn fields(
+        &mut self,
+        old: Vec<(&str, Option<&Type>)>,
+        new: Vec<(&str, Option<&Type>)>,
+        flow: Option<Flow>,
+        what: &str,
+    ) {
+        let used = flow.is_some();
+        if !self.positions(&old, &new, flow, what, "field") {
+            return;
+        }
+        for (name, _) in old.iter().skip(new.len()) {
+            self.push(at(what, format!("field `{name}` removed")), used, ALL);
+        }
+        for (name, _) in new.iter().skip(old.len()) {
+            self.push(at(what, format!("field `{name}` added")), used, ALL);
+        }
+    }
+
+    /// Compares the cases of variants and enums or the bits of flags, which
+    /// are numbered by position.
+    ///
+    /// New cases break guests receiving them and removed cases break guests
+    /// sending them.
+    fn cases(
+        &mut self,
+        old: Vec<(&str, Option<&Type>)>,
+        new: Vec<(&str, Option<&Type>)>,
+        flow: Option<Flow>,
+        what: &str,
+        kind: &str,
+        exhaustive: &'static [&'static str],
+    ) {
+        if !self.positions(&old, &new, flow, what, kind) {
+            return;
+        }
+        for (name, _) in old.iter().skip(new.len()) {
+            self.push(
+                at(what, format!("{kind} `{name}` removed")),
+                from_guest(flow),
+                ALL,
+            );
+        }
+        for (name, _) in new.iter().skip(old.len()) {
+            self.push(
+                at(what, format!("{kind} `{name}` added")),
+                to_guest(flow),
+                exhaustive,
+            );
+        }
+    }
+
+    /// Compares the entries two lists have in the same positions, returning
+    /// whether the trailing entries can be compared as removed or added.
+    fn positions(
+        &mut self,
+        old: &[(&str, Option<&Type>)],
+        new: &[(&str, Option<&Type>)],
+        flow: Option<Flow>,
+        what: &str,
+        kind: &str,
+    ) -> bool {
+        let used = flow.is_some();
+        for ((old_name, old_ty), (new_name, new_ty)) in old.iter().zip(new) {
+            if old_name != new_name {
+                let moved = old.iter().any(|(name, _)| name == new_name)
+                    || new.iter().any(|(name, _)| name == old_name);
+                if moved {
+                    self.push(
+                        at(what, format!("{kind}s reordered, removed or inserted")),
+                        used,
+                        ALL,
+                    );
+                    return false;
+                }
+                // Names are part of the component type, so a renamed field or
+                // case no longer matches the other side of the call.
+                self.push(
+                    at(what, format!("{kind} `{old_name}` renamed to `{new_name}`")),
+                    used,
+                    ALL,
+                );
+            }
+            self.payload(
+                *old_ty,
+                *new_ty,
+                flow,
+                &at(what, format!("{kind} `{new_name}`")),
+            );
+        }
+        true
+    }
+}
+
+/// Joins the description of where a change is with the change itself.
+fn at(what: &str, change: String) -> String {
+    if what.is_empty() {
+        change
+    } else {
+        format!("{what}: {change}")
+    }
+}
+
+/// The name of a world item, which for interfaces leaves out the version so
+/// different versions of an interface are matched with each other.
+fn item_name(resolve: &Resolve, key: &WorldKey) -> String {
+    match key {
+        WorldKey::Name(name) => name.clone(),
+        WorldKey::Interface(id) => {
+            let interface = &resolve.interfaces[*id];
+            match (interface.package, &interface.name) {
+                (Some(package), Some(name)) => {
+                    let package = &resolve.packages[package].name;
+                    format!("{}:{}/{name}", package.namespace, package.name)
+                }
+                _ => resolve.name_world_key(key),
+            }
+        }
+    }
+}
+
+fn item_kind(item: &WorldItem) -> &'static str {
+    match item {
+        WorldItem::Interface { .. } => "interface",
+        WorldItem::Function(_) => "function",
+        WorldItem::Type(_) => "type",
+    }
+}
+
+fn func_name(resolve: &Resolve, func: &Function) -> String {
+    let resource = |id: &TypeId| resolve.types[*id].name.as_deref().unwrap_or("");
+    match &func.kind {
+        FunctionKind::Freestanding => format!("function `{}`", func.name),
+        FunctionKind::Method(id) => format!("method `{}.{}`", resource(id), func.item_name()),
+        FunctionKind::Static(id) => {
+            format!("static function `{}.{}`", resource(id), func.item_name())
+        }
+        FunctionKind::Constructor(id) => format!("constructor of `{}`", resource(id)),
+    }
+}
+
+fn params_str(resolve: &Resolve, params: &Params) -> String {
+    let params = params
+        .iter()
+        .map(|(name, ty)| format!("{name}: {}", type_name(resolve, ty)))
+        .collect::<Vec<_>>();
+    format!("({})", params.join(", "))
+}
+
+fn results_str(resolve: &Resolve, results: &Results) -> String {
+    match results {
+        Results::Anon(ty) => type_name(resolve, ty),
+        Results::Named(params) => params_str(resolve, params),
+    }
+}
+
+/// Follows `type a = b` aliases to the type they name.
+fn dealias(resolve: &Resolve, ty: &Type) -> Type {
+    match ty {
+        Type::Id(id) => match &resolve.types[*id].kind {
+            TypeDefKind::Type(ty) => dealias(resolve, ty),
+            _ => *ty,
+        },
+        _ => *ty,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare(old: &str, new: &str) -> Vec<Change> {
+        let mut old_resolve = Resolve::default();
+        let pkg = old_resolve.push_str("old.wit", old).unwrap();
+        let old_world = old_resolve.select_world(pkg, None).unwrap();
+        let mut new_resolve = Resolve::default();
+        let pkg = new_resolve.push_str("new.wit", new).unwrap();
+        let new_world = new_resolve.select_world(pkg, None).unwrap();
+        super::compare(&old_resolve, old_world, &new_resolve, new_world)
+    }
+
+    fn find<'a>(changes: &'a [Change], change: &str) -> &'a Change {
+        changes
+            .iter()
+            .find(|c| c.to_string() == change)
+            .unwrap_or_else(|| panic!("`{change}` not found in {changes:#?}"))
+    }
+
+    #[test]
+    fn unchanged() {
+        let wit = "package a:b;
+            interface i { record r { a: u32, b: list<string> } f: func(r: r) -> option<r>; }
+            world w { import i; export g: func(); }";
+        assert_eq!(compare(wit, wit), []);
+    }
+
+    #[test]
+    fn functions() {
+        let changes = compare(
+            "package a:b; world w {
+                import f: func(a: u32);
+                import g: func();
+                export h: func(a: u32) -> string;
+            }",
+            "package a:b; world w {
+                import f: func(b: u32);
+                export h: func(a: u64) -> string;
+                export i: func();
+            }",
+        );
+        let renamed = find(&changes, "import `f`: parameter `a` renamed to `b`");
+        assert!(!renamed.breaking);
+        assert_eq!(renamed.bindings, LABELLED);
+        assert!(find(&changes, "import `g`: function removed").breaking);
+        let changed = find(
+            &changes,
+            "export `h`: parameter `a`: type changed from `u32` to `u64`",
+        );
+        assert!(changed.breaking);
+        assert_eq!(changed.bindings, ALL);
+        assert!(find(&changes, "export `i`: function added").breaking);
+        assert_eq!(changes.len(), 4);
+    }
+
+    #[test]
+    fn cases() {
+        let old = "package a:b;
+            interface i {
+                enum sent { a, b }
+                enum received { a, b }
+                variant both { a(u32), b }
+                send: func(x: sent);
+                receive: func() -> received;
+                echo: func(x: both) -> both;
+            }
+            world w { import i; }";
+        let new = "package a:b;
+            interface i {
+                enum sent { a, b, c }
+                enum received { a }
+                variant both { a(u32) }
+                send: func(x: sent);
+                receive: func() -> received;
+                echo: func(x: both) -> both;
+            }
+            world w { import i; }";
+        let changes = compare(old, new);
+        let added = find(&changes, "import `a:b/i`: type `sent`: case `c` added");
+        assert!(!added.breaking);
+        assert_eq!(added.bindings, EXHAUSTIVE);
+        let removed = find(
+            &changes,
+            "import `a:b/i`: type `received`: case `b` removed",
+        );
+        assert!(!removed.breaking);
+        assert!(find(&changes, "import `a:b/i`: type `both`: case `b` removed").breaking);
+        assert_eq!(changes.len(), 3);
+    }
+
+    #[test]
+    fn renamed_cases() {
+        let changes = compare(
+            "package a:b;
+            interface i {
+                variant v { a(u32), b }
+                variant unused { a, b }
+                f: func(x: v);
+            }
+            world w { import i; }",
+            "package a:b;
+            interface i {
+                variant v { a(u32), c }
+                variant unused { a, c }
+                f: func(x: v);
+            }
+            world w { import i; }",
+        );
+        let renamed = find(
+            &changes,
+            "import `a:b/i`: type `v`: case `b` renamed to `c`",
+        );
+        assert!(renamed.breaking);
+        assert_eq!(renamed.bindings, ALL);
+        assert!(
+            !find(
+                &changes,
+                "import `a:b/i`: type `unused`: case `b` renamed to `c`"
+            )
+            .breaking
+        );
+        assert_eq!(changes.len(), 2);
+    }
+
+    #[test]
+    fn records() {
+        let changes = compare(
+            "package a:b@0.1.0;
+            interface i {
+                record r { a: u32, b: option<u32> }
+                f: func() -> r;
+            }
+            world w { export i; }",
+            "package a:b@0.2.0;
+            interface i {
+                record r { x: u32, b: option<string> }
+                f: func() -> r;
+            }
+            world w { export i; }",
+        );
+        assert!(
+            find(
+                &changes,
+                "export `a:b/i`: version changed from 0.1.0 to 0.2.0"
+            )
+            .breaking
+        );
+        let renamed = find(
+            &changes,
+            "export `a:b/i`: type `r`: field `a` renamed to `x`",
+        );
+        assert!(renamed.breaking);
+        assert_eq!(renamed.bindings, ALL);
+        assert!(find(
+            &changes,
+            "export `a:b/i`: type `r`: field `b`: type changed from `option<u32>` to `option<string>`"
+        )
+        .breaking);
+        assert_eq!(changes.len(), 3);
+    }
+}
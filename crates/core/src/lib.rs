@@ -4,10 +4,12 @@ use anyhow::Result;
 pub use wit_parser;
 use wit_parser::*;
 pub mod abi;
+pub mod compat;
 mod ns;
 pub use ns::Ns;
 pub mod source;
 pub use source::{Files, Source};
+pub mod trace;
 mod types;
 pub use types::{TypeInfo, Types};
 mod path;
@@ -23,6 +25,7 @@ pub enum Direction {
 pub trait WorldGenerator {
     fn generate(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
         let world = &resolve.worlds[id];
+        self.validate(resolve, id)?;
         self.preprocess(resolve, id);
 
         fn unwrap_name(key: &WorldKey) -> &str {
@@ -83,6 +86,13 @@ pub trait WorldGenerator {
         let _ = (resolve, world, files);
     }
 
+    /// Called before any bindings are generated to reject worlds using
+    /// features this generator doesn't support.
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        let _ = (resolve, world);
+        Ok(())
+    }
+
     fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
         let _ = (resolve, world);
     }
@@ -229,3 +239,38 @@ pub fn dealias(resolve: &Resolve, mut id: TypeId) -> TypeId {
         }
     }
 }
+
+/// Returns the first import or export of `world` which uses a `future` or
+/// `stream` type, if any.
+pub fn find_future_or_stream_item(resolve: &Resolve, world: WorldId) -> Option<&WorldKey> {
+    let world = &resolve.worlds[world];
+    world
+        .imports
+        .iter()
+        .chain(world.exports.iter())
+        .find(|(_, item)| {
+            let mut live = LiveTypes::default();
+            live.add_world_item(resolve, item);
+            let uses_futures_or_streams = live.iter().any(|id| {
+                matches!(
+                    resolve.types[id].kind,
+                    TypeDefKind::Future(_) | TypeDefKind::Stream(_)
+                )
+            });
+            uses_futures_or_streams
+        })
+        .map(|(name, _)| name)
+}
+
+/// Returns an error naming the first import or export of `world` which uses a
+/// `future` or `stream` type, for generators which don't support them yet.
+pub fn reject_futures_and_streams(resolve: &Resolve, world: WorldId, language: &str) -> Result<()> {
+    match find_future_or_stream_item(resolve, world) {
+        Some(name) => anyhow::bail!(
+            "`{}` uses `future` or `stream` types, which aren't supported by the \
+             {language} generator yet",
+            resolve.name_world_key(name),
+        ),
+        None => Ok(()),
+    }
+}
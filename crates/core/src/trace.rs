@@ -0,0 +1,569 @@
+//! Printing the instructions `abi::call` generates for functions, which is
+//! useful to check how a generator is expected to lift and lower them.
+
+use crate::abi::{self, AbiVariant, Bindgen, Bitcast, Instruction, LiftLower, WasmType};
+use crate::{uwriteln, Files, Source, WorldGenerator};
+use anyhow::Result;
+use heck::ToKebabCase;
+use std::fmt::Write;
+use std::mem;
+use wit_parser::*;
+
+/// A [`Bindgen`] which records each instruction it's given as a line of
+/// pseudo-assembly, naming operands `%0`, `%1`, and so on.
+///
+/// Blocks are printed where they're finished, labelled `block0`, `block1`,
+/// and so on, and listed after the operands of the instruction using them.
+pub struct Trace<'a> {
+    sizes: &'a SizeAlign,
+    /// Whether lists of primitives are lifted and lowered with the
+    /// `list-canon-*` instructions.
+    pub canonical_lists: bool,
+    src: Source,
+    next_operand: usize,
+    next_block: usize,
+    block_storage: Vec<Source>,
+    /// The labels of the blocks which have been finished but not yet used by
+    /// an instruction.
+    blocks: Vec<String>,
+}
+
+impl<'a> Trace<'a> {
+    pub fn new(sizes: &'a SizeAlign) -> Trace<'a> {
+        Trace {
+            sizes,
+            canonical_lists: false,
+            src: Source::default(),
+            next_operand: 0,
+            next_block: 0,
+            block_storage: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Returns the instructions recorded so far.
+    pub fn finish(self) -> String {
+        self.src.into()
+    }
+
+    fn operand(&mut self) -> String {
+        let operand = format!("%{}", self.next_operand);
+        self.next_operand += 1;
+        operand
+    }
+}
+
+impl Bindgen for Trace<'_> {
+    type Operand = String;
+
+    fn emit(
+        &mut self,
+        resolve: &Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<String>,
+        results: &mut Vec<String>,
+    ) {
+        let mut line = String::new();
+        for _ in 0..inst.results_len() {
+            let result = self.operand();
+            results.push(result);
+        }
+        if !results.is_empty() {
+            line.push_str(&results.join(", "));
+            line.push_str(" = ");
+        }
+        line.push_str(&instruction_name(inst));
+        for immediate in immediates(resolve, inst) {
+            line.push(' ');
+            line.push_str(&immediate);
+        }
+        if !operands.is_empty() {
+            line.push(' ');
+            line.push_str(&operands.join(", "));
+        }
+        let blocks = blocks_used(inst);
+        if blocks > 0 {
+            let labels = self.blocks.split_off(self.blocks.len() - blocks);
+            write!(line, " [{}]", labels.join(", ")).unwrap();
+        }
+        uwriteln!(self.src, "{line}");
+    }
+
+    fn return_pointer(&mut self, size: usize, align: usize) -> String {
+        let ptr = self.operand();
+        uwriteln!(self.src, "{ptr} = return-pointer size={size} align={align}");
+        ptr
+    }
+
+    fn push_block(&mut self) {
+        let prev = mem::take(&mut self.src);
+        self.block_storage.push(prev);
+    }
+
+    fn finish_block(&mut self, operands: &mut Vec<String>) {
+        let prev = self.block_storage.pop().unwrap();
+        let body = mem::replace(&mut self.src, prev);
+        let label = format!("block{}", self.next_block);
+        self.next_block += 1;
+
+        uwriteln!(self.src, "{label} {{");
+        self.src.push_str(&body);
+        if !operands.is_empty() {
+            uwriteln!(self.src, "yield {}", operands.join(", "));
+        }
+        uwriteln!(self.src, "}}");
+        operands.clear();
+        self.blocks.push(label);
+    }
+
+    fn sizes(&self) -> &SizeAlign {
+        self.sizes
+    }
+
+    fn is_list_canonical(&self, resolve: &Resolve, element: &Type) -> bool {
+        if !self.canonical_lists {
+            return false;
+        }
+        match element {
+            Type::Bool | Type::Char | Type::String => false,
+            Type::Id(id) => match &resolve.types[*id].kind {
+                TypeDefKind::Type(ty) => self.is_list_canonical(resolve, ty),
+                _ => false,
+            },
+            _ => true,
+        }
+    }
+}
+
+/// Returns the name of `inst`, such as `i32-from-u8` for
+/// `Instruction::I32FromU8`.
+fn instruction_name(inst: &Instruction<'_>) -> String {
+    let debug = format!("{inst:?}");
+    let end = debug
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(debug.len());
+    debug[..end].to_kebab_case()
+}
+
+/// Returns the immediates of `inst` worth printing, such as the offsets of
+/// loads and stores or the types being lifted and lowered.
+fn immediates(resolve: &Resolve, inst: &Instruction<'_>) -> Vec<String> {
+    use Instruction::*;
+
+    let ty = |id: &TypeId| format!("type={}", type_name(resolve, &Type::Id(*id)));
+    let element = |ty: &Type| format!("element={}", type_name(resolve, ty));
+    let realloc = |realloc: &Option<&str>| realloc.map(|name| format!("realloc={name}"));
+
+    match inst {
+        GetArg { nth } => vec![nth.to_string()],
+        I32Const { val } => vec![val.to_string()],
+        Bitcasts { casts } => casts.iter().map(bitcast_name).collect(),
+        ConstZero { tys } => vec![wasm_types(tys)],
+
+        I32Load { offset }
+        | I32Load8U { offset }
+        | I32Load8S { offset }
+        | I32Load16U { offset }
+        | I32Load16S { offset }
+        | I64Load { offset }
+        | F32Load { offset }
+        | F64Load { offset }
+        | PointerLoad { offset }
+        | LengthLoad { offset }
+        | I32Store { offset }
+        | I32Store8 { offset }
+        | I32Store16 { offset }
+        | I64Store { offset }
+        | F32Store { offset }
+        | F64Store { offset }
+        | PointerStore { offset }
+        | LengthStore { offset } => vec![format!("offset={offset}")],
+
+        ListCanonLower {
+            element: elem,
+            realloc: r,
+        }
+        | ListLower {
+            element: elem,
+            realloc: r,
+        } => [Some(element(elem)), realloc(r)]
+            .into_iter()
+            .flatten()
+            .collect(),
+        StringLower { realloc: r } => realloc(r).into_iter().collect(),
+        ListCanonLift { element: elem, .. }
+        | ListLift { element: elem, .. }
+        | IterElem { element: elem }
+        | GuestDeallocateList { element: elem } => vec![element(elem)],
+
+        RecordLower { ty: id, .. }
+        | RecordLift { ty: id, .. }
+        | HandleLower { ty: id, .. }
+        | HandleLift { ty: id, .. }
+        | FutureLower { ty: id, .. }
+        | FutureLift { ty: id, .. }
+        | StreamLower { ty: id, .. }
+        | StreamLift { ty: id, .. }
+        | TupleLower { ty: id, .. }
+        | TupleLift { ty: id, .. }
+        | FlagsLower { ty: id, .. }
+        | FlagsLift { ty: id, .. }
+        | VariantLift { ty: id, .. }
+        | EnumLower { ty: id, .. }
+        | EnumLift { ty: id, .. }
+        | OptionLift { ty: id, .. }
+        | ResultLift { ty: id, .. } => vec![ty(id)],
+        VariantLower {
+            ty: id, results, ..
+        }
+        | OptionLower {
+            ty: id, results, ..
+        }
+        | ResultLower {
+            ty: id, results, ..
+        } => vec![ty(id), format!("results={}", wasm_types(results))],
+
+        CallWasm { name, sig } | AsyncCallWasm { name, sig } => vec![
+            format!("{name:?}"),
+            format!(
+                "{} -> {}",
+                wasm_types(&sig.params),
+                wasm_types(&sig.results)
+            ),
+        ],
+        CallInterface { func, async_ } => {
+            let mut immediates = vec![format!("{:?}", func.name)];
+            if *async_ {
+                immediates.push("async".to_string());
+            }
+            immediates
+        }
+        AsyncPostCallInterface { func } => vec![format!("{:?}", func.name)],
+        AsyncCallReturn { name, params } => vec![format!("{name:?}"), wasm_types(params)],
+
+        Malloc {
+            realloc,
+            size,
+            align,
+        } => vec![
+            format!("realloc={realloc}"),
+            format!("size={size}"),
+            format!("align={align}"),
+        ],
+        AsyncMalloc { size, align } | GuestDeallocate { size, align } => {
+            vec![format!("size={size}"), format!("align={align}")]
+        }
+
+        _ => Vec::new(),
+    }
+}
+
+/// Returns how many of the blocks finished before `inst` it uses.
+fn blocks_used(inst: &Instruction<'_>) -> usize {
+    match inst {
+        Instruction::ListLower { .. }
+        | Instruction::ListLift { .. }
+        | Instruction::GuestDeallocateList { .. } => 1,
+        Instruction::VariantLower { variant, .. } | Instruction::VariantLift { variant, .. } => {
+            variant.cases.len()
+        }
+        Instruction::OptionLower { .. }
+        | Instruction::OptionLift { .. }
+        | Instruction::ResultLower { .. }
+        | Instruction::ResultLift { .. } => 2,
+        Instruction::GuestDeallocateVariant { blocks } => *blocks,
+        _ => 0,
+    }
+}
+
+fn bitcast_name(cast: &Bitcast) -> String {
+    match cast {
+        Bitcast::Sequence(seq) => {
+            let [a, b] = &**seq;
+            format!("{}+{}", bitcast_name(a), bitcast_name(b))
+        }
+        _ => format!("{cast:?}").to_kebab_case(),
+    }
+}
+
+fn wasm_type(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 => "i32",
+        WasmType::I64 => "i64",
+        WasmType::F32 => "f32",
+        WasmType::F64 => "f64",
+        WasmType::Pointer => "pointer",
+        WasmType::PointerOrI64 => "pointer-or-i64",
+        WasmType::Length => "length",
+    }
+}
+
+/// Returns `ty` as it would be written in WIT.
+pub(crate) fn type_name(resolve: &Resolve, ty: &Type) -> String {
+    let id = match ty {
+        Type::Bool => return "bool".to_string(),
+        Type::U8 => return "u8".to_string(),
+        Type::U16 => return "u16".to_string(),
+        Type::U32 => return "u32".to_string(),
+        Type::U64 => return "u64".to_string(),
+        Type::S8 => return "s8".to_string(),
+        Type::S16 => return "s16".to_string(),
+        Type::S32 => return "s32".to_string(),
+        Type::S64 => return "s64".to_string(),
+        Type::F32 => return "f32".to_string(),
+        Type::F64 => return "f64".to_string(),
+        Type::Char => return "char".to_string(),
+        Type::String => return "string".to_string(),
+        Type::Id(id) => *id,
+    };
+    let ty = &resolve.types[id];
+    if let Some(name) = &ty.name {
+        return name.clone();
+    }
+    let optional = |ty: &Option<Type>| match ty {
+        Some(ty) => type_name(resolve, ty),
+        None => "_".to_string(),
+    };
+    match &ty.kind {
+        TypeDefKind::Handle(Handle::Own(id)) => {
+            format!("own<{}>", type_name(resolve, &Type::Id(*id)))
+        }
+        TypeDefKind::Handle(Handle::Borrow(id)) => {
+            format!("borrow<{}>", type_name(resolve, &Type::Id(*id)))
+        }
+        TypeDefKind::Tuple(tuple) => {
+            let types = tuple
+                .types
+                .iter()
+                .map(|ty| type_name(resolve, ty))
+                .collect::<Vec<_>>();
+            format!("tuple<{}>", types.join(", "))
+        }
+        TypeDefKind::Option(ty) => format!("option<{}>", type_name(resolve, ty)),
+        TypeDefKind::Result(result) => match (&result.ok, &result.err) {
+            (None, None) => "result".to_string(),
+            (ok, err) => format!("result<{}, {}>", optional(ok), optional(err)),
+        },
+        TypeDefKind::List(ty) => format!("list<{}>", type_name(resolve, ty)),
+        TypeDefKind::Future(ty) => format!("future<{}>", optional(ty)),
+        TypeDefKind::Stream(stream) => format!(
+            "stream<{}, {}>",
+            optional(&stream.element),
+            optional(&stream.end)
+        ),
+        TypeDefKind::Type(ty) => type_name(resolve, ty),
+        _ => unreachable!("only named types can be {}", ty.kind.as_str()),
+    }
+}
+
+/// Options for [`TraceWorld`], the generator printing the instructions of
+/// every function in a world.
+#[derive(Default, Debug, Clone)]
+pub struct Opts {
+    /// Whether lists of primitives are lifted and lowered with the
+    /// `list-canon-*` instructions.
+    pub canonical_lists: bool,
+}
+
+impl Opts {
+    pub fn build(&self) -> Box<dyn WorldGenerator> {
+        Box::new(TraceWorld {
+            opts: self.clone(),
+            sizes: SizeAlign::default(),
+            src: Source::default(),
+        })
+    }
+}
+
+/// A generator writing a `*.abi.txt` file with the instructions of every
+/// function imported or exported by a world, as recorded by [`Trace`].
+pub struct TraceWorld {
+    opts: Opts,
+    sizes: SizeAlign,
+    src: Source,
+}
+
+impl TraceWorld {
+    fn funcs<'a>(
+        &mut self,
+        resolve: &Resolve,
+        module: Option<&str>,
+        funcs: impl IntoIterator<Item = &'a Function>,
+        variant: AbiVariant,
+    ) {
+        for func in funcs {
+            let sig = resolve.wasm_signature(variant, func);
+            let sig = format!(
+                "{} -> {}",
+                wasm_types(&sig.params),
+                wasm_types(&sig.results)
+            );
+            let export_name = func.core_export_name(module);
+            let lift_lower = match variant {
+                AbiVariant::GuestImport => {
+                    let module = module.unwrap_or("$root");
+                    uwriteln!(self.src, "\nimport {module:?} {:?} {sig} {{", func.name);
+                    LiftLower::LowerArgsLiftResults
+                }
+                AbiVariant::GuestExport => {
+                    uwriteln!(self.src, "\nexport {export_name:?} {sig} {{");
+                    LiftLower::LiftArgsLowerResults
+                }
+            };
+            let mut trace = Trace::new(&self.sizes);
+            trace.canonical_lists = self.opts.canonical_lists;
+            abi::call(resolve, variant, lift_lower, func, &mut trace, false);
+            self.src.push_str(&trace.finish());
+            uwriteln!(self.src, "}}");
+
+            if matches!(variant, AbiVariant::GuestExport)
+                && abi::guest_export_needs_post_return(resolve, func)
+            {
+                let sig = resolve.wasm_signature(variant, func);
+                uwriteln!(
+                    self.src,
+                    "\nexport \"cabi_post_{export_name}\" {} -> () {{",
+                    wasm_types(&sig.results)
+                );
+                let mut trace = Trace::new(&self.sizes);
+                trace.canonical_lists = self.opts.canonical_lists;
+                abi::post_return(resolve, func, &mut trace);
+                self.src.push_str(&trace.finish());
+                uwriteln!(self.src, "}}");
+            }
+        }
+    }
+}
+
+fn wasm_types(tys: &[WasmType]) -> String {
+    let tys = tys.iter().map(|ty| wasm_type(*ty)).collect::<Vec<_>>();
+    format!("({})", tys.join(", "))
+}
+
+impl WorldGenerator for TraceWorld {
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+        self.sizes.fill(resolve);
+        let world = &resolve.worlds[world];
+        uwriteln!(
+            self.src,
+            ";; The canonical ABI instructions of the functions of `{}`.",
+            world.name
+        );
+    }
+
+    fn import_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        iface: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let module = resolve.name_world_key(name);
+        let funcs = resolve.interfaces[iface].functions.values();
+        self.funcs(resolve, Some(&module), funcs, AbiVariant::GuestImport);
+        Ok(())
+    }
+
+    fn export_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        iface: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let module = resolve.name_world_key(name);
+        let funcs = resolve.interfaces[iface].functions.values();
+        self.funcs(resolve, Some(&module), funcs, AbiVariant::GuestExport);
+        Ok(())
+    }
+
+    fn import_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) {
+        let funcs = funcs.iter().map(|(_, func)| *func);
+        self.funcs(resolve, None, funcs, AbiVariant::GuestImport);
+    }
+
+    fn export_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) -> Result<()> {
+        let funcs = funcs.iter().map(|(_, func)| *func);
+        self.funcs(resolve, None, funcs, AbiVariant::GuestExport);
+        Ok(())
+    }
+
+    fn import_types(
+        &mut self,
+        _resolve: &Resolve,
+        _world: WorldId,
+        _types: &[(&str, TypeId)],
+        _files: &mut Files,
+    ) {
+    }
+
+    fn finish(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) -> Result<()> {
+        let name = &resolve.worlds[world].name;
+        files.push(&format!("{name}.abi.txt"), self.src.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(wit: &str, canonical_lists: bool) -> String {
+        let mut resolve = Resolve::default();
+        let pkg = resolve.push_str("test.wit", wit).unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let mut files = Files::default();
+        Opts { canonical_lists }
+            .build()
+            .generate(&resolve, world, &mut files)
+            .unwrap();
+        let (_, contents) = files.iter().next().unwrap();
+        String::from_utf8(contents.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn import_string() {
+        let src = trace(
+            "package a:b; world w { import f: func(s: string) -> u8; }",
+            false,
+        );
+        assert!(src.contains("import \"$root\" \"f\" (pointer, length) -> (i32) {"));
+        assert!(src.contains("%0 = get-arg 0"));
+        assert!(src.contains("%1, %2 = string-lower %0"));
+        assert!(src.contains("%3 = call-wasm \"f\" (pointer, length) -> (i32) %1, %2"));
+        assert!(src.contains("%4 = u8-from-i32 %3"));
+        assert!(src.contains("return %4"));
+    }
+
+    #[test]
+    fn export_blocks() {
+        let src = trace(
+            "package a:b; world w { export f: func() -> option<list<u32>>; }",
+            false,
+        );
+        assert!(src.contains("export \"f\" () -> (pointer) {"));
+        assert!(src.contains("list-lower element=u32 realloc=cabi_realloc"));
+        assert!(src.contains("option-lower type=option<list<u32>>"));
+        assert!(src.contains("export \"cabi_post_f\" (pointer) -> () {"));
+        assert!(src.contains("guest-deallocate-list element=u32"));
+    }
+
+    #[test]
+    fn canonical_lists() {
+        let wit = "package a:b; world w { import f: func(l: list<u32>); }";
+        assert!(trace(wit, false).contains("list-lower element=u32"));
+        assert!(trace(wit, true).contains("list-canon-lower element=u32"));
+    }
+}
@@ -115,6 +115,25 @@ impl Types {
             }
         }
 
+        // Payloads of futures and streams are always transferred by value, so
+        // they're owned regardless of where the future or stream appears.
+        let mut live = LiveTypes::default();
+        for id in crate::abi::find_futures_and_streams(resolve, func) {
+            let payloads = match &resolve.types[id].kind {
+                TypeDefKind::Future(ty) => [ty, &None],
+                TypeDefKind::Stream(s) => [&s.element, &s.end],
+                _ => unreachable!(),
+            };
+            for ty in payloads.into_iter().flatten() {
+                live.add_type(resolve, ty);
+            }
+        }
+        for id in live.iter() {
+            if resolve.types[id].name.is_some() {
+                self.type_info.get_mut(&id).unwrap().owned = true;
+            }
+        }
+
         for ty in func.results.iter_types() {
             let id = match ty {
                 Type::Id(id) => *id,
@@ -193,12 +212,11 @@ impl Types {
                 info = self.optional_type_info(resolve, r.ok.as_ref());
                 info |= self.optional_type_info(resolve, r.err.as_ref());
             }
-            TypeDefKind::Future(ty) => {
-                info = self.optional_type_info(resolve, ty.as_ref());
-            }
-            TypeDefKind::Stream(stream) => {
-                info = self.optional_type_info(resolve, stream.element.as_ref());
-                info |= self.optional_type_info(resolve, stream.end.as_ref());
+            // Futures and streams are represented as handles which own their
+            // payloads, so their payloads never need to be borrowed.
+            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {
+                info.has_resource = true;
+                info.has_own_handle = true;
             }
             TypeDefKind::Unknown => unreachable!(),
         }
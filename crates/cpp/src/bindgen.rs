@@ -0,0 +1,927 @@
+use crate::{variant_case_name, InterfaceGenerator, TypeMode};
+use std::fmt::Write as _;
+use std::mem;
+use wit_bindgen_c::{flags_repr, to_c_ident};
+use wit_bindgen_core::abi::{Bindgen, Bitcast, Instruction, WasmType};
+use wit_bindgen_core::{dealias, uwrite, uwriteln, wit_parser::*, Direction, Ns, Source};
+
+pub(super) struct FunctionBindgen<'a, 'b> {
+    pub gen: &'b mut InterfaceGenerator<'a>,
+    /// The function `CallWasm` or `CallInterface` calls.
+    func_to_call: String,
+    pub params: Vec<String>,
+    pub locals: Ns,
+    pub src: Source,
+    block_storage: Vec<Source>,
+    blocks: Vec<(String, Vec<String>)>,
+    payloads: Vec<String>,
+    /// Whether this is the constructor of an imported resource, which
+    /// assigns its result to `*this` rather than returning it.
+    pub in_constructor: bool,
+    pub needs_cleanup_list: bool,
+    pub import_return_pointer_area_size: usize,
+    pub import_return_pointer_area_align: usize,
+}
+
+impl<'a, 'b> FunctionBindgen<'a, 'b> {
+    pub(super) fn new(
+        gen: &'b mut InterfaceGenerator<'a>,
+        func_to_call: String,
+    ) -> FunctionBindgen<'a, 'b> {
+        FunctionBindgen {
+            gen,
+            func_to_call,
+            params: Vec::new(),
+            locals: Ns::default(),
+            src: Source::default(),
+            block_storage: Vec::new(),
+            blocks: Vec::new(),
+            payloads: Vec::new(),
+            in_constructor: false,
+            needs_cleanup_list: false,
+            import_return_pointer_area_size: 0,
+            import_return_pointer_area_align: 0,
+        }
+    }
+
+    fn in_import(&self) -> bool {
+        self.gen.direction == Direction::Import
+    }
+
+    fn type_name(&self, ty: &Type) -> String {
+        self.gen.type_name(ty, TypeMode::Owned)
+    }
+
+    /// Binds `op` to a new reference so that it's only evaluated once, and
+    /// returns its name.
+    fn bind(&mut self, name: &str, op: &str) -> String {
+        let tmp = self.locals.tmp(name);
+        uwriteln!(self.src, "auto&& {tmp} = {op};");
+        tmp
+    }
+
+    /// Returns `op`, which is a value of type `ty`, moved if it names a local
+    /// which owns resources or memory.
+    fn move_op(&self, op: &str, ty: &Type) -> String {
+        let is_ident = op.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        match ty {
+            Type::Id(_) | Type::String if is_ident => format!("std::move({op})"),
+            _ => op.to_string(),
+        }
+    }
+
+    fn load(&mut self, ty: &str, offset: i32, operands: &[String], results: &mut Vec<String>) {
+        results.push(format!("*(({ty} *) ({} + {offset}))", operands[0]));
+    }
+
+    fn load_ext(&mut self, ty: &str, offset: i32, operands: &[String], results: &mut Vec<String>) {
+        self.load(ty, offset, operands, results);
+        let result = results.pop().unwrap();
+        results.push(format!("(int32_t) ({result})"));
+    }
+
+    fn store(&mut self, ty: &str, offset: i32, operands: &[String]) {
+        uwriteln!(
+            self.src,
+            "*(({ty} *) ({} + {offset})) = {};",
+            operands[1],
+            operands[0]
+        );
+    }
+
+    /// Returns an expression allocating `size` bytes aligned to `align`,
+    /// which are freed after the call for imports and by the caller for
+    /// exports.
+    fn alloc(&mut self, size: &str, align: usize) -> String {
+        if self.in_import() {
+            self.needs_cleanup_list = true;
+            format!("(uint8_t *) std::malloc({size})")
+        } else {
+            format!("(uint8_t *) cabi_realloc(nullptr, 0, {align}, {size})")
+        }
+    }
+
+    /// Declares a variable for each of the core wasm `types` which the
+    /// results of a variant's cases are assigned to.
+    fn declare_results(&mut self, name: &str, types: &[WasmType]) -> Vec<String> {
+        types
+            .iter()
+            .map(|ty| {
+                let tmp = self.locals.tmp(name);
+                uwriteln!(self.src, "{} {tmp}{{}};", wasm_type(*ty));
+                tmp
+            })
+            .collect()
+    }
+}
+
+impl Bindgen for FunctionBindgen<'_, '_> {
+    type Operand = String;
+
+    fn sizes(&self) -> &SizeAlign {
+        &self.gen.gen.sizes
+    }
+
+    fn push_block(&mut self) {
+        let prev = mem::take(&mut self.src);
+        self.block_storage.push(prev);
+    }
+
+    fn finish_block(&mut self, operands: &mut Vec<String>) {
+        let to_restore = self.block_storage.pop().unwrap();
+        let src = mem::replace(&mut self.src, to_restore);
+        self.blocks.push((src.into(), mem::take(operands)));
+    }
+
+    fn return_pointer(&mut self, size: usize, align: usize) -> String {
+        let ptr = self.locals.tmp("ptr");
+
+        // Use a stack-based return area for imports, because exports need
+        // their return area to be live until the post-return call.
+        if self.in_import() {
+            self.import_return_pointer_area_size = self.import_return_pointer_area_size.max(size);
+            self.import_return_pointer_area_align =
+                self.import_return_pointer_area_align.max(align);
+            uwriteln!(self.src, "uint8_t *{ptr} = (uint8_t *) &ret_area;");
+        } else {
+            self.gen.gen.return_pointer_area_size = self.gen.gen.return_pointer_area_size.max(size);
+            self.gen.gen.return_pointer_area_align =
+                self.gen.gen.return_pointer_area_align.max(align);
+            uwriteln!(self.src, "uint8_t *{ptr} = (uint8_t *) &RET_AREA;");
+        }
+
+        ptr
+    }
+
+    fn is_list_canonical(&self, _resolve: &Resolve, ty: &Type) -> bool {
+        // Only lists of numbers are represented as `std::vector`s with the
+        // same layout as the canonical ABI; `bool` and `char` are validated.
+        matches!(
+            ty,
+            Type::U8
+                | Type::S8
+                | Type::U16
+                | Type::S16
+                | Type::U32
+                | Type::S32
+                | Type::U64
+                | Type::S64
+                | Type::F32
+                | Type::F64
+        )
+    }
+
+    fn emit(
+        &mut self,
+        resolve: &Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<String>,
+        results: &mut Vec<String>,
+    ) {
+        match inst {
+            Instruction::GetArg { nth } => results.push(self.params[*nth].clone()),
+            Instruction::I32Const { val } => results.push(val.to_string()),
+            Instruction::ConstZero { tys } => {
+                for _ in tys.iter() {
+                    results.push("0".to_string());
+                }
+            }
+
+            Instruction::U8FromI32 => results.push(format!("(uint8_t) ({})", operands[0])),
+            Instruction::S8FromI32 => results.push(format!("(int8_t) ({})", operands[0])),
+            Instruction::U16FromI32 => results.push(format!("(uint16_t) ({})", operands[0])),
+            Instruction::S16FromI32 => results.push(format!("(int16_t) ({})", operands[0])),
+            Instruction::U32FromI32 => results.push(format!("(uint32_t) ({})", operands[0])),
+            Instruction::S32FromI32 | Instruction::S64FromI64 => results.push(operands[0].clone()),
+            Instruction::U64FromI64 => results.push(format!("(uint64_t) ({})", operands[0])),
+
+            Instruction::I32FromU8
+            | Instruction::I32FromS8
+            | Instruction::I32FromU16
+            | Instruction::I32FromS16
+            | Instruction::I32FromU32 => {
+                results.push(format!("(int32_t) ({})", operands[0]));
+            }
+            Instruction::I32FromS32 | Instruction::I64FromS64 => results.push(operands[0].clone()),
+            Instruction::I64FromU64 => results.push(format!("(int64_t) ({})", operands[0])),
+
+            Instruction::CoreF32FromF32
+            | Instruction::CoreF64FromF64
+            | Instruction::F32FromCoreF32
+            | Instruction::F64FromCoreF64 => {
+                results.push(operands[0].clone());
+            }
+
+            Instruction::CharFromI32 => results.push(format!("(char32_t) ({})", operands[0])),
+            Instruction::I32FromChar => results.push(format!("(int32_t) ({})", operands[0])),
+            Instruction::BoolFromI32 => results.push(format!("(({}) != 0)", operands[0])),
+            Instruction::I32FromBool => results.push(format!("(int32_t) ({})", operands[0])),
+
+            Instruction::Bitcasts { casts } => {
+                for (cast, op) in casts.iter().zip(operands) {
+                    results.push(perform_cast(op, cast));
+                }
+            }
+
+            Instruction::RecordLower { record, .. } => {
+                if record.fields.is_empty() {
+                    return;
+                }
+                let op = self.bind("record", &operands[0]);
+                for f in record.fields.iter() {
+                    results.push(format!("{op}.{}", to_c_ident(&f.name)));
+                }
+            }
+            Instruction::RecordLift { record, ty, .. } => {
+                let name = self.gen.type_id_name(*ty, TypeMode::Owned);
+                let fields = record
+                    .fields
+                    .iter()
+                    .zip(operands.iter())
+                    .map(|(field, op)| self.move_op(op, &field.ty))
+                    .collect::<Vec<_>>();
+                results.push(format!("{name}{{{}}}", fields.join(", ")));
+            }
+
+            Instruction::TupleLower { tuple, .. } => {
+                if tuple.types.is_empty() {
+                    return;
+                }
+                let op = self.bind("tuple", &operands[0]);
+                for i in 0..tuple.types.len() {
+                    results.push(format!("std::get<{i}>({op})"));
+                }
+            }
+            Instruction::TupleLift { tuple, ty, .. } => {
+                let name = self.gen.type_id_name(*ty, TypeMode::Owned);
+                let ops = tuple
+                    .types
+                    .iter()
+                    .zip(operands.iter())
+                    .map(|(ty, op)| self.move_op(op, ty))
+                    .collect::<Vec<_>>();
+                results.push(format!("{name}({})", ops.join(", ")));
+            }
+
+            Instruction::HandleLower { handle, .. } => {
+                let op = &operands[0];
+                match handle {
+                    Handle::Own(resource) => {
+                        let resource = dealias(resolve, *resource);
+                        match self.gen.gen.resources[&resource] {
+                            Direction::Import => results.push(format!("({op}).into_handle()")),
+                            Direction::Export => {
+                                let name = self.gen.qualified_name(resource);
+                                results.push(format!("{name}::into_handle(std::move({op}))"));
+                            }
+                        }
+                    }
+                    Handle::Borrow(_) => results.push(format!("({op}).handle()")),
+                }
+            }
+            Instruction::HandleLift { handle, .. } => {
+                let op = &operands[0];
+                let resource = match handle {
+                    Handle::Own(resource) | Handle::Borrow(resource) => dealias(resolve, *resource),
+                };
+                let name = self.gen.qualified_name(resource);
+                match (handle, self.gen.gen.resources[&resource]) {
+                    // Borrows of resources we export are passed as their
+                    // representation, which is a pointer to them.
+                    (Handle::Borrow(_), Direction::Export) => {
+                        results.push(format!("(*({name} *) (uintptr_t) ({op}))"));
+                    }
+                    // Borrows of imported resources passed to exports take
+                    // ownership of their handle so that it's dropped once the
+                    // export returns, which ends the borrow.
+                    _ => results.push(format!("{name}::from_handle({op})")),
+                }
+            }
+
+            Instruction::FlagsLower { flags, .. } => match flags.repr() {
+                FlagsRepr::U32(n) if n > 2 => {
+                    let len = flags.flags.len();
+                    let tmp = self.locals.tmp("flags");
+                    uwriteln!(
+                        self.src,
+                        "std::bitset<{len}> {tmp} = ({}).bits;",
+                        operands[0]
+                    );
+                    for i in 0..n {
+                        results.push(format!(
+                            "(int32_t) (uint32_t) (({tmp} >> {}) & std::bitset<{len}>(0xffffffff)).to_ulong()",
+                            i * 32
+                        ));
+                    }
+                }
+                _ => match flags_repr(flags) {
+                    Int::U8 | Int::U16 | Int::U32 => {
+                        results.push(format!("(int32_t) ({})", operands[0]));
+                    }
+                    Int::U64 => {
+                        let tmp = self.locals.tmp("flags");
+                        uwriteln!(self.src, "uint64_t {tmp} = (uint64_t) ({});", operands[0]);
+                        results.push(format!("(int32_t) ({tmp} & 0xffffffff)"));
+                        results.push(format!("(int32_t) ({tmp} >> 32)"));
+                    }
+                },
+            },
+            Instruction::FlagsLift { flags, ty, .. } => {
+                let name = self.gen.type_id_name(*ty, TypeMode::Owned);
+                if let FlagsRepr::U32(n) = flags.repr() {
+                    if n > 2 {
+                        let len = flags.flags.len();
+                        let bits = operands
+                            .iter()
+                            .enumerate()
+                            .map(|(i, op)| {
+                                format!("(std::bitset<{len}>((uint32_t) ({op})) << {})", i * 32)
+                            })
+                            .collect::<Vec<_>>()
+                            .join(" | ");
+                        results.push(format!("{name}{{{bits}}}"));
+                        return;
+                    }
+                }
+                match flags_repr(flags) {
+                    Int::U8 | Int::U16 | Int::U32 => {
+                        results.push(format!("({name}) ({})", operands[0]));
+                    }
+                    Int::U64 => {
+                        let op0 = &operands[0];
+                        let op1 = &operands[1];
+                        results.push(format!(
+                            "({name}) ((uint64_t) (uint32_t) ({op0}) | ((uint64_t) (uint32_t) ({op1}) << 32))"
+                        ));
+                    }
+                }
+            }
+
+            Instruction::VariantPayloadName => {
+                let name = self.locals.tmp("payload");
+                results.push(name.clone());
+                self.payloads.push(name);
+            }
+
+            Instruction::VariantLower {
+                variant,
+                results: result_types,
+                ..
+            } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+                let payloads = self
+                    .payloads
+                    .drain(self.payloads.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+                let variant_results = self.declare_results("variant", result_types);
+                let op = self.bind("variant", &operands[0]);
+
+                uwriteln!(self.src, "switch ({op}.variants.index()) {{");
+                for (i, ((case, (block, block_results)), payload)) in
+                    variant.cases.iter().zip(blocks).zip(payloads).enumerate()
+                {
+                    uwriteln!(self.src, "case {i}: {{");
+                    if case.ty.is_some() {
+                        uwriteln!(
+                            self.src,
+                            "[[maybe_unused]] auto&& {payload} = std::get<{i}>({op}.variants).value;"
+                        );
+                    }
+                    self.src.push_str(&block);
+                    for (name, result) in variant_results.iter().zip(&block_results) {
+                        uwriteln!(self.src, "{name} = {result};");
+                    }
+                    self.src.push_str("break;\n}\n");
+                }
+                self.src.push_str("}\n");
+                results.extend(variant_results);
+            }
+            Instruction::VariantLift { variant, ty, .. } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+                let name = self.gen.type_id_name(*ty, TypeMode::Owned);
+                let variant_name = self.gen.class_name(*ty);
+                let result = self.locals.tmp("variant");
+                uwriteln!(self.src, "auto {result} = [&]() -> {name} {{");
+                uwriteln!(self.src, "switch ({}) {{", operands[0]);
+                for (i, (case, (block, block_results))) in
+                    variant.cases.iter().zip(blocks).enumerate()
+                {
+                    let case_name = variant_case_name(&variant_name, &case.name);
+                    uwriteln!(self.src, "case {i}: {{");
+                    self.src.push_str(&block);
+                    match &case.ty {
+                        Some(ty) => {
+                            let value = self.move_op(&block_results[0], ty);
+                            uwriteln!(self.src, "return {name}{{{name}::{case_name}{{{value}}}}};");
+                        }
+                        None => uwriteln!(self.src, "return {name}{{{name}::{case_name}{{}}}};"),
+                    }
+                    self.src.push_str("}\n");
+                }
+                uwriteln!(self.src, "default: std::abort();");
+                uwriteln!(self.src, "}}");
+                uwriteln!(self.src, "}}();");
+                results.push(result);
+            }
+
+            Instruction::EnumLower { .. } => {
+                results.push(format!("(int32_t) ({})", operands[0]));
+            }
+            Instruction::EnumLift { ty, .. } => {
+                let name = self.gen.type_id_name(*ty, TypeMode::Owned);
+                results.push(format!("({name}) ({})", operands[0]));
+            }
+
+            Instruction::OptionLower {
+                results: result_types,
+                ..
+            } => {
+                let (some, some_results) = self.blocks.pop().unwrap();
+                let (none, none_results) = self.blocks.pop().unwrap();
+                let some_payload = self.payloads.pop().unwrap();
+                let _none_payload = self.payloads.pop().unwrap();
+                let option_results = self.declare_results("option", result_types);
+                let op = self.bind("option", &operands[0]);
+
+                uwriteln!(self.src, "if ({op}.has_value()) {{");
+                uwriteln!(self.src, "[[maybe_unused]] auto&& {some_payload} = *{op};");
+                self.src.push_str(&some);
+                for (name, result) in option_results.iter().zip(&some_results) {
+                    uwriteln!(self.src, "{name} = {result};");
+                }
+                uwriteln!(self.src, "}} else {{");
+                self.src.push_str(&none);
+                for (name, result) in option_results.iter().zip(&none_results) {
+                    uwriteln!(self.src, "{name} = {result};");
+                }
+                uwriteln!(self.src, "}}");
+                results.extend(option_results);
+            }
+            Instruction::OptionLift { payload, ty } => {
+                let (some, some_results) = self.blocks.pop().unwrap();
+                let (none, none_results) = self.blocks.pop().unwrap();
+                assert!(none_results.is_empty());
+                assert!(some_results.len() == 1);
+
+                let name = self.gen.type_id_name(*ty, TypeMode::Owned);
+                let value = self.move_op(&some_results[0], payload);
+                let result = self.locals.tmp("option");
+                self.src.push_str(&format!(
+                    "
+                        auto {result} = [&]() -> {name} {{
+                            switch ({}) {{
+                                case 0: {{
+                                    {none}\
+                                    return std::nullopt;
+                                }}
+                                case 1: {{
+                                    {some}\
+                                    return {name}({value});
+                                }}
+                                default: std::abort();
+                            }}
+                        }}();
+                    ",
+                    operands[0],
+                ));
+                results.push(result);
+            }
+
+            Instruction::ResultLower {
+                result,
+                results: result_types,
+                ..
+            } => {
+                let (err, err_results) = self.blocks.pop().unwrap();
+                let (ok, ok_results) = self.blocks.pop().unwrap();
+                let err_payload = self.payloads.pop().unwrap();
+                let ok_payload = self.payloads.pop().unwrap();
+                let variant_results = self.declare_results("result", result_types);
+                let op = self.bind("result", &operands[0]);
+
+                uwriteln!(self.src, "if ({op}.has_value()) {{");
+                if result.ok.is_some() {
+                    uwriteln!(self.src, "[[maybe_unused]] auto&& {ok_payload} = *{op};");
+                }
+                self.src.push_str(&ok);
+                for (name, result) in variant_results.iter().zip(&ok_results) {
+                    uwriteln!(self.src, "{name} = {result};");
+                }
+                uwriteln!(self.src, "}} else {{");
+                if result.err.is_some() {
+                    uwriteln!(
+                        self.src,
+                        "[[maybe_unused]] auto&& {err_payload} = {op}.error();"
+                    );
+                }
+                self.src.push_str(&err);
+                for (name, result) in variant_results.iter().zip(&err_results) {
+                    uwriteln!(self.src, "{name} = {result};");
+                }
+                uwriteln!(self.src, "}}");
+                results.extend(variant_results);
+            }
+            Instruction::ResultLift { result, ty } => {
+                let (err, err_results) = self.blocks.pop().unwrap();
+                assert!(err_results.len() == (result.err.is_some() as usize));
+                let (ok, ok_results) = self.blocks.pop().unwrap();
+                assert!(ok_results.len() == (result.ok.is_some() as usize));
+
+                let name = self.gen.type_id_name(*ty, TypeMode::Owned);
+                let ok_value = match &result.ok {
+                    Some(ty) => format!("{name}({})", self.move_op(&ok_results[0], ty)),
+                    None => format!("{name}()"),
+                };
+                let err_value = match &result.err {
+                    Some(ty) => {
+                        let ty_name = self.type_name(ty);
+                        let value = self.move_op(&err_results[0], ty);
+                        format!("wit::unexpected<{ty_name}>({value})")
+                    }
+                    None => "wit::unexpected<std::monostate>(std::monostate{})".to_string(),
+                };
+                let result = self.locals.tmp("result");
+                self.src.push_str(&format!(
+                    "
+                        auto {result} = [&]() -> {name} {{
+                            switch ({}) {{
+                                case 0: {{
+                                    {ok}\
+                                    return {ok_value};
+                                }}
+                                case 1: {{
+                                    {err}\
+                                    return {err_value};
+                                }}
+                                default: std::abort();
+                            }}
+                        }}();
+                    ",
+                    operands[0],
+                ));
+                results.push(result);
+            }
+
+            Instruction::ListCanonLower { element, realloc } => {
+                let vec = self.bind("vec", &operands[0]);
+                match realloc {
+                    None => {
+                        results.push(format!("(uint8_t *) {vec}.data()"));
+                        results.push(format!("{vec}.size()"));
+                    }
+                    Some(_) => {
+                        let size = self.gen.gen.sizes.size(element).size_wasm32();
+                        let align = self.gen.gen.sizes.align(element).align_wasm32();
+                        let ptr = self.locals.tmp("ptr");
+                        let alloc = self.alloc(&format!("{vec}.size() * {size}"), align);
+                        self.src.push_str(&format!(
+                            "
+                                uint8_t *{ptr} = {alloc};
+                                if ({vec}.size() > 0) {{
+                                    std::memcpy({ptr}, {vec}.data(), {vec}.size() * {size});
+                                }}
+                            "
+                        ));
+                        results.push(ptr);
+                        results.push(format!("{vec}.size()"));
+                    }
+                }
+            }
+            Instruction::StringLower { realloc } => {
+                let str = self.bind("str", &operands[0]);
+                match realloc {
+                    None => {
+                        results.push(format!("(uint8_t *) {str}.data()"));
+                        results.push(format!("{str}.size()"));
+                    }
+                    Some(_) => {
+                        let ptr = self.locals.tmp("ptr");
+                        let alloc = self.alloc(&format!("{str}.size()"), 1);
+                        self.src.push_str(&format!(
+                            "
+                                uint8_t *{ptr} = {alloc};
+                                if ({str}.size() > 0) {{
+                                    std::memcpy({ptr}, {str}.data(), {str}.size());
+                                }}
+                            "
+                        ));
+                        results.push(ptr);
+                        results.push(format!("{str}.size()"));
+                    }
+                }
+            }
+            Instruction::ListLower { element, .. } => {
+                let (body, body_results) = self.blocks.pop().unwrap();
+                assert!(body_results.is_empty());
+                let vec = self.bind("vec", &operands[0]);
+                let len = self.locals.tmp("len");
+                let ptr = self.locals.tmp("ptr");
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.gen.sizes.align(element).align_wasm32();
+                let alloc = self.alloc(&format!("{len} * {size}"), align);
+                uwriteln!(self.src, "size_t {len} = {vec}.size();");
+                uwriteln!(self.src, "uint8_t *{ptr} = {alloc};");
+                if self.in_import() {
+                    uwriteln!(self.src, "cleanup_list.push_back({ptr});");
+                }
+                self.src.push_str(&format!(
+                    "
+                        for (size_t i = 0; i < {len}; i++) {{
+                            uint8_t *base = {ptr} + i * {size};
+                            (void) base;
+                            [[maybe_unused]] auto&& e = {vec}[i];
+                            {body}\
+                        }}
+                    "
+                ));
+                results.push(ptr);
+                results.push(len);
+            }
+
+            Instruction::ListCanonLift { element, .. } => {
+                let ty = self.type_name(element);
+                let ptr = self.locals.tmp("ptr");
+                let len = self.locals.tmp("len");
+                let vec = self.locals.tmp("vec");
+                self.src.push_str(&format!(
+                    "
+                        uint8_t *{ptr} = {};
+                        size_t {len} = {};
+                        std::vector<{ty}> {vec}(({ty} *) {ptr}, ({ty} *) {ptr} + {len});
+                        if ({len} > 0) {{
+                            std::free({ptr});
+                        }}
+                    ",
+                    operands[0], operands[1],
+                ));
+                results.push(vec);
+            }
+            Instruction::StringLift => {
+                let ptr = self.locals.tmp("ptr");
+                let len = self.locals.tmp("len");
+                let str = self.locals.tmp("str");
+                self.src.push_str(&format!(
+                    "
+                        uint8_t *{ptr} = {};
+                        size_t {len} = {};
+                        std::string {str}((char const *) {ptr}, {len});
+                        if ({len} > 0) {{
+                            std::free({ptr});
+                        }}
+                    ",
+                    operands[0], operands[1],
+                ));
+                results.push(str);
+            }
+            Instruction::ListLift { element, .. } => {
+                let (body, body_results) = self.blocks.pop().unwrap();
+                assert!(body_results.len() == 1);
+                let ty = self.type_name(element);
+                let value = self.move_op(&body_results[0], element);
+                let ptr = self.locals.tmp("ptr");
+                let len = self.locals.tmp("len");
+                let vec = self.locals.tmp("vec");
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                self.src.push_str(&format!(
+                    "
+                        uint8_t *{ptr} = {};
+                        size_t {len} = {};
+                        std::vector<{ty}> {vec};
+                        {vec}.reserve({len});
+                        for (size_t i = 0; i < {len}; i++) {{
+                            uint8_t *base = {ptr} + i * {size};
+                            (void) base;
+                            {body}\
+                            {vec}.push_back({value});
+                        }}
+                        if ({len} > 0) {{
+                            std::free({ptr});
+                        }}
+                    ",
+                    operands[0], operands[1],
+                ));
+                results.push(vec);
+            }
+
+            Instruction::IterElem { .. } => results.push("e".to_string()),
+            Instruction::IterBasePointer => results.push("base".to_string()),
+
+            Instruction::CallWasm { sig, .. } => {
+                let args = operands.join(", ");
+                match sig.results.len() {
+                    0 => uwriteln!(self.src, "{}({args});", self.func_to_call),
+                    1 => {
+                        let ret = self.locals.tmp("ret");
+                        uwriteln!(
+                            self.src,
+                            "{} {ret} = {}({args});",
+                            wasm_type(sig.results[0]),
+                            self.func_to_call
+                        );
+                        results.push(ret);
+                    }
+                    _ => unreachable!("multiple results are returned through a return pointer"),
+                }
+            }
+
+            Instruction::CallInterface { func, .. } => {
+                let mut operands = operands.iter();
+                let callee = match func.kind {
+                    FunctionKind::Method(_) => {
+                        format!("{}.{}", operands.next().unwrap(), self.func_to_call)
+                    }
+                    _ => self.func_to_call.clone(),
+                };
+                let skip = matches!(func.kind, FunctionKind::Method(_)) as usize;
+                let args = operands
+                    .zip(func.params.iter().skip(skip))
+                    .map(|(op, (_, ty))| self.move_op(op, ty))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                match func.results.len() {
+                    0 => uwriteln!(self.src, "{callee}({args});"),
+                    1 => {
+                        let ret = self.locals.tmp("ret");
+                        uwriteln!(self.src, "auto {ret} = {callee}({args});");
+                        results.push(ret);
+                    }
+                    n => {
+                        let rets = (0..n).map(|_| self.locals.tmp("ret")).collect::<Vec<_>>();
+                        uwriteln!(self.src, "auto [{}] = {callee}({args});", rets.join(", "));
+                        results.extend(rets);
+                    }
+                }
+            }
+
+            Instruction::Return { amt, func } => {
+                if self.needs_cleanup_list {
+                    self.src.push_str(
+                        "
+                            for (void *ptr : cleanup_list) {
+                                std::free(ptr);
+                            }
+                        ",
+                    );
+                }
+                if !self.in_import() {
+                    assert!(*amt <= 1);
+                    if *amt == 1 {
+                        uwriteln!(self.src, "return {};", operands[0]);
+                    }
+                } else if self.in_constructor {
+                    uwriteln!(self.src, "*this = {};", operands[0]);
+                } else {
+                    let types = func.results.iter_types().collect::<Vec<_>>();
+                    match types.len() {
+                        0 => {}
+                        1 => uwriteln!(self.src, "return {};", operands[0]),
+                        _ => {
+                            let (ret, _) = self.gen.signature(func, TypeMode::ImportParam);
+                            let ops = operands
+                                .iter()
+                                .zip(types)
+                                .map(|(op, ty)| self.move_op(op, ty))
+                                .collect::<Vec<_>>();
+                            uwriteln!(self.src, "return {ret}({});", ops.join(", "));
+                        }
+                    }
+                }
+            }
+
+            Instruction::I32Load { offset } => self.load("int32_t", *offset, operands, results),
+            Instruction::I64Load { offset } => self.load("int64_t", *offset, operands, results),
+            Instruction::F32Load { offset } => self.load("float", *offset, operands, results),
+            Instruction::F64Load { offset } => self.load("double", *offset, operands, results),
+            Instruction::PointerLoad { offset } => {
+                self.load("uint8_t *", *offset, operands, results)
+            }
+            Instruction::LengthLoad { offset } => self.load("size_t", *offset, operands, results),
+            Instruction::I32Store { offset } => self.store("int32_t", *offset, operands),
+            Instruction::I64Store { offset } => self.store("int64_t", *offset, operands),
+            Instruction::F32Store { offset } => self.store("float", *offset, operands),
+            Instruction::F64Store { offset } => self.store("double", *offset, operands),
+            Instruction::I32Store8 { offset } => self.store("int8_t", *offset, operands),
+            Instruction::I32Store16 { offset } => self.store("int16_t", *offset, operands),
+            Instruction::PointerStore { offset } => self.store("uint8_t *", *offset, operands),
+            Instruction::LengthStore { offset } => self.store("size_t", *offset, operands),
+
+            Instruction::I32Load8U { offset } => {
+                self.load_ext("uint8_t", *offset, operands, results)
+            }
+            Instruction::I32Load8S { offset } => {
+                self.load_ext("int8_t", *offset, operands, results)
+            }
+            Instruction::I32Load16U { offset } => {
+                self.load_ext("uint16_t", *offset, operands, results)
+            }
+            Instruction::I32Load16S { offset } => {
+                self.load_ext("int16_t", *offset, operands, results)
+            }
+
+            Instruction::Malloc { .. } => unreachable!(),
+
+            Instruction::GuestDeallocate { .. } => {
+                uwriteln!(self.src, "std::free({});", operands[0]);
+            }
+            Instruction::GuestDeallocateString => {
+                uwriteln!(self.src, "if (({}) > 0) {{", operands[1]);
+                uwriteln!(self.src, "std::free({});", operands[0]);
+                uwriteln!(self.src, "}}");
+            }
+            Instruction::GuestDeallocateVariant { blocks } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - blocks..)
+                    .collect::<Vec<_>>();
+
+                uwriteln!(self.src, "switch ((int32_t) {}) {{", operands[0]);
+                for (i, (block, results)) in blocks.into_iter().enumerate() {
+                    assert!(results.is_empty());
+                    uwriteln!(self.src, "case {i}: {{");
+                    self.src.push_str(&block);
+                    self.src.push_str("break;\n}\n");
+                }
+                self.src.push_str("}\n");
+            }
+            Instruction::GuestDeallocateList { element } => {
+                let (body, results) = self.blocks.pop().unwrap();
+                assert!(results.is_empty());
+                let len = self.locals.tmp("len");
+                uwriteln!(self.src, "size_t {len} = {};", operands[1]);
+                uwriteln!(self.src, "if ({len} > 0) {{");
+                let ptr = self.locals.tmp("ptr");
+                uwriteln!(self.src, "uint8_t *{ptr} = {};", operands[0]);
+                uwriteln!(self.src, "for (size_t i = 0; i < {len}; i++) {{");
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                uwriteln!(self.src, "uint8_t *base = {ptr} + i * {size};");
+                uwriteln!(self.src, "(void) base;");
+                uwrite!(self.src, "{body}");
+                uwriteln!(self.src, "}}");
+                uwriteln!(self.src, "std::free({ptr});");
+                uwriteln!(self.src, "}}");
+            }
+
+            Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. }
+            | Instruction::Flush { .. } => {
+                unreachable!("async functions are never requested by this generator")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+        }
+    }
+}
+
+fn perform_cast(op: &str, cast: &Bitcast) -> String {
+    match cast {
+        Bitcast::I32ToF32 | Bitcast::I64ToF32 => {
+            format!("std::bit_cast<float>((uint32_t) ({op}))")
+        }
+        Bitcast::F32ToI32 => format!("std::bit_cast<int32_t>({op})"),
+        Bitcast::F32ToI64 => format!("(int64_t) std::bit_cast<uint32_t>({op})"),
+        Bitcast::I64ToF64 => format!("std::bit_cast<double>((int64_t) ({op}))"),
+        Bitcast::F64ToI64 => format!("std::bit_cast<int64_t>({op})"),
+        Bitcast::I32ToI64 | Bitcast::LToI64 => format!("(int64_t) ({op})"),
+        Bitcast::PToP64 => format!("(int64_t) (uintptr_t) ({op})"),
+        Bitcast::I64ToI32 | Bitcast::LToI32 => format!("(int32_t) ({op})"),
+        Bitcast::I64ToL | Bitcast::I32ToL => format!("(size_t) ({op})"),
+        // P64 is currently represented as int64_t, so no conversion is needed.
+        Bitcast::I64ToP64 | Bitcast::P64ToI64 => op.to_string(),
+        Bitcast::P64ToP | Bitcast::I32ToP | Bitcast::LToP => {
+            format!("(uint8_t *) (uintptr_t) ({op})")
+        }
+        Bitcast::PToI32 => format!("(int32_t) (uintptr_t) ({op})"),
+        Bitcast::PToL => format!("(size_t) (uintptr_t) ({op})"),
+        Bitcast::None => op.to_string(),
+        Bitcast::Sequence(sequence) => {
+            let [first, second] = &**sequence;
+            let inner = perform_cast(op, first);
+            perform_cast(&inner, second)
+        }
+    }
+}
+
+pub(super) fn wasm_type(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 => "int32_t",
+        WasmType::I64 => "int64_t",
+        WasmType::F32 => "float",
+        WasmType::F64 => "double",
+        WasmType::Pointer => "uint8_t *",
+        WasmType::PointerOrI64 => "int64_t",
+        WasmType::Length => "size_t",
+    }
+}
@@ -0,0 +1,1260 @@
+mod bindgen;
+
+use anyhow::Result;
+use bindgen::{wasm_type, FunctionBindgen};
+use heck::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+use wit_bindgen_c::{component_type_object, flags_repr, int_repr, to_c_ident};
+use wit_bindgen_core::abi::{self, AbiVariant, LiftLower};
+use wit_bindgen_core::{
+    dealias, uwriteln, wit_parser::*, Direction, Files, Ns, Source, WorldGenerator,
+};
+use wit_component::StringEncoding;
+
+/// Support types shared by all generated headers.
+const RUNTIME: &str = include_str!("wit.h");
+
+#[derive(Default)]
+struct Cpp {
+    opts: Opts,
+    world: String,
+    sizes: SizeAlign,
+    h: Source,
+    cpp: Source,
+    names: Ns,
+    return_pointer_area_size: usize,
+    return_pointer_area_align: usize,
+
+    /// The C++ namespace of each interface, which changes to the exported
+    /// namespace once an interface which is both imported and exported is
+    /// exported.
+    interface_namespaces: HashMap<InterfaceId, String>,
+    /// Whether each resource is imported or exported, which changes in the
+    /// same way as `interface_namespaces`.
+    resources: HashMap<TypeId, Direction>,
+}
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct Opts {
+    /// Skip generating an object file which contains type information for the
+    /// world that is being generated.
+    #[cfg_attr(feature = "clap", arg(long, default_value_t = false))]
+    pub no_object_file: bool,
+
+    /// Add the specified suffix to the name of the custom section containing
+    /// the component type.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub type_section_suffix: Option<String>,
+}
+
+impl Opts {
+    pub fn build(&self) -> Box<dyn WorldGenerator> {
+        Box::new(Cpp {
+            opts: self.clone(),
+            ..Cpp::default()
+        })
+    }
+}
+
+/// How a type is used, which determines how it's spelled in C++.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TypeMode {
+    /// A value owned by its holder, such as a field or a result.
+    Owned,
+    /// A parameter of an imported function, which only borrows its
+    /// arguments unless they contain owned handles.
+    ImportParam,
+    /// A parameter of an exported function, which is passed ownership of
+    /// its arguments.
+    ExportParam,
+}
+
+impl WorldGenerator for Cpp {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        wit_bindgen_core::reject_futures_and_streams(resolve, world, "C++")
+    }
+
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+        self.world = resolve.worlds[world].name.clone();
+        self.sizes.fill(resolve);
+    }
+
+    fn import_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let ns = self.interface_namespace(resolve, name, Direction::Import);
+        self.interface_namespaces.insert(id, ns.clone());
+        let module = resolve.name_world_key(name);
+        let iface = &resolve.interfaces[id];
+        let funcs = iface.functions.values().collect::<Vec<_>>();
+        let mut gen = self.interface(resolve, ns, Direction::Import, Some(module));
+        gen.define_types(
+            iface.types.values().copied(),
+            TypeOwner::Interface(id),
+            &funcs,
+        );
+        gen.import_funcs(&funcs);
+        gen.finish();
+        Ok(())
+    }
+
+    fn import_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) {
+        let ns = to_c_ident(&self.world);
+        let funcs = funcs.iter().map(|(_, f)| *f).collect::<Vec<_>>();
+        let mut gen = self.interface(resolve, ns, Direction::Import, Some("$root".to_string()));
+        gen.import_funcs(&funcs);
+        gen.finish();
+    }
+
+    fn import_types(
+        &mut self,
+        resolve: &Resolve,
+        world: WorldId,
+        types: &[(&str, TypeId)],
+        _files: &mut Files,
+    ) {
+        let ns = to_c_ident(&self.world);
+        let mut gen = self.interface(resolve, ns, Direction::Import, Some("$root".to_string()));
+        gen.define_types(
+            types.iter().map(|(_, id)| *id),
+            TypeOwner::World(world),
+            &[],
+        );
+        gen.finish();
+    }
+
+    fn export_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let ns = self.interface_namespace(resolve, name, Direction::Export);
+        self.interface_namespaces.insert(id, ns.clone());
+        let module = resolve.name_world_key(name);
+        let iface = &resolve.interfaces[id];
+        let funcs = iface.functions.values().collect::<Vec<_>>();
+        let mut gen = self.interface(resolve, ns, Direction::Export, Some(module));
+        gen.define_types(
+            iface.types.values().copied(),
+            TypeOwner::Interface(id),
+            &funcs,
+        );
+        let types = iface.types.keys().map(|name| name.to_upper_camel_case());
+        gen.export_funcs(&funcs, types.collect());
+        gen.finish();
+        Ok(())
+    }
+
+    fn export_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) -> Result<()> {
+        let ns = format!("exports::{}", to_c_ident(&self.world));
+        let funcs = funcs.iter().map(|(_, f)| *f).collect::<Vec<_>>();
+        let mut gen = self.interface(resolve, ns, Direction::Export, None);
+        gen.export_funcs(&funcs, Vec::new());
+        gen.finish();
+        Ok(())
+    }
+
+    fn finish(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
+        let snake = self.world.to_snake_case();
+        let version = env!("CARGO_PKG_VERSION");
+
+        let mut h_str = Source::default();
+        wit_bindgen_core::generated_preamble(&mut h_str, version);
+        uwriteln!(
+            h_str,
+            "#ifndef __BINDINGS_{0}_CPP_H\n#define __BINDINGS_{0}_CPP_H\n",
+            self.world.to_shouty_snake_case(),
+        );
+        h_str.as_mut_string().push_str(RUNTIME);
+        h_str.push_str(self.h.as_str());
+        uwriteln!(h_str, "\n#endif");
+
+        let mut cpp_str = Source::default();
+        wit_bindgen_core::generated_preamble(&mut cpp_str, version);
+        uwriteln!(cpp_str, "#include \"{snake}_cpp.h\"");
+        uwriteln!(cpp_str, "#include <cstdlib>");
+        uwriteln!(cpp_str, "#include <cstring>");
+
+        let linking_symbol = component_type_object::linking_symbol(&self.world);
+        cpp_str.push_str(&format!(
+            "
+                // Ensure that the *_component_type.o object is linked in
+                extern \"C\" void {linking_symbol}(void);
+                extern \"C\" void {linking_symbol}_public_use_in_this_compilation_unit(void) {{
+                    {linking_symbol}();
+                }}
+            ",
+        ));
+        cpp_str.push_str(
+            r#"
+                // Canonical ABI intrinsics
+                extern "C" __attribute__((__weak__, __export_name__("cabi_realloc")))
+                void *cabi_realloc(void *ptr, size_t old_size, size_t align, size_t new_size) {
+                    (void) old_size;
+                    if (new_size == 0) return (void*) align;
+                    void *ret = realloc(ptr, new_size);
+                    if (!ret) abort();
+                    return ret;
+                }
+            "#,
+        );
+
+        // Declare a statically-allocated return area, if needed. Only exports
+        // use this since imports allocate their return area on the stack.
+        if self.return_pointer_area_size > 0 {
+            cpp_str.push_str(&format!(
+                "
+                    alignas({}) static uint8_t RET_AREA[{}];
+                ",
+                self.return_pointer_area_align, self.return_pointer_area_size,
+            ));
+        }
+        cpp_str.push_str(self.cpp.as_str());
+
+        files.push(&format!("{snake}_cpp.h"), h_str.as_bytes());
+        files.push(&format!("{snake}.cpp"), cpp_str.as_bytes());
+        if !self.opts.no_object_file {
+            files.push(
+                &format!("{snake}_component_type.o"),
+                component_type_object::object(
+                    resolve,
+                    id,
+                    &self.world,
+                    StringEncoding::UTF8,
+                    self.opts.type_section_suffix.as_deref(),
+                )?
+                .as_slice(),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Cpp {
+    fn interface<'a>(
+        &'a mut self,
+        resolve: &'a Resolve,
+        ns: String,
+        direction: Direction,
+        module: Option<String>,
+    ) -> InterfaceGenerator<'a> {
+        InterfaceGenerator {
+            gen: self,
+            resolve,
+            ns,
+            direction,
+            module,
+            h: Source::default(),
+            cpp: Source::default(),
+        }
+    }
+
+    /// Returns the namespace, without a leading `::`, bindings for the
+    /// interface `key` are placed in.
+    fn interface_namespace(
+        &self,
+        resolve: &Resolve,
+        key: &WorldKey,
+        direction: Direction,
+    ) -> String {
+        let mut path = Vec::new();
+        if direction == Direction::Export {
+            path.push("exports".to_string());
+        }
+        match key {
+            WorldKey::Name(name) => {
+                path.push(to_c_ident(&self.world));
+                path.push(to_c_ident(name));
+            }
+            WorldKey::Interface(id) => {
+                let iface = &resolve.interfaces[*id];
+                let pkg = &resolve.packages[iface.package.unwrap()].name;
+                path.push(to_c_ident(&pkg.namespace));
+                path.push(to_c_ident(&pkg.name));
+                // Only disambiguate by version if more than one version of
+                // this package is in use.
+                if let Some(version) = &pkg.version {
+                    let versions = resolve
+                        .packages
+                        .iter()
+                        .filter(|(_, p)| {
+                            p.name.namespace == pkg.namespace && p.name.name == pkg.name
+                        })
+                        .count();
+                    if versions > 1 {
+                        let version = version.to_string().replace(['.', '-', '+'], "_");
+                        path.push(format!("v{version}"));
+                    }
+                }
+                path.push(to_c_ident(iface.name.as_ref().unwrap()));
+            }
+        }
+        path.join("::")
+    }
+}
+
+struct InterfaceGenerator<'a> {
+    gen: &'a mut Cpp,
+    resolve: &'a Resolve,
+    /// The namespace everything is generated in, without a leading `::`.
+    ns: String,
+    direction: Direction,
+    /// The core wasm module imports come from, or the name of the exported
+    /// interface, which is `None` for functions exported by the world itself.
+    module: Option<String>,
+    h: Source,
+    cpp: Source,
+}
+
+impl InterfaceGenerator<'_> {
+    fn finish(self) {
+        for (dst, src) in [(&mut self.gen.h, &self.h), (&mut self.gen.cpp, &self.cpp)] {
+            if src.as_str().is_empty() {
+                continue;
+            }
+            dst.push_str(&format!("\nnamespace {} {{\n", self.ns));
+            dst.push_str(src.as_str());
+            dst.push_str(&format!("}} // namespace {}\n", self.ns));
+        }
+    }
+
+    /// Defines the named types among `types`, and the types they use, which
+    /// are owned by `owner`.
+    ///
+    /// Everything is forward-declared first, then resources are defined since
+    /// their members only need their signatures' types to be declared, and
+    /// then everything else is defined in dependency order.
+    fn define_types(
+        &mut self,
+        types: impl Iterator<Item = TypeId>,
+        owner: TypeOwner,
+        funcs: &[&Function],
+    ) {
+        let mut live = LiveTypes::default();
+        for id in types {
+            live.add_type_id(self.resolve, id);
+        }
+        let ids = live
+            .iter()
+            .filter(|id| {
+                let ty = &self.resolve.types[*id];
+                ty.owner == owner && ty.name.is_some()
+            })
+            .collect::<Vec<_>>();
+
+        for id in ids.iter() {
+            let ty = &self.resolve.types[*id];
+            let name = ty.name.as_ref().unwrap().to_upper_camel_case();
+            match &ty.kind {
+                TypeDefKind::Record(_) | TypeDefKind::Variant(_) => {
+                    uwriteln!(self.h, "struct {name};");
+                }
+                TypeDefKind::Resource => {
+                    self.gen.resources.insert(*id, self.direction);
+                    uwriteln!(self.h, "class {name};");
+                }
+                TypeDefKind::Enum(enum_) => {
+                    uwriteln!(self.h, "enum class {name} : {};", int_repr(enum_.tag()));
+                }
+                TypeDefKind::Flags(flags) => match flags.repr() {
+                    FlagsRepr::U32(n) if n > 2 => uwriteln!(self.h, "struct {name};"),
+                    _ => uwriteln!(
+                        self.h,
+                        "enum class {name} : {};",
+                        int_repr(flags_repr(flags))
+                    ),
+                },
+                _ => {}
+            }
+        }
+
+        for id in ids.iter() {
+            if let TypeDefKind::Resource = self.resolve.types[*id].kind {
+                let funcs = funcs
+                    .iter()
+                    .copied()
+                    .filter(|f| match f.kind {
+                        FunctionKind::Method(r)
+                        | FunctionKind::Static(r)
+                        | FunctionKind::Constructor(r) => r == *id,
+                        FunctionKind::Freestanding => false,
+                    })
+                    .collect::<Vec<_>>();
+                match self.direction {
+                    Direction::Import => self.define_imported_resource(*id, &funcs),
+                    Direction::Export => self.define_exported_resource(*id, &funcs),
+                }
+            }
+        }
+
+        for id in ids {
+            let ty = &self.resolve.types[id];
+            let name = ty.name.as_ref().unwrap().to_upper_camel_case();
+            match &ty.kind {
+                TypeDefKind::Record(record) => self.define_record(&name, record, &ty.docs),
+                TypeDefKind::Variant(variant) => self.define_variant(&name, variant, &ty.docs),
+                TypeDefKind::Enum(enum_) => self.define_enum(&name, enum_, &ty.docs),
+                TypeDefKind::Flags(flags) => self.define_flags(&name, flags, &ty.docs),
+                TypeDefKind::Resource => {}
+                TypeDefKind::Type(_)
+                | TypeDefKind::Handle(_)
+                | TypeDefKind::List(_)
+                | TypeDefKind::Option(_)
+                | TypeDefKind::Result(_)
+                | TypeDefKind::Tuple(_) => {
+                    let target = self.type_id_name(id, TypeMode::Owned);
+                    self.docs(&ty.docs);
+                    uwriteln!(self.h, "using {name} = {target};");
+                }
+                TypeDefKind::Future(_) | TypeDefKind::Stream(_) | TypeDefKind::Unknown => {
+                    unreachable!()
+                }
+            }
+        }
+    }
+
+    fn define_record(&mut self, name: &str, record: &Record, docs: &Docs) {
+        self.h.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.h, "struct {name} {{");
+        for field in record.fields.iter() {
+            self.docs(&field.docs);
+            let ty = self.type_name(&field.ty, TypeMode::Owned);
+            uwriteln!(self.h, "{ty} {};", to_c_ident(&field.name));
+        }
+        uwriteln!(self.h, "}};");
+    }
+
+    fn define_variant(&mut self, name: &str, variant: &Variant, docs: &Docs) {
+        self.h.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.h, "struct {name} {{");
+        let mut cases = Vec::new();
+        for case in variant.cases.iter() {
+            let case_name = variant_case_name(name, &case.name);
+            self.docs(&case.docs);
+            match &case.ty {
+                Some(ty) => {
+                    let ty = self.type_name(ty, TypeMode::Owned);
+                    uwriteln!(self.h, "struct {case_name} {{");
+                    uwriteln!(self.h, "{ty} value;");
+                    uwriteln!(self.h, "}};");
+                }
+                None => uwriteln!(self.h, "struct {case_name} {{}};"),
+            }
+            cases.push(case_name);
+        }
+        uwriteln!(self.h, "std::variant<{}> variants;", cases.join(", "));
+        uwriteln!(self.h, "}};");
+    }
+
+    fn define_enum(&mut self, name: &str, enum_: &Enum, docs: &Docs) {
+        self.h.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.h, "enum class {name} : {} {{", int_repr(enum_.tag()));
+        for case in enum_.cases.iter() {
+            self.docs(&case.docs);
+            uwriteln!(self.h, "{},", case.name.to_upper_camel_case());
+        }
+        uwriteln!(self.h, "}};");
+    }
+
+    fn define_flags(&mut self, name: &str, flags: &Flags, docs: &Docs) {
+        if let FlagsRepr::U32(n) = flags.repr() {
+            if n > 2 {
+                return self.define_large_flags(name, flags, docs);
+            }
+        }
+        let repr = int_repr(flags_repr(flags));
+        self.h.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.h, "enum class {name} : {repr} {{");
+        for (i, flag) in flags.flags.iter().enumerate() {
+            self.docs(&flag.docs);
+            uwriteln!(self.h, "{} = 1ULL << {i},", flag.name.to_upper_camel_case());
+        }
+        uwriteln!(self.h, "}};");
+        for op in ["|", "&", "^"] {
+            uwriteln!(
+                self.h,
+                "constexpr {name} operator{op}({name} a, {name} b) {{ return {name}({repr}(a) {op} {repr}(b)); }}"
+            );
+        }
+        uwriteln!(
+            self.h,
+            "constexpr {name} operator~({name} a) {{ return {name}(~{repr}(a)); }}"
+        );
+    }
+
+    /// Flags with more than 64 members don't fit in an integer, so they're
+    /// a `std::bitset` indexed by their `Flag` enumerators instead.
+    fn define_large_flags(&mut self, name: &str, flags: &Flags, docs: &Docs) {
+        let n = flags.flags.len();
+        self.h.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.h, "struct {name} {{");
+        uwriteln!(self.h, "enum Flag : std::size_t {{");
+        for (i, flag) in flags.flags.iter().enumerate() {
+            self.docs(&flag.docs);
+            uwriteln!(self.h, "{} = {i},", flag.name.to_upper_camel_case());
+        }
+        uwriteln!(self.h, "}};");
+        uwriteln!(self.h, "std::bitset<{n}> bits;");
+        uwriteln!(self.h, "bool operator==({name} const &) const = default;");
+        uwriteln!(self.h, "}};");
+        for op in ["|", "&", "^"] {
+            uwriteln!(
+                self.h,
+                "inline {name} operator{op}({name} a, {name} b) {{ return {name}{{a.bits {op} b.bits}}; }}"
+            );
+        }
+        uwriteln!(
+            self.h,
+            "inline {name} operator~({name} a) {{ return {name}{{~a.bits}}; }}"
+        );
+    }
+
+    fn define_imported_resource(&mut self, id: TypeId, funcs: &[&Function]) {
+        let ty = &self.resolve.types[id];
+        let name = ty.name.as_ref().unwrap();
+        let class = name.to_upper_camel_case();
+        self.h.push_str("\n");
+        self.docs(&ty.docs);
+        uwriteln!(self.h, "class {class} {{");
+        uwriteln!(self.h, "public:");
+        for func in funcs {
+            self.docs(&func.docs);
+            let (ret, params) = self.signature(func, TypeMode::ImportParam);
+            let params = params
+                .iter()
+                .map(|(ty, name)| format!("{ty} {name}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let name = self.func_name(func);
+            match func.kind {
+                FunctionKind::Constructor(_) => uwriteln!(self.h, "explicit {class}({params});"),
+                FunctionKind::Method(_) => uwriteln!(self.h, "{ret} {name}({params}) const;"),
+                FunctionKind::Static(_) => uwriteln!(self.h, "static {ret} {name}({params});"),
+                FunctionKind::Freestanding => unreachable!(),
+            }
+        }
+        self.h.push_str(&format!(
+            "
+                ~{class}();
+                {class}({class} &&other) noexcept;
+                {class} &operator=({class} &&other) noexcept;
+                {class}({class} const &) = delete;
+                {class} &operator=({class} const &) = delete;
+                // Returns a `borrow` of this resource, which doesn't own its
+                // handle.
+                {class} borrow() const;
+                // Returns this resource's handle.
+                int32_t handle() const;
+                // Releases ownership of this resource's handle and returns it.
+                int32_t into_handle();
+                // Takes ownership of the resource with handle `handle`.
+                static {class} from_handle(int32_t handle);
+                // Borrows the resource with handle `handle` without taking
+                // ownership of it.
+                static {class} from_borrow(int32_t handle);
+
+                private:
+                struct Raw {{
+                    int32_t handle;
+                    bool owned;
+                }};
+                explicit {class}(Raw raw);
+                int32_t handle_;
+                bool owned_;
+                }};
+            "
+        ));
+
+        let module = self.module.clone().unwrap();
+        let drop = self.gen.names.tmp(&format!(
+            "__wasm_import_{}_{}_drop",
+            self.ns.replace("::", "_"),
+            name.to_snake_case()
+        ));
+        self.cpp.push_str(&format!(
+            r#"
+                extern "C" __attribute__((__import_module__("{module}"), __import_name__("[resource-drop]{name}")))
+                void {drop}(int32_t handle);
+
+                {class}::{class}(Raw raw) : handle_(raw.handle), owned_(raw.owned) {{}}
+
+                {class}::~{class}() {{
+                    if (owned_) {{
+                        {drop}(handle_);
+                    }}
+                }}
+
+                {class}::{class}({class} &&other) noexcept : handle_(other.handle_), owned_(other.owned_) {{
+                    other.owned_ = false;
+                }}
+
+                {class} &{class}::operator=({class} &&other) noexcept {{
+                    if (this != &other) {{
+                        if (owned_) {{
+                            {drop}(handle_);
+                        }}
+                        handle_ = other.handle_;
+                        owned_ = other.owned_;
+                        other.owned_ = false;
+                    }}
+                    return *this;
+                }}
+
+                {class} {class}::borrow() const {{
+                    return {class}(Raw{{handle_, false}});
+                }}
+
+                int32_t {class}::handle() const {{
+                    return handle_;
+                }}
+
+                int32_t {class}::into_handle() {{
+                    owned_ = false;
+                    return handle_;
+                }}
+
+                {class} {class}::from_handle(int32_t handle) {{
+                    return {class}(Raw{{handle, true}});
+                }}
+
+                {class} {class}::from_borrow(int32_t handle) {{
+                    return {class}(Raw{{handle, false}});
+                }}
+            "#
+        ));
+    }
+
+    fn define_exported_resource(&mut self, id: TypeId, funcs: &[&Function]) {
+        let ty = &self.resolve.types[id];
+        let name = ty.name.as_ref().unwrap();
+        let class = name.to_upper_camel_case();
+        self.h.push_str("\n");
+        self.docs(&ty.docs);
+        uwriteln!(self.h, "class {class} {{");
+        uwriteln!(self.h, "public:");
+        uwriteln!(self.h, "virtual ~{class}() = default;");
+        for func in funcs {
+            if let FunctionKind::Method(_) = func.kind {
+                self.docs(&func.docs);
+                let (ret, params) = self.signature(func, TypeMode::ExportParam);
+                let params = params
+                    .iter()
+                    .map(|(ty, name)| format!("{ty} {name}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let name = self.func_name(func);
+                uwriteln!(self.h, "virtual {ret} {name}({params}) = 0;");
+            }
+        }
+        self.h.push_str(&format!(
+            "
+                // Transfers ownership of `self` to a handle, creating one if
+                // it doesn't have one yet, and returns the handle.
+                static int32_t into_handle(wit::Owned<{class}> self);
+                // Takes ownership of the resource with handle `handle`.
+                static wit::Owned<{class}> from_handle(int32_t handle);
+                // Drops the handle to `self` if it has one, which destroys it,
+                // and otherwise deletes it. Used by `wit::Owned`.
+                static void destroy({class} *self);
+
+                private:
+                int32_t handle_ = -1;
+                }};
+            "
+        ));
+
+        let module = self.module.clone().unwrap();
+        let prefix = format!("{}_{}", self.ns.replace("::", "_"), name.to_snake_case());
+        let new = self.gen.names.tmp(&format!("__wasm_import_{prefix}_new"));
+        let rep = self.gen.names.tmp(&format!("__wasm_import_{prefix}_rep"));
+        let drop = self.gen.names.tmp(&format!("__wasm_import_{prefix}_drop"));
+        let dtor = self.gen.names.tmp(&format!("__wasm_export_{prefix}_dtor"));
+        self.cpp.push_str(&format!(
+            r#"
+                extern "C" __attribute__((__import_module__("[export]{module}"), __import_name__("[resource-new]{name}")))
+                int32_t {new}(int32_t rep);
+
+                extern "C" __attribute__((__import_module__("[export]{module}"), __import_name__("[resource-rep]{name}")))
+                int32_t {rep}(int32_t handle);
+
+                extern "C" __attribute__((__import_module__("[export]{module}"), __import_name__("[resource-drop]{name}")))
+                void {drop}(int32_t handle);
+
+                extern "C" __attribute__((__export_name__("{module}#[dtor]{name}")))
+                void {dtor}(int32_t rep) {{
+                    delete ({class} *) (uintptr_t) rep;
+                }}
+
+                int32_t {class}::into_handle(wit::Owned<{class}> self) {{
+                    {class} *rep = self.release();
+                    if (rep->handle_ < 0) {{
+                        rep->handle_ = {new}((int32_t) (uintptr_t) rep);
+                    }}
+                    return rep->handle_;
+                }}
+
+                wit::Owned<{class}> {class}::from_handle(int32_t handle) {{
+                    {class} *rep = ({class} *) (uintptr_t) {rep}(handle);
+                    rep->handle_ = handle;
+                    return wit::Owned<{class}>(rep);
+                }}
+
+                void {class}::destroy({class} *self) {{
+                    if (self->handle_ >= 0) {{
+                        {drop}(self->handle_);
+                    }} else {{
+                        delete self;
+                    }}
+                }}
+            "#
+        ));
+    }
+
+    fn import_funcs(&mut self, funcs: &[&Function]) {
+        for func in funcs {
+            if let FunctionKind::Freestanding = func.kind {
+                self.h.push_str("\n");
+                self.docs(&func.docs);
+                let (ret, params) = self.signature(func, TypeMode::ImportParam);
+                let params = params
+                    .iter()
+                    .map(|(ty, name)| format!("{ty} {name}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                uwriteln!(self.h, "{ret} {}({params});", self.func_name(func));
+            }
+            self.import_func(func);
+        }
+    }
+
+    /// Defines the function, or resource member, which calls the imported
+    /// `func`.
+    fn import_func(&mut self, func: &Function) {
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestImport, func);
+        let module = self.module.clone().unwrap();
+        let import_name = self.gen.names.tmp(&format!(
+            "__wasm_import_{}_{}",
+            self.ns.replace("::", "_"),
+            func.name.to_snake_case()
+        ));
+
+        // Declare the core wasm import with its raw signature ...
+        uwriteln!(
+            self.cpp,
+            "\nextern \"C\" __attribute__((__import_module__(\"{module}\"), __import_name__(\"{}\")))",
+            func.name
+        );
+        let params = sig
+            .params
+            .iter()
+            .map(|p| wasm_type(*p))
+            .collect::<Vec<_>>()
+            .join(", ");
+        uwriteln!(
+            self.cpp,
+            "{} {import_name}({params});",
+            wasm_result_type(&sig)
+        );
+
+        // ... and then define the function calling it.
+        let (ret, params) = self.signature(func, TypeMode::ImportParam);
+        let param_list = params
+            .iter()
+            .map(|(ty, name)| format!("{ty} {name}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let name = self.func_name(func);
+        self.cpp.push_str("\n");
+        match func.kind {
+            FunctionKind::Freestanding => {
+                uwriteln!(self.cpp, "{ret} {name}({param_list}) {{");
+            }
+            FunctionKind::Method(id) => {
+                let class = self.class_name(id);
+                uwriteln!(self.cpp, "{ret} {class}::{name}({param_list}) const {{");
+            }
+            FunctionKind::Static(id) => {
+                let class = self.class_name(id);
+                uwriteln!(self.cpp, "{ret} {class}::{name}({param_list}) {{");
+            }
+            FunctionKind::Constructor(id) => {
+                let class = self.class_name(id);
+                uwriteln!(
+                    self.cpp,
+                    "{class}::{class}({param_list}) : {class}(Raw{{-1, false}}) {{"
+                );
+            }
+        }
+
+        let mut f = FunctionBindgen::new(self, import_name);
+        if let FunctionKind::Method(_) = func.kind {
+            f.params.push("(*this)".to_string());
+        }
+        for (_, name) in params.iter() {
+            f.locals.insert(name).unwrap();
+            f.params.push(name.clone());
+        }
+        f.in_constructor = matches!(func.kind, FunctionKind::Constructor(_));
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            func,
+            &mut f,
+            false,
+        );
+        let FunctionBindgen {
+            src,
+            needs_cleanup_list,
+            import_return_pointer_area_size,
+            import_return_pointer_area_align,
+            ..
+        } = f;
+        if import_return_pointer_area_size > 0 {
+            uwriteln!(
+                self.cpp,
+                "alignas({import_return_pointer_area_align}) uint8_t ret_area[{import_return_pointer_area_size}];"
+            );
+        }
+        if needs_cleanup_list {
+            uwriteln!(self.cpp, "std::vector<void *> cleanup_list;");
+        }
+        self.cpp.push_str(&src);
+        uwriteln!(self.cpp, "}}");
+    }
+
+    /// Defines the exports `funcs` in a namespace which also contains the types
+    /// named `types`.
+    fn export_funcs(&mut self, funcs: &[&Function], types: Vec<String>) {
+        // Everything which isn't a resource method is implemented by the
+        // `Guest` class, which is renamed, along with `guest()`, if it would
+        // clash with a type or function.
+        let mut class = "Guest".to_string();
+        while types.contains(&class) {
+            class.push('_');
+        }
+        let mut getter = "guest".to_string();
+        while funcs.iter().any(|f| self.func_name(f) == getter) {
+            getter.push('_');
+        }
+        let guest_funcs = funcs
+            .iter()
+            .copied()
+            .filter(|f| !matches!(f.kind, FunctionKind::Method(_)))
+            .collect::<Vec<_>>();
+        if !guest_funcs.is_empty() {
+            self.h.push_str(&format!(
+                "
+                    // The implementation of these exports, which is returned by
+                    // `{getter}()`.
+                    class {class} {{
+                    public:
+                    virtual ~{class}() = default;
+                "
+            ));
+            for func in guest_funcs {
+                self.docs(&func.docs);
+                let (ret, params) = self.signature(func, TypeMode::ExportParam);
+                let params = params
+                    .iter()
+                    .map(|(ty, name)| format!("{ty} {name}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                uwriteln!(
+                    self.h,
+                    "virtual {ret} {}({params}) = 0;",
+                    self.func_name(func)
+                );
+            }
+            self.h.push_str(&format!(
+                "
+                    }};
+
+                    // Returns the implementation of these exports, which is
+                    // defined by the user.
+                    {class} &{getter}();
+                "
+            ));
+        }
+        for func in funcs {
+            self.export_func(func, &getter);
+        }
+    }
+
+    /// Defines the core wasm export `func`, which calls its implementation.
+    fn export_func(&mut self, func: &Function, getter: &str) {
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
+        let export_name = func.core_export_name(self.module.as_deref());
+        let export_fn = self.gen.names.tmp(&format!(
+            "__wasm_export_{}_{}",
+            self.ns.replace("::", "_"),
+            func.name.to_snake_case()
+        ));
+
+        uwriteln!(
+            self.cpp,
+            "\nextern \"C\" __attribute__((__export_name__(\"{export_name}\")))"
+        );
+        let callee = match func.kind {
+            FunctionKind::Method(_) => self.func_name(func),
+            _ => format!("{getter}().{}", self.func_name(func)),
+        };
+        let mut f = FunctionBindgen::new(self, callee);
+        let mut params = Vec::new();
+        for (i, param) in sig.params.iter().enumerate() {
+            let name = format!("arg{i}");
+            f.locals.insert(&name).unwrap();
+            params.push(format!("{} {name}", wasm_type(*param)));
+            f.params.push(name);
+        }
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestExport,
+            LiftLower::LiftArgsLowerResults,
+            func,
+            &mut f,
+            false,
+        );
+        let src = f.src;
+        uwriteln!(
+            self.cpp,
+            "{} {export_fn}({}) {{",
+            wasm_result_type(&sig),
+            params.join(", ")
+        );
+        self.cpp.push_str(&src);
+        uwriteln!(self.cpp, "}}");
+
+        if abi::guest_export_needs_post_return(self.resolve, func) {
+            uwriteln!(
+                self.cpp,
+                "\nextern \"C\" __attribute__((__weak__, __export_name__(\"cabi_post_{export_name}\")))"
+            );
+            let mut f = FunctionBindgen::new(self, String::new());
+            let mut params = Vec::new();
+            for (i, result) in sig.results.iter().enumerate() {
+                let name = format!("arg{i}");
+                f.locals.insert(&name).unwrap();
+                params.push(format!("{} {name}", wasm_type(*result)));
+                f.params.push(name);
+            }
+            abi::post_return(f.gen.resolve, func, &mut f);
+            let src = f.src;
+            uwriteln!(
+                self.cpp,
+                "void {export_fn}_post_return({}) {{",
+                params.join(", ")
+            );
+            self.cpp.push_str(&src);
+            uwriteln!(self.cpp, "}}");
+        }
+    }
+
+    /// Returns the return type and parameters of the C++ function for `func`,
+    /// which excludes `self` for methods.
+    fn signature(&self, func: &Function, mode: TypeMode) -> (String, Vec<(String, String)>) {
+        let ret = match func.results.len() {
+            0 => "void".to_string(),
+            1 => self.type_name(func.results.iter_types().next().unwrap(), TypeMode::Owned),
+            _ => format!(
+                "std::tuple<{}>",
+                func.results
+                    .iter_types()
+                    .map(|ty| self.type_name(ty, TypeMode::Owned))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let skip = matches!(func.kind, FunctionKind::Method(_)) as usize;
+        let params = func
+            .params
+            .iter()
+            .skip(skip)
+            .map(|(name, ty)| (self.type_name(ty, mode), param_name(name)))
+            .collect();
+        (ret, params)
+    }
+
+    /// Returns the name of the C++ function for `func`, which for exports is
+    /// a member of `Guest` unless it's a resource method.
+    fn func_name(&self, func: &Function) -> String {
+        match func.kind {
+            FunctionKind::Freestanding => to_c_ident(&func.name),
+            FunctionKind::Method(_) => resource_member_name(func.item_name(), self.direction),
+            FunctionKind::Static(id) => match self.direction {
+                Direction::Import => resource_member_name(func.item_name(), self.direction),
+                Direction::Export => format!(
+                    "{}_{}",
+                    self.resolve.types[id]
+                        .name
+                        .as_ref()
+                        .unwrap()
+                        .to_snake_case(),
+                    func.item_name().to_snake_case()
+                ),
+            },
+            FunctionKind::Constructor(id) => match self.direction {
+                Direction::Import => self.class_name(id),
+                Direction::Export => format!(
+                    "constructor_{}",
+                    self.resolve.types[id]
+                        .name
+                        .as_ref()
+                        .unwrap()
+                        .to_snake_case()
+                ),
+            },
+        }
+    }
+
+    fn class_name(&self, id: TypeId) -> String {
+        self.resolve.types[id]
+            .name
+            .as_ref()
+            .unwrap()
+            .to_upper_camel_case()
+    }
+
+    fn type_name(&self, ty: &Type, mode: TypeMode) -> String {
+        match ty {
+            Type::Bool => "bool".to_string(),
+            Type::U8 => "uint8_t".to_string(),
+            Type::S8 => "int8_t".to_string(),
+            Type::U16 => "uint16_t".to_string(),
+            Type::S16 => "int16_t".to_string(),
+            Type::U32 => "uint32_t".to_string(),
+            Type::S32 => "int32_t".to_string(),
+            Type::U64 => "uint64_t".to_string(),
+            Type::S64 => "int64_t".to_string(),
+            Type::F32 => "float".to_string(),
+            Type::F64 => "double".to_string(),
+            Type::Char => "char32_t".to_string(),
+            Type::String => match mode {
+                TypeMode::ImportParam => "std::string_view".to_string(),
+                TypeMode::Owned | TypeMode::ExportParam => "std::string".to_string(),
+            },
+            Type::Id(id) => self.type_id_name(*id, mode),
+        }
+    }
+
+    /// Returns the name of the type `id`, where aliases are always spelled
+    /// out so signatures don't depend on the order aliases are defined in.
+    fn type_id_name(&self, id: TypeId, mode: TypeMode) -> String {
+        let ty = &self.resolve.types[id];
+        // Parameters of imports are borrowed, unless ownership of handles
+        // within them is being passed.
+        let by_ref = |name: String| {
+            if mode == TypeMode::ImportParam && !contains_own(self.resolve, &Type::Id(id)) {
+                format!("{name} const &")
+            } else {
+                name
+            }
+        };
+        match &ty.kind {
+            TypeDefKind::Type(t) => self.type_name(t, mode),
+            TypeDefKind::Enum(_) | TypeDefKind::Flags(_) | TypeDefKind::Resource => {
+                self.qualified_name(id)
+            }
+            TypeDefKind::Record(_) | TypeDefKind::Variant(_) => by_ref(self.qualified_name(id)),
+            TypeDefKind::Handle(Handle::Own(resource)) => {
+                let resource = dealias(self.resolve, *resource);
+                let name = self.qualified_name(resource);
+                match self.gen.resources[&resource] {
+                    Direction::Import => name,
+                    Direction::Export => format!("wit::Owned<{name}>"),
+                }
+            }
+            TypeDefKind::Handle(Handle::Borrow(resource)) => {
+                let resource = dealias(self.resolve, *resource);
+                let name = self.qualified_name(resource);
+                match (self.gen.resources[&resource], mode) {
+                    (Direction::Import, TypeMode::Owned) => name,
+                    (Direction::Import, _) => format!("{name} const &"),
+                    (Direction::Export, TypeMode::Owned) => {
+                        format!("std::reference_wrapper<{name}>")
+                    }
+                    (Direction::Export, _) => format!("{name} &"),
+                }
+            }
+            TypeDefKind::Tuple(tuple) => {
+                let types = tuple
+                    .types
+                    .iter()
+                    .map(|ty| self.type_name(ty, TypeMode::Owned))
+                    .collect::<Vec<_>>();
+                by_ref(format!("std::tuple<{}>", types.join(", ")))
+            }
+            TypeDefKind::Option(t) => by_ref(format!(
+                "std::optional<{}>",
+                self.type_name(t, TypeMode::Owned)
+            )),
+            TypeDefKind::Result(r) => {
+                let ok = match &r.ok {
+                    Some(ty) => self.type_name(ty, TypeMode::Owned),
+                    None => "void".to_string(),
+                };
+                let err = match &r.err {
+                    Some(ty) => self.type_name(ty, TypeMode::Owned),
+                    None => "std::monostate".to_string(),
+                };
+                by_ref(format!("wit::expected<{ok}, {err}>"))
+            }
+            TypeDefKind::List(t) => {
+                let element = self.type_name(t, TypeMode::Owned);
+                if mode != TypeMode::ImportParam || contains_own(self.resolve, t) {
+                    format!("std::vector<{element}>")
+                } else if let Type::Bool = dealias_type(self.resolve, t) {
+                    // `std::vector<bool>` isn't contiguous so it can't be
+                    // viewed by a `std::span`.
+                    "std::vector<bool> const &".to_string()
+                } else {
+                    format!("std::span<{element} const>")
+                }
+            }
+            // Worlds using futures and streams are rejected in `validate`.
+            TypeDefKind::Future(_) | TypeDefKind::Stream(_) | TypeDefKind::Unknown => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Returns the fully qualified name of the named type `id`.
+    fn qualified_name(&self, id: TypeId) -> String {
+        let ty = &self.resolve.types[id];
+        let ns = match ty.owner {
+            TypeOwner::Interface(iface) => self.gen.interface_namespaces[&iface].clone(),
+            TypeOwner::World(_) => to_c_ident(&self.gen.world),
+            TypeOwner::None => unreachable!("anonymous types don't have names"),
+        };
+        format!(
+            "::{ns}::{}",
+            ty.name.as_ref().unwrap().to_upper_camel_case()
+        )
+    }
+
+    fn docs(&mut self, docs: &Docs) {
+        if let Some(docs) = &docs.contents {
+            for line in docs.trim().lines() {
+                uwriteln!(self.h, "/// {}", line.trim());
+            }
+        }
+    }
+}
+
+/// Returns the name of the resource member for the method or static function
+/// `name`, avoiding those generated for every resource.
+fn resource_member_name(name: &str, direction: Direction) -> String {
+    let name = to_c_ident(name);
+    let reserved: &[&str] = match direction {
+        Direction::Import => &[
+            "borrow",
+            "handle",
+            "into_handle",
+            "from_handle",
+            "from_borrow",
+        ],
+        Direction::Export => &["into_handle", "from_handle", "destroy"],
+    };
+    if reserved.contains(&name.as_str()) {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
+
+/// Returns the name of the parameter `name`, avoiding the locals declared at
+/// the start of every imported function.
+fn param_name(name: &str) -> String {
+    let name = to_c_ident(name);
+    match name.as_str() {
+        "ret_area" | "cleanup_list" => format!("{name}_"),
+        _ => name,
+    }
+}
+
+/// Returns the name of the struct for the case `case` of the variant `name`,
+/// which can't have the same name as the variant itself.
+fn variant_case_name(variant: &str, case: &str) -> String {
+    let name = case.to_upper_camel_case();
+    if name == variant {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
+
+fn dealias_type(resolve: &Resolve, ty: &Type) -> Type {
+    match ty {
+        Type::Id(id) => match &resolve.types[*id].kind {
+            TypeDefKind::Type(t) => dealias_type(resolve, t),
+            _ => *ty,
+        },
+        _ => *ty,
+    }
+}
+
+/// Returns whether `ty` contains owned handles, which lowering it consumes.
+fn contains_own(resolve: &Resolve, ty: &Type) -> bool {
+    let id = match ty {
+        Type::Id(id) => *id,
+        _ => return false,
+    };
+    match &resolve.types[id].kind {
+        TypeDefKind::Handle(Handle::Own(_)) => true,
+        TypeDefKind::Type(t) | TypeDefKind::List(t) | TypeDefKind::Option(t) => {
+            contains_own(resolve, t)
+        }
+        TypeDefKind::Record(r) => r.fields.iter().any(|f| contains_own(resolve, &f.ty)),
+        TypeDefKind::Tuple(t) => t.types.iter().any(|t| contains_own(resolve, t)),
+        TypeDefKind::Variant(v) => v
+            .cases
+            .iter()
+            .filter_map(|c| c.ty.as_ref())
+            .any(|t| contains_own(resolve, t)),
+        TypeDefKind::Result(r) => {
+            r.ok.iter()
+                .chain(r.err.iter())
+                .any(|t| contains_own(resolve, t))
+        }
+        TypeDefKind::Handle(Handle::Borrow(_))
+        | TypeDefKind::Resource
+        | TypeDefKind::Enum(_)
+        | TypeDefKind::Flags(_)
+        | TypeDefKind::Future(_)
+        | TypeDefKind::Stream(_)
+        | TypeDefKind::Unknown => false,
+    }
+}
+
+fn wasm_result_type(sig: &abi::WasmSignature) -> &'static str {
+    match sig.results.len() {
+        0 => "void",
+        1 => wasm_type(sig.results[0]),
+        _ => unreachable!("multiple results are returned through a return pointer"),
+    }
+}
@@ -0,0 +1,107 @@
+#ifndef WIT_BINDGEN_CPP_RUNTIME
+#define WIT_BINDGEN_CPP_RUNTIME
+
+#include <bit>
+#include <bitset>
+#include <cstddef>
+#include <cstdint>
+#include <functional>
+#include <memory>
+#include <optional>
+#include <span>
+#include <string>
+#include <string_view>
+#include <tuple>
+#include <utility>
+#include <variant>
+#include <vector>
+#include <version>
+#if defined(__cpp_lib_expected) && __cpp_lib_expected >= 202202L
+#include <expected>
+#endif
+
+namespace wit {
+
+#if defined(__cpp_lib_expected) && __cpp_lib_expected >= 202202L
+template <class T, class E> using expected = std::expected<T, E>;
+template <class E> using unexpected = std::unexpected<E>;
+#else
+// A minimal stand-in for `std::unexpected` where the standard library
+// doesn't provide it.
+template <class E> class unexpected {
+public:
+  explicit unexpected(E error) : error_(std::move(error)) {}
+  E &error() & { return error_; }
+  E const &error() const & { return error_; }
+  E &&error() && { return std::move(error_); }
+
+private:
+  E error_;
+};
+
+// A minimal stand-in for `std::expected` where the standard library doesn't
+// provide it, supporting the subset of its interface the bindings use.
+template <class T, class E> class expected {
+public:
+  using value_type = T;
+  using error_type = E;
+
+  expected(T value) : storage_(std::in_place_index<0>, std::move(value)) {}
+  expected(unexpected<E> error)
+      : storage_(std::in_place_index<1>, std::move(error).error()) {}
+
+  bool has_value() const { return storage_.index() == 0; }
+  explicit operator bool() const { return has_value(); }
+
+  T &value() & { return std::get<0>(storage_); }
+  T const &value() const & { return std::get<0>(storage_); }
+  T &&value() && { return std::get<0>(std::move(storage_)); }
+  T &operator*() & { return value(); }
+  T const &operator*() const & { return value(); }
+  T &&operator*() && { return std::move(*this).value(); }
+  T *operator->() { return &value(); }
+  T const *operator->() const { return &value(); }
+
+  E &error() & { return std::get<1>(storage_); }
+  E const &error() const & { return std::get<1>(storage_); }
+  E &&error() && { return std::get<1>(std::move(storage_)); }
+
+private:
+  std::variant<T, E> storage_;
+};
+
+template <class E> class expected<void, E> {
+public:
+  using value_type = void;
+  using error_type = E;
+
+  expected() {}
+  expected(unexpected<E> error) : error_(std::move(error).error()) {}
+
+  bool has_value() const { return !error_.has_value(); }
+  explicit operator bool() const { return has_value(); }
+
+  void value() const {}
+  void operator*() const {}
+
+  E &error() & { return *error_; }
+  E const &error() const & { return *error_; }
+  E &&error() && { return std::move(*error_); }
+
+private:
+  std::optional<E> error_;
+};
+#endif
+
+// Destroys an exported resource through its `destroy` function, which drops
+// its handle if it has been given one.
+template <class R> struct ResourceDeleter {
+  void operator()(R *resource) const { R::destroy(resource); }
+};
+
+// An owned instance of the exported resource `R`.
+template <class R> using Owned = std::unique_ptr<R, ResourceDeleter<R>>;
+
+} // namespace wit
+
+#endif
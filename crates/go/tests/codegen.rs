@@ -6,6 +6,7 @@ use std::process::Command;
 use heck::*;
 
 macro_rules! codegen_test {
+    (flags_large $name:tt $test:tt) => {};
     (issue668 $name:tt $test:tt) => {};
     (multiversion $name:tt $test:tt) => {};
     ($id:ident $name:tt $test:tt) => {
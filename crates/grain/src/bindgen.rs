@@ -0,0 +1,831 @@
+use crate::{tuple_type, InterfaceGenerator};
+use heck::ToUpperCamelCase;
+use std::fmt::Write as _;
+use std::mem;
+use wit_bindgen_core::abi::{Bindgen, Bitcast, Instruction, WasmType};
+use wit_bindgen_core::{dealias, uwrite, uwriteln, wit_parser::*, Ns, Source};
+
+pub(super) struct FunctionBindgen<'a, 'b> {
+    pub gen: &'b mut InterfaceGenerator<'a>,
+    /// The function `CallWasm` or `CallInterface` calls.
+    pub callee: String,
+    pub params: Vec<String>,
+    pub locals: Ns,
+    pub src: Source,
+    block_storage: Vec<(Source, Vec<String>)>,
+    blocks: Vec<(String, Vec<String>)>,
+    payloads: Vec<String>,
+    /// The names of the elements and base pointers of the lists being lowered
+    /// or lifted, innermost last.
+    iter_elems: Vec<String>,
+    iter_bases: Vec<String>,
+    /// The allocations of an import's arguments, which are freed before it
+    /// returns.
+    cleanup: Vec<String>,
+    /// Whether allocations are made in a loop, so that they're collected in
+    /// the `cleanup` list at runtime instead.
+    pub needs_cleanup_list: bool,
+    /// The borrowed handles to imported resources an export is called with,
+    /// which are dropped once it returns.
+    borrows: Vec<String>,
+    /// Whether borrowed handles are lifted in blocks, so that they're
+    /// collected in the `borrows` list at runtime instead.
+    pub needs_borrow_list: bool,
+}
+
+impl<'a, 'b> FunctionBindgen<'a, 'b> {
+    pub(super) fn new(gen: &'b mut InterfaceGenerator<'a>, locals: Ns) -> FunctionBindgen<'a, 'b> {
+        FunctionBindgen {
+            gen,
+            callee: String::new(),
+            params: Vec::new(),
+            locals,
+            src: Source::default(),
+            block_storage: Vec::new(),
+            blocks: Vec::new(),
+            payloads: Vec::new(),
+            iter_elems: Vec::new(),
+            iter_bases: Vec::new(),
+            cleanup: Vec::new(),
+            needs_cleanup_list: false,
+            borrows: Vec::new(),
+            needs_borrow_list: false,
+        }
+    }
+
+    /// Binds `op` to a new local so that it's only evaluated once, and
+    /// returns its name.
+    fn bind(&mut self, name: &str, op: &str) -> String {
+        let tmp = self.locals.tmp(name);
+        uwriteln!(self.src, "let {tmp} = {op}");
+        tmp
+    }
+
+    /// Binds `op` to a new local of type `ty`, and returns its name.
+    fn bind_typed(&mut self, name: &str, ty: &str, op: &str) -> String {
+        let tmp = self.locals.tmp(name);
+        uwriteln!(self.src, "let {tmp}: {ty} = {op}");
+        tmp
+    }
+
+    fn load(&mut self, module: &str, func: &str, offset: i32, operands: &[String]) -> String {
+        self.bind(
+            "load",
+            &format!("{module}.{func}({}, {offset}n)", operands[0]),
+        )
+    }
+
+    fn store(&mut self, module: &str, func: &str, offset: i32, operands: &[String]) {
+        uwriteln!(
+            self.src,
+            "{module}.{func}({}, {}, {offset}n)",
+            operands[1],
+            operands[0]
+        );
+    }
+
+    /// Records `address` as an allocation to free before an import returns.
+    fn push_cleanup(&mut self, address: &str) {
+        if self.block_storage.is_empty() {
+            self.cleanup.push(address.to_string());
+        } else {
+            // Allocations in blocks may be made any number of times, so
+            // they're collected at runtime.
+            self.needs_cleanup_list = true;
+            uwriteln!(self.src, "cleanup = [Conv.toInt32({address}), ...cleanup]");
+        }
+    }
+
+    /// Returns the constructor of the case `case` of the variant, enum,
+    /// option or result `ty`, which is declared by `owner`.
+    fn constructor(&self, owner: &TypeOwner, case: &str) -> String {
+        self.gen.qualify(owner, &case.to_upper_camel_case())
+    }
+
+    /// Lowers a variant by matching `op` against `patterns`, one for each of
+    /// the blocks of its cases, and assigning the values of the blocks to
+    /// the locals it's lowered to.
+    fn lower_variant(
+        &mut self,
+        patterns: Vec<String>,
+        lowered_types: &[WasmType],
+        op: &str,
+        results: &mut Vec<String>,
+    ) {
+        let blocks = self
+            .blocks
+            .drain(self.blocks.len() - patterns.len()..)
+            .collect::<Vec<_>>();
+        let lowered = lowered_types
+            .iter()
+            .map(|ty| {
+                let local = self.locals.tmp("lowered");
+                uwriteln!(self.src, "let mut {local} = {}", zero(*ty));
+                local
+            })
+            .collect::<Vec<_>>();
+
+        uwriteln!(self.src, "match ({op}) {{");
+        for (pattern, (body, block_results)) in patterns.iter().zip(blocks) {
+            uwriteln!(self.src, "{pattern} => {{");
+            self.src.push_str(&body);
+            for (local, result) in lowered.iter().zip(block_results.iter()) {
+                uwriteln!(self.src, "{local} = {result}");
+            }
+            if body.trim().is_empty() && lowered.is_empty() {
+                uwriteln!(self.src, "void");
+            }
+            uwriteln!(self.src, "}},");
+        }
+        uwriteln!(self.src, "}}");
+        results.extend(lowered);
+    }
+
+    /// Lifts a variant of type `ty` by checking the discriminant `op` against
+    /// the index of each case, constructing the case with `constructors` from
+    /// the values of the blocks of the cases.
+    fn lift_variant(
+        &mut self,
+        ty: &str,
+        constructors: Vec<(String, bool)>,
+        op: &str,
+        results: &mut Vec<String>,
+    ) {
+        let blocks = self
+            .blocks
+            .drain(self.blocks.len() - constructors.len()..)
+            .collect::<Vec<_>>();
+        let discriminant = self.bind("discriminant", op);
+        let lifted = self.locals.tmp("lifted");
+        uwrite!(self.src, "let {lifted}: {ty} = ");
+        for (i, ((constructor, has_payload), (body, block_results))) in
+            constructors.iter().zip(blocks).enumerate()
+        {
+            uwriteln!(self.src, "if (WasmI32.eq({discriminant}, {i}n)) {{");
+            self.src.push_str(&body);
+            if *has_payload {
+                let payload = match block_results.as_slice() {
+                    [payload] => payload.as_str(),
+                    _ => "void",
+                };
+                uwriteln!(self.src, "{constructor}({payload})");
+            } else {
+                uwriteln!(self.src, "{constructor}");
+            }
+            self.src.push_str("} else ");
+        }
+        uwriteln!(self.src, "{{");
+        uwriteln!(self.src, "fail \"invalid discriminant\"");
+        uwriteln!(self.src, "}}");
+        results.push(lifted);
+    }
+
+    /// Frees `address` once the blocks of the elements of a list of `len`
+    /// elements of `element` have been run.
+    fn deallocate_list(&mut self, element: &Type, address: &str, len: &str) {
+        let (body, results) = self.blocks.pop().unwrap();
+        assert!(results.is_empty());
+        if !body.trim().is_empty() {
+            let size = self.gen.sizes().size(element).size_wasm32();
+            let index = self.locals.tmp("index");
+            let base = self.iter_bases.pop().unwrap();
+            uwriteln!(
+                self.src,
+                "for (let mut {index} = 0n; WasmI32.ltU({index}, {len}); {index} = WasmI32.add({index}, 1n)) {{
+                    let {base} = WasmI32.add({address}, WasmI32.mul({index}, {size}n))"
+            );
+            self.src.push_str(&body);
+            uwriteln!(self.src, "}}");
+        } else {
+            self.iter_bases.pop();
+        }
+        uwriteln!(self.src, "Memory.free({address})");
+    }
+}
+
+impl Bindgen for FunctionBindgen<'_, '_> {
+    type Operand = String;
+
+    fn emit(
+        &mut self,
+        resolve: &Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<String>,
+        results: &mut Vec<String>,
+    ) {
+        match inst {
+            Instruction::GetArg { nth } => results.push(self.params[*nth].clone()),
+            Instruction::I32Const { val } => results.push(format!("{val}n")),
+            Instruction::ConstZero { tys } => {
+                for ty in tys.iter() {
+                    results.push(zero(*ty).to_string());
+                }
+            }
+
+            Instruction::U8FromI32 => results.push(format!(
+                "DataStructures.tagUint8(WasmI32.and({}, 0xFFn))",
+                operands[0]
+            )),
+            Instruction::S8FromI32 => results.push(format!(
+                "DataStructures.tagInt8(WasmI32.extendS8({}))",
+                operands[0]
+            )),
+            Instruction::U16FromI32 => results.push(format!(
+                "DataStructures.tagUint16(WasmI32.and({}, 0xFFFFn))",
+                operands[0]
+            )),
+            Instruction::S16FromI32 => results.push(format!(
+                "DataStructures.tagInt16(WasmI32.extendS16({}))",
+                operands[0]
+            )),
+            Instruction::U32FromI32 => results.push(format!("Conv.toUint32({})", operands[0])),
+            Instruction::S32FromI32 => results.push(format!("Conv.toInt32({})", operands[0])),
+            Instruction::U64FromI64 => results.push(format!("Conv.toUint64({})", operands[0])),
+            Instruction::S64FromI64 => results.push(format!("Conv.toInt64({})", operands[0])),
+            Instruction::F32FromCoreF32 => results.push(format!("Conv.toFloat32({})", operands[0])),
+            Instruction::F64FromCoreF64 => results.push(format!("Conv.toFloat64({})", operands[0])),
+            Instruction::CharFromI32 => {
+                results.push(format!("DataStructures.tagChar({})", operands[0]))
+            }
+
+            Instruction::I32FromU8 => {
+                results.push(format!("DataStructures.untagUint8({})", operands[0]))
+            }
+            Instruction::I32FromS8 => {
+                results.push(format!("DataStructures.untagInt8({})", operands[0]))
+            }
+            Instruction::I32FromU16 => {
+                results.push(format!("DataStructures.untagUint16({})", operands[0]))
+            }
+            Instruction::I32FromS16 => {
+                results.push(format!("DataStructures.untagInt16({})", operands[0]))
+            }
+            Instruction::I32FromU32 => results.push(format!("Conv.fromUint32({})", operands[0])),
+            Instruction::I32FromS32 => results.push(format!("Conv.fromInt32({})", operands[0])),
+            Instruction::I64FromU64 => results.push(format!("Conv.fromUint64({})", operands[0])),
+            Instruction::I64FromS64 => results.push(format!("Conv.fromInt64({})", operands[0])),
+            Instruction::CoreF32FromF32 => {
+                results.push(format!("Conv.fromFloat32({})", operands[0]))
+            }
+            Instruction::CoreF64FromF64 => {
+                results.push(format!("Conv.fromFloat64({})", operands[0]))
+            }
+            Instruction::I32FromChar => {
+                results.push(format!("DataStructures.untagChar({})", operands[0]))
+            }
+
+            Instruction::I32FromBool => results.push(format!("(if ({}) 1n else 0n)", operands[0])),
+            Instruction::BoolFromI32 => results.push(format!("WasmI32.ne({}, 0n)", operands[0])),
+
+            Instruction::Bitcasts { casts } => {
+                for (cast, op) in casts.iter().zip(operands.iter()) {
+                    results.push(bitcast(cast, op));
+                }
+            }
+
+            Instruction::RecordLower { record, .. } => {
+                let op = self.bind("record", &operands[0]);
+                for field in record.fields.iter() {
+                    results.push(format!("{op}.{}", crate::ident(&field.name)));
+                }
+            }
+            Instruction::RecordLift { record, ty, .. } => {
+                let ty = self.gen.type_name(&Type::Id(*ty));
+                let fields = record
+                    .fields
+                    .iter()
+                    .zip(operands.iter())
+                    .map(|(field, op)| format!("{}: {op}", crate::ident(&field.name)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                results.push(self.bind_typed("record", &ty, &format!("{{ {fields} }}")));
+            }
+
+            Instruction::TupleLower { tuple, .. } => {
+                let names = tuple
+                    .types
+                    .iter()
+                    .map(|_| self.locals.tmp("tuple"))
+                    .collect::<Vec<_>>();
+                match names.len() {
+                    0 => {}
+                    1 => uwriteln!(self.src, "let ({},) = {}", names[0], operands[0]),
+                    _ => uwriteln!(self.src, "let ({}) = {}", names.join(", "), operands[0]),
+                }
+                results.extend(names);
+            }
+            Instruction::TupleLift { .. } => {
+                results.push(match operands.len() {
+                    0 => "void".to_string(),
+                    1 => format!("({},)", operands[0]),
+                    _ => format!("({})", operands.join(", ")),
+                });
+            }
+
+            Instruction::HandleLower { handle, .. } => {
+                let resource = self.bind("resource", &operands[0]);
+                let lowered = self.bind(
+                    "handle",
+                    &format!("DataStructures.untagSimpleNumber({resource}.handle)"),
+                );
+                // Owned handles are passed on, so they mustn't be dropped
+                // again.
+                if let Handle::Own(_) = handle {
+                    uwriteln!(self.src, "{resource}.handle = 0");
+                }
+                results.push(lowered);
+            }
+            Instruction::HandleLift { handle, .. } => {
+                let op = &operands[0];
+                match handle {
+                    Handle::Borrow(id) if self.gen.is_exported_resource(*id) => {
+                        results.push(format!("DataStructures.tagSimpleNumber({op})"));
+                    }
+                    Handle::Own(id) | Handle::Borrow(id) => {
+                        let ty = self.gen.qualified_name(dealias(resolve, *id));
+                        let resource = self.bind_typed(
+                            "resource",
+                            &ty,
+                            &format!("{{ handle: DataStructures.tagSimpleNumber({op}) }}"),
+                        );
+                        if let Handle::Borrow(_) = handle {
+                            if self.block_storage.is_empty() {
+                                self.borrows.push(format!("{ty}.drop({resource})"));
+                            } else {
+                                self.needs_borrow_list = true;
+                                uwriteln!(
+                                    self.src,
+                                    "borrows = [() => {ty}.drop({resource}), ...borrows]"
+                                );
+                            }
+                        }
+                        results.push(resource);
+                    }
+                }
+            }
+
+            Instruction::FlagsLower { flags, .. } => {
+                let op = self.bind("flags", &operands[0]);
+                let count = flags.repr().count();
+                for word in 0..count {
+                    let bits = self.locals.tmp("bits");
+                    uwriteln!(self.src, "let mut {bits} = 0n");
+                    for (i, flag) in flags.flags.iter().enumerate().skip(word * 32).take(32) {
+                        let bit = 1u32 << (i % 32);
+                        uwriteln!(
+                            self.src,
+                            "if ({op}.{}) {bits} = WasmI32.or({bits}, 0x{bit:x}n)",
+                            crate::ident(&flag.name)
+                        );
+                    }
+                    results.push(bits);
+                }
+            }
+            Instruction::FlagsLift { flags, ty, .. } => {
+                let ty = self.gen.type_name(&Type::Id(*ty));
+                let words = operands
+                    .iter()
+                    .map(|op| self.bind("bits", op))
+                    .collect::<Vec<_>>();
+                let fields = flags
+                    .flags
+                    .iter()
+                    .enumerate()
+                    .map(|(i, flag)| {
+                        let bit = 1u32 << (i % 32);
+                        format!(
+                            "{}: WasmI32.ne(WasmI32.and({}, 0x{bit:x}n), 0n)",
+                            crate::ident(&flag.name),
+                            words[i / 32]
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                results.push(self.bind_typed("flags", &ty, &format!("{{ {fields} }}")));
+            }
+
+            Instruction::VariantPayloadName => {
+                let name = self.locals.tmp("payload");
+                results.push(name.clone());
+                self.payloads.push(name);
+            }
+            Instruction::VariantLower {
+                variant,
+                ty,
+                results: lowered_types,
+                ..
+            } => {
+                let owner = resolve.types[*ty].owner;
+                let payloads = self
+                    .payloads
+                    .drain(self.payloads.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+                let patterns = variant
+                    .cases
+                    .iter()
+                    .zip(payloads)
+                    .map(|(case, payload)| {
+                        let constructor = self.constructor(&owner, &case.name);
+                        match case.ty {
+                            Some(_) => format!("{constructor}({payload})"),
+                            None => constructor,
+                        }
+                    })
+                    .collect();
+                self.lower_variant(patterns, lowered_types, &operands[0], results);
+            }
+            Instruction::VariantLift { variant, ty, .. } => {
+                let owner = resolve.types[*ty].owner;
+                let name = self.gen.type_name(&Type::Id(*ty));
+                let constructors = variant
+                    .cases
+                    .iter()
+                    .map(|case| (self.constructor(&owner, &case.name), case.ty.is_some()))
+                    .collect();
+                self.lift_variant(&name, constructors, &operands[0], results);
+            }
+
+            Instruction::EnumLower { enum_, ty, .. } => {
+                let owner = resolve.types[*ty].owner;
+                let arms = enum_
+                    .cases
+                    .iter()
+                    .enumerate()
+                    .map(|(i, case)| format!("{} => {i}n,", self.constructor(&owner, &case.name)))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let lowered = self.locals.tmp("lowered");
+                uwriteln!(
+                    self.src,
+                    "let {lowered} = match ({}) {{
+                        {arms}
+                    }}",
+                    operands[0]
+                );
+                results.push(lowered);
+            }
+            Instruction::EnumLift { enum_, ty, .. } => {
+                let owner = resolve.types[*ty].owner;
+                let name = self.gen.type_name(&Type::Id(*ty));
+                let discriminant = self.bind("discriminant", &operands[0]);
+                let lifted = self.locals.tmp("lifted");
+                uwrite!(self.src, "let {lifted}: {name} = ");
+                for (i, case) in enum_.cases.iter().enumerate() {
+                    let constructor = self.constructor(&owner, &case.name);
+                    uwriteln!(
+                        self.src,
+                        "if (WasmI32.eq({discriminant}, {i}n)) {constructor} else"
+                    );
+                }
+                uwriteln!(self.src, "fail \"invalid discriminant\"");
+                results.push(lifted);
+            }
+
+            Instruction::OptionLower {
+                results: lowered_types,
+                ..
+            } => {
+                let some = self.payloads.pop().unwrap();
+                let _none = self.payloads.pop().unwrap();
+                let patterns = vec!["None".to_string(), format!("Some({some})")];
+                self.lower_variant(patterns, lowered_types, &operands[0], results);
+            }
+            Instruction::OptionLift { ty, .. } => {
+                let name = self.gen.type_name(&Type::Id(*ty));
+                let constructors = vec![("None".to_string(), false), ("Some".to_string(), true)];
+                self.lift_variant(&name, constructors, &operands[0], results);
+            }
+
+            Instruction::ResultLower {
+                results: lowered_types,
+                result,
+                ..
+            } => {
+                let err = self.payloads.pop().unwrap();
+                let ok = self.payloads.pop().unwrap();
+                let pattern = |constructor: &str, ty: &Option<Type>, payload: String| match ty {
+                    Some(_) => format!("{constructor}({payload})"),
+                    None => format!("{constructor}(_)"),
+                };
+                let patterns = vec![
+                    pattern("Ok", &result.ok, ok),
+                    pattern("Err", &result.err, err),
+                ];
+                self.lower_variant(patterns, lowered_types, &operands[0], results);
+            }
+            Instruction::ResultLift { ty, .. } => {
+                let name = self.gen.type_name(&Type::Id(*ty));
+                let constructors = vec![("Ok".to_string(), true), ("Err".to_string(), true)];
+                self.lift_variant(&name, constructors, &operands[0], results);
+            }
+
+            // `Bytes` and `String` share the same layout, with their length
+            // stored at offset 4 and their contents from offset 8.
+            Instruction::ListCanonLower { realloc, .. } | Instruction::StringLower { realloc } => {
+                let value = self.bind("value", &format!("WasmI32.fromGrain({})", operands[0]));
+                let len = self.bind("len", &format!("WasmI32.load({value}, 4n)"));
+                let address = match realloc {
+                    // Imports borrow the contents, which are kept alive by
+                    // the argument.
+                    None => format!("WasmI32.add({value}, 8n)"),
+                    Some(_) => {
+                        let address = self.bind("address", &format!("Memory.malloc({len})"));
+                        uwriteln!(
+                            self.src,
+                            "Memory.copy({address}, WasmI32.add({value}, 8n), {len})"
+                        );
+                        address
+                    }
+                };
+                results.push(address);
+                results.push(len);
+            }
+            Instruction::ListCanonLift { .. } | Instruction::StringLift => {
+                let (alloc, ty) = match inst {
+                    Instruction::StringLift => ("allocateString", "String"),
+                    _ => ("allocateBytes", "Bytes"),
+                };
+                let address = self.bind("address", &operands[0]);
+                let len = self.bind("len", &operands[1]);
+                let value = self.bind("value", &format!("DataStructures.{alloc}({len})"));
+                uwriteln!(
+                    self.src,
+                    "Memory.copy(WasmI32.add({value}, 8n), {address}, {len})
+                     Memory.free({address})"
+                );
+                results.push(self.bind_typed("lifted", ty, &format!("WasmI32.toGrain({value})")));
+            }
+
+            Instruction::ListLower { element, realloc } => {
+                let (body, block_results) = self.blocks.pop().unwrap();
+                assert!(block_results.is_empty());
+                let elem = self.iter_elems.pop().unwrap();
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.sizes().size(element).size_wasm32();
+                let array = self.bind("array", &operands[0]);
+                let len = self.bind(
+                    "len",
+                    &format!("DataStructures.untagSimpleNumber(Array.length({array}))"),
+                );
+                let address = self.bind(
+                    "address",
+                    &format!("Memory.malloc(WasmI32.mul({len}, {size}n))"),
+                );
+                let index = self.locals.tmp("index");
+                uwriteln!(
+                    self.src,
+                    "for (let mut {index} = 0n; WasmI32.ltU({index}, {len}); {index} = WasmI32.add({index}, 1n)) {{
+                        let {elem} = {array}[DataStructures.tagSimpleNumber({index})]
+                        let {base} = WasmI32.add({address}, WasmI32.mul({index}, {size}n))"
+                );
+                self.src.push_str(&body);
+                uwriteln!(self.src, "}}");
+                if realloc.is_none() {
+                    self.push_cleanup(&address);
+                }
+                results.push(address);
+                results.push(len);
+            }
+            Instruction::ListLift { element, .. } => {
+                let (body, block_results) = self.blocks.pop().unwrap();
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.sizes().size(element).size_wasm32();
+                let ty = self.gen.type_name(element);
+                let address = self.bind("address", &operands[0]);
+                let len = self.bind("len", &operands[1]);
+                let list = self.locals.tmp("list");
+                let index = self.locals.tmp("index");
+                // Elements are lifted into a list since there's no value to
+                // fill an array with before they're lifted.
+                uwriteln!(
+                    self.src,
+                    "let mut {list}: List<{ty}> = []
+                    for (let mut {index} = 0n; WasmI32.ltU({index}, {len}); {index} = WasmI32.add({index}, 1n)) {{
+                        let {base} = WasmI32.add({address}, WasmI32.mul({index}, {size}n))"
+                );
+                self.src.push_str(&body);
+                uwriteln!(self.src, "{list} = [{}, ...{list}]", block_results[0]);
+                uwriteln!(self.src, "}}");
+                uwriteln!(self.src, "Memory.free({address})");
+                results.push(self.bind_typed(
+                    "lifted",
+                    &format!("Array<{ty}>"),
+                    &format!("Array.fromList(List.reverse({list}))"),
+                ));
+            }
+            Instruction::IterElem { .. } => {
+                let elem = self.locals.tmp("element");
+                self.iter_elems.push(elem.clone());
+                results.push(elem);
+            }
+            Instruction::IterBasePointer => {
+                let base = self.locals.tmp("base");
+                self.iter_bases.push(base.clone());
+                results.push(base);
+            }
+
+            Instruction::CallWasm { sig, .. } => {
+                let call = format!("{}({})", self.callee, operands.join(", "));
+                match sig.results.len() {
+                    0 => uwriteln!(self.src, "{call}"),
+                    _ => results.push(self.bind("result", &call)),
+                }
+            }
+            Instruction::CallInterface { func, .. } => {
+                let call = format!("{}({})", self.callee, operands.join(", "));
+                match func.results.len() {
+                    0 => uwriteln!(self.src, "{call}"),
+                    1 => {
+                        let ty = self.gen.results_type(&func.results);
+                        results.push(self.bind_typed("result", &ty, &call));
+                    }
+                    n => {
+                        let names = (0..n)
+                            .map(|_| self.locals.tmp("result"))
+                            .collect::<Vec<_>>();
+                        uwriteln!(self.src, "let ({}) = {call}", names.join(", "));
+                        results.extend(names);
+                    }
+                }
+                for borrow in mem::take(&mut self.borrows) {
+                    uwriteln!(self.src, "{borrow}");
+                }
+                if self.needs_borrow_list {
+                    uwriteln!(self.src, "List.forEach(drop => drop(), borrows)");
+                }
+            }
+
+            Instruction::Return { amt, .. } => {
+                for address in mem::take(&mut self.cleanup) {
+                    uwriteln!(self.src, "Memory.free({address})");
+                }
+                if self.needs_cleanup_list {
+                    uwriteln!(
+                        self.src,
+                        "List.forEach(address => Memory.free(Conv.fromInt32(address)), cleanup)"
+                    );
+                }
+                match amt {
+                    0 => uwriteln!(self.src, "void"),
+                    1 => uwriteln!(self.src, "{}", operands[0]),
+                    _ => uwriteln!(self.src, "{}", tuple_type(operands.iter().cloned())),
+                }
+            }
+
+            Instruction::I32Load { offset }
+            | Instruction::PointerLoad { offset }
+            | Instruction::LengthLoad { offset } => {
+                results.push(self.load("WasmI32", "load", *offset, operands))
+            }
+            Instruction::I32Load8U { offset } => {
+                results.push(self.load("WasmI32", "load8U", *offset, operands))
+            }
+            Instruction::I32Load8S { offset } => {
+                results.push(self.load("WasmI32", "load8S", *offset, operands))
+            }
+            Instruction::I32Load16U { offset } => {
+                results.push(self.load("WasmI32", "load16U", *offset, operands))
+            }
+            Instruction::I32Load16S { offset } => {
+                results.push(self.load("WasmI32", "load16S", *offset, operands))
+            }
+            Instruction::I64Load { offset } => {
+                results.push(self.load("WasmI64", "load", *offset, operands))
+            }
+            Instruction::F32Load { offset } => {
+                results.push(self.load("WasmF32", "load", *offset, operands))
+            }
+            Instruction::F64Load { offset } => {
+                results.push(self.load("WasmF64", "load", *offset, operands))
+            }
+
+            Instruction::I32Store { offset }
+            | Instruction::PointerStore { offset }
+            | Instruction::LengthStore { offset } => {
+                self.store("WasmI32", "store", *offset, operands)
+            }
+            Instruction::I32Store8 { offset } => self.store("WasmI32", "store8", *offset, operands),
+            Instruction::I32Store16 { offset } => {
+                self.store("WasmI32", "store16", *offset, operands)
+            }
+            Instruction::I64Store { offset } => self.store("WasmI64", "store", *offset, operands),
+            Instruction::F32Store { offset } => self.store("WasmF32", "store", *offset, operands),
+            Instruction::F64Store { offset } => self.store("WasmF64", "store", *offset, operands),
+
+            Instruction::Malloc { .. } => unreachable!(),
+
+            Instruction::GuestDeallocate { .. } | Instruction::GuestDeallocateString => {
+                uwriteln!(self.src, "Memory.free({})", operands[0]);
+            }
+            Instruction::GuestDeallocateVariant { blocks } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - blocks..)
+                    .collect::<Vec<_>>();
+                let discriminant = self.bind("discriminant", &operands[0]);
+                for (i, (body, results)) in blocks.into_iter().enumerate() {
+                    assert!(results.is_empty());
+                    if body.trim().is_empty() {
+                        continue;
+                    }
+                    uwriteln!(self.src, "if (WasmI32.eq({discriminant}, {i}n)) {{");
+                    self.src.push_str(&body);
+                    uwriteln!(self.src, "}}");
+                }
+            }
+            Instruction::GuestDeallocateList { element } => {
+                let (address, len) = (operands[0].clone(), operands[1].clone());
+                self.deallocate_list(element, &address, &len);
+            }
+
+            Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. }
+            | Instruction::Flush { .. } => {
+                unreachable!("async functions are never requested by this generator")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+        }
+    }
+
+    fn return_pointer(&mut self, size: usize, align: usize) -> String {
+        self.gen.ret_area(size, align);
+        "retArea".to_string()
+    }
+
+    fn push_block(&mut self) {
+        let prev = mem::take(&mut self.src);
+        let cleanup = mem::take(&mut self.cleanup);
+        self.block_storage.push((prev, cleanup));
+    }
+
+    fn finish_block(&mut self, operands: &mut Vec<String>) {
+        let (prev, cleanup) = self.block_storage.pop().unwrap();
+        let src = mem::replace(&mut self.src, prev);
+        self.cleanup = cleanup;
+        self.blocks.push((src.into(), mem::take(operands)));
+    }
+
+    fn sizes(&self) -> &SizeAlign {
+        self.gen.sizes()
+    }
+
+    fn is_list_canonical(&self, _resolve: &Resolve, element: &Type) -> bool {
+        matches!(element, Type::U8)
+    }
+}
+
+pub(super) fn wasm_type(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 | WasmType::Pointer | WasmType::Length => "WasmI32",
+        WasmType::I64 | WasmType::PointerOrI64 => "WasmI64",
+        WasmType::F32 => "WasmF32",
+        WasmType::F64 => "WasmF64",
+    }
+}
+
+fn zero(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 | WasmType::Pointer | WasmType::Length => "0n",
+        WasmType::I64 | WasmType::PointerOrI64 => "0N",
+        WasmType::F32 => "0.0w",
+        WasmType::F64 => "0.0W",
+    }
+}
+
+fn bitcast(cast: &Bitcast, op: &str) -> String {
+    match cast {
+        Bitcast::I32ToF32 => format!("WasmF32.reinterpretI32({op})"),
+        Bitcast::F32ToI32 => format!("WasmI32.reinterpretF32({op})"),
+        Bitcast::I64ToF64 => format!("WasmF64.reinterpretI64({op})"),
+        Bitcast::F64ToI64 => format!("WasmI64.reinterpretF64({op})"),
+        Bitcast::F32ToI64 => format!("WasmI64.extendI32U(WasmI32.reinterpretF32({op}))"),
+        Bitcast::I64ToF32 => format!("WasmF32.reinterpretI32(WasmI32.wrapI64({op}))"),
+        Bitcast::I32ToI64 | Bitcast::LToI64 | Bitcast::PToP64 => {
+            format!("WasmI64.extendI32U({op})")
+        }
+        Bitcast::I64ToI32 | Bitcast::I64ToL | Bitcast::P64ToP => {
+            format!("WasmI32.wrapI64({op})")
+        }
+        Bitcast::I64ToP64
+        | Bitcast::P64ToI64
+        | Bitcast::I32ToP
+        | Bitcast::PToI32
+        | Bitcast::I32ToL
+        | Bitcast::LToI32
+        | Bitcast::LToP
+        | Bitcast::PToL
+        | Bitcast::None => op.to_string(),
+        Bitcast::Sequence(seq) => {
+            let [a, b] = &**seq;
+            bitcast(b, &bitcast(a, op))
+        }
+    }
+}
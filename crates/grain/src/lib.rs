@@ -0,0 +1,1077 @@
+mod bindgen;
+
+use anyhow::Result;
+use bindgen::{wasm_type, FunctionBindgen};
+use heck::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::mem;
+use wit_bindgen_core::abi::{self, AbiVariant, LiftLower};
+use wit_bindgen_core::{
+    dealias, uwriteln, wit_parser::*, AnonymousTypeGenerator, Direction, Files,
+    InterfaceGenerator as _, Ns, Source, WorldGenerator,
+};
+
+/// The modules of Grain's runtime and standard library the generated files
+/// include.
+const INCLUDES: &str = r#"from "runtime/unsafe/wasmi32" include WasmI32
+from "runtime/unsafe/wasmi64" include WasmI64
+from "runtime/unsafe/wasmf32" include WasmF32
+from "runtime/unsafe/wasmf64" include WasmF64
+from "runtime/unsafe/memory" include Memory
+from "runtime/unsafe/conv" include Conv
+from "runtime/dataStructures" include DataStructures
+from "array" include Array
+from "list" include List
+"#;
+
+/// The names generated code refers to, which parameters and locals mustn't
+/// shadow.
+const RESERVED_NAMES: &[&str] = &["retArea", "cleanup", "borrows", "self"];
+
+#[derive(Default)]
+struct Grain {
+    opts: Opts,
+    /// The name of the module holding the world's types and imports.
+    world: String,
+    sizes: SizeAlign,
+    /// The types and imports, which are placed in the world's module.
+    src: Source,
+    /// The functions exporting the world's exports, which are placed in the
+    /// component module since they call into the exports module, which itself
+    /// includes the world's module for its types.
+    exports: Source,
+    /// The stub implementation of the exports module.
+    stub: Source,
+    /// The names of the submodules of the world's module.
+    module_names: Ns,
+    /// The names of the functions of the component module.
+    export_names: Ns,
+    /// The submodule each interface's bindings are placed in, which changes
+    /// to the exported submodule once an interface which is both imported and
+    /// exported is exported.
+    modules: HashMap<InterfaceId, String>,
+    /// Whether each resource is imported or exported.
+    resources: HashMap<TypeId, Direction>,
+    import_ret_area: (usize, usize),
+    export_ret_area: (usize, usize),
+}
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct Opts {
+    /// The module the world's exports are implemented in, relative to the
+    /// generated bindings.
+    #[cfg_attr(feature = "clap", arg(long, default_value = "./exports.gr"))]
+    pub exports_module: Option<String>,
+
+    /// Whether or not to generate a stub exports module
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub generate_stub: bool,
+}
+
+impl Opts {
+    pub fn build(&self) -> Box<dyn WorldGenerator> {
+        Box::new(Grain {
+            opts: self.clone(),
+            ..Grain::default()
+        })
+    }
+}
+
+impl WorldGenerator for Grain {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        wit_bindgen_core::reject_futures_and_streams(resolve, world, "Grain")
+    }
+
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+        self.world = resolve.worlds[world].name.to_upper_camel_case();
+        self.sizes.fill(resolve);
+        // World-level types share the world's module with the submodules.
+        for item in resolve.worlds[world].imports.values() {
+            if let WorldItem::Type(id) = item {
+                let name = type_ident(resolve.types[*id].name.as_ref().unwrap());
+                let _ = self.module_names.insert(&name);
+            }
+        }
+    }
+
+    fn import_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let module = self.module_name(resolve, name);
+        let module = self.module_names.tmp(&module);
+        self.modules.insert(id, module.clone());
+        let wasm_module = resolve.name_world_key(name);
+        let mut gen = self.interface(resolve, Some(module), Direction::Import, wasm_module);
+        gen.funcs = resolve.interfaces[id].functions.values().collect();
+        gen.types(id);
+        gen.import_funcs();
+        gen.finish();
+        Ok(())
+    }
+
+    fn import_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) {
+        let mut gen = self.interface(resolve, None, Direction::Import, "$root".to_string());
+        gen.funcs = funcs.iter().map(|(_, f)| *f).collect();
+        gen.import_funcs();
+        gen.finish();
+    }
+
+    fn import_types(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        types: &[(&str, TypeId)],
+        _files: &mut Files,
+    ) {
+        let mut gen = self.interface(resolve, None, Direction::Import, "$root".to_string());
+        for (name, id) in types {
+            gen.define_type(name, *id);
+        }
+        gen.finish();
+    }
+
+    fn export_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let path = self.module_name(resolve, name);
+        let module = self.module_names.tmp(&format!("Exports{path}"));
+        self.modules.insert(id, module.clone());
+        let wasm_module = resolve.name_world_key(name);
+        let mut gen = self.interface(resolve, Some(module), Direction::Export, wasm_module);
+        gen.impl_path = format!("Exports.{path}");
+        gen.funcs = resolve.interfaces[id].functions.values().collect();
+        gen.types(id);
+        gen.export_funcs();
+        gen.finish();
+        Ok(())
+    }
+
+    fn export_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) -> Result<()> {
+        let mut gen = self.interface(resolve, None, Direction::Export, "$root".to_string());
+        gen.funcs = funcs.iter().map(|(_, f)| *f).collect();
+        gen.export_funcs();
+        gen.finish();
+        Ok(())
+    }
+
+    fn finish(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
+        let snake = resolve.worlds[id].name.to_snake_case();
+        let world = &self.world;
+        let version = env!("CARGO_PKG_VERSION");
+
+        let mut src = Source::default();
+        wit_bindgen_core::generated_preamble(&mut src, version);
+        uwriteln!(src, "module {world}\n");
+        src.push_str(INCLUDES);
+        // Imports use a statically-allocated return area, which is separate
+        // from that of exports since exports return a pointer into theirs.
+        if self.import_ret_area.0 > 0 {
+            uwriteln!(
+                src,
+                "\n@unsafe\nlet retArea = Memory.malloc({}n)",
+                self.import_ret_area.0
+            );
+        }
+        src.as_mut_string().push_str(self.src.as_str());
+        files.push(&format!("{snake}.gr"), src.as_bytes());
+
+        // The component module is the entry point, which exports the world's
+        // exports along with the `cabi_realloc` the canonical ABI allocates
+        // the arguments of exports and the results of imports with.
+        let exports_module = self
+            .opts
+            .exports_module
+            .as_deref()
+            .unwrap_or("./exports.gr");
+        let mut component = Source::default();
+        wit_bindgen_core::generated_preamble(&mut component, version);
+        uwriteln!(component, "module {world}Component\n");
+        component.push_str(INCLUDES);
+        uwriteln!(component, "from \"./{snake}.gr\" include {world}");
+        if !self.exports.as_str().is_empty() {
+            uwriteln!(component, "from \"{exports_module}\" include Exports");
+        }
+        if self.export_ret_area.0 > 0 {
+            uwriteln!(
+                component,
+                "\n@unsafe\nlet retArea = Memory.malloc({}n)",
+                self.export_ret_area.0
+            );
+        }
+        uwriteln!(
+            component,
+            "
+                @unsafe
+                @externalName(\"cabi_realloc\")
+                provide let cabiRealloc = (ptr: WasmI32, oldSize: WasmI32, align: WasmI32, newSize: WasmI32) => {{
+                    let newPtr = Memory.malloc(newSize)
+                    if (WasmI32.ne(ptr, 0n)) {{
+                        Memory.copy(newPtr, ptr, if (WasmI32.ltU(oldSize, newSize)) oldSize else newSize)
+                        Memory.free(ptr)
+                    }}
+                    newPtr
+                }}"
+        );
+        component.as_mut_string().push_str(self.exports.as_str());
+        files.push(&format!("{snake}_component.gr"), component.as_bytes());
+
+        if self.opts.generate_stub {
+            let mut stub = Source::default();
+            wit_bindgen_core::generated_preamble(&mut stub, version);
+            uwriteln!(stub, "module Exports\n");
+            uwriteln!(stub, "from \"./{snake}.gr\" include {world}");
+            stub.as_mut_string().push_str(self.stub.as_str());
+            let path = exports_module.trim_start_matches("./");
+            files.push(path, stub.as_bytes());
+        }
+        Ok(())
+    }
+}
+
+impl Grain {
+    fn interface<'a>(
+        &'a mut self,
+        resolve: &'a Resolve,
+        module: Option<String>,
+        direction: Direction,
+        wasm_module: String,
+    ) -> InterfaceGenerator<'a> {
+        InterfaceGenerator {
+            gen: self,
+            resolve,
+            module,
+            direction,
+            wasm_module,
+            impl_path: "Exports".to_string(),
+            funcs: Vec::new(),
+            src: Source::default(),
+            stub: Source::default(),
+            in_component: false,
+        }
+    }
+
+    /// Returns the name of the submodule bindings for the interface `key` are
+    /// placed in, without the `Exports` prefix of an exported interface.
+    fn module_name(&self, resolve: &Resolve, key: &WorldKey) -> String {
+        match key {
+            WorldKey::Name(name) => name.to_upper_camel_case(),
+            WorldKey::Interface(id) => {
+                let iface = &resolve.interfaces[*id];
+                let pkg = &resolve.packages[iface.package.unwrap()].name;
+                let mut path = pkg.namespace.to_upper_camel_case();
+                path.push_str(&pkg.name.to_upper_camel_case());
+                // Only disambiguate by version if more than one version of
+                // this package is in use.
+                if let Some(version) = &pkg.version {
+                    let versions = resolve
+                        .packages
+                        .iter()
+                        .filter(|(_, p)| {
+                            p.name.namespace == pkg.namespace && p.name.name == pkg.name
+                        })
+                        .count();
+                    if versions > 1 {
+                        let version = version.to_string().replace(['.', '-', '+'], "_");
+                        path.push_str(&format!("V{version}"));
+                    }
+                }
+                path.push_str(&iface.name.as_ref().unwrap().to_upper_camel_case());
+                path
+            }
+        }
+    }
+}
+
+pub(crate) struct InterfaceGenerator<'a> {
+    gen: &'a mut Grain,
+    resolve: &'a Resolve,
+    /// The submodule of the world's module declarations are placed in, which
+    /// is `None` for the world itself.
+    module: Option<String>,
+    direction: Direction,
+    /// The core wasm module imports come from, or the name exports are
+    /// prefixed with.
+    wasm_module: String,
+    /// The module of the exports module exported functions are implemented
+    /// in.
+    impl_path: String,
+    /// The functions of the interface, including the functions of resources.
+    funcs: Vec<&'a Function>,
+    src: Source,
+    /// The signatures the exports module implements, which are collected into
+    /// the stub.
+    stub: Source,
+    /// Whether code is being generated for the component module, where the
+    /// world's declarations are referred to through the world's module.
+    in_component: bool,
+}
+
+impl<'a> InterfaceGenerator<'a> {
+    fn finish(self) {
+        // The source is appended verbatim, since `Source::push_str` would
+        // strip the indentation of the continuation lines of doc comments.
+        let src = self.gen.src.as_mut_string();
+        match &self.module {
+            Some(module) if !self.src.as_str().is_empty() => {
+                uwriteln!(src, "\nprovide module {module} {{");
+                indent_into(src, self.src.as_str());
+                src.push_str("}\n");
+            }
+            _ => src.push_str(self.src.as_str()),
+        }
+
+        if !self.stub.as_str().is_empty() {
+            let stub = self.gen.stub.as_mut_string();
+            match self.impl_path.strip_prefix("Exports.") {
+                Some(module) => {
+                    uwriteln!(stub, "\nprovide module {module} {{");
+                    indent_into(stub, self.stub.as_str());
+                    stub.push_str("}\n");
+                }
+                None => stub.push_str(self.stub.as_str()),
+            }
+        }
+    }
+
+    /// Returns a namespace for the parameters and locals of a function, which
+    /// mustn't shadow the names generated code refers to.
+    fn locals(&self) -> Ns {
+        let mut ns = Ns::default();
+        for name in RESERVED_NAMES {
+            ns.insert(name).unwrap();
+        }
+        ns
+    }
+
+    /// Returns the methods, static functions and constructor of the resource
+    /// `id`.
+    fn resource_funcs(&self, id: TypeId) -> Vec<&'a Function> {
+        self.funcs
+            .iter()
+            .copied()
+            .filter(|f| match f.kind {
+                FunctionKind::Freestanding => false,
+                FunctionKind::Method(r)
+                | FunctionKind::Static(r)
+                | FunctionKind::Constructor(r) => r == id,
+            })
+            .collect()
+    }
+
+    /// Declares the core wasm function `name` imported from `module`,
+    /// returning the name it's declared as.
+    fn declare_foreign(
+        &mut self,
+        module: &str,
+        name: &str,
+        params: &[abi::WasmType],
+        results: &[abi::WasmType],
+    ) -> String {
+        let func = format!("wasmImport{}", name.to_upper_camel_case());
+        let params = params
+            .iter()
+            .map(|ty| wasm_type(*ty))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let result = match results {
+            [] => "Void",
+            [ty] => wasm_type(*ty),
+            _ => unreachable!("multiple results are returned through a return pointer"),
+        };
+        uwriteln!(
+            self.src,
+            "\n@externalName(\"{name}\")
+             foreign wasm {func}: ({params}) => {result} from \"{module}\""
+        );
+        func
+    }
+
+    /// Defines the record holding handles to the resource `id`, and the
+    /// submodule of functions managing them.
+    fn define_resource(&mut self, id: TypeId, name: &str, docs: &Docs) {
+        let resource = self.resolve.types[id].name.clone().unwrap();
+        self.gen.resources.insert(id, self.direction);
+
+        self.src.push_str("\n");
+        self.docs(docs);
+        // Formatted up front so that `Source` sees each line whole.
+        self.src.push_str(&format!(
+            "provide record {name} {{
+                mut handle: Number,
+            }}\n"
+        ));
+
+        let outer = mem::take(&mut self.src);
+        uwriteln!(self.src, "\nprovide module {name} {{");
+        match self.direction {
+            Direction::Import => {
+                let module = self.wasm_module.clone();
+                let drop = self.declare_foreign(
+                    &module,
+                    &format!("[resource-drop]{resource}"),
+                    &[abi::WasmType::I32],
+                    &[],
+                );
+                self.drop_fn(name, &drop);
+                for func in self.resource_funcs(id) {
+                    self.import_func(func);
+                }
+            }
+            Direction::Export => {
+                let module = format!("[export]{}", self.wasm_module);
+                let i32 = abi::WasmType::I32;
+                let new = self.declare_foreign(
+                    &module,
+                    &format!("[resource-new]{resource}"),
+                    &[i32],
+                    &[i32],
+                );
+                let rep = self.declare_foreign(
+                    &module,
+                    &format!("[resource-rep]{resource}"),
+                    &[i32],
+                    &[i32],
+                );
+                let drop = self.declare_foreign(
+                    &module,
+                    &format!("[resource-drop]{resource}"),
+                    &[i32],
+                    &[],
+                );
+                self.src.push_str(&format!(
+                    "
+                        /** Creates a resource represented by `rep`, passed to `dtor` once dropped. */
+                        @unsafe
+                        provide let new = (rep: Number) => {{
+                            let resource: {name} = {{
+                                handle: DataStructures.tagSimpleNumber({new}(DataStructures.untagSimpleNumber(rep))),
+                            }}
+                            resource
+                        }}
+
+                        /** Returns the number `self` was created with by `new`. */
+                        @unsafe
+                        provide let rep = (self: {name}) => {{
+                            DataStructures.tagSimpleNumber({rep}(DataStructures.untagSimpleNumber(self.handle)))
+                        }}\n"
+                ));
+                self.drop_fn(name, &drop);
+
+                let impl_path = format!("{}.{name}", self.impl_path);
+                let mut stub = format!("\nprovide module {name} {{\n");
+                for func in self.resource_funcs(id) {
+                    let sig = self.signature(func, true, &mut None);
+                    let ident = self.func_name(func);
+                    uwriteln!(
+                        stub,
+                        "  provide let {ident} = {sig} => fail \"unimplemented\""
+                    );
+                }
+                uwriteln!(stub, "\n  provide let dtor = (rep: Number) => void");
+                stub.push_str("}\n");
+                self.stub.as_mut_string().push_str(&stub);
+
+                let export_name = match self.module {
+                    Some(_) => format!("{}#[dtor]{resource}", self.wasm_module),
+                    None => format!("[dtor]{resource}"),
+                };
+                let shim = self
+                    .gen
+                    .export_names
+                    .tmp(&format!("wasmExport{}", export_name.to_upper_camel_case()));
+                self.gen.exports.push_str(&format!(
+                    "
+                        @unsafe
+                        @externalName(\"{export_name}\")
+                        provide let {shim} = (rep: WasmI32) => {{
+                            {impl_path}.dtor(DataStructures.tagSimpleNumber(rep))
+                        }}\n"
+                ));
+            }
+        }
+        self.src.push_str("}\n");
+        let inner = mem::take(&mut self.src);
+        self.src = outer;
+        self.src.as_mut_string().push_str(inner.as_str());
+    }
+
+    /// Defines the function dropping a handle with the core wasm function
+    /// `drop`.
+    fn drop_fn(&mut self, name: &str, drop: &str) {
+        uwriteln!(
+            self.src,
+            "
+                /** Drops the handle, unless it has already been dropped or passed on. */
+                @unsafe
+                provide let drop = (self: {name}) => {{
+                    if (self.handle != 0) {{
+                        {drop}(DataStructures.untagSimpleNumber(self.handle))
+                        self.handle = 0
+                    }}
+                }}"
+        );
+    }
+
+    fn import_funcs(&mut self) {
+        for func in self.funcs.clone() {
+            if let FunctionKind::Freestanding = func.kind {
+                self.import_func(func);
+            }
+        }
+    }
+
+    /// Generates a function calling the import `func`.
+    fn import_func(&mut self, func: &Function) {
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestImport, func);
+        let module = self.wasm_module.clone();
+        let foreign = self.declare_foreign(&module, &func.name, &sig.params, &sig.results);
+
+        self.src.push_str("\n");
+        self.docs(&func.docs);
+        let mut locals = self.locals();
+        let mut params = Some(Vec::new());
+        let decl = self.signature(func, false, &mut params);
+        let params = params.unwrap();
+        for param in params.iter() {
+            let _ = locals.insert(param);
+        }
+
+        let mut f = FunctionBindgen::new(self, locals);
+        f.params = params;
+        f.callee = foreign;
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            func,
+            &mut f,
+            false,
+        );
+        let FunctionBindgen {
+            src: body,
+            needs_cleanup_list,
+            ..
+        } = f;
+
+        let ident = self.func_name(func);
+        uwriteln!(self.src, "@unsafe");
+        uwriteln!(self.src, "provide let {ident} = {decl} => {{");
+        if needs_cleanup_list {
+            uwriteln!(self.src, "let mut cleanup = []");
+        }
+        self.src.push_str(&body);
+        self.src.push_str("}\n");
+    }
+
+    fn export_funcs(&mut self) {
+        for func in self.funcs.clone() {
+            if let FunctionKind::Freestanding = func.kind {
+                let sig = self.signature(func, true, &mut None);
+                let ident = self.func_name(func);
+                uwriteln!(
+                    self.stub,
+                    "\nprovide let {ident} = {sig} => fail \"unimplemented\""
+                );
+            }
+        }
+        for func in self.funcs.clone() {
+            self.export_func(func);
+        }
+    }
+
+    /// Generates a function which exports `func` by lifting its arguments,
+    /// calling its implementation, and lowering its results.
+    fn export_func(&mut self, func: &Function) {
+        let module = self.module.as_ref().map(|_| self.wasm_module.as_str());
+        let export_name = func.core_export_name(module).into_owned();
+        let shim = self
+            .gen
+            .export_names
+            .tmp(&format!("wasmExport{}", export_name.to_upper_camel_case()));
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
+
+        let ident = self.func_name(func);
+        let callee = match func.kind {
+            FunctionKind::Freestanding => format!("{}.{ident}", self.impl_path),
+            FunctionKind::Method(id) | FunctionKind::Static(id) | FunctionKind::Constructor(id) => {
+                let name = type_ident(self.resolve.types[id].name.as_ref().unwrap());
+                format!("{}.{name}.{ident}", self.impl_path)
+            }
+        };
+
+        self.in_component = true;
+        let mut locals = self.locals();
+        let params = sig
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| (locals.tmp(&format!("arg{i}")), *ty))
+            .collect::<Vec<_>>();
+        let mut f = FunctionBindgen::new(self, locals);
+        f.callee = callee;
+        f.params = params.iter().map(|(name, _)| name.clone()).collect();
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestExport,
+            LiftLower::LiftArgsLowerResults,
+            func,
+            &mut f,
+            false,
+        );
+        let mut body = Source::default();
+        if f.needs_borrow_list {
+            uwriteln!(body, "let mut borrows: List<() => Void> = []");
+        }
+        body.push_str(&f.src);
+        self.push_export(&shim, &params, &body, &export_name);
+
+        if abi::guest_export_needs_post_return(self.resolve, func) {
+            let mut locals = self.locals();
+            let params = sig
+                .results
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| (locals.tmp(&format!("arg{i}")), *ty))
+                .collect::<Vec<_>>();
+            let mut f = FunctionBindgen::new(self, locals);
+            f.params = params.iter().map(|(name, _)| name.clone()).collect();
+            abi::post_return(f.gen.resolve, func, &mut f);
+            let body = f.src;
+            let shim = self.gen.export_names.tmp(&format!("{shim}PostReturn"));
+            self.push_export(&shim, &params, &body, &format!("cabi_post_{export_name}"));
+        }
+        self.in_component = false;
+    }
+
+    /// Adds the function `func`, taking `params`, to the functions the
+    /// component module exports as `export_name`.
+    fn push_export(
+        &mut self,
+        func: &str,
+        params: &[(String, abi::WasmType)],
+        body: &str,
+        export_name: &str,
+    ) {
+        let params = params
+            .iter()
+            .map(|(name, ty)| format!("{name}: {}", wasm_type(*ty)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        uwriteln!(self.gen.exports, "\n@unsafe");
+        uwriteln!(self.gen.exports, "@externalName(\"{export_name}\")");
+        uwriteln!(self.gen.exports, "provide let {func} = ({params}) => {{");
+        self.gen.exports.push_str(body);
+        self.gen.exports.push_str("}\n");
+    }
+
+    /// Returns the parameters of the function for `func`, collecting the
+    /// names they're bound to in `params` if it's given.
+    ///
+    /// Types are referred to from the exports module if `qualified` is set.
+    fn signature(
+        &mut self,
+        func: &Function,
+        qualified: bool,
+        params: &mut Option<Vec<String>>,
+    ) -> String {
+        let in_component = std::mem::replace(&mut self.in_component, qualified);
+        let mut names = self.locals();
+        let mut decls = Vec::new();
+        for (i, (name, ty)) in func.params.iter().enumerate() {
+            let local = if i == 0 && matches!(func.kind, FunctionKind::Method(_)) {
+                "self".to_string()
+            } else {
+                names.tmp(&ident(name))
+            };
+            decls.push(format!("{local}: {}", self.type_name(ty)));
+            if let Some(params) = params {
+                params.push(local);
+            }
+        }
+        self.in_component = in_component;
+        format!("({})", decls.join(", "))
+    }
+
+    /// Returns the name of the function for `func`, which is placed in its
+    /// resource's submodule unless it's freestanding.
+    fn func_name(&self, func: &Function) -> String {
+        match func.kind {
+            FunctionKind::Freestanding => ident(&func.name),
+            FunctionKind::Constructor(_) => "make".to_string(),
+            FunctionKind::Method(_) | FunctionKind::Static(_) => {
+                let name = ident(func.item_name());
+                match name.as_str() {
+                    "new" | "rep" | "drop" | "make" | "dtor" => format!("{name}_"),
+                    _ => name,
+                }
+            }
+        }
+    }
+
+    fn docs(&mut self, docs: &Docs) {
+        if let Some(docs) = &docs.contents {
+            let lines = docs.trim().lines().collect::<Vec<_>>();
+            if let [line] = lines.as_slice() {
+                uwriteln!(self.src, "/** {} */", line.trim());
+                return;
+            }
+            uwriteln!(self.src, "/**");
+            for line in lines {
+                let line = line.trim();
+                if line.is_empty() {
+                    uwriteln!(self.src, " *");
+                } else {
+                    uwriteln!(self.src, " * {line}");
+                }
+            }
+            uwriteln!(self.src, " */");
+        }
+    }
+
+    pub(crate) fn type_name(&self, ty: &Type) -> String {
+        match ty {
+            Type::Bool => "Bool".to_string(),
+            Type::U8 => "Uint8".to_string(),
+            Type::S8 => "Int8".to_string(),
+            Type::U16 => "Uint16".to_string(),
+            Type::S16 => "Int16".to_string(),
+            Type::U32 => "Uint32".to_string(),
+            Type::S32 => "Int32".to_string(),
+            Type::U64 => "Uint64".to_string(),
+            Type::S64 => "Int64".to_string(),
+            Type::F32 => "Float32".to_string(),
+            Type::F64 => "Float64".to_string(),
+            Type::Char => "Char".to_string(),
+            Type::String => "String".to_string(),
+            Type::Id(id) => {
+                if self.resolve.types[*id].name.is_some() {
+                    self.qualified_name(*id)
+                } else {
+                    self.structural_name(*id)
+                }
+            }
+        }
+    }
+
+    /// Returns the structure of the type `id`, such as `Array<T>` for a list,
+    /// whether or not it's named.
+    fn structural_name(&self, id: TypeId) -> String {
+        let mut printer = TypePrinter {
+            gen: self,
+            name: String::new(),
+        };
+        printer.define_anonymous_type(id);
+        printer.name
+    }
+
+    fn optional_type_name(&self, ty: Option<&Type>) -> String {
+        ty.map_or("Void".to_string(), |ty| self.type_name(ty))
+    }
+
+    /// Returns the name of the named type `id`, qualified by the modules it's
+    /// in unless it's declared in the module being generated.
+    pub(crate) fn qualified_name(&self, id: TypeId) -> String {
+        let ty = &self.resolve.types[id];
+        let name = type_ident(ty.name.as_ref().unwrap());
+        self.qualify(&ty.owner, &name)
+    }
+
+    /// Returns `name`, which is declared by `owner`, qualified by the modules
+    /// it's in unless it's declared in the module being generated.
+    pub(crate) fn qualify(&self, owner: &TypeOwner, name: &str) -> String {
+        let mut path = String::new();
+        if self.in_component {
+            path.push_str(&self.gen.world);
+            path.push('.');
+        }
+        if let TypeOwner::Interface(iface) = owner {
+            let module = &self.gen.modules[iface];
+            if self.in_component || self.module.as_ref() != Some(module) {
+                path.push_str(module);
+                path.push('.');
+            }
+        }
+        path.push_str(name);
+        path
+    }
+
+    /// Returns the type of the value a function returning `results` returns.
+    pub(crate) fn results_type(&self, results: &Results) -> String {
+        let types = results.iter_types().collect::<Vec<_>>();
+        match types.as_slice() {
+            [] => "Void".to_string(),
+            [ty] => self.type_name(ty),
+            types => tuple_type(types.iter().map(|ty| self.type_name(ty))),
+        }
+    }
+
+    /// Returns whether handles to the resource `id` are exported.
+    pub(crate) fn is_exported_resource(&self, id: TypeId) -> bool {
+        self.gen.resources.get(&dealias(self.resolve, id)) == Some(&Direction::Export)
+    }
+
+    pub(crate) fn ret_area(&mut self, size: usize, align: usize) {
+        let area = if self.in_component {
+            &mut self.gen.export_ret_area
+        } else {
+            &mut self.gen.import_ret_area
+        };
+        area.0 = area.0.max(size);
+        area.1 = area.1.max(align);
+    }
+
+    pub(crate) fn sizes(&self) -> &SizeAlign {
+        &self.gen.sizes
+    }
+
+    fn type_alias_to(&mut self, name: &str, docs: &Docs, ty: String) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.src, "provide type {} = {ty}", type_ident(name));
+    }
+}
+
+impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
+    fn resolve(&self) -> &'a Resolve {
+        self.resolve
+    }
+
+    fn type_record(&mut self, _id: TypeId, name: &str, record: &Record, docs: &Docs) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.src, "provide record {} {{", type_ident(name));
+        for field in record.fields.iter() {
+            self.docs(&field.docs);
+            let ty = self.type_name(&field.ty);
+            uwriteln!(self.src, "{}: {ty},", ident(&field.name));
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_resource(&mut self, id: TypeId, name: &str, docs: &Docs) {
+        self.define_resource(id, &type_ident(name), docs);
+    }
+
+    fn type_flags(&mut self, _id: TypeId, name: &str, flags: &Flags, docs: &Docs) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.src, "provide record {} {{", type_ident(name));
+        for flag in flags.flags.iter() {
+            self.docs(&flag.docs);
+            uwriteln!(self.src, "{}: Bool,", ident(&flag.name));
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_tuple(&mut self, id: TypeId, name: &str, _tuple: &Tuple, docs: &Docs) {
+        let ty = self.structural_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_variant(&mut self, _id: TypeId, name: &str, variant: &Variant, docs: &Docs) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.src, "provide enum {} {{", type_ident(name));
+        for case in variant.cases.iter() {
+            self.docs(&case.docs);
+            let case_name = case.name.to_upper_camel_case();
+            match &case.ty {
+                Some(ty) => uwriteln!(self.src, "{case_name}({}),", self.type_name(ty)),
+                None => uwriteln!(self.src, "{case_name},"),
+            }
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_option(&mut self, id: TypeId, name: &str, _payload: &Type, docs: &Docs) {
+        let ty = self.structural_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_result(&mut self, id: TypeId, name: &str, _result: &Result_, docs: &Docs) {
+        let ty = self.structural_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_enum(&mut self, _id: TypeId, name: &str, enum_: &Enum, docs: &Docs) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.src, "provide enum {} {{", type_ident(name));
+        for case in enum_.cases.iter() {
+            self.docs(&case.docs);
+            uwriteln!(self.src, "{},", case.name.to_upper_camel_case());
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_alias(&mut self, _id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        let ty = self.type_name(ty);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_list(&mut self, id: TypeId, name: &str, _ty: &Type, docs: &Docs) {
+        let ty = self.structural_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_builtin(&mut self, _id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        let ty = self.type_name(ty);
+        self.type_alias_to(name, docs, ty);
+    }
+}
+
+/// Prints the type of an anonymous type.
+struct TypePrinter<'a, 'b> {
+    gen: &'b InterfaceGenerator<'a>,
+    name: String,
+}
+
+impl<'a> AnonymousTypeGenerator<'a> for TypePrinter<'a, '_> {
+    fn resolve(&self) -> &'a Resolve {
+        self.gen.resolve
+    }
+
+    fn anonymous_typ_type(&mut self, _id: TypeId, ty: &Type, _docs: &Docs) {
+        self.name = self.gen.type_name(ty);
+    }
+
+    fn anonymous_type_handle(&mut self, _id: TypeId, handle: &Handle, _docs: &Docs) {
+        // Borrowed handles to exported resources are lifted to the number
+        // representing the resource, since there's no handle to wrap.
+        self.name = match handle {
+            Handle::Borrow(resource) if self.gen.is_exported_resource(*resource) => {
+                "Number".to_string()
+            }
+            Handle::Own(resource) | Handle::Borrow(resource) => self
+                .gen
+                .qualified_name(dealias(self.gen.resolve, *resource)),
+        };
+    }
+
+    fn anonymous_type_tuple(&mut self, _id: TypeId, ty: &Tuple, _docs: &Docs) {
+        self.name = tuple_type(ty.types.iter().map(|ty| self.gen.type_name(ty)));
+    }
+
+    fn anonymous_type_option(&mut self, _id: TypeId, ty: &Type, _docs: &Docs) {
+        self.name = format!("Option<{}>", self.gen.type_name(ty));
+    }
+
+    fn anonymous_type_result(&mut self, _id: TypeId, ty: &Result_, _docs: &Docs) {
+        let ok = self.gen.optional_type_name(ty.ok.as_ref());
+        let err = self.gen.optional_type_name(ty.err.as_ref());
+        self.name = format!("Result<{ok}, {err}>");
+    }
+
+    fn anonymous_type_list(&mut self, _id: TypeId, ty: &Type, _docs: &Docs) {
+        self.name = match ty {
+            Type::U8 => "Bytes".to_string(),
+            _ => format!("Array<{}>", self.gen.type_name(ty)),
+        };
+    }
+
+    fn anonymous_type_future(&mut self, _id: TypeId, _ty: &Option<Type>, _docs: &Docs) {
+        unreachable!("worlds using futures and streams are rejected in `validate`")
+    }
+
+    fn anonymous_type_stream(&mut self, _id: TypeId, _ty: &Stream, _docs: &Docs) {
+        unreachable!("worlds using futures and streams are rejected in `validate`")
+    }
+}
+
+/// Returns the type of a tuple of values of `types`, which is `Void` for an
+/// empty tuple.
+pub(crate) fn tuple_type(types: impl ExactSizeIterator<Item = String>) -> String {
+    match types.len() {
+        0 => "Void".to_string(),
+        1 => format!("({},)", types.collect::<Vec<_>>()[0]),
+        _ => format!("({})", types.collect::<Vec<_>>().join(", ")),
+    }
+}
+
+/// Appends `body` to `dst`, indented by one level.
+fn indent_into(dst: &mut String, body: &str) {
+    for line in body.trim_start_matches('\n').lines() {
+        if !line.is_empty() {
+            dst.push_str("  ");
+        }
+        dst.push_str(line);
+        dst.push('\n');
+    }
+}
+
+/// Returns the name of the type or constructor for `name`.
+fn type_ident(name: &str) -> String {
+    name.to_upper_camel_case()
+}
+
+/// Returns the name of the function, field, parameter or record for `name`,
+/// appending `_` if it's a keyword.
+pub(crate) fn ident(name: &str) -> String {
+    let name = name.to_lower_camel_case();
+    let keywords = [
+        "abstract",
+        "and",
+        "as",
+        "assert",
+        "break",
+        "continue",
+        "else",
+        "enum",
+        "except",
+        "exception",
+        "fail",
+        "false",
+        "for",
+        "foreign",
+        "from",
+        "if",
+        "include",
+        "let",
+        "macro",
+        "match",
+        "module",
+        "mut",
+        "not",
+        "or",
+        "primitive",
+        "provide",
+        "rec",
+        "record",
+        "return",
+        "throw",
+        "true",
+        "type",
+        "use",
+        "void",
+        "wasm",
+        "when",
+        "while",
+    ];
+    if keywords.contains(&name.as_str()) {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
@@ -0,0 +1,36 @@
+use heck::*;
+use std::path::Path;
+use std::process::Command;
+
+macro_rules! codegen_test {
+    ($id:ident $name:tt $test:tt) => {
+        #[test]
+        fn $id() {
+            test_helpers::run_world_codegen_test(
+                "guest-grain",
+                $test.as_ref(),
+                |resolve, world, files| {
+                    wit_bindgen_grain::Opts {
+                        exports_module: None,
+                        generate_stub: true,
+                    }
+                    .build()
+                    .generate(resolve, world, files)
+                    .unwrap()
+                },
+                verify,
+            );
+        }
+    };
+}
+
+test_helpers::codegen_tests!();
+
+fn verify(dir: &Path, name: &str) {
+    // Compiling the component module also compiles the bindings and the
+    // generated exports stub it includes.
+    let mut cmd = Command::new("grain");
+    cmd.arg("compile");
+    cmd.arg(dir.join(format!("{}_component.gr", name.to_snake_case())));
+    test_helpers::run_command(&mut cmd);
+}
@@ -8,7 +8,7 @@ use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::{braced, token, LitStr, Token};
 use wit_bindgen_core::wit_parser::{PackageId, Resolve, UnresolvedPackageGroup, WorldId};
-use wit_bindgen_rust::{Opts, Ownership, WithOption};
+use wit_bindgen_rust::{AsyncConfig, Opts, Ownership, WithOption};
 
 #[proc_macro]
 pub fn generate(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -98,9 +98,11 @@ impl Parse for Config {
                     }
                     Opt::UseStdFeature => opts.std_feature = true,
                     Opt::RawStrings => opts.raw_strings = true,
+                    Opt::BorrowedExportArgs => opts.borrowed_export_args = true,
                     Opt::Ownership(ownership) => opts.ownership = ownership,
                     Opt::Skip(list) => opts.skip.extend(list.iter().map(|i| i.value())),
                     Opt::RuntimePath(path) => opts.runtime_path = Some(path.value()),
+                    Opt::Allocator(path) => opts.allocator = Some(path.value()),
                     Opt::BitflagsPath(path) => opts.bitflags_path = Some(path.value()),
                     Opt::Stubs => {
                         opts.stubs = true;
@@ -112,6 +114,8 @@ impl Parse for Config {
                             .map(|p| p.into_token_stream().to_string())
                             .collect()
                     }
+                    Opt::TypeAttributes(attrs) => opts.type_attributes.extend(attrs),
+                    Opt::Serde(enable) => opts.serde = enable.value(),
                     Opt::With(with) => opts.with.extend(with),
                     Opt::GenerateAll => {
                         opts.generate_all = true;
@@ -140,6 +144,8 @@ impl Parse for Config {
                     Opt::DisableCustomSectionLinkHelpers(disable) => {
                         opts.disable_custom_section_link_helpers = disable.value();
                     }
+                    Opt::Async(config) => opts.async_ = config,
+                    Opt::Host(enable) => opts.host = enable.value(),
                 }
             }
         } else {
@@ -154,6 +160,24 @@ impl Parse for Config {
             parse_source(&source, &features).map_err(|err| anyhow_to_syn(call_site, err))?;
         let world = select_world(&resolve, &pkgs, world.as_deref())
             .map_err(|e| anyhow_to_syn(call_site, e))?;
+        if !cfg!(feature = "async") {
+            if !matches!(opts.async_, AsyncConfig::None) {
+                return Err(Error::new(
+                    call_site,
+                    "the `async` option requires the `async` feature of `wit-bindgen`",
+                ));
+            }
+            if let Some(name) = wit_bindgen_core::find_future_or_stream_item(&resolve, world) {
+                return Err(Error::new(
+                    call_site,
+                    format!(
+                        "`{}` uses `future` or `stream` types, which require the \
+                         `async` feature of `wit-bindgen`",
+                        resolve.name_world_key(name),
+                    ),
+                ));
+            }
+        }
         Ok(Config {
             opts,
             resolve,
@@ -292,17 +316,21 @@ impl Config {
 mod kw {
     syn::custom_keyword!(std_feature);
     syn::custom_keyword!(raw_strings);
+    syn::custom_keyword!(borrowed_export_args);
     syn::custom_keyword!(skip);
     syn::custom_keyword!(world);
     syn::custom_keyword!(path);
     syn::custom_keyword!(inline);
     syn::custom_keyword!(ownership);
     syn::custom_keyword!(runtime_path);
+    syn::custom_keyword!(allocator);
     syn::custom_keyword!(bitflags_path);
     syn::custom_keyword!(exports);
     syn::custom_keyword!(stubs);
     syn::custom_keyword!(export_prefix);
     syn::custom_keyword!(additional_derives);
+    syn::custom_keyword!(type_attributes);
+    syn::custom_keyword!(serde);
     syn::custom_keyword!(with);
     syn::custom_keyword!(generate_all);
     syn::custom_keyword!(type_section_suffix);
@@ -313,6 +341,8 @@ mod kw {
     syn::custom_keyword!(generate_unused_types);
     syn::custom_keyword!(features);
     syn::custom_keyword!(disable_custom_section_link_helpers);
+    syn::custom_keyword!(imports);
+    syn::custom_keyword!(host);
 }
 
 #[derive(Clone)]
@@ -348,14 +378,18 @@ enum Opt {
     Inline(syn::LitStr),
     UseStdFeature,
     RawStrings,
+    BorrowedExportArgs,
     Skip(Vec<syn::LitStr>),
     Ownership(Ownership),
     RuntimePath(syn::LitStr),
+    Allocator(syn::LitStr),
     BitflagsPath(syn::LitStr),
     Stubs,
     ExportPrefix(syn::LitStr),
     // Parse as paths so we can take the concrete types/macro names rather than raw strings
     AdditionalDerives(Vec<syn::Path>),
+    TypeAttributes(Vec<(String, String)>),
+    Serde(syn::LitBool),
     With(HashMap<String, WithOption>),
     GenerateAll,
     TypeSectionSuffix(syn::LitStr),
@@ -366,6 +400,8 @@ enum Opt {
     GenerateUnusedTypes(syn::LitBool),
     Features(Vec<syn::LitStr>),
     DisableCustomSectionLinkHelpers(syn::LitBool),
+    Async(AsyncConfig),
+    Host(syn::LitBool),
 }
 
 impl Parse for Opt {
@@ -400,6 +436,9 @@ impl Parse for Opt {
         } else if l.peek(kw::raw_strings) {
             input.parse::<kw::raw_strings>()?;
             Ok(Opt::RawStrings)
+        } else if l.peek(kw::borrowed_export_args) {
+            input.parse::<kw::borrowed_export_args>()?;
+            Ok(Opt::BorrowedExportArgs)
         } else if l.peek(kw::ownership) {
             input.parse::<kw::ownership>()?;
             input.parse::<Token![:]>()?;
@@ -449,6 +488,10 @@ impl Parse for Opt {
             input.parse::<kw::runtime_path>()?;
             input.parse::<Token![:]>()?;
             Ok(Opt::RuntimePath(input.parse()?))
+        } else if l.peek(kw::allocator) {
+            input.parse::<kw::allocator>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Allocator(input.parse()?))
         } else if l.peek(kw::bitflags_path) {
             input.parse::<kw::bitflags_path>()?;
             input.parse::<Token![:]>()?;
@@ -467,6 +510,18 @@ impl Parse for Opt {
             syn::bracketed!(contents in input);
             let list = Punctuated::<_, Token![,]>::parse_terminated(&contents)?;
             Ok(Opt::AdditionalDerives(list.iter().cloned().collect()))
+        } else if l.peek(kw::type_attributes) {
+            input.parse::<kw::type_attributes>()?;
+            input.parse::<Token![:]>()?;
+            let contents;
+            let _lbrace = braced!(contents in input);
+            let fields: Punctuated<_, Token![,]> =
+                contents.parse_terminated(type_attributes_field_parse, Token![,])?;
+            Ok(Opt::TypeAttributes(fields.into_iter().flatten().collect()))
+        } else if l.peek(kw::serde) {
+            input.parse::<kw::serde>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Serde(input.parse()?))
         } else if l.peek(kw::with) {
             input.parse::<kw::with>()?;
             input.parse::<Token![:]>()?;
@@ -513,12 +568,45 @@ impl Parse for Opt {
             input.parse::<kw::disable_custom_section_link_helpers>()?;
             input.parse::<Token![:]>()?;
             Ok(Opt::DisableCustomSectionLinkHelpers(input.parse()?))
+        } else if l.peek(Token![async]) {
+            input.parse::<Token![async]>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Async(parse_async_config(input)?))
+        } else if l.peek(kw::host) {
+            input.parse::<kw::host>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::Host(input.parse()?))
         } else {
             Err(l.error())
         }
     }
 }
 
+/// Parses `"<types>": [Derive, #[attribute], ...]`, returning an entry of
+/// `Opts::type_attributes` for each derive and attribute.
+fn type_attributes_field_parse(input: ParseStream<'_>) -> Result<Vec<(String, String)>> {
+    let types = input.parse::<syn::LitStr>()?.value();
+    input.parse::<Token![:]>()?;
+    let contents;
+    syn::bracketed!(contents in input);
+    let mut values = Vec::new();
+    while !contents.is_empty() {
+        if contents.peek(Token![#]) {
+            for attr in syn::Attribute::parse_outer(&contents)? {
+                values.push(attr.into_token_stream().to_string());
+            }
+        } else {
+            let path = contents.parse::<syn::Path>()?;
+            values.push(path.into_token_stream().to_string());
+        }
+        if contents.is_empty() {
+            break;
+        }
+        contents.parse::<Token![,]>()?;
+    }
+    Ok(values.into_iter().map(|v| (types.clone(), v)).collect())
+}
+
 fn with_field_parse(input: ParseStream<'_>) -> Result<(String, WithOption)> {
     let interface = input.parse::<syn::LitStr>()?.value();
     input.parse::<Token![:]>()?;
@@ -571,3 +659,42 @@ fn fmt(input: &str) -> Result<String> {
     let syntax_tree = syn::parse_file(&input)?;
     Ok(prettyplease::unparse(&syntax_tree))
 }
+
+/// Parses the value of the `async` option which is either a boolean or a set
+/// of import and export names.
+fn parse_async_config(input: ParseStream<'_>) -> Result<AsyncConfig> {
+    if input.peek(syn::LitBool) {
+        return Ok(if input.parse::<syn::LitBool>()?.value {
+            AsyncConfig::All
+        } else {
+            AsyncConfig::None
+        });
+    }
+
+    let mut imports = Vec::new();
+    let mut exports = Vec::new();
+    let contents;
+    braced!(contents in input);
+    while !contents.is_empty() {
+        let l = contents.lookahead1();
+        let list = if l.peek(kw::imports) {
+            contents.parse::<kw::imports>()?;
+            &mut imports
+        } else if l.peek(kw::exports) {
+            contents.parse::<kw::exports>()?;
+            &mut exports
+        } else {
+            return Err(l.error());
+        };
+        contents.parse::<Token![:]>()?;
+        let names;
+        syn::bracketed!(names in contents);
+        let names = Punctuated::<LitStr, Token![,]>::parse_terminated(&names)?;
+        list.extend(names.iter().map(|name| name.value()));
+        if contents.is_empty() {
+            break;
+        }
+        contents.parse::<Token![,]>()?;
+    }
+    Ok(AsyncConfig::Some { imports, exports })
+}
@@ -0,0 +1,468 @@
+//! Runtime support for the component model async ABI.
+//!
+//! This module contains a small single-threaded executor which drives the
+//! futures created by bindings for functions which are lifted or lowered
+//! `async`. Each async export call gets its own task which is polled once when
+//! the export is first invoked and then again from the export's callback each
+//! time an event is delivered for a waitable the task is interested in.
+//!
+//! Futures are polled with a no-op waker. Instead of relying on wakeups every
+//! pending future in a task is polled again whenever the task receives an
+//! event, which is sufficient for the single-threaded environment bindings
+//! run in.
+//!
+//! Futures and streams passed to and from other components are represented by
+//! [`FutureReader`], [`FutureWriter`], [`StreamReader`] and [`StreamWriter`].
+
+use alloc::alloc::Layout;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+use core::future::{self, Future};
+use core::mem;
+use core::pin::Pin;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering::Relaxed};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+pub use future_support::{future_new, FutureReader, FutureVtable, FutureWrite, FutureWriter};
+pub use stream_support::{stream_new, StreamReader, StreamVtable, StreamWriter};
+
+mod future_support;
+mod stream_support;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + 'static>>;
+
+const STATUS_STARTING: u32 = 0;
+const STATUS_STARTED: u32 = 1;
+const STATUS_RETURNED: u32 = 2;
+
+const EVENT_NONE: u32 = 0;
+const EVENT_SUBTASK: u32 = 1;
+const EVENT_STREAM_READ: u32 = 2;
+const EVENT_STREAM_WRITE: u32 = 3;
+const EVENT_FUTURE_READ: u32 = 4;
+const EVENT_FUTURE_WRITE: u32 = 5;
+
+const BLOCKED: u32 = 0xffff_ffff;
+const COMPLETED: u32 = 0;
+
+const CALLBACK_CODE_EXIT: i32 = 0;
+const CALLBACK_CODE_YIELD: i32 = 1;
+const CALLBACK_CODE_WAIT: i32 = 2;
+
+/// The task currently being polled, if any.
+static CURRENT: AtomicPtr<Task> = AtomicPtr::new(ptr::null_mut());
+
+/// State of a single async export call, or of a `block_on` invocation.
+struct Task {
+    /// Futures belonging to this task which have not yet completed.
+    futures: RefCell<Vec<BoxFuture>>,
+
+    /// Futures spawned while this task was being polled which are moved into
+    /// `futures` once polling finishes.
+    spawned: RefCell<Vec<BoxFuture>>,
+
+    /// The waitable set, created on demand, which all waitables this task is
+    /// waiting on are joined to.
+    waitable_set: Cell<Option<u32>>,
+
+    /// Waitables this task is waiting on, mapped to the slot that the code of
+    /// their next event is delivered to.
+    waiting: RefCell<BTreeMap<u32, Rc<Cell<Option<u32>>>>>,
+}
+
+impl Task {
+    fn new(future: BoxFuture) -> Task {
+        Task {
+            futures: RefCell::new(alloc::vec![future]),
+            spawned: RefCell::new(Vec::new()),
+            waitable_set: Cell::new(None),
+            waiting: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Polls every pending future of this task, including any spawned in the
+    /// process, until none of them can make further progress.
+    fn poll(&self) {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let prev = CURRENT.swap(self as *const Task as *mut Task, Relaxed);
+
+        let mut futures = mem::take(&mut *self.futures.borrow_mut());
+        loop {
+            futures.retain_mut(|future| future.as_mut().poll(&mut cx).is_pending());
+            let mut spawned = mem::take(&mut *self.spawned.borrow_mut());
+            if spawned.is_empty() {
+                break;
+            }
+            futures.append(&mut spawned);
+        }
+        *self.futures.borrow_mut() = futures;
+
+        CURRENT.store(prev, Relaxed);
+    }
+
+    /// Returns the code to return to the runtime after this task has been
+    /// polled.
+    fn callback_code(&self) -> i32 {
+        if self.futures.borrow().is_empty() {
+            return CALLBACK_CODE_EXIT;
+        }
+        match self.waitable_set.get() {
+            Some(set) if !self.waiting.borrow().is_empty() => {
+                CALLBACK_CODE_WAIT | ((set as i32) << 4)
+            }
+            _ => CALLBACK_CODE_YIELD,
+        }
+    }
+
+    /// Registers interest in the next event for `waitable`, returning the slot
+    /// the event's code will be delivered to.
+    fn wait_for(&self, waitable: u32) -> Rc<Cell<Option<u32>>> {
+        let set = match self.waitable_set.get() {
+            Some(set) => set,
+            None => {
+                let set = unsafe { waitable_set_new() };
+                self.waitable_set.set(Some(set));
+                set
+            }
+        };
+        unsafe {
+            waitable_join(waitable, set);
+        }
+        let slot = Rc::new(Cell::new(None));
+        self.waiting.borrow_mut().insert(waitable, slot.clone());
+        slot
+    }
+
+    /// Withdraws interest in events for `waitable`, for example because the
+    /// operation being waited on was cancelled.
+    fn forget(&self, waitable: u32) {
+        if self.waiting.borrow_mut().remove(&waitable).is_some() {
+            unsafe {
+                waitable_join(waitable, 0);
+            }
+        }
+    }
+
+    /// Delivers an event received from the runtime to whichever future is
+    /// waiting on it.
+    fn deliver(&self, event: u32, waitable: u32, code: u32) {
+        match event {
+            EVENT_NONE => {}
+            EVENT_SUBTASK | EVENT_STREAM_READ | EVENT_STREAM_WRITE | EVENT_FUTURE_READ
+            | EVENT_FUTURE_WRITE => {
+                if let Some(slot) = self.waiting.borrow_mut().remove(&waitable) {
+                    unsafe {
+                        waitable_join(waitable, 0);
+                    }
+                    slot.set(Some(code));
+                }
+            }
+            other => unreachable!("unknown event {other}"),
+        }
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.futures.get_mut().clear();
+        self.spawned.get_mut().clear();
+        if let Some(set) = self.waitable_set.get() {
+            unsafe {
+                waitable_set_drop(set);
+            }
+        }
+    }
+}
+
+/// Returns the task currently being polled.
+fn current() -> &'static Task {
+    let task = CURRENT.load(Relaxed);
+    assert!(
+        !task.is_null(),
+        "async operations must run within an async export or `block_on`"
+    );
+    unsafe { &*task }
+}
+
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        raw()
+    }
+    fn noop(_: *const ()) {}
+    fn raw() -> RawWaker {
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(ptr::null(), &VTABLE)
+    }
+    unsafe { Waker::from_raw(raw()) }
+}
+
+/// Waits for the next event on `waitable` within the current task, returning
+/// the code of that event.
+pub(crate) async fn wait_on(waitable: u32) -> u32 {
+    let slot = current().wait_for(waitable);
+    future::poll_fn(|_| match slot.get() {
+        Some(code) => Poll::Ready(code),
+        None => Poll::Pending,
+    })
+    .await
+}
+
+/// A read or write on a future or stream which may still be in progress.
+struct Operation {
+    handle: u32,
+    state: OperationState,
+}
+
+enum OperationState {
+    Pending(Rc<Cell<Option<u32>>>),
+    Done(u32),
+}
+
+impl Operation {
+    /// Tracks an operation on `handle` which, when started, returned `code`.
+    fn new(handle: u32, code: u32) -> Operation {
+        let state = if code == BLOCKED {
+            OperationState::Pending(current().wait_for(handle))
+        } else {
+            OperationState::Done(code)
+        };
+        Operation { handle, state }
+    }
+
+    /// Returns the result code of this operation once it has completed.
+    fn poll(&mut self) -> Poll<u32> {
+        match &self.state {
+            OperationState::Pending(slot) => match slot.get() {
+                Some(code) => {
+                    self.state = OperationState::Done(code);
+                    Poll::Ready(code)
+                }
+                None => Poll::Pending,
+            },
+            OperationState::Done(code) => Poll::Ready(*code),
+        }
+    }
+
+    /// Cancels this operation if it's still in progress using `cancel`,
+    /// returning its final result code.
+    fn cancel(&mut self, cancel: unsafe extern "C" fn(u32) -> u32) -> u32 {
+        if let Poll::Ready(code) = self.poll() {
+            return code;
+        }
+        let task = CURRENT.load(Relaxed);
+        if !task.is_null() {
+            unsafe { (*task).forget(self.handle) };
+        }
+        let code = unsafe { cancel(self.handle) };
+        self.state = OperationState::Done(code);
+        code
+    }
+}
+
+/// Memory allocated for the values transferred by a read or write.
+struct Buffer {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Buffer {
+    /// Allocates room for `count` values of `layout`.
+    fn new(layout: Layout, count: usize) -> Buffer {
+        let layout = Layout::from_size_align(layout.size() * count, layout.align())
+            .expect("buffer too large");
+        let ptr = if layout.size() == 0 {
+            layout.align() as *mut u8
+        } else {
+            let ptr = unsafe { alloc::alloc::alloc(layout) };
+            if ptr.is_null() {
+                alloc::alloc::handle_alloc_error(layout);
+            }
+            ptr
+        };
+        Buffer { ptr, layout }
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        if self.layout.size() != 0 {
+            unsafe { alloc::alloc::dealloc(self.ptr, self.layout) }
+        }
+    }
+}
+
+/// Error returned when writing to a future or stream whose readable end has
+/// been dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReaderDropped;
+
+impl fmt::Display for ReaderDropped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the readable end was dropped")
+    }
+}
+
+/// Polls `future` for the first time on behalf of an async export.
+///
+/// Once `future` completes its result is passed to `fun` which is expected to
+/// call the export's `[task-return]` intrinsic. The returned value is the
+/// callback code to return from the export itself.
+#[doc(hidden)]
+pub fn first_poll<T: 'static>(
+    future: impl Future<Output = T> + 'static,
+    fun: impl FnOnce(T) + 'static,
+) -> i32 {
+    let task = Box::new(Task::new(Box::pin(async move { fun(future.await) })));
+    task.poll();
+    let code = task.callback_code();
+    if code != CALLBACK_CODE_EXIT {
+        unsafe {
+            context_set(Box::into_raw(task).cast());
+        }
+    }
+    code
+}
+
+/// Implementation of the `[callback]` export for async exports.
+///
+/// This delivers the event to the task previously stored by `first_poll` and
+/// polls it again.
+#[doc(hidden)]
+pub unsafe fn callback(event0: i32, event1: i32, event2: i32) -> i32 {
+    let task = context_get().cast::<Task>();
+    assert!(!task.is_null());
+    (*task).deliver(event0 as u32, event1 as u32, event2 as u32);
+    (*task).poll();
+    let code = (*task).callback_code();
+    if code == CALLBACK_CODE_EXIT {
+        context_set(ptr::null_mut());
+        drop(Box::from_raw(task));
+    }
+    code
+}
+
+/// Waits for an async-lowered import call, which returned `code`, to finish.
+///
+/// Once this returns the callee has written its results, if any, and has
+/// finished reading its parameters.
+#[doc(hidden)]
+pub async unsafe fn await_result(code: i32) {
+    let status = (code as u32) & 0xf;
+    let subtask = (code as u32) >> 4;
+    match status {
+        STATUS_RETURNED => {}
+        STATUS_STARTING | STATUS_STARTED => {
+            while wait_on(subtask).await != STATUS_RETURNED {}
+            subtask_drop(subtask);
+        }
+        other => unreachable!("unexpected subtask status {other}"),
+    }
+}
+
+/// Spawns `future` onto the task currently running.
+///
+/// The future is polled alongside the task's other futures and the task does
+/// not exit until all of them have completed, even if the async export which
+/// started the task has already returned its results.
+///
+/// # Panics
+///
+/// Panics if called outside of an async export or [`block_on`].
+pub fn spawn(future: impl Future<Output = ()> + 'static) {
+    current().spawned.borrow_mut().push(Box::pin(future));
+}
+
+/// Runs `future` to completion, blocking the current component instance
+/// until it finishes.
+///
+/// This is useful to call async imports from synchronous code, for example
+/// from a synchronous export. Any futures spawned with [`spawn`] which are
+/// still pending when `future` completes are dropped.
+pub fn block_on<T: 'static>(future: impl Future<Output = T> + 'static) -> T {
+    let result = Rc::new(Cell::new(None));
+    let task = Task::new(Box::pin({
+        let result = result.clone();
+        async move { result.set(Some(future.await)) }
+    }));
+    loop {
+        task.poll();
+        if let Some(result) = result.take() {
+            return result;
+        }
+        match task.waitable_set.get() {
+            Some(set) if !task.waiting.borrow().is_empty() => {
+                let mut payload = [0u32; 2];
+                let event = unsafe { waitable_set_wait(set, payload.as_mut_ptr()) };
+                task.deliver(event, payload[0], payload[1]);
+            }
+            _ => unsafe { yield_() },
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+#[link(wasm_import_module = "$root")]
+extern "C" {
+    #[link_name = "[waitable-set-new]"]
+    fn waitable_set_new() -> u32;
+    #[link_name = "[waitable-set-drop]"]
+    fn waitable_set_drop(set: u32);
+    #[link_name = "[waitable-set-wait]"]
+    fn waitable_set_wait(set: u32, payload: *mut u32) -> u32;
+    #[link_name = "[waitable-join]"]
+    fn waitable_join(waitable: u32, set: u32);
+    #[link_name = "[context-get-0]"]
+    fn context_get() -> *mut u8;
+    #[link_name = "[context-set-0]"]
+    fn context_set(value: *mut u8);
+    #[link_name = "[subtask-drop]"]
+    fn subtask_drop(subtask: u32);
+    #[link_name = "[yield]"]
+    fn yield_();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn waitable_set_new() -> u32 {
+    unreachable!()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn waitable_set_drop(_set: u32) {
+    unreachable!()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn waitable_set_wait(_set: u32, _payload: *mut u32) -> u32 {
+    unreachable!()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn waitable_join(_waitable: u32, _set: u32) {
+    unreachable!()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn context_get() -> *mut u8 {
+    unreachable!()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn context_set(_value: *mut u8) {
+    unreachable!()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn subtask_drop(_subtask: u32) {
+    unreachable!()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe fn yield_() {
+    unreachable!()
+}
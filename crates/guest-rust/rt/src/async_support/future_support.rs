@@ -0,0 +1,232 @@
+//! Runtime support for `future<T>` values.
+
+use super::{Buffer, Operation, ReaderDropped, COMPLETED};
+use alloc::alloc::Layout;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use core::task::{Context, Poll};
+
+/// Functions used to create, transfer and close futures with payloads of type
+/// `T`.
+///
+/// Bindings generate one of these for each payload type used in a `future`,
+/// and it's not intended to be constructed by hand.
+#[doc(hidden)]
+pub struct FutureVtable<T> {
+    /// Layout of the payload in linear memory.
+    pub layout: Layout,
+    /// Writes a value to memory, transferring ownership of any lists in it.
+    pub lower: unsafe fn(value: T, dst: *mut u8),
+    /// Releases lists written to memory by `lower`.
+    pub dealloc_lists: unsafe fn(dst: *mut u8),
+    /// Reads a value from memory, taking ownership of any lists in it.
+    pub lift: unsafe fn(src: *mut u8) -> T,
+    pub new: unsafe extern "C" fn() -> u64,
+    pub start_read: unsafe extern "C" fn(future: u32, dst: *mut u8) -> u32,
+    pub start_write: unsafe extern "C" fn(future: u32, src: *mut u8) -> u32,
+    pub cancel_read: unsafe extern "C" fn(future: u32) -> u32,
+    pub cancel_write: unsafe extern "C" fn(future: u32) -> u32,
+    pub drop_readable: unsafe extern "C" fn(future: u32),
+    pub drop_writable: unsafe extern "C" fn(future: u32),
+}
+
+/// Creates a new future, returning both of its ends.
+///
+/// # Safety
+///
+/// `vtable` must describe the payload type `T`.
+#[doc(hidden)]
+pub unsafe fn future_new<T>(
+    vtable: &'static FutureVtable<T>,
+) -> (FutureWriter<T>, FutureReader<T>) {
+    let handles = (vtable.new)();
+    (
+        FutureWriter::new((handles >> 32) as u32, vtable),
+        FutureReader::new(handles as u32, vtable),
+    )
+}
+
+/// The writable end of a `future<T>`.
+///
+/// A single value can be written with [`FutureWriter::write`]. Dropping the
+/// writer without writing a value closes the future, which the reader observes
+/// as `None`.
+pub struct FutureWriter<T: 'static> {
+    handle: u32,
+    vtable: &'static FutureVtable<T>,
+}
+
+impl<T> FutureWriter<T> {
+    /// Creates a writer from the raw `handle` of a writable end.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be an owned writable end of a future whose payload is
+    /// described by `vtable`.
+    #[doc(hidden)]
+    pub unsafe fn new(handle: u32, vtable: &'static FutureVtable<T>) -> Self {
+        Self { handle, vtable }
+    }
+
+    /// Writes `value` to this future.
+    ///
+    /// The returned future resolves once the reader has received the value,
+    /// or with an error if the reader was dropped first.
+    pub fn write(self, value: T) -> FutureWrite<T> {
+        let buffer = Buffer::new(self.vtable.layout, 1);
+        unsafe { (self.vtable.lower)(value, buffer.ptr) };
+        FutureWrite {
+            writer: self,
+            buffer,
+            operation: None,
+        }
+    }
+}
+
+impl<T> fmt::Debug for FutureWriter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FutureWriter")
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl<T> Drop for FutureWriter<T> {
+    fn drop(&mut self) {
+        unsafe { (self.vtable.drop_writable)(self.handle) }
+    }
+}
+
+/// A write in progress on a [`FutureWriter`], created by
+/// [`FutureWriter::write`].
+///
+/// Dropping this before it completes cancels the write.
+pub struct FutureWrite<T: 'static> {
+    writer: FutureWriter<T>,
+    buffer: Buffer,
+    operation: Option<Operation>,
+}
+
+impl<T> Future for FutureWrite<T> {
+    type Output = Result<(), ReaderDropped>;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = self.get_mut();
+        let operation = me.operation.get_or_insert_with(|| {
+            let code = unsafe { (me.writer.vtable.start_write)(me.writer.handle, me.buffer.ptr) };
+            Operation::new(me.writer.handle, code)
+        });
+        let code = match operation.poll() {
+            Poll::Ready(code) => code,
+            Poll::Pending => return Poll::Pending,
+        };
+        Poll::Ready(if code & 0xf == COMPLETED {
+            Ok(())
+        } else {
+            Err(ReaderDropped)
+        })
+    }
+}
+
+impl<T> Drop for FutureWrite<T> {
+    fn drop(&mut self) {
+        if let Some(operation) = &mut self.operation {
+            operation.cancel(self.writer.vtable.cancel_write);
+        }
+        // The reader gets its own copy of any lists, so they're released
+        // whether or not the value was received.
+        unsafe { (self.writer.vtable.dealloc_lists)(self.buffer.ptr) }
+    }
+}
+
+/// The readable end of a `future<T>`.
+///
+/// This is itself a [`Future`] which resolves to the value written to the
+/// future, or `None` if the writer was dropped without writing a value.
+pub struct FutureReader<T: 'static> {
+    handle: AtomicU32,
+    vtable: &'static FutureVtable<T>,
+    read: Option<(Buffer, Operation)>,
+}
+
+impl<T> FutureReader<T> {
+    /// Creates a reader from the raw `handle` of a readable end.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be an owned readable end of a future whose payload is
+    /// described by `vtable`.
+    #[doc(hidden)]
+    pub unsafe fn new(handle: u32, vtable: &'static FutureVtable<T>) -> Self {
+        Self {
+            handle: AtomicU32::new(handle),
+            vtable,
+            read: None,
+        }
+    }
+
+    /// Takes ownership of the raw handle of this reader, after which it
+    /// must not be used other than being dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a read is in progress.
+    #[doc(hidden)]
+    pub fn take_handle(&self) -> u32 {
+        assert!(self.read.is_none(), "cannot transfer a future being read");
+        self.handle.swap(u32::MAX, Relaxed)
+    }
+
+    fn handle(&self) -> u32 {
+        self.handle.load(Relaxed)
+    }
+}
+
+impl<T> fmt::Debug for FutureReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FutureReader")
+            .field("handle", &self.handle())
+            .finish()
+    }
+}
+
+impl<T> Future for FutureReader<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let me = self.get_mut();
+        let handle = me.handle();
+        let (buffer, operation) = me.read.get_or_insert_with(|| {
+            let buffer = Buffer::new(me.vtable.layout, 1);
+            let code = unsafe { (me.vtable.start_read)(handle, buffer.ptr) };
+            (buffer, Operation::new(handle, code))
+        });
+        let code = match operation.poll() {
+            Poll::Ready(code) => code,
+            Poll::Pending => return Poll::Pending,
+        };
+        let value = if code & 0xf == COMPLETED {
+            Some(unsafe { (me.vtable.lift)(buffer.ptr) })
+        } else {
+            None
+        };
+        me.read = None;
+        Poll::Ready(value)
+    }
+}
+
+impl<T> Drop for FutureReader<T> {
+    fn drop(&mut self) {
+        if let Some((buffer, mut operation)) = self.read.take() {
+            if operation.cancel(self.vtable.cancel_read) & 0xf == COMPLETED {
+                drop(unsafe { (self.vtable.lift)(buffer.ptr) });
+            }
+        }
+        let handle = self.handle();
+        if handle != u32::MAX {
+            unsafe { (self.vtable.drop_readable)(handle) }
+        }
+    }
+}
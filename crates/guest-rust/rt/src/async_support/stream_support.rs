@@ -0,0 +1,291 @@
+//! Runtime support for `stream<T>` values.
+
+use super::{Buffer, Operation, ReaderDropped, COMPLETED};
+use alloc::alloc::Layout;
+use alloc::vec::Vec;
+use core::fmt;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use core::task::{Context, Poll};
+use futures::{Sink, Stream};
+
+/// Maximum number of values read from a stream at once.
+const READ_CAPACITY: usize = 64;
+
+/// Functions used to create, transfer and close streams with elements of type
+/// `T`.
+///
+/// Bindings generate one of these for each element type used in a `stream`,
+/// and it's not intended to be constructed by hand.
+#[doc(hidden)]
+pub struct StreamVtable<T> {
+    /// Layout of a single element in linear memory.
+    pub layout: Layout,
+    /// Writes an element to memory, transferring ownership of any lists in it.
+    pub lower: unsafe fn(value: T, dst: *mut u8),
+    /// Releases lists written to memory by `lower`.
+    pub dealloc_lists: unsafe fn(dst: *mut u8),
+    /// Reads an element from memory, taking ownership of any lists in it.
+    pub lift: unsafe fn(src: *mut u8) -> T,
+    pub new: unsafe extern "C" fn() -> u64,
+    pub start_read: unsafe extern "C" fn(stream: u32, dst: *mut u8, len: usize) -> u32,
+    pub start_write: unsafe extern "C" fn(stream: u32, src: *mut u8, len: usize) -> u32,
+    pub cancel_read: unsafe extern "C" fn(stream: u32) -> u32,
+    pub cancel_write: unsafe extern "C" fn(stream: u32) -> u32,
+    pub drop_readable: unsafe extern "C" fn(stream: u32),
+    pub drop_writable: unsafe extern "C" fn(stream: u32),
+}
+
+/// Creates a new stream, returning both of its ends.
+///
+/// # Safety
+///
+/// `vtable` must describe the element type `T`.
+#[doc(hidden)]
+pub unsafe fn stream_new<T>(
+    vtable: &'static StreamVtable<T>,
+) -> (StreamWriter<T>, StreamReader<T>) {
+    let handles = (vtable.new)();
+    (
+        StreamWriter::new((handles >> 32) as u32, vtable),
+        StreamReader::new(handles as u32, vtable),
+    )
+}
+
+/// The writable end of a `stream<T>`.
+///
+/// Values are written through its [`Sink`] implementation, for example with
+/// `futures::SinkExt::send`. Each item sent is a batch of values which is
+/// completely received by the reader before the send completes, unless the
+/// reader is dropped first.
+pub struct StreamWriter<T: 'static> {
+    handle: u32,
+    vtable: &'static StreamVtable<T>,
+    write: Option<Write>,
+}
+
+/// A batch of values, already written to memory, being sent to the reader.
+struct Write {
+    buffer: Buffer,
+    len: usize,
+    written: usize,
+    operation: Operation,
+}
+
+impl<T> StreamWriter<T> {
+    /// Creates a writer from the raw `handle` of a writable end.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be an owned writable end of a stream whose elements are
+    /// described by `vtable`.
+    #[doc(hidden)]
+    pub unsafe fn new(handle: u32, vtable: &'static StreamVtable<T>) -> Self {
+        Self {
+            handle,
+            vtable,
+            write: None,
+        }
+    }
+
+    /// Drives the write in progress, if any, until it has been fully received
+    /// by the reader.
+    fn poll_write(&mut self) -> Poll<Result<(), ReaderDropped>> {
+        let size = self.vtable.layout.size();
+        loop {
+            let write = match &mut self.write {
+                Some(write) => write,
+                None => return Poll::Ready(Ok(())),
+            };
+            let code = match write.operation.poll() {
+                Poll::Ready(code) => code,
+                Poll::Pending => return Poll::Pending,
+            };
+            write.written += (code >> 4) as usize;
+            if code & 0xf == COMPLETED && write.written < write.len {
+                let code = unsafe {
+                    (self.vtable.start_write)(
+                        self.handle,
+                        write.buffer.ptr.add(write.written * size),
+                        write.len - write.written,
+                    )
+                };
+                write.operation = Operation::new(self.handle, code);
+                continue;
+            }
+            let write = self.write.take().unwrap();
+            self.dealloc_lists(&write);
+            return Poll::Ready(if write.written == write.len {
+                Ok(())
+            } else {
+                Err(ReaderDropped)
+            });
+        }
+    }
+
+    /// Releases the lists of all values in `write`, since the reader gets its
+    /// own copy of any it receives.
+    fn dealloc_lists(&self, write: &Write) {
+        let size = self.vtable.layout.size();
+        for i in 0..write.len {
+            unsafe { (self.vtable.dealloc_lists)(write.buffer.ptr.add(i * size)) }
+        }
+    }
+}
+
+impl<T> fmt::Debug for StreamWriter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamWriter")
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl<T> Sink<Vec<T>> for StreamWriter<T> {
+    type Error = ReaderDropped;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), ReaderDropped>> {
+        self.get_mut().poll_write()
+    }
+
+    fn start_send(self: Pin<&mut Self>, values: Vec<T>) -> Result<(), ReaderDropped> {
+        let me = self.get_mut();
+        assert!(me.write.is_none(), "`poll_ready` must be called first");
+        let size = me.vtable.layout.size();
+        let len = values.len();
+        let buffer = Buffer::new(me.vtable.layout, len);
+        for (i, value) in values.into_iter().enumerate() {
+            unsafe { (me.vtable.lower)(value, buffer.ptr.add(i * size)) }
+        }
+        let code = unsafe { (me.vtable.start_write)(me.handle, buffer.ptr, len) };
+        me.write = Some(Write {
+            buffer,
+            len,
+            written: 0,
+            operation: Operation::new(me.handle, code),
+        });
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), ReaderDropped>> {
+        self.get_mut().poll_write()
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), ReaderDropped>> {
+        self.get_mut().poll_write()
+    }
+}
+
+impl<T> Drop for StreamWriter<T> {
+    fn drop(&mut self) {
+        if let Some(mut write) = self.write.take() {
+            write.operation.cancel(self.vtable.cancel_write);
+            self.dealloc_lists(&write);
+        }
+        unsafe { (self.vtable.drop_writable)(self.handle) }
+    }
+}
+
+/// The readable end of a `stream<T>`.
+///
+/// Values are read through its [`Stream`] implementation, for example with
+/// `futures::StreamExt::next`, in batches of however many values the writer
+/// made available at once. The stream ends once the writer is dropped.
+pub struct StreamReader<T: 'static> {
+    handle: AtomicU32,
+    vtable: &'static StreamVtable<T>,
+    read: Option<(Buffer, Operation)>,
+    done: bool,
+}
+
+impl<T> StreamReader<T> {
+    /// Creates a reader from the raw `handle` of a readable end.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be an owned readable end of a stream whose elements are
+    /// described by `vtable`.
+    #[doc(hidden)]
+    pub unsafe fn new(handle: u32, vtable: &'static StreamVtable<T>) -> Self {
+        Self {
+            handle: AtomicU32::new(handle),
+            vtable,
+            read: None,
+            done: false,
+        }
+    }
+
+    /// Takes ownership of the raw handle of this reader, after which it
+    /// must not be used other than being dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a read is in progress.
+    #[doc(hidden)]
+    pub fn take_handle(&self) -> u32 {
+        assert!(self.read.is_none(), "cannot transfer a stream being read");
+        self.handle.swap(u32::MAX, Relaxed)
+    }
+
+    fn handle(&self) -> u32 {
+        self.handle.load(Relaxed)
+    }
+
+    /// Takes the values that a read of `code` wrote to `buffer`.
+    fn lift(&self, buffer: &Buffer, code: u32) -> Vec<T> {
+        let size = self.vtable.layout.size();
+        (0..(code >> 4) as usize)
+            .map(|i| unsafe { (self.vtable.lift)(buffer.ptr.add(i * size)) })
+            .collect()
+    }
+}
+
+impl<T> fmt::Debug for StreamReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamReader")
+            .field("handle", &self.handle())
+            .finish()
+    }
+}
+
+impl<T> Stream for StreamReader<T> {
+    type Item = Vec<T>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Vec<T>>> {
+        let me = self.get_mut();
+        let handle = me.handle();
+        loop {
+            if me.done {
+                return Poll::Ready(None);
+            }
+            let (_, operation) = me.read.get_or_insert_with(|| {
+                let buffer = Buffer::new(me.vtable.layout, READ_CAPACITY);
+                let code = unsafe { (me.vtable.start_read)(handle, buffer.ptr, READ_CAPACITY) };
+                (buffer, Operation::new(handle, code))
+            });
+            let code = match operation.poll() {
+                Poll::Ready(code) => code,
+                Poll::Pending => return Poll::Pending,
+            };
+            let (buffer, _) = me.read.take().unwrap();
+            me.done = code & 0xf != COMPLETED;
+            let values = me.lift(&buffer, code);
+            if !values.is_empty() {
+                return Poll::Ready(Some(values));
+            }
+        }
+    }
+}
+
+impl<T> Drop for StreamReader<T> {
+    fn drop(&mut self) {
+        if let Some((buffer, mut operation)) = self.read.take() {
+            let code = operation.cancel(self.vtable.cancel_read);
+            drop(self.lift(&buffer, code));
+        }
+        let handle = self.handle();
+        if handle != u32::MAX {
+            unsafe { (self.vtable.drop_readable)(handle) }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Runtime support for bindings generated in host mode.
+//!
+//! Host bindings call into, and are called from, a component's core wasm
+//! module directly rather than through an embedding such as wasmtime. All
+//! they need from the embedding is described by the [`Instance`] trait:
+//! access to the module's linear memory and the ability to call its core
+//! exports, including `cabi_realloc`.
+//!
+//! Values which don't match their type when lifted out of guest memory, such
+//! as an invalid `char` or an out-of-range discriminant, are reported as an
+//! [`InvalidValue`] converted into the embedding's error type.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A core wasm value passed to or returned from a core wasm function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Val {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+macro_rules! val_accessors {
+    ($($variant:ident $ty:ident $unwrap:ident,)*) => {
+        impl Val {
+            $(
+                /// Returns this value's payload, panicking if it's of a
+                /// different type.
+                pub fn $unwrap(&self) -> $ty {
+                    match self {
+                        Val::$variant(v) => *v,
+                        other => panic!(
+                            "expected a core wasm `{}`, found {other:?}",
+                            stringify!($ty),
+                        ),
+                    }
+                }
+            )*
+        }
+
+        $(
+            impl From<$ty> for Val {
+                fn from(v: $ty) -> Val {
+                    Val::$variant(v)
+                }
+            }
+        )*
+    };
+}
+
+val_accessors! {
+    I32 i32 unwrap_i32,
+    I64 i64 unwrap_i64,
+    F32 f32 unwrap_f32,
+    F64 f64 unwrap_f64,
+}
+
+/// The operations host bindings need from an instantiated core wasm module.
+///
+/// Guest pointers are passed around as the `i32` values they are in core
+/// wasm; [`Instance::read`] and [`Instance::write`] receive them converted
+/// to an unsigned address and are expected to bounds-check it against the
+/// module's memory.
+pub trait Instance {
+    /// The error produced when the guest traps or memory is accessed out of
+    /// bounds.
+    type Error: From<InvalidValue>;
+
+    /// Calls the core export `name` with `args`, returning its results.
+    fn call(&mut self, name: &str, args: &[Val]) -> Result<Vec<Val>, Self::Error>;
+
+    /// Fills `dst` with the bytes of linear memory starting at `address`.
+    fn read(&mut self, address: usize, dst: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Copies `src` into linear memory starting at `address`.
+    fn write(&mut self, address: usize, src: &[u8]) -> Result<(), Self::Error>;
+
+    /// Allocates guest memory through the module's `cabi_realloc` export.
+    fn realloc(
+        &mut self,
+        old_ptr: i32,
+        old_len: i32,
+        align: i32,
+        new_len: i32,
+    ) -> Result<i32, Self::Error> {
+        let args = [old_ptr, old_len, align, new_len].map(Val::I32);
+        Ok(self.call("cabi_realloc", &args)?[0].unwrap_i32())
+    }
+
+    /// Loads a little-endian `T` from `ptr + offset`.
+    fn load<T: Primitive>(&mut self, ptr: i32, offset: i32) -> Result<T, Self::Error> {
+        let mut bytes = T::Bytes::default();
+        self.read(address(ptr, offset), bytes.as_mut())?;
+        Ok(T::from_le_bytes(bytes))
+    }
+
+    /// Stores `val` in little-endian order at `ptr + offset`.
+    fn store<T: Primitive>(&mut self, ptr: i32, offset: i32, val: T) -> Result<(), Self::Error> {
+        self.write(address(ptr, offset), val.to_le_bytes().as_ref())
+    }
+
+    /// Reads `len` bytes starting at `ptr`.
+    fn read_bytes(&mut self, ptr: i32, len: i32) -> Result<Vec<u8>, Self::Error> {
+        let mut bytes = vec![0; len as u32 as usize];
+        self.read(address(ptr, 0), &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Writes `bytes` starting at `ptr`.
+    fn write_bytes(&mut self, ptr: i32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.write(address(ptr, 0), bytes)
+    }
+}
+
+fn address(ptr: i32, offset: i32) -> usize {
+    ptr as u32 as usize + offset as u32 as usize
+}
+
+/// A primitive value which can be loaded from and stored to guest memory.
+pub trait Primitive: Copy {
+    #[doc(hidden)]
+    type Bytes: Default + AsRef<[u8]> + AsMut<[u8]>;
+    #[doc(hidden)]
+    fn from_le_bytes(bytes: Self::Bytes) -> Self;
+    #[doc(hidden)]
+    fn to_le_bytes(self) -> Self::Bytes;
+}
+
+macro_rules! primitives {
+    ($($ty:ident)*) => ($(
+        impl Primitive for $ty {
+            type Bytes = [u8; core::mem::size_of::<$ty>()];
+
+            fn from_le_bytes(bytes: Self::Bytes) -> Self {
+                $ty::from_le_bytes(bytes)
+            }
+
+            fn to_le_bytes(self) -> Self::Bytes {
+                $ty::to_le_bytes(self)
+            }
+        }
+    )*)
+}
+
+primitives!(i8 u8 i16 u16 i32 u32 i64 u64 f32 f64);
+
+/// A value lifted from guest memory which isn't valid for its type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidValue {
+    what: &'static str,
+}
+
+impl InvalidValue {
+    #[doc(hidden)]
+    pub fn new(what: &'static str) -> InvalidValue {
+        InvalidValue { what }
+    }
+}
+
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} lifted from guest", self.what)
+    }
+}
+
+#[doc(hidden)]
+pub fn string_lift<E: From<InvalidValue>>(bytes: Vec<u8>) -> Result<String, E> {
+    String::from_utf8(bytes).map_err(|_| InvalidValue::new("string").into())
+}
+
+#[doc(hidden)]
+pub fn char_lift<E: From<InvalidValue>>(val: i32) -> Result<char, E> {
+    char::from_u32(val as u32).ok_or_else(|| InvalidValue::new("char").into())
+}
@@ -11,6 +11,86 @@ pub use bitflags;
 #[cfg(not(target_env = "p2"))]
 mod cabi_realloc;
 
+#[cfg(feature = "async")]
+pub mod async_support;
+
+pub mod host;
+
+/// Conversions between a Rust type and the type generated for a WIT type which
+/// is remapped to it through the `with` option of bindings generation.
+///
+/// `W` is the type generated for the WIT type. Values are lowered before
+/// they're passed to imports or returned from exports, and they're lifted
+/// after they're returned from imports or before they're passed to exports.
+pub trait LiftLower<W>: Sized {
+    /// Converts a value of the type generated for the WIT type into `Self`.
+    fn lift(wit: W) -> Self;
+
+    /// Converts `self` into a value of the type generated for the WIT type.
+    fn lower(self) -> W;
+}
+
+/// An allocator for the memory generated bindings use to lower values,
+/// configured through the `allocator` option of bindings generation.
+///
+/// This allows, for example, allocating the results of exports in an arena
+/// which is reset after their `post-return` has run.
+///
+/// # Safety
+///
+/// Implementations must uphold the same contract as
+/// [`GlobalAlloc`](core::alloc::GlobalAlloc). Layouts passed to `alloc` and
+/// `dealloc` never have a size of zero.
+pub unsafe trait Allocator {
+    /// Allocates memory as described by `layout`, returning null on failure.
+    ///
+    /// # Safety
+    ///
+    /// `layout` must have a non-zero size.
+    unsafe fn alloc(layout: core::alloc::Layout) -> *mut u8;
+
+    /// Deallocates `ptr`, which was returned by `alloc` with `layout`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `alloc` with the same `layout` and
+    /// not have been deallocated yet.
+    unsafe fn dealloc(ptr: *mut u8, layout: core::alloc::Layout);
+}
+
+/// An implementation of `cabi_realloc` allocating memory with `A`.
+///
+/// Bindings generated with the `allocator` option export this as the
+/// module's `cabi_realloc`, so that memory the canonical ABI allocates, such
+/// as that of lists and strings passed to exports, comes from `A` as well.
+///
+/// # Safety
+///
+/// The arguments must uphold the contract of `cabi_realloc` from the
+/// canonical ABI, where `old_ptr` was returned by this function.
+pub unsafe fn cabi_realloc_with<A: Allocator>(
+    old_ptr: *mut u8,
+    old_len: usize,
+    align: usize,
+    new_len: usize,
+) -> *mut u8 {
+    use core::alloc::Layout;
+
+    if new_len == 0 {
+        return align as *mut u8;
+    }
+    let layout = Layout::from_size_align_unchecked(new_len, align);
+    let ptr = A::alloc(layout);
+    if ptr.is_null() {
+        alloc::alloc::handle_alloc_error(layout);
+    }
+    if old_len != 0 {
+        core::ptr::copy_nonoverlapping(old_ptr, ptr, old_len.min(new_len));
+        A::dealloc(old_ptr, Layout::from_size_align_unchecked(old_len, align));
+    }
+    ptr
+}
+
 /// This function is called from generated bindings and will be deleted by
 /// the linker. The purpose of this function is to force a reference to the
 /// symbol `cabi_realloc` to make its way through to the final linker
@@ -674,6 +674,29 @@
 ///     // By default this set is empty.
 ///     additional_derives: [PartialEq, Eq, Hash, Clone],
 ///
+///     // Additional derives and attributes for specific types, for example
+///     // when only some types can implement a trait. Types are named
+///     // `<interface>/<type>`, or just `<type>` when defined in a world.
+///     // Naming an interface applies to all of its types and a trailing `*`
+///     // matches any suffix.
+///     //
+///     // These apply to records, variants, enums and flags. By default this
+///     // map is empty.
+///     type_attributes: {
+///         "wasi:http/types/method": [Hash],
+///         "my:pkg/my-interface": [#[non_exhaustive]],
+///     },
+///
+///     // Generates `serde::Serialize` and `serde::Deserialize` for records,
+///     // variants, enums and flags, using the WIT names of fields and cases.
+///     // Flags are serialized as lists of the names of the flags set. Types
+///     // containing resources are skipped, and types borrowing their contents
+///     // only implement `Serialize`. This requires a dependency on `serde`
+///     // with its `derive` feature.
+///     //
+///     // This defaults to `false`.
+///     serde: false,
+///
 ///     // When generating bindings for interfaces that are not defined in the
 ///     // same package as `world`, this option can be used to either generate
 ///     // those bindings or point to already generated bindings.
@@ -686,18 +709,24 @@
 ///     // already generated bindings then you can use the special `generate` value
 ///     // to have those bindings generated.
 ///     //
-///     // The `with` key only supports replacing types at the interface level
-///     // at this time.
-///     //
 ///     // When an interface is specified no bindings will be generated at
 ///     // all. It's assumed bindings are fully generated somewhere else. This is an
 ///     // indicator that any further references to types defined in these
 ///     // interfaces should use the upstream paths specified here instead.
 ///     //
+///     // A single type of an interface can also be specified, for example
+///     // `"my:pkg/types/uuid"`, to use an existing Rust type in its place
+///     // wherever it's used, including within other types such as records or
+///     // lists. The type is still generated and the Rust type must implement
+///     // `wit_bindgen::rt::LiftLower` to convert to and from it, as well as
+///     // `Clone` and `Debug`. Only records, variants, enums and flags without
+///     // resources can be remapped.
+///     //
 ///     // Any unused keys in this map are considered an error.
 ///     with: {
 ///         "wasi:io/poll": wasi::io::poll,
 ///         "some:package/my-interface": generate,
+///         "some:package/types/uuid": uuid::Uuid,
 ///     },
 ///
 ///     // Indicates that all interfaces not present in `with` should be assumed
@@ -781,6 +810,15 @@
 ///     // `wit-bindgen` crate itself.
 ///     runtime_path: "path::to::wit_bindgen",
 ///
+///     // Configures a type implementing `wit_bindgen::rt::Allocator` which
+///     // allocates the memory the bindings use to lower values, such as the
+///     // results of exports which are released in `post-return`, and which
+///     // is used for the module's `cabi_realloc` export. Lists and strings
+///     // are copied between it and the global allocator when they're lifted
+///     // and lowered, which only happens when this option is set. Only one
+///     // set of bindings in a component may set this option.
+///     allocator: "crate::MyArena",
+///
 ///     // Configure where the `bitflags` crate is located. By default this
 ///     // is `wit_bindgen::bitflags` which already reexports `bitflags` for
 ///     // you.
@@ -792,6 +830,12 @@
 ///     // UTF-8 validation.
 ///     raw_strings,
 ///
+///     // Indicates that exported functions receive `string` and `list<T>`
+///     // arguments as `&str` and `&[T]`, borrowed for the duration of the
+///     // call, instead of `String` and `Vec<T>`. The bindings free the
+///     // arguments' memory after the call returns.
+///     borrowed_export_args,
+///
 ///     // Emits `#[cfg(feature = "std")]` around `impl Error for ... {}` blocks
 ///     // for generated types. This is a niche option that is only here to
 ///     // support the standard library itself depending on this crate one day.
@@ -818,6 +862,48 @@
 ///     // used in library-like situations. This is `false` by default with
 ///     // `#[used]` statics being emitted.
 ///     disable_custom_section_link_helpers: false,
+///
+///     // Configures which functions use the component model async ABI. This
+///     // can be `true` or `false` to make all or none of them async, or it
+///     // can list the imports and exports that should be async by name. Names
+///     // are of the form `"my:pkg/iface#func"` for interface functions and
+///     // `"func"` for world functions, and a trailing `*` matches any name
+///     // with that prefix.
+///     //
+///     // Async imports are generated as `async fn`s and async exports are
+///     // generated as `async fn`s in their trait which are driven by the
+///     // executor in `wit_bindgen::rt::async_support`. Values of `future<T>`
+///     // and `stream<T>` types are `FutureReader<T>` and `StreamReader<T>`
+///     // from that module, and new ones are created with the `wit_future::new`
+///     // and `wit_stream::new` functions generated next to the functions
+///     // using them.
+///     //
+///     // Async functions as well as `future` and `stream` types require the
+///     // `async` feature of this crate, which is disabled by default.
+///     //
+///     // Note that `wit-component` can't yet encode `future` and `stream`
+///     // types, so no component type custom section is emitted for worlds
+///     // using them. Such modules must be turned into components with the
+///     // world passed to `wit-component` separately, for example with
+///     // `wasm-tools component embed`.
+///     //
+///     // This is `false` by default.
+///     async: {
+///         imports: ["my:pkg/iface#fetch"],
+///         exports: ["run"],
+///     },
+///
+///     // Generates bindings for a host which instantiates the component's core
+///     // wasm module itself, for example in a custom interpreter, instead of
+///     // for the guest. Each imported interface gets a `Host` trait for the
+///     // embedder to implement along with a `dispatch` function which calls it
+///     // with the core wasm arguments the guest passed, and exported functions
+///     // take a `&mut impl wit_bindgen::rt::host::Instance` through which they
+///     // call into the guest.
+///     //
+///     // Resources, futures, streams and async functions aren't supported in
+///     // host bindings. This is `false` by default.
+///     host: false,
 /// });
 /// ```
 ///
@@ -830,6 +916,9 @@ pub use wit_bindgen_rust_macro::generate;
 #[doc(hidden)]
 pub use rt::bitflags;
 
+#[cfg(feature = "async")]
+pub use rt::async_support::{block_on, spawn};
+
 mod pre_wit_bindgen_0_20_0;
 
 #[cfg(docsrs)]
@@ -851,5 +940,12 @@ pub mod rt {
     #[cfg(all(feature = "realloc", not(target_env = "p2")))]
     pub use wit_bindgen_rt::cabi_realloc;
 
+    #[cfg(feature = "async")]
+    pub use wit_bindgen_rt::async_support;
+
+    pub use wit_bindgen_rt::host;
+
+    pub use wit_bindgen_rt::{cabi_realloc_with, Allocator, LiftLower};
+
     pub use crate::pre_wit_bindgen_0_20_0::*;
 }
@@ -0,0 +1,667 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use wit_bindgen_core::abi::{self, AbiVariant, WasmType};
+use wit_bindgen_core::{dealias, wit_parser::*, Files, WorldGenerator};
+
+/// The version of the format of the generated JSON, which is bumped whenever
+/// it changes in a way that isn't backwards compatible.
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Default)]
+struct Json {
+    opts: Opts,
+    sizes: SizeAlign,
+    /// The names of the world's interfaces, including those of interfaces
+    /// declared inline in the world.
+    interface_names: HashMap<InterfaceId, String>,
+    /// The indices into `types` of the types described so far.
+    type_indices: HashMap<TypeId, usize>,
+    types: Vec<TypeDesc>,
+    imports: Vec<Item>,
+    exports: Vec<Item>,
+}
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct Opts {
+    /// Emit the JSON on a single line instead of pretty-printing it.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub compact: bool,
+}
+
+impl Opts {
+    pub fn build(&self) -> Box<dyn WorldGenerator> {
+        Box::new(Json {
+            opts: self.clone(),
+            ..Json::default()
+        })
+    }
+}
+
+/// The description of a world, which is the root of the generated JSON.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Document<'a> {
+    format: u32,
+    package: Option<String>,
+    world: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    docs: Option<&'a str>,
+    /// Every type used by the world, each of which is referred to by its
+    /// index. Types only refer to types before them.
+    types: &'a [TypeDesc],
+    imports: &'a [Item],
+    exports: &'a [Item],
+}
+
+/// A reference to a type, which is the name of a primitive type or the index
+/// of the type in `Document::types`.
+#[derive(Serialize, Clone, Copy)]
+#[serde(untagged)]
+enum TypeRef {
+    Primitive(&'static str),
+    Index(usize),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct TypeDesc {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<Owner>,
+    /// The canonical ABI size and alignment of the type on wasm32, which
+    /// resources don't have.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    align: Option<usize>,
+    #[serde(flatten)]
+    kind: TypeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    docs: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Owner {
+    Interface(String),
+    World(String),
+}
+
+#[derive(Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+enum TypeKind {
+    Record {
+        fields: Vec<Field>,
+    },
+    Resource,
+    Own {
+        resource: usize,
+    },
+    Borrow {
+        resource: usize,
+    },
+    Flags {
+        flags: Vec<String>,
+    },
+    Tuple {
+        types: Vec<TypeRef>,
+        offsets: Vec<usize>,
+    },
+    Variant {
+        cases: Vec<Case>,
+        payload_offset: usize,
+    },
+    Enum {
+        cases: Vec<String>,
+    },
+    Option {
+        #[serde(rename = "type")]
+        ty: TypeRef,
+        payload_offset: usize,
+    },
+    Result {
+        ok: Option<TypeRef>,
+        err: Option<TypeRef>,
+        payload_offset: usize,
+    },
+    List {
+        element: TypeRef,
+    },
+    Future {
+        #[serde(rename = "type")]
+        ty: Option<TypeRef>,
+    },
+    Stream {
+        element: Option<TypeRef>,
+        end: Option<TypeRef>,
+    },
+    Alias {
+        #[serde(rename = "type")]
+        ty: TypeRef,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Field {
+    name: String,
+    #[serde(rename = "type")]
+    ty: TypeRef,
+    offset: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Case {
+    name: String,
+    #[serde(rename = "type")]
+    ty: Option<TypeRef>,
+}
+
+#[derive(Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+enum Item {
+    Interface {
+        name: String,
+        /// The module the interface's functions are imported from, or the
+        /// prefix of the names they're exported under.
+        module: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        docs: Option<String>,
+        types: Vec<usize>,
+        resources: Vec<ResourceDesc>,
+        functions: Vec<Func>,
+    },
+    Function(Func),
+    Type {
+        name: String,
+        #[serde(rename = "type")]
+        ty: usize,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Func {
+    name: String,
+    /// Whether this is a freestanding function or belongs to a resource,
+    /// which isn't named `kind` as that's the tag of `Item::Function`.
+    function_kind: &'static str,
+    /// The resource a method, static function or constructor belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resource: Option<usize>,
+    params: Vec<Param>,
+    results: Vec<FuncResult>,
+    core: CoreFunc,
+    /// The name of the export freeing the results of an exported function,
+    /// if it needs one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_return: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    docs: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Param {
+    name: String,
+    #[serde(rename = "type")]
+    ty: TypeRef,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct FuncResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(rename = "type")]
+    ty: TypeRef,
+}
+
+/// A core wasm function, which is imported from `module` or exported if it
+/// has none.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct CoreFunc {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    module: Option<String>,
+    name: String,
+    params: Vec<&'static str>,
+    results: Vec<&'static str>,
+    indirect_params: bool,
+    retptr: bool,
+}
+
+/// The core wasm functions managing a resource.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ResourceDesc {
+    #[serde(rename = "type")]
+    ty: usize,
+    /// The intrinsics imported for the resource, such as `[resource-drop]x`.
+    intrinsics: Vec<CoreFunc>,
+    /// The export called when an exported resource is dropped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dtor: Option<String>,
+}
+
+impl WorldGenerator for Json {
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+        self.sizes.fill(resolve);
+
+        let world = &resolve.worlds[world];
+        for (key, item) in world.imports.iter().chain(world.exports.iter()) {
+            if let WorldItem::Interface { id, .. } = item {
+                self.interface_names
+                    .insert(*id, resolve.name_world_key(key));
+            }
+        }
+    }
+
+    fn import_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let item = self.interface(resolve, name, id, AbiVariant::GuestImport);
+        self.imports.push(item);
+        Ok(())
+    }
+
+    fn import_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) {
+        for (_, func) in funcs {
+            let func = self.func(resolve, None, func, AbiVariant::GuestImport);
+            self.imports.push(Item::Function(func));
+        }
+    }
+
+    fn import_types(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        types: &[(&str, TypeId)],
+        _files: &mut Files,
+    ) {
+        for (name, id) in types {
+            let ty = self.type_index(resolve, *id);
+            self.imports.push(Item::Type {
+                name: name.to_string(),
+                ty,
+            });
+        }
+    }
+
+    fn export_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let item = self.interface(resolve, name, id, AbiVariant::GuestExport);
+        self.exports.push(item);
+        Ok(())
+    }
+
+    fn export_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) -> Result<()> {
+        for (_, func) in funcs {
+            let func = self.func(resolve, None, func, AbiVariant::GuestExport);
+            self.exports.push(Item::Function(func));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) -> Result<()> {
+        let world = &resolve.worlds[world];
+        let document = Document {
+            format: FORMAT_VERSION,
+            package: world
+                .package
+                .map(|pkg| resolve.packages[pkg].name.to_string()),
+            world: &world.name,
+            docs: world.docs.contents.as_deref(),
+            types: &self.types,
+            imports: &self.imports,
+            exports: &self.exports,
+        };
+        let mut json = if self.opts.compact {
+            serde_json::to_string(&document)?
+        } else {
+            serde_json::to_string_pretty(&document)?
+        };
+        json.push('\n');
+        files.push(&format!("{}.json", world.name), json.as_bytes());
+        Ok(())
+    }
+}
+
+impl Json {
+    fn interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        variant: AbiVariant,
+    ) -> Item {
+        let iface = &resolve.interfaces[id];
+        let module = resolve.name_world_key(name);
+
+        let types = iface
+            .types
+            .values()
+            .map(|id| self.type_index(resolve, *id))
+            .collect();
+        let resources = iface
+            .types
+            .values()
+            .filter(|id| matches!(resolve.types[**id].kind, TypeDefKind::Resource))
+            .map(|id| self.resource(resolve, &module, *id, variant))
+            .collect();
+        let functions = iface
+            .functions
+            .values()
+            .map(|func| self.func(resolve, Some(&module), func, variant))
+            .collect();
+
+        Item::Interface {
+            name: module.clone(),
+            module,
+            docs: iface.docs.contents.clone(),
+            types,
+            resources,
+            functions,
+        }
+    }
+
+    fn resource(
+        &mut self,
+        resolve: &Resolve,
+        module: &str,
+        id: TypeId,
+        variant: AbiVariant,
+    ) -> ResourceDesc {
+        let name = resolve.types[id].name.as_deref().unwrap();
+        let intrinsic = |module: &str, intrinsic: &str, results: Vec<&'static str>| CoreFunc {
+            module: Some(module.to_string()),
+            name: format!("[resource-{intrinsic}]{name}"),
+            params: vec!["i32"],
+            results,
+            indirect_params: false,
+            retptr: false,
+        };
+        let (intrinsics, dtor) = match variant {
+            AbiVariant::GuestImport => (vec![intrinsic(module, "drop", vec![])], None),
+            AbiVariant::GuestExport => {
+                let module = format!("[export]{module}");
+                (
+                    vec![
+                        intrinsic(&module, "new", vec!["i32"]),
+                        intrinsic(&module, "rep", vec!["i32"]),
+                        intrinsic(&module, "drop", vec![]),
+                    ],
+                    Some(format!("{}#[dtor]{name}", &module["[export]".len()..])),
+                )
+            }
+        };
+        ResourceDesc {
+            ty: self.type_index(resolve, id),
+            intrinsics,
+            dtor,
+        }
+    }
+
+    /// Describes `func`, which is imported from or exported under `module`,
+    /// or by the world itself if it's `None`.
+    fn func(
+        &mut self,
+        resolve: &Resolve,
+        module: Option<&str>,
+        func: &Function,
+        variant: AbiVariant,
+    ) -> Func {
+        let (kind, resource) = match func.kind {
+            FunctionKind::Freestanding => ("freestanding", None),
+            FunctionKind::Method(id) => ("method", Some(id)),
+            FunctionKind::Static(id) => ("static", Some(id)),
+            FunctionKind::Constructor(id) => ("constructor", Some(id)),
+        };
+        let resource = resource.map(|id| self.type_index(resolve, id));
+
+        let params = func
+            .params
+            .iter()
+            .map(|(name, ty)| Param {
+                name: name.clone(),
+                ty: self.type_ref(resolve, ty),
+            })
+            .collect();
+        let results = match &func.results {
+            Results::Named(results) => results
+                .iter()
+                .map(|(name, ty)| FuncResult {
+                    name: Some(name.clone()),
+                    ty: self.type_ref(resolve, ty),
+                })
+                .collect(),
+            Results::Anon(ty) => vec![FuncResult {
+                name: None,
+                ty: self.type_ref(resolve, ty),
+            }],
+        };
+
+        let sig = resolve.wasm_signature(variant, func);
+        let (core_module, core_name, post_return) = match variant {
+            AbiVariant::GuestImport => (
+                Some(module.unwrap_or("$root").to_string()),
+                func.name.clone(),
+                None,
+            ),
+            AbiVariant::GuestExport => {
+                let name = func.core_export_name(module).into_owned();
+                let post_return = abi::guest_export_needs_post_return(resolve, func)
+                    .then(|| format!("cabi_post_{name}"));
+                (None, name, post_return)
+            }
+        };
+
+        Func {
+            name: func.name.clone(),
+            function_kind: kind,
+            resource,
+            params,
+            results,
+            core: CoreFunc {
+                module: core_module,
+                name: core_name,
+                params: sig.params.iter().map(|ty| wasm_type(*ty)).collect(),
+                results: sig.results.iter().map(|ty| wasm_type(*ty)).collect(),
+                indirect_params: sig.indirect_params,
+                retptr: sig.retptr,
+            },
+            post_return,
+            docs: func.docs.contents.clone(),
+        }
+    }
+
+    fn type_ref(&mut self, resolve: &Resolve, ty: &Type) -> TypeRef {
+        TypeRef::Primitive(match ty {
+            Type::Bool => "bool",
+            Type::U8 => "u8",
+            Type::U16 => "u16",
+            Type::U32 => "u32",
+            Type::U64 => "u64",
+            Type::S8 => "s8",
+            Type::S16 => "s16",
+            Type::S32 => "s32",
+            Type::S64 => "s64",
+            Type::F32 => "f32",
+            Type::F64 => "f64",
+            Type::Char => "char",
+            Type::String => "string",
+            Type::Id(id) => return TypeRef::Index(self.type_index(resolve, *id)),
+        })
+    }
+
+    /// Returns the index of the type `id`, describing it and the types it
+    /// refers to first if they haven't been already.
+    fn type_index(&mut self, resolve: &Resolve, id: TypeId) -> usize {
+        if let Some(index) = self.type_indices.get(&id) {
+            return *index;
+        }
+
+        let ty = &resolve.types[id];
+        let kind = match &ty.kind {
+            TypeDefKind::Record(record) => {
+                let offsets = self
+                    .sizes
+                    .field_offsets(record.fields.iter().map(|f| &f.ty));
+                let fields = record
+                    .fields
+                    .iter()
+                    .zip(offsets)
+                    .map(|(field, (offset, _))| Field {
+                        name: field.name.clone(),
+                        ty: self.type_ref(resolve, &field.ty),
+                        offset: offset.size_wasm32(),
+                    })
+                    .collect();
+                TypeKind::Record { fields }
+            }
+            TypeDefKind::Resource => TypeKind::Resource,
+            TypeDefKind::Handle(Handle::Own(resource)) => TypeKind::Own {
+                resource: self.type_index(resolve, *resource),
+            },
+            TypeDefKind::Handle(Handle::Borrow(resource)) => TypeKind::Borrow {
+                resource: self.type_index(resolve, *resource),
+            },
+            TypeDefKind::Flags(flags) => TypeKind::Flags {
+                flags: flags.flags.iter().map(|f| f.name.clone()).collect(),
+            },
+            TypeDefKind::Tuple(tuple) => TypeKind::Tuple {
+                types: tuple
+                    .types
+                    .iter()
+                    .map(|ty| self.type_ref(resolve, ty))
+                    .collect(),
+                offsets: self
+                    .sizes
+                    .field_offsets(tuple.types.iter())
+                    .into_iter()
+                    .map(|(offset, _)| offset.size_wasm32())
+                    .collect(),
+            },
+            TypeDefKind::Variant(variant) => TypeKind::Variant {
+                cases: variant
+                    .cases
+                    .iter()
+                    .map(|case| Case {
+                        name: case.name.clone(),
+                        ty: case.ty.as_ref().map(|ty| self.type_ref(resolve, ty)),
+                    })
+                    .collect(),
+                payload_offset: self
+                    .sizes
+                    .payload_offset(variant.tag(), variant.cases.iter().map(|c| c.ty.as_ref()))
+                    .size_wasm32(),
+            },
+            TypeDefKind::Enum(enum_) => TypeKind::Enum {
+                cases: enum_.cases.iter().map(|c| c.name.clone()).collect(),
+            },
+            TypeDefKind::Option(payload) => TypeKind::Option {
+                ty: self.type_ref(resolve, payload),
+                payload_offset: self
+                    .sizes
+                    .payload_offset(Int::U8, [None, Some(payload)])
+                    .size_wasm32(),
+            },
+            TypeDefKind::Result(result) => TypeKind::Result {
+                ok: result.ok.as_ref().map(|ty| self.type_ref(resolve, ty)),
+                err: result.err.as_ref().map(|ty| self.type_ref(resolve, ty)),
+                payload_offset: self
+                    .sizes
+                    .payload_offset(Int::U8, [result.ok.as_ref(), result.err.as_ref()])
+                    .size_wasm32(),
+            },
+            TypeDefKind::List(element) => TypeKind::List {
+                element: self.type_ref(resolve, element),
+            },
+            TypeDefKind::Future(ty) => TypeKind::Future {
+                ty: ty.as_ref().map(|ty| self.type_ref(resolve, ty)),
+            },
+            TypeDefKind::Stream(stream) => TypeKind::Stream {
+                element: stream.element.as_ref().map(|ty| self.type_ref(resolve, ty)),
+                end: stream.end.as_ref().map(|ty| self.type_ref(resolve, ty)),
+            },
+            TypeDefKind::Type(ty) => TypeKind::Alias {
+                ty: self.type_ref(resolve, ty),
+            },
+            TypeDefKind::Unknown => unreachable!(),
+        };
+
+        let owner = match ty.owner {
+            TypeOwner::Interface(iface) => {
+                Some(Owner::Interface(match self.interface_names.get(&iface) {
+                    Some(name) => name.clone(),
+                    None => resolve.id_of(iface).unwrap(),
+                }))
+            }
+            TypeOwner::World(world) => Some(Owner::World(resolve.worlds[world].name.clone())),
+            TypeOwner::None => None,
+        };
+        let (size, align) = match resolve.types[dealias(resolve, id)].kind {
+            TypeDefKind::Resource => (None, None),
+            _ => (
+                Some(self.sizes.size(&Type::Id(id)).size_wasm32()),
+                Some(self.sizes.align(&Type::Id(id)).align_wasm32()),
+            ),
+        };
+        let index = self.types.len();
+        self.types.push(TypeDesc {
+            name: ty.name.clone(),
+            owner,
+            size,
+            align,
+            kind,
+            docs: ty.docs.contents.clone(),
+        });
+        self.type_indices.insert(id, index);
+        index
+    }
+}
+
+fn wasm_type(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 => "i32",
+        WasmType::I64 => "i64",
+        WasmType::F32 => "f32",
+        WasmType::F64 => "f64",
+        WasmType::Pointer => "pointer",
+        WasmType::PointerOrI64 => "pointer-or-i64",
+        WasmType::Length => "length",
+    }
+}
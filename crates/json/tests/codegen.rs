@@ -0,0 +1,100 @@
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+macro_rules! codegen_test {
+    ($id:ident $name:tt $test:tt) => {
+        #[test]
+        fn $id() {
+            test_helpers::run_world_codegen_test(
+                "json",
+                $test.as_ref(),
+                |resolve, world, files| {
+                    wit_bindgen_json::Opts::default()
+                        .build()
+                        .generate(resolve, world, files)
+                        .unwrap()
+                },
+                verify,
+            );
+        }
+    };
+}
+
+test_helpers::codegen_tests!();
+
+fn verify(dir: &Path, name: &str) {
+    let json = fs::read(dir.join(format!("{name}.json"))).unwrap();
+    let document: Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(document["format"], 1);
+
+    // Types may only refer to the types before them, so that they can be
+    // defined in order.
+    let types = document["types"].as_array().unwrap();
+    for (index, ty) in types.iter().enumerate() {
+        for_each_index(ty, &mut |i| assert!(i < index, "{ty} refers to type {i}"));
+    }
+    for item in ["imports", "exports"] {
+        for_each_index(&document[item], &mut |i| assert!(i < types.len()));
+    }
+
+    // Freestanding functions are tagged as items, so their own kind must not
+    // be under the same key.
+    for item in ["imports", "exports"] {
+        for item in document[item].as_array().unwrap() {
+            if item["kind"] == "function" {
+                assert!(item["function-kind"].is_string(), "{item}");
+            }
+        }
+    }
+
+    // Keys are kebab-case, like the names of kinds.
+    for_each_key(&document, &mut |key| {
+        assert!(!key.contains('_'), "key `{key}` isn't kebab-case")
+    });
+}
+
+/// Calls `f` with each key of the objects in `value`.
+fn for_each_key(value: &Value, f: &mut impl FnMut(&str)) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                f(key);
+                for_each_key(value, f);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                for_each_key(value, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Calls `f` with each type index in `value`.
+fn for_each_index(value: &Value, f: &mut impl FnMut(usize)) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match (key.as_str(), value) {
+                    ("type" | "element" | "ok" | "err" | "end" | "resource", Value::Number(n)) => {
+                        f(n.as_u64().unwrap() as usize)
+                    }
+                    ("types", Value::Array(types)) => {
+                        for ty in types.iter().filter_map(|ty| ty.as_u64()) {
+                            f(ty as usize);
+                        }
+                    }
+                    _ => for_each_index(value, f),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                for_each_index(value, f);
+            }
+        }
+        _ => {}
+    }
+}
@@ -269,6 +269,10 @@ impl MoonBit {
 }
 
 impl WorldGenerator for MoonBit {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        wit_bindgen_core::reject_futures_and_streams(resolve, world, "MoonBit")
+    }
+
     fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
         self.name = world_name(resolve, world);
         self.sizes.fill(resolve);
@@ -757,6 +761,7 @@ impl InterfaceGenerator<'_> {
             LiftLower::LowerArgsLiftResults,
             func,
             &mut bindgen,
+            false,
         );
 
         let src = bindgen.src;
@@ -841,6 +846,7 @@ impl InterfaceGenerator<'_> {
             LiftLower::LiftArgsLowerResults,
             func,
             &mut bindgen,
+            false,
         );
 
         assert!(!bindgen.needs_cleanup_list);
@@ -1885,6 +1891,21 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 ));
             }
 
+            Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. }
+            | Instruction::Flush { .. } => {
+                unreachable!("async functions are never requested by this generator")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+
             Instruction::TupleLower { tuple, .. } => {
                 let op = &operands[0];
                 // Empty tuple is Unit
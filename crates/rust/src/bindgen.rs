@@ -1,8 +1,8 @@
-use crate::{int_repr, to_rust_ident, wasm_type, InterfaceGenerator, RustFlagsRepr};
+use crate::{int_repr, to_rust_ident, wasm_type, Identifier, InterfaceGenerator, RustFlagsRepr};
 use heck::*;
 use std::fmt::Write as _;
 use std::mem;
-use wit_bindgen_core::abi::{Bindgen, Instruction, LiftLower, WasmType};
+use wit_bindgen_core::abi::{self, Bindgen, Bitcast, Instruction, LiftLower, WasmType};
 use wit_bindgen_core::{dealias, uwrite, uwriteln, wit_parser::*, Source};
 
 pub(super) struct FunctionBindgen<'a, 'b> {
@@ -17,6 +17,10 @@ pub(super) struct FunctionBindgen<'a, 'b> {
     pub import_return_pointer_area_size: usize,
     pub import_return_pointer_area_align: usize,
     pub handle_decls: Vec<String>,
+    pub async_: bool,
+    /// In host mode, the core wasm results of the export called through
+    /// `CallWasm`, passed on to its post-return function.
+    wasm_results: Vec<String>,
 }
 
 impl<'a, 'b> FunctionBindgen<'a, 'b> {
@@ -36,22 +40,24 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
             import_return_pointer_area_size: 0,
             import_return_pointer_area_align: 0,
             handle_decls: Vec::new(),
+            async_: false,
+            wasm_results: Vec::new(),
         }
     }
 
     fn emit_cleanup(&mut self) {
         for (ptr, layout) in mem::take(&mut self.cleanup) {
-            let alloc = self.gen.path_to_std_alloc_module();
+            let dealloc = self.path_to_lowered_dealloc();
             self.push_str(&format!(
-                "if {layout}.size() != 0 {{\n{alloc}::dealloc({ptr}.cast(), {layout});\n}}\n"
+                "if {layout}.size() != 0 {{\n{dealloc}({ptr}.cast(), {layout});\n}}\n"
             ));
         }
         if self.needs_cleanup_list {
-            let alloc = self.gen.path_to_std_alloc_module();
+            let dealloc = self.path_to_lowered_dealloc();
             self.push_str(&format!(
                 "for (ptr, layout) in cleanup_list {{\n
                     if layout.size() != 0 {{\n
-                        {alloc}::dealloc(ptr.cast(), layout);\n
+                        {dealloc}(ptr.cast(), layout);\n
                     }}\n
                 }}\n",
             ));
@@ -142,7 +148,8 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
             results.push(arg);
         }
         self.push_str("} = ");
-        self.push_str(operand);
+        let operand = self.lower_remapped(id, operand);
+        self.push_str(&operand);
         self.push_str(";\n");
     }
 
@@ -162,7 +169,7 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
             result.push_str(",\n");
         }
         result.push('}');
-        results.push(result);
+        results.push(self.lift_remapped(id, result));
     }
 
     fn tuple_lower(&mut self, tuple: &Tuple, operand: &str, results: &mut Vec<String>) {
@@ -199,6 +206,124 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
         self.gen.type_path(id, true)
     }
 
+    /// Converts `operand`, if `id` is remapped through `with`, from the Rust
+    /// type it's remapped to into the type generated for it to be lowered.
+    fn lower_remapped(&self, id: TypeId, operand: &str) -> String {
+        let Some((path, wit)) = self.gen.remapped_type(id) else {
+            return operand.to_string();
+        };
+        let value = match self.lift_lower() {
+            // Arguments of imports are borrowed, so they're cloned to be
+            // converted.
+            LiftLower::LowerArgsLiftResults => {
+                format!("<{path} as ::core::clone::Clone>::clone(&{operand})")
+            }
+            LiftLower::LiftArgsLowerResults => operand.to_string(),
+        };
+        let rt = self.gen.gen.runtime_path();
+        format!("<{path} as {rt}::LiftLower<{wit}>>::lower({value})")
+    }
+
+    /// Converts the lifted `value`, if `id` is remapped through `with`, from
+    /// the type generated for it into the Rust type it's remapped to.
+    fn lift_remapped(&self, id: TypeId, value: String) -> String {
+        let Some((path, wit)) = self.gen.remapped_type(id) else {
+            return value;
+        };
+        let rt = self.gen.gen.runtime_path();
+        format!("<{path} as {rt}::LiftLower<{wit}>>::lift({value})")
+    }
+
+    /// Returns the path to the function allocating memory, given a `Layout`,
+    /// to lower values into.
+    fn path_to_lowered_alloc(&mut self) -> String {
+        match &self.gen.gen.opts.allocator {
+            Some(allocator) => {
+                let rt = self.gen.gen.runtime_path();
+                format!("<{allocator} as {rt}::Allocator>::alloc")
+            }
+            None => format!("{}::alloc", self.gen.path_to_std_alloc_module()),
+        }
+    }
+
+    /// Returns the path to the function deallocating memory allocated with
+    /// `path_to_lowered_alloc`, given the pointer and its `Layout`.
+    fn path_to_lowered_dealloc(&mut self) -> String {
+        match &self.gen.gen.opts.allocator {
+            Some(allocator) => {
+                let rt = self.gen.gen.runtime_path();
+                format!("<{allocator} as {rt}::Allocator>::dealloc")
+            }
+            None => format!("{}::dealloc", self.gen.path_to_std_alloc_module()),
+        }
+    }
+
+    /// Emits code deallocating `size` bytes at `ptr` which were allocated to
+    /// lower a value into, either by the bindings or by `cabi_realloc`.
+    fn push_lowered_dealloc(&mut self, ptr: &str, size: &str, align: usize) {
+        if self.gen.gen.opts.allocator.is_none() {
+            let dealloc = self.gen.path_to_cabi_dealloc();
+            uwriteln!(self.src, "{dealloc}({ptr}, {size}, {align});");
+            return;
+        }
+        let alloc = self.gen.path_to_std_alloc_module();
+        let dealloc = self.path_to_lowered_dealloc();
+        uwriteln!(
+            self.src,
+            "if {size} != 0 {{
+                {dealloc}({ptr}.cast(), {alloc}::Layout::from_size_align_unchecked({size}, {align}));
+            }}"
+        );
+    }
+
+    /// Emits code moving the contents of the list `vec`, with elements of
+    /// `size` bytes aligned to `align`, into memory from the configured
+    /// allocator, returning the pointer to it.
+    fn push_copy_to_allocator(&mut self, vec: &str, size: usize, align: usize) -> String {
+        let alloc = self.gen.path_to_std_alloc_module();
+        let lowered_alloc = self.path_to_lowered_alloc();
+        let tmp = self.tmp();
+        uwriteln!(
+            self.src,
+            "let ptr{tmp} = if {vec}.is_empty() {{
+                {vec}.as_ptr().cast::<u8>().cast_mut()
+            }} else {{
+                let layout = {alloc}::Layout::from_size_align_unchecked({vec}.len() * {size}, {align});
+                let ptr = {lowered_alloc}(layout);
+                if ptr.is_null() {{
+                    {alloc}::handle_alloc_error(layout);
+                }}
+                ::core::ptr::copy_nonoverlapping({vec}.as_ptr().cast::<u8>(), ptr, layout.size());
+                ptr
+            }};"
+        );
+        format!("ptr{tmp}")
+    }
+
+    /// Emits code copying the `len` elements, of `size` bytes aligned to
+    /// `align`, at `ptr` out of memory from the configured allocator into a
+    /// list, and releasing that memory, returning the list.
+    fn push_copy_from_allocator(
+        &mut self,
+        ptr: &str,
+        len: &str,
+        size: usize,
+        align: usize,
+    ) -> String {
+        let vec = self.gen.path_to_vec();
+        let tmp = self.tmp();
+        uwriteln!(
+            self.src,
+            "let vec{tmp} = if {len} == 0 {{
+                {vec}::new()
+            }} else {{
+                ::core::slice::from_raw_parts({ptr}.cast(), {len}).to_vec()
+            }};"
+        );
+        self.push_lowered_dealloc(ptr, &format!("{len} * {size}"), align);
+        format!("vec{tmp}")
+    }
+
     fn push_str(&mut self, s: &str) {
         self.src.push_str(s);
     }
@@ -210,7 +335,9 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
     }
 
     fn lift_lower(&self) -> LiftLower {
-        if self.gen.in_import {
+        // The host lowers the arguments to the guest's exports and lifts the
+        // arguments to its imports, the opposite of the guest.
+        if self.gen.in_import != self.gen.gen.opts.host {
             LiftLower::LowerArgsLiftResults
         } else {
             LiftLower::LiftArgsLowerResults
@@ -288,7 +415,12 @@ impl Bindgen for FunctionBindgen<'_, '_> {
     }
 
     fn is_list_canonical(&self, resolve: &Resolve, ty: &Type) -> bool {
-        if !resolve.all_bits_valid(ty) {
+        // The host can't assume its own layout of a type matches the guest's,
+        // so only lists of bytes are copied wholesale.
+        if self.gen.gen.opts.host {
+            return matches!(ty, Type::U8 | Type::S8);
+        }
+        if !resolve.all_bits_valid(ty) || self.gen.has_remapped_type(ty) {
             return false;
         }
         match ty {
@@ -304,6 +436,10 @@ impl Bindgen for FunctionBindgen<'_, '_> {
         operands: &mut Vec<String>,
         results: &mut Vec<String>,
     ) {
+        if self.gen.gen.opts.host && self.emit_host(resolve, inst, operands, results) {
+            return;
+        }
+
         let mut top_as = |cvt: &str| {
             let mut s = operands.pop().unwrap();
             s.push_str(" as ");
@@ -386,9 +522,10 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 ));
             }
 
-            Instruction::FlagsLower { flags, .. } => {
+            Instruction::FlagsLower { flags, ty, .. } => {
                 let tmp = self.tmp();
-                self.push_str(&format!("let flags{} = {};\n", tmp, operands[0]));
+                let operand = self.lower_remapped(*ty, &operands[0]);
+                self.push_str(&format!("let flags{} = {};\n", tmp, operand));
                 for i in 0..flags.repr().count() {
                     results.push(format!("(flags{}.bits() >> {}) as i32", tmp, i * 32));
                 }
@@ -403,7 +540,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                         i * 32
                     ));
                 }
-                results.push(result);
+                results.push(self.lift_remapped(*ty, result));
             }
 
             Instruction::HandleLower {
@@ -463,6 +600,26 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 self.record_lift(*ty, record, operands, results);
             }
 
+            Instruction::FutureLower { .. } | Instruction::StreamLower { .. } => {
+                results.push(format!("({}).take_handle() as i32", operands[0]));
+            }
+            Instruction::FutureLift { payload, .. } => {
+                let async_support = self.gen.path_to_async_support();
+                let payload = self.gen.type_name_owned(payload.as_ref());
+                results.push(format!(
+                    "{async_support}::FutureReader::new({} as u32, <{payload} as wit_future::FuturePayload>::VTABLE)",
+                    operands[0]
+                ));
+            }
+            Instruction::StreamLift { payload, .. } => {
+                let async_support = self.gen.path_to_async_support();
+                let payload = self.gen.type_name_owned(payload.element.as_ref());
+                results.push(format!(
+                    "{async_support}::StreamReader::new({} as u32, <{payload} as wit_stream::StreamPayload>::VTABLE)",
+                    operands[0]
+                ));
+            }
+
             Instruction::TupleLower { tuple, .. } => {
                 self.tuple_lower(tuple, &operands[0], results);
             }
@@ -491,7 +648,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                     name
                 };
                 self.let_results(result_types.len(), results);
-                let op0 = &operands[0];
+                let op0 = self.lower_remapped(*ty, &operands[0]);
                 self.push_str(&format!("match {op0} {{\n"));
                 for (case, block) in variant.cases.iter().zip(blocks) {
                     let case_name = case.name.to_upper_camel_case();
@@ -545,7 +702,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                     uwriteln!(self.src, "}}");
                 }
                 uwriteln!(self.src, "}};");
-                results.push(format!("v{tmp}"));
+                results.push(self.lift_remapped(*ty, format!("v{tmp}")));
             }
 
             Instruction::OptionLower {
@@ -621,8 +778,9 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 ));
             }
 
-            Instruction::EnumLower { .. } => {
-                results.push(format!("{}.clone() as i32", operands[0]));
+            Instruction::EnumLower { ty, .. } => {
+                let operand = self.lower_remapped(*ty, &operands[0]);
+                results.push(format!("{operand}.clone() as i32"));
             }
 
             Instruction::EnumLift { enum_, ty, .. } => {
@@ -630,14 +788,25 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 let repr = int_repr(enum_.tag());
                 let op = &operands[0];
                 let result = format!("{name}::_lift({op} as {repr})");
-                results.push(result);
+                results.push(self.lift_remapped(*ty, result));
             }
 
-            Instruction::ListCanonLower { realloc, .. } => {
+            Instruction::ListCanonLower { element, realloc } => {
                 let tmp = self.tmp();
                 let val = format!("vec{}", tmp);
                 let ptr = format!("ptr{}", tmp);
                 let len = format!("len{}", tmp);
+                // With a custom allocator the list's contents are copied into
+                // memory from it, to be released after the call.
+                if realloc.is_some() && self.gen.gen.opts.allocator.is_some() {
+                    uwriteln!(self.src, "let {val} = {};", operands[0]);
+                    let size = self.gen.sizes.size(element).size_wasm32();
+                    let align = self.gen.sizes.align(element).align_wasm32();
+                    let ptr = self.push_copy_to_allocator(&val, size, align);
+                    results.push(ptr);
+                    results.push(format!("{val}.len()"));
+                    return;
+                }
                 if realloc.is_none() {
                     self.push_str(&format!("let {} = {};\n", val, operands[0]));
                 } else {
@@ -653,10 +822,17 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 results.push(len);
             }
 
-            Instruction::ListCanonLift { .. } => {
+            Instruction::ListCanonLift { element, .. } => {
                 let tmp = self.tmp();
                 let len = format!("len{}", tmp);
                 self.push_str(&format!("let {} = {};\n", len, operands[1]));
+                if self.gen.gen.opts.allocator.is_some() {
+                    let size = self.gen.sizes.size(element).size_wasm32();
+                    let align = self.gen.sizes.align(element).align_wasm32();
+                    let vec = self.push_copy_from_allocator(&operands[0], &len, size, align);
+                    results.push(vec);
+                    return;
+                }
                 let vec = self.gen.path_to_vec();
                 let result = format!(
                     "{vec}::from_raw_parts({}.cast(), {1}, {1})",
@@ -670,6 +846,13 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 let val = format!("vec{}", tmp);
                 let ptr = format!("ptr{}", tmp);
                 let len = format!("len{}", tmp);
+                if realloc.is_some() && self.gen.gen.opts.allocator.is_some() {
+                    uwriteln!(self.src, "let {val} = {};", operands[0]);
+                    let ptr = self.push_copy_to_allocator(&val, 1, 1);
+                    results.push(ptr);
+                    results.push(format!("{val}.len()"));
+                    return;
+                }
                 if realloc.is_none() {
                     self.push_str(&format!("let {} = {};\n", val, operands[0]));
                 } else {
@@ -690,11 +873,16 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 let tmp = self.tmp();
                 let len = format!("len{}", tmp);
                 uwriteln!(self.src, "let {len} = {};", operands[1]);
-                uwriteln!(
-                    self.src,
-                    "let bytes{tmp} = {vec}::from_raw_parts({}.cast(), {len}, {len});",
-                    operands[0],
-                );
+                if self.gen.gen.opts.allocator.is_some() {
+                    let bytes = self.push_copy_from_allocator(&operands[0], &len, 1, 1);
+                    uwriteln!(self.src, "let bytes{tmp}: {vec}<u8> = {bytes};");
+                } else {
+                    uwriteln!(
+                        self.src,
+                        "let bytes{tmp} = {vec}::from_raw_parts({}.cast(), {len}, {len});",
+                        operands[0],
+                    );
+                }
                 if self.gen.gen.opts.raw_strings {
                     results.push(format!("bytes{tmp}"));
                 } else {
@@ -704,6 +892,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
 
             Instruction::ListLower { element, realloc } => {
                 let alloc = self.gen.path_to_std_alloc_module();
+                let lowered_alloc = self.path_to_lowered_alloc();
                 let body = self.blocks.pop().unwrap();
                 let tmp = self.tmp();
                 let vec = format!("vec{tmp}");
@@ -722,7 +911,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 ));
                 self.push_str(&format!("let {result} = if {layout}.size() != 0 {{\n"));
                 self.push_str(&format!(
-                    "let ptr = {alloc}::alloc({layout}).cast::<u8>();\n",
+                    "let ptr = {lowered_alloc}({layout}).cast::<u8>();\n",
                 ));
                 self.push_str(&format!(
                     "if ptr.is_null()\n{{\n{alloc}::handle_alloc_error({layout});\n}}\nptr\n}}",
@@ -770,8 +959,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 uwriteln!(self.src, "{result}.push(e{tmp});");
                 uwriteln!(self.src, "}}");
                 results.push(result);
-                let dealloc = self.gen.path_to_cabi_dealloc();
-                self.push_str(&format!("{dealloc}({base}, {len} * {size}, {align});\n",));
+                self.push_lowered_dealloc(&base, &format!("{len} * {size}"), align);
             }
 
             Instruction::IterElem { .. } => results.push("e".to_string()),
@@ -797,8 +985,41 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 self.push_str(");\n");
             }
 
-            Instruction::CallInterface { func, .. } => {
-                self.let_results(func.results.len(), results);
+            Instruction::AsyncCallWasm { name, sig } => {
+                let func = self.declare_import(
+                    self.gen.wasm_import_module.unwrap(),
+                    name,
+                    &sig.params,
+                    &sig.results,
+                );
+                let async_support = self.gen.path_to_async_support();
+                uwriteln!(
+                    self.src,
+                    "let ret = {func}({});\n\
+                     {async_support}::await_result(ret).await;",
+                    operands.join(", ")
+                );
+            }
+
+            Instruction::CallInterface { func, async_ } => {
+                if *async_ {
+                    let tmp = self.tmp();
+                    let result = format!("result{tmp}");
+                    // Arguments are lifted within the future, during its
+                    // first poll, so that borrowed handles live as long as
+                    // the call does. The result may itself be a future,
+                    // such as a `FutureReader`, which is returned as-is.
+                    let lift = mem::take(&mut self.src);
+                    uwriteln!(self.src, "#[allow(clippy::async_yields_async)]");
+                    uwriteln!(self.src, "let {result} = async move {{");
+                    for decl in mem::take(&mut self.handle_decls) {
+                        uwriteln!(self.src, "{decl}");
+                    }
+                    self.push_str(&lift);
+                    results.push(result);
+                } else {
+                    self.let_results(func.results.len(), results);
+                }
                 match &func.kind {
                     FunctionKind::Freestanding => {
                         self.push_str(&format!("T::{}", to_rust_ident(&func.name)));
@@ -823,7 +1044,15 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                         self.push_str(", ");
                     }
 
-                    self.push_str(operand);
+                    // Borrowed lists and strings are freed when the lifted
+                    // value is dropped after the call.
+                    let ty = &func.params[i].1;
+                    if self.gen.gen.opts.borrowed_export_args && self.gen.is_borrowed_export_arg(ty)
+                    {
+                        self.push_str(&format!("&{operand}"));
+                    } else {
+                        self.push_str(operand);
+                    }
 
                     // Automatically convert `Borrow<'_, AResource>` to
                     // `&Self` since traits have `&self` as their
@@ -833,14 +1062,67 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                     }
                 }
                 self.push_str(")");
+                if *async_ {
+                    self.push_str(".await");
+                }
                 if let FunctionKind::Constructor(_) = &func.kind {
                     self.push_str(")");
                 }
+                if *async_ {
+                    self.push_str(" }");
+                }
                 self.push_str(";\n");
             }
 
-            Instruction::Return { amt, .. } => {
+            Instruction::AsyncPostCallInterface { func } => {
+                let async_support = self.gen.path_to_async_support();
+                let names = (0..func.results.len())
+                    .map(|_| {
+                        let tmp = self.tmp();
+                        format!("result{tmp}")
+                    })
+                    .collect::<Vec<_>>();
+                let pattern = match names.len() {
+                    1 => names[0].clone(),
+                    _ => format!("({})", names.join(", ")),
+                };
+                uwriteln!(
+                    self.src,
+                    "let result = {async_support}::first_poll({}, move |{pattern}| {{",
+                    operands[0]
+                );
+                results.push("result".to_string());
+                results.extend(names);
+            }
+
+            Instruction::AsyncCallReturn { name, params } => {
                 self.emit_cleanup();
+                let module = match self.gen.identifier {
+                    Identifier::Interface(_, key) => {
+                        format!("[export]{}", self.gen.resolve.name_world_key(key))
+                    }
+                    Identifier::World(_) => "[export]$root".to_string(),
+                };
+                let func = self.declare_import(&module, name, params, &[]);
+                uwriteln!(self.src, "{func}({});", operands.join(", "));
+                self.push_str("});\n");
+            }
+
+            Instruction::Flush { amt } => {
+                for operand in operands.iter().take(*amt) {
+                    let tmp = self.tmp();
+                    let result = format!("result{tmp}");
+                    uwriteln!(self.src, "let {result} = {operand};");
+                    results.push(result);
+                }
+            }
+
+            Instruction::Return { amt, .. } => {
+                // Async exports have already cleaned up after handing their
+                // results to `[task-return]`.
+                if !self.async_ {
+                    self.emit_cleanup();
+                }
                 match amt {
                     0 => {}
                     1 => {
@@ -999,21 +1281,32 @@ impl Bindgen for FunctionBindgen<'_, '_> {
 
             Instruction::Malloc { .. } => unimplemented!(),
 
+            Instruction::AsyncMalloc { size, align } => {
+                let alloc = self.gen.path_to_std_alloc_module();
+                let lowered_alloc = self.path_to_lowered_alloc();
+                let tmp = self.tmp();
+                let ptr = format!("ptr{tmp}");
+                if *size == 0 {
+                    uwriteln!(self.src, "let {ptr} = {align} as *mut u8;");
+                } else {
+                    uwriteln!(
+                        self.src,
+                        "let layout{tmp} = {alloc}::Layout::from_size_align_unchecked({size}, {align});
+                         let {ptr} = {lowered_alloc}(layout{tmp});
+                         if {ptr}.is_null() {{
+                             {alloc}::handle_alloc_error(layout{tmp});
+                         }}"
+                    );
+                }
+                results.push(ptr);
+            }
+
             Instruction::GuestDeallocate { size, align } => {
-                let dealloc = self.gen.path_to_cabi_dealloc();
-                self.push_str(&format!(
-                    "{dealloc}({op}, {size}, {align});\n",
-                    op = operands[0]
-                ));
+                self.push_lowered_dealloc(&operands[0], &size.to_string(), *align);
             }
 
             Instruction::GuestDeallocateString => {
-                let dealloc = self.gen.path_to_cabi_dealloc();
-                self.push_str(&format!(
-                    "{dealloc}({op0}, {op1}, 1);\n",
-                    op0 = operands[0],
-                    op1 = operands[1],
-                ));
+                self.push_lowered_dealloc(&operands[0], &operands[1], 1);
             }
 
             Instruction::GuestDeallocateVariant { blocks } => {
@@ -1063,9 +1356,384 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                     self.push_str(&body);
                     self.push_str("\n}\n");
                 }
-                let dealloc = self.gen.path_to_cabi_dealloc();
-                self.push_str(&format!("{dealloc}({base}, {len} * {size}, {align});\n",));
+                self.push_lowered_dealloc(&base, &format!("{len} * {size}"), align);
+            }
+        }
+    }
+}
+
+impl FunctionBindgen<'_, '_> {
+    /// Emits `inst` for host bindings, returning `false` if it's generated the
+    /// same way as for guest bindings.
+    ///
+    /// Guest memory is only accessible through the `instance` implementing
+    /// `host::Instance`, so pointers and lengths are represented as the `i32`s
+    /// they are in core wasm and all loads, stores, allocations and calls go
+    /// through `instance`, propagating its errors with `?`.
+    fn emit_host(
+        &mut self,
+        resolve: &Resolve,
+        inst: &Instruction<'_>,
+        operands: &[String],
+        results: &mut Vec<String>,
+    ) -> bool {
+        let host = self.gen.path_to_host();
+        match inst {
+            Instruction::ConstZero { tys } => {
+                for ty in tys.iter() {
+                    results.push(
+                        match ty {
+                            WasmType::I32 | WasmType::Pointer | WasmType::Length => "0i32",
+                            WasmType::I64 | WasmType::PointerOrI64 => "0i64",
+                            WasmType::F32 => "0.0f32",
+                            WasmType::F64 => "0.0f64",
+                        }
+                        .to_string(),
+                    );
+                }
+            }
+            Instruction::Bitcasts { casts } => {
+                for (cast, operand) in casts.iter().zip(operands.iter()) {
+                    results.push(host_cast(operand, cast));
+                }
             }
+
+            Instruction::CharFromI32 => {
+                results.push(format!("{host}::char_lift({})?", operands[0]));
+            }
+            Instruction::BoolFromI32 => results.push(format!("{} != 0", operands[0])),
+
+            Instruction::OptionLift { .. } => {
+                let some = self.blocks.pop().unwrap();
+                let none = self.blocks.pop().unwrap();
+                assert_eq!(none, "()");
+                let invalid = self.invalid_value(&host, "option discriminant");
+                results.push(format!(
+                    "match {} {{
+                        0 => None,
+                        1 => {{
+                            let e = {some};
+                            Some(e)
+                        }}
+                        _ => {invalid},
+                    }}",
+                    operands[0]
+                ));
+            }
+            Instruction::ResultLift { .. } => {
+                let err = self.blocks.pop().unwrap();
+                let ok = self.blocks.pop().unwrap();
+                let invalid = self.invalid_value(&host, "result discriminant");
+                results.push(format!(
+                    "match {} {{
+                        0 => {{
+                            let e = {ok};
+                            Ok(e)
+                        }}
+                        1 => {{
+                            let e = {err};
+                            Err(e)
+                        }}
+                        _ => {invalid},
+                    }}",
+                    operands[0]
+                ));
+            }
+            Instruction::EnumLift { enum_, ty, .. } => {
+                let name = self.gen.type_path(*ty, true);
+                let invalid = self.invalid_value(&host, "enum discriminant");
+                let mut result = format!("match {} {{\n", operands[0]);
+                for (i, case) in enum_.cases.iter().enumerate() {
+                    let case = case.name.to_upper_camel_case();
+                    uwriteln!(result, "{i} => {name}::{case},");
+                }
+                uwrite!(result, "_ => {invalid},\n}}");
+                results.push(result);
+            }
+
+            Instruction::ListCanonLower { element, .. } => {
+                let tmp = self.tmp();
+                uwriteln!(self.src, "let vec{tmp} = {};", operands[0]);
+                uwriteln!(self.src, "let len{tmp} = vec{tmp}.len() as i32;");
+                uwriteln!(
+                    self.src,
+                    "let ptr{tmp} = instance.realloc(0, 0, 1, len{tmp})?;"
+                );
+                let bytes = match element {
+                    Type::U8 => format!("vec{tmp}.as_ref()"),
+                    Type::S8 => {
+                        let vec = self.gen.path_to_vec();
+                        format!("&vec{tmp}.iter().map(|b| *b as u8).collect::<{vec}<u8>>()")
+                    }
+                    _ => unreachable!("only lists of bytes are canonical on the host"),
+                };
+                uwriteln!(self.src, "instance.write_bytes(ptr{tmp}, {bytes})?;");
+                results.push(format!("ptr{tmp}"));
+                results.push(format!("len{tmp}"));
+            }
+            Instruction::ListCanonLift { element, .. } => {
+                let tmp = self.tmp();
+                uwriteln!(
+                    self.src,
+                    "let bytes{tmp} = instance.read_bytes({}, {})?;",
+                    operands[0],
+                    operands[1]
+                );
+                match element {
+                    Type::U8 => results.push(format!("bytes{tmp}")),
+                    Type::S8 => {
+                        let vec = self.gen.path_to_vec();
+                        results.push(format!(
+                            "bytes{tmp}.into_iter().map(|b| b as i8).collect::<{vec}<i8>>()"
+                        ));
+                    }
+                    _ => unreachable!("only lists of bytes are canonical on the host"),
+                }
+            }
+
+            Instruction::StringLower { .. } => {
+                let tmp = self.tmp();
+                uwriteln!(self.src, "let vec{tmp} = {};", operands[0]);
+                uwriteln!(self.src, "let len{tmp} = vec{tmp}.len() as i32;");
+                uwriteln!(
+                    self.src,
+                    "let ptr{tmp} = instance.realloc(0, 0, 1, len{tmp})?;
+                     instance.write_bytes(ptr{tmp}, vec{tmp}.as_ref())?;"
+                );
+                results.push(format!("ptr{tmp}"));
+                results.push(format!("len{tmp}"));
+            }
+            Instruction::StringLift => {
+                let tmp = self.tmp();
+                uwriteln!(
+                    self.src,
+                    "let bytes{tmp} = instance.read_bytes({}, {})?;",
+                    operands[0],
+                    operands[1]
+                );
+                if self.gen.gen.opts.raw_strings {
+                    results.push(format!("bytes{tmp}"));
+                } else {
+                    results.push(format!("{host}::string_lift(bytes{tmp})?"));
+                }
+            }
+
+            Instruction::ListLower { element, .. } => {
+                let body = self.blocks.pop().unwrap();
+                let tmp = self.tmp();
+                let size = self.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.sizes.align(element).align_wasm32();
+                uwriteln!(self.src, "let vec{tmp} = {};", operands[0]);
+                uwriteln!(self.src, "let len{tmp} = vec{tmp}.len() as i32;");
+                uwriteln!(
+                    self.src,
+                    "let result{tmp} = instance.realloc(0, 0, {align}, len{tmp} * {size})?;"
+                );
+                self.push_str(&format!(
+                    "for (i, e) in vec{tmp}.into_iter().enumerate() {{
+                        let base = result{tmp} + (i as i32) * {size};
+                        {body}
+                    }}\n"
+                ));
+                results.push(format!("result{tmp}"));
+                results.push(format!("len{tmp}"));
+            }
+            Instruction::ListLift { element, .. } => {
+                let body = self.blocks.pop().unwrap();
+                let tmp = self.tmp();
+                let size = self.gen.sizes.size(element).size_wasm32();
+                let vec = self.gen.path_to_vec();
+                self.push_str(&format!(
+                    "let base{tmp} = {};
+                     let len{tmp} = {};
+                     let mut result{tmp} = {vec}::new();
+                     for i in 0..len{tmp} as u32 {{
+                        let base = base{tmp}.wrapping_add((i as i32).wrapping_mul({size}));
+                        let e{tmp} = {body};
+                        result{tmp}.push(e{tmp});
+                     }}\n",
+                    operands[0], operands[1]
+                ));
+                results.push(format!("result{tmp}"));
+            }
+
+            Instruction::CallWasm { name, sig } => {
+                let export_name = self.export_name(name);
+                let tmp = self.tmp();
+                let args = operands
+                    .iter()
+                    .map(|op| format!("{host}::Val::from({op})"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                uwriteln!(
+                    self.src,
+                    "let ret{tmp} = instance.call(\"{export_name}\", &[{args}])?;"
+                );
+                for (i, ty) in sig.results.iter().enumerate() {
+                    let ty = crate::host::host_wasm_type(*ty);
+                    let result = format!("ret{tmp}_{i}");
+                    uwriteln!(self.src, "let {result} = ret{tmp}[{i}].unwrap_{ty}();");
+                    results.push(result.clone());
+                    self.wasm_results.push(result);
+                }
+            }
+
+            Instruction::CallInterface { func, .. } => {
+                self.let_results(func.results.len(), results);
+                uwriteln!(
+                    self.src,
+                    "host.{}({});",
+                    to_rust_ident(&func.name),
+                    operands.join(", ")
+                );
+            }
+
+            Instruction::Return { func, amt } => match self.lift_lower() {
+                // Lifted results are bound before calling the post-return
+                // function, which may free the memory they're read from.
+                LiftLower::LowerArgsLiftResults => {
+                    let tmp = self.tmp();
+                    let result = match amt {
+                        0 => "()".to_string(),
+                        1 => operands[0].clone(),
+                        _ => format!("({})", operands.join(", ")),
+                    };
+                    uwriteln!(self.src, "let result{tmp} = {result};");
+                    if abi::guest_export_needs_post_return(resolve, func) {
+                        let export_name = self.export_name(&func.name);
+                        let args = self
+                            .wasm_results
+                            .iter()
+                            .map(|ret| format!("{host}::Val::from({ret})"))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        uwriteln!(
+                            self.src,
+                            "instance.call(\"cabi_post_{export_name}\", &[{args}])?;"
+                        );
+                    }
+                    uwriteln!(self.src, "Ok(result{tmp})");
+                }
+                LiftLower::LiftArgsLowerResults => {
+                    let vec = self.gen.path_to_vec();
+                    match operands.first() {
+                        None => uwriteln!(self.src, "Ok({vec}::new())"),
+                        Some(op) => {
+                            uwriteln!(self.src, "Ok({vec}::from([{host}::Val::from({op})]))")
+                        }
+                    }
+                }
+            },
+
+            Instruction::I32Load { offset }
+            | Instruction::PointerLoad { offset }
+            | Instruction::LengthLoad { offset } => self.load("i32", operands, offset, results),
+            Instruction::I32Load8U { offset } => self.load("u8", operands, offset, results),
+            Instruction::I32Load8S { offset } => self.load("i8", operands, offset, results),
+            Instruction::I32Load16U { offset } => self.load("u16", operands, offset, results),
+            Instruction::I32Load16S { offset } => self.load("i16", operands, offset, results),
+            Instruction::I64Load { offset } => self.load("i64", operands, offset, results),
+            Instruction::F32Load { offset } => self.load("f32", operands, offset, results),
+            Instruction::F64Load { offset } => self.load("f64", operands, offset, results),
+
+            Instruction::I32Store { offset }
+            | Instruction::PointerStore { offset }
+            | Instruction::LengthStore { offset } => self.store("i32", operands, offset),
+            Instruction::I32Store8 { offset } => self.store("u8", operands, offset),
+            Instruction::I32Store16 { offset } => self.store("u16", operands, offset),
+            Instruction::I64Store { offset } => self.store("i64", operands, offset),
+            Instruction::F32Store { offset } => self.store("f32", operands, offset),
+            Instruction::F64Store { offset } => self.store("f64", operands, offset),
+
+            Instruction::Malloc { size, align, .. } => {
+                let tmp = self.tmp();
+                uwriteln!(
+                    self.src,
+                    "let ptr{tmp} = instance.realloc(0, 0, {align}, {size})?;"
+                );
+                results.push(format!("ptr{tmp}"));
+            }
+
+            Instruction::HandleLower { .. }
+            | Instruction::HandleLift { .. }
+            | Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. } => {
+                unreachable!("host bindings don't support resources or async")
+            }
+            Instruction::GuestDeallocate { .. }
+            | Instruction::GuestDeallocateString
+            | Instruction::GuestDeallocateList { .. }
+            | Instruction::GuestDeallocateVariant { .. } => {
+                unreachable!("the guest frees its own memory in post-return functions")
+            }
+
+            _ => return false,
+        }
+        true
+    }
+
+    fn load(&mut self, ty: &str, operands: &[String], offset: &i32, results: &mut Vec<String>) {
+        let tmp = self.tmp();
+        let load = format!("instance.load::<{ty}>({}, {offset})?", operands[0]);
+        // Narrow integers are widened to the `i32` they're loaded as.
+        if matches!(ty, "u8" | "i8" | "u16" | "i16") {
+            uwriteln!(self.src, "let l{tmp} = i32::from({load});");
+        } else {
+            uwriteln!(self.src, "let l{tmp} = {load};");
+        }
+        results.push(format!("l{tmp}"));
+    }
+
+    fn store(&mut self, ty: &str, operands: &[String], offset: &i32) {
+        uwriteln!(
+            self.src,
+            "instance.store::<{ty}>({}, {offset}, ({}) as {ty})?;",
+            operands[1],
+            operands[0]
+        );
+    }
+
+    /// Returns an expression which returns an error for an invalid `what`.
+    fn invalid_value(&self, host: &str, what: &str) -> String {
+        format!("return Err({host}::InvalidValue::new(\"{what}\").into())")
+    }
+
+    /// Returns the name of the core export for the function `name` in the
+    /// interface being generated.
+    fn export_name(&self, name: &str) -> String {
+        match self.gen.identifier {
+            Identifier::Interface(_, key) => {
+                format!("{}#{name}", self.gen.resolve.name_world_key(key))
+            }
+            Identifier::World(_) => name.to_string(),
+        }
+    }
+}
+
+fn host_cast(operand: &str, cast: &Bitcast) -> String {
+    match cast {
+        // Pointers and lengths are `i32`s and `PointerOrI64` is an `i64` on
+        // the host, so casts between them are plain integer conversions.
+        Bitcast::I64ToP64
+        | Bitcast::P64ToI64
+        | Bitcast::I32ToP
+        | Bitcast::LToP
+        | Bitcast::PToI32
+        | Bitcast::LToI32
+        | Bitcast::I32ToL
+        | Bitcast::PToL => operand.to_owned(),
+        Bitcast::PToP64 | Bitcast::LToI64 => format!("i64::from({operand})"),
+        Bitcast::P64ToP | Bitcast::I64ToL => format!("{operand} as i32"),
+        Bitcast::Sequence(sequence) => {
+            let [first, second] = &**sequence;
+            host_cast(&host_cast(operand, first), second)
         }
+        other => crate::perform_cast(operand, other),
     }
 }
@@ -0,0 +1,193 @@
+use crate::bindgen::FunctionBindgen;
+use crate::{FnSig, InterfaceGenerator};
+use heck::*;
+use std::fmt::Write as _;
+use wit_bindgen_core::abi::{self, AbiVariant, LiftLower, WasmType};
+use wit_bindgen_core::{uwrite, uwriteln, wit_parser::*};
+
+impl InterfaceGenerator<'_> {
+    /// Generates a `Host` trait for the embedder to implement `funcs`, which
+    /// the guest imports, along with a `dispatch` function which calls them
+    /// with the core wasm arguments passed by the guest.
+    pub(super) fn generate_host_imports<'a>(&mut self, funcs: impl Iterator<Item = &'a Function>) {
+        let funcs = funcs
+            .filter(|func| !self.gen.skip.contains(&func.name))
+            .collect::<Vec<_>>();
+        if funcs.is_empty() || self.gen.host_error.is_some() {
+            return;
+        }
+        let host = self.path_to_host();
+        let vec = self.path_to_vec();
+
+        uwriteln!(
+            self.src,
+            "/// Functions imported by the guest, implemented by the host."
+        );
+        uwriteln!(self.src, "pub trait Host {{");
+        for func in funcs.iter() {
+            let sig = FnSig {
+                private: true,
+                self_arg: Some("&mut self".into()),
+                ..Default::default()
+            };
+            self.print_signature(func, true, &sig);
+            self.src.push_str(";\n");
+        }
+        uwriteln!(self.src, "}}");
+
+        let module = self.wasm_import_module.unwrap();
+        uwrite!(
+            self.src,
+            "
+                /// The core wasm module name the guest imports these functions
+                /// from.
+                pub const MODULE: &str = \"{module}\";
+
+                /// Calls the implementation in `host` of the function the guest
+                /// imports from `MODULE` as `name` with the core wasm `args`
+                /// it was passed, returning its core wasm results.
+                ///
+                /// Returns `None` if no such function is imported.
+                pub fn dispatch<I: {host}::Instance, H: Host + ?Sized>(
+                    instance: &mut I,
+                    host: &mut H,
+                    name: &str,
+                    args: &[{host}::Val],
+                ) -> Option<Result<{vec}<{host}::Val>, I::Error>> {{
+                    Some(match name {{
+            "
+        );
+        for func in funcs.iter() {
+            let name = &func.name;
+            let snake = func.name.to_snake_case();
+            uwriteln!(
+                self.src,
+                "\"{name}\" => call_{snake}(instance, host, args),"
+            );
+        }
+        uwriteln!(self.src, "_ => return None,");
+        uwriteln!(self.src, "}})");
+        uwriteln!(self.src, "}}");
+
+        for func in funcs {
+            let snake = func.name.to_snake_case();
+            uwrite!(
+                self.src,
+                "
+                    #[doc(hidden)]
+                    #[allow(unused_unsafe, unused_variables, clippy::all)]
+                    pub fn call_{snake}<I: {host}::Instance, H: Host + ?Sized>(
+                        instance: &mut I,
+                        host: &mut H,
+                        args: &[{host}::Val],
+                    ) -> Result<{vec}<{host}::Val>, I::Error> {{
+                "
+            );
+            let sig = self.resolve.wasm_signature(AbiVariant::GuestImport, func);
+            let mut params = Vec::new();
+            for (i, param) in sig.params.iter().enumerate() {
+                let name = format!("arg{i}");
+                uwriteln!(
+                    self.src,
+                    "let {name} = args[{i}].unwrap_{}();",
+                    host_wasm_type(*param)
+                );
+                params.push(name);
+            }
+            self.src.push_str("unsafe {\n");
+            let mut f = FunctionBindgen::new(self, params);
+            abi::call(
+                f.gen.resolve,
+                AbiVariant::GuestImport,
+                LiftLower::LiftArgsLowerResults,
+                func,
+                &mut f,
+                false,
+            );
+            let src = f.src;
+            self.src.push_str(&src);
+            self.src.push_str("}\n");
+            self.src.push_str("}\n");
+        }
+    }
+
+    /// Generates functions which call `funcs`, exported by the guest, through
+    /// a `host::Instance` of its core wasm module.
+    pub(super) fn generate_host_exports<'a>(&mut self, funcs: impl Iterator<Item = &'a Function>) {
+        if self.gen.host_error.is_some() {
+            return;
+        }
+        let host = self.path_to_host();
+        for func in funcs {
+            if self.gen.skip.contains(&func.name) {
+                continue;
+            }
+            self.src
+                .push_str("#[allow(unused_unsafe, unused_variables, clippy::all)]\n");
+            let sig = FnSig {
+                generics: Some(format!("<I: {host}::Instance>")),
+                self_arg: Some("instance: &mut I".into()),
+                ..Default::default()
+            };
+            let params = self.print_docs_and_params(func, false, &sig);
+            let result = match func.results.len() {
+                0 => "()".to_string(),
+                1 => self.type_name_owned(func.results.iter_types().next()),
+                _ => {
+                    let tys = func
+                        .results
+                        .iter_types()
+                        .map(|ty| self.type_name_owned(Some(ty)))
+                        .collect::<Vec<_>>();
+                    format!("({})", tys.join(", "))
+                }
+            };
+            uwriteln!(self.src, " -> Result<{result}, I::Error> {{");
+            self.src.push_str("unsafe {\n");
+            let mut f = FunctionBindgen::new(self, params);
+            abi::call(
+                f.gen.resolve,
+                AbiVariant::GuestExport,
+                LiftLower::LowerArgsLiftResults,
+                func,
+                &mut f,
+                false,
+            );
+            let src = f.src;
+            self.src.push_str(&src);
+            self.src.push_str("}\n");
+            self.src.push_str("}\n");
+        }
+    }
+}
+
+/// Returns the name of the `host::Val` accessor for `ty`.
+pub(super) fn host_wasm_type(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 | WasmType::Pointer | WasmType::Length => "i32",
+        WasmType::I64 | WasmType::PointerOrI64 => "i64",
+        WasmType::F32 => "f32",
+        WasmType::F64 => "f64",
+    }
+}
+
+/// Returns why host bindings can't be generated for `world`, if they can't.
+pub(super) fn unsupported(resolve: &Resolve, world: WorldId) -> Option<String> {
+    let mut live = LiveTypes::default();
+    live.add_world(resolve, world);
+    for id in live.iter() {
+        let ty = &resolve.types[id];
+        let what = match ty.kind {
+            TypeDefKind::Resource | TypeDefKind::Handle(_) => "resources",
+            TypeDefKind::Future(_) => "futures",
+            TypeDefKind::Stream(_) => "streams",
+            _ => continue,
+        };
+        let name = match &ty.name {
+            Some(name) => format!("`{name}`"),
+            None => "an anonymous type".to_string(),
+        };
+        return Some(format!("host bindings don't support {what}, found {name}"));
+    }
+    None
+}
@@ -5,6 +5,7 @@ use crate::{
 };
 use anyhow::Result;
 use heck::*;
+use indexmap::IndexMap;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Write as _;
 use std::mem;
@@ -24,6 +25,66 @@ pub struct InterfaceGenerator<'a> {
     pub return_pointer_area_size: usize,
     pub return_pointer_area_align: usize,
     pub(super) needs_runtime_module: bool,
+    pub(super) payload_vtables: PayloadVtables,
+}
+
+/// Vtables generated for the payload types of the `future`s and `stream`s
+/// used within a module, keyed by the Rust name of the payload type.
+///
+/// Each vtable imports its intrinsics under the name of the first function
+/// using its payload type, such as `[future-new-0]first`. Future and stream
+/// types are structural, so the ends these create can be passed to any other
+/// function using the same payload type as well.
+#[derive(Default)]
+pub(super) struct PayloadVtables {
+    futures: IndexMap<String, String>,
+    streams: IndexMap<String, String>,
+}
+
+impl PayloadVtables {
+    fn get_mut(&mut self, kind: &str) -> &mut IndexMap<String, String> {
+        match kind {
+            "future" => &mut self.futures,
+            "stream" => &mut self.streams,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Emits the `wit_future` and `wit_stream` modules containing these
+    /// vtables, if any, to `src`.
+    pub(super) fn finish(self, src: &mut Source, async_support: &str) {
+        for (kind, vtables) in [("future", self.futures), ("stream", self.streams)] {
+            if vtables.is_empty() {
+                continue;
+            }
+            let camel = kind.to_upper_camel_case();
+            uwrite!(
+                src,
+                "
+                    /// Support for creating `{kind}`s of the payload types
+                    /// used in this module.
+                    pub mod wit_{kind} {{
+                        #![allow(dead_code, unused_variables, clippy::all)]
+                        use super::*;
+
+                        #[doc(hidden)]
+                        pub trait {camel}Payload: Sized + 'static {{
+                            const VTABLE: &'static {async_support}::{camel}Vtable<Self>;
+                        }}
+
+                        /// Creates a new `{kind}`, returning its writable and
+                        /// readable ends.
+                        pub fn new<T: {camel}Payload>() -> ({async_support}::{camel}Writer<T>, {async_support}::{camel}Reader<T>) {{
+                            unsafe {{ {async_support}::{kind}_new(T::VTABLE) }}
+                        }}
+                "
+            );
+            for vtable in vtables.values() {
+                src.push_str(vtable);
+            }
+            src.push_str("}\n");
+        }
+    }
 }
 
 /// A description of the "mode" in which a type is printed.
@@ -163,20 +224,28 @@ impl InterfaceGenerator<'_> {
                 | FunctionKind::Static(id) => Some(id),
             };
 
-            funcs_to_export.push((func, resource));
+            let async_ =
+                self.gen
+                    .is_async(self.resolve, interface.map(|(_, key)| key), func, false);
+            funcs_to_export.push((func, resource, async_));
             let (trait_name, methods) = traits.get_mut(&resource).unwrap();
-            self.generate_guest_export(func, &trait_name);
+            self.generate_guest_export(func, &trait_name, async_);
 
             let prev = mem::take(&mut self.src);
             let mut sig = FnSig {
+                async_,
                 use_item_name: true,
                 private: true,
+                borrowed_args: self.gen.opts.borrowed_export_args,
                 ..Default::default()
             };
             if let FunctionKind::Method(_) = &func.kind {
                 sig.self_arg = Some("&self".into());
                 sig.self_is_first_param = true;
             }
+            if async_ {
+                self.src.push_str("#[allow(async_fn_in_trait)]\n");
+            }
             self.print_signature(func, true, &sig);
             self.src.push_str(";\n");
             let trait_method = mem::replace(&mut self.src, prev);
@@ -292,7 +361,7 @@ macro_rules! {macro_name} {{
 "
         );
 
-        for (func, resource) in funcs_to_export {
+        for (func, resource, async_) in funcs_to_export {
             let ty = match resource {
                 None => "$ty".to_string(),
                 Some(id) => {
@@ -304,7 +373,7 @@ macro_rules! {macro_name} {{
                     format!("<$ty as $($path_to_types)*::Guest>::{name}")
                 }
             };
-            self.generate_raw_cabi_export(func, &ty, "$($path_to_types)*");
+            self.generate_raw_cabi_export(func, &ty, "$($path_to_types)*", async_);
         }
         let export_prefix = self.gen.opts.export_prefix.as_deref().unwrap_or("");
         for name in resources_to_drop {
@@ -377,6 +446,14 @@ macro_rules! {macro_name} {{
             );
         }
 
+        // Vtables for functions in the root module are collected across
+        // generators so they're all emitted in one place.
+        let payload_vtables = mem::take(&mut self.payload_vtables);
+        if let Identifier::Interface(..) = self.identifier {
+            let async_support = self.path_to_async_support();
+            payload_vtables.finish(&mut self.src, &async_support);
+        }
+
         let src = mem::take(&mut self.src).into();
 
         if self.needs_runtime_module {
@@ -425,7 +502,8 @@ macro_rules! {macro_name} {{
     pub fn finish_append_submodule(mut self, snake: &str, module_path: Vec<String>) {
         let module = self.finish();
         let path_to_root = self.path_to_root();
-        let used_static = if self.gen.opts.disable_custom_section_link_helpers {
+        let used_static = if self.gen.opts.disable_custom_section_link_helpers || self.gen.opts.host
+        {
             String::new()
         } else {
             format!(
@@ -459,7 +537,16 @@ macro_rules! {macro_name} {{
             return;
         }
 
-        let mut sig = FnSig::default();
+        let interface = match self.identifier {
+            Identifier::Interface(_, key) => Some(key),
+            Identifier::World(_) => None,
+        };
+        let async_ = self.gen.is_async(self.resolve, interface, func, true);
+        self.generate_futures_and_streams(func, self.wasm_import_module.unwrap());
+        let mut sig = FnSig {
+            async_,
+            ..Default::default()
+        };
         match func.kind {
             FunctionKind::Freestanding => {}
             FunctionKind::Method(id) | FunctionKind::Static(id) | FunctionKind::Constructor(id) => {
@@ -485,6 +572,7 @@ macro_rules! {macro_name} {{
             LiftLower::LowerArgsLiftResults,
             func,
             &mut f,
+            async_,
         );
         let FunctionBindgen {
             needs_cleanup_list,
@@ -523,19 +611,30 @@ macro_rules! {macro_name} {{
         }
     }
 
-    fn generate_guest_export(&mut self, func: &Function, trait_name: &str) {
+    fn generate_guest_export(&mut self, func: &Function, trait_name: &str, async_: bool) {
         let name_snake = func.name.to_snake_case().replace('.', "_");
+        // Futures returned by async trait methods are boxed by the runtime
+        // and so must not borrow from `T`.
+        let bound = if async_ { " + 'static" } else { "" };
         uwrite!(
             self.src,
             "\
                 #[doc(hidden)]
                 #[allow(non_snake_case)]
-                pub unsafe fn _export_{name_snake}_cabi<T: {trait_name}>\
+                pub unsafe fn _export_{name_snake}_cabi<T: {trait_name}{bound}>\
 ",
         );
-        let params = self.print_export_sig(func);
+        let params = self.print_export_sig(func, async_);
         self.push_str(" {");
 
+        let module = match self.identifier {
+            Identifier::Interface(_, key) => {
+                format!("[export]{}", self.resolve.name_world_key(key))
+            }
+            Identifier::World(_) => "[export]$root".to_string(),
+        };
+        self.generate_futures_and_streams(func, &module);
+
         if !self.gen.opts.disable_run_ctors_once_workaround {
             let run_ctors_once = self.path_to_run_ctors_once();
             // Before executing any other code, use this function to run all
@@ -553,12 +652,14 @@ macro_rules! {macro_name} {{
         }
 
         let mut f = FunctionBindgen::new(self, params);
+        f.async_ = async_;
         abi::call(
             f.gen.resolve,
             AbiVariant::GuestExport,
             LiftLower::LiftArgsLowerResults,
             func,
             &mut f,
+            async_,
         );
         let FunctionBindgen {
             needs_cleanup_list,
@@ -566,7 +667,13 @@ macro_rules! {macro_name} {{
             handle_decls,
             ..
         } = f;
-        assert!(!needs_cleanup_list);
+        // Results of async exports are lowered without transferring
+        // ownership, so they may need to be cleaned up like import arguments.
+        assert!(!needs_cleanup_list || async_);
+        if needs_cleanup_list {
+            let vec = self.path_to_vec();
+            uwriteln!(self.src, "let mut cleanup_list = {vec}::new();");
+        }
         for decl in handle_decls {
             self.src.push_str(&decl);
             self.src.push_str("\n");
@@ -574,7 +681,19 @@ macro_rules! {macro_name} {{
         self.src.push_str(&String::from(src));
         self.src.push_str("}\n");
 
-        if abi::guest_export_needs_post_return(self.resolve, func) {
+        if async_ {
+            let async_support = self.path_to_async_support();
+            uwrite!(
+                self.src,
+                "\
+                    #[doc(hidden)]
+                    #[allow(non_snake_case)]
+                    pub unsafe fn __callback_{name_snake}(event0: i32, event1: i32, event2: i32) -> i32 {{
+                        {async_support}::callback(event0, event1, event2)
+                    }}
+"
+            );
+        } else if abi::guest_export_needs_post_return(self.resolve, func) {
             uwrite!(
                 self.src,
                 "\
@@ -601,14 +720,31 @@ macro_rules! {macro_name} {{
         }
     }
 
-    fn generate_raw_cabi_export(&mut self, func: &Function, ty: &str, path_to_self: &str) {
+    fn generate_raw_cabi_export(
+        &mut self,
+        func: &Function,
+        ty: &str,
+        path_to_self: &str,
+        async_: bool,
+    ) {
         let name_snake = func.name.to_snake_case().replace('.', "_");
         let wasm_module_export_name = match self.identifier {
             Identifier::Interface(_, key) => Some(self.resolve.name_world_key(key)),
             Identifier::World(_) => None,
         };
-        let export_prefix = self.gen.opts.export_prefix.as_deref().unwrap_or("");
+        let export_prefix = self
+            .gen
+            .opts
+            .export_prefix
+            .as_deref()
+            .unwrap_or("")
+            .to_string();
         let export_name = func.core_export_name(wasm_module_export_name.as_deref());
+        let export_name = if async_ {
+            format!("[async-lift]{export_name}")
+        } else {
+            export_name.into_owned()
+        };
         uwrite!(
             self.src,
             "\
@@ -617,7 +753,7 @@ macro_rules! {macro_name} {{
 ",
         );
 
-        let params = self.print_export_sig(func);
+        let params = self.print_export_sig(func, async_);
         self.push_str(" {\n");
         uwriteln!(
             self.src,
@@ -626,7 +762,17 @@ macro_rules! {macro_name} {{
         );
         self.push_str("}\n");
 
-        if abi::guest_export_needs_post_return(self.resolve, func) {
+        if async_ {
+            uwrite!(
+                self.src,
+                "\
+                    #[export_name = \"{export_prefix}[callback]{export_name}\"]
+                    unsafe extern \"C\" fn _callback_{name_snake}(event0: i32, event1: i32, event2: i32) -> i32 {{
+                        {path_to_self}::__callback_{name_snake}(event0, event1, event2)
+                    }}
+"
+            );
+        } else if abi::guest_export_needs_post_return(self.resolve, func) {
             let export_prefix = self.gen.opts.export_prefix.as_deref().unwrap_or("");
             uwrite!(
                 self.src,
@@ -646,9 +792,175 @@ macro_rules! {macro_name} {{
         }
     }
 
-    fn print_export_sig(&mut self, func: &Function) -> Vec<String> {
+    /// Generates the vtables for the futures and streams used by `func`,
+    /// whose intrinsics are imported from `module`, if they haven't been
+    /// generated already for the same payload type.
+    fn generate_futures_and_streams(&mut self, func: &Function, module: &str) {
+        let types = abi::find_futures_and_streams(self.resolve, func);
+        for (index, ty) in types.into_iter().enumerate() {
+            let (kind, payload) = future_or_stream_payload(self.resolve, ty);
+            let name = self.type_name_owned(payload);
+            let count = self.payload_vtables_mut().get_mut(kind).len();
+            if self.payload_vtables_mut().get_mut(kind).contains_key(&name) {
+                continue;
+            }
+            let vtable = self.generate_payload_vtable(ty, &name, count, module, &func.name, index);
+            self.payload_vtables_mut()
+                .get_mut(kind)
+                .insert(name, vtable);
+        }
+    }
+
+    fn payload_vtables_mut(&mut self) -> &mut PayloadVtables {
+        match self.identifier {
+            Identifier::World(_) => &mut self.gen.payload_vtables,
+            Identifier::Interface(..) => &mut self.payload_vtables,
+        }
+    }
+
+    /// Generates the vtable for the `future` or `stream` type `id`, whose
+    /// payload's Rust type is `name`.
+    fn generate_payload_vtable(
+        &mut self,
+        id: TypeId,
+        name: &str,
+        index: usize,
+        module: &str,
+        func: &str,
+        func_index: usize,
+    ) -> String {
+        let (kind, ty) = future_or_stream_payload(self.resolve, id);
+        let async_support = self.path_to_async_support();
+        let alloc = self.path_to_std_alloc_module();
+        let camel = kind.to_upper_camel_case();
+        let (size, align) = match ty {
+            Some(ty) => (
+                self.sizes.size(ty).size_wasm32(),
+                self.sizes.align(ty).align_wasm32(),
+            ),
+            None => (0, 1),
+        };
+
+        let (lower, dealloc_lists, lift) = match ty {
+            Some(ty) => {
+                let mut f = FunctionBindgen::new(self, Vec::new());
+                abi::lower_to_memory(f.gen.resolve, &mut f, "dst".into(), "value".into(), ty);
+                let lower = String::from(mem::take(&mut f.src));
+                abi::deallocate_lists_in_memory(f.gen.resolve, &mut f, "dst".into(), ty);
+                let dealloc_lists = String::from(mem::take(&mut f.src));
+                let result = abi::lift_from_memory(f.gen.resolve, &mut f, "src".into(), ty);
+                let lift = format!("{}{result}", String::from(mem::take(&mut f.src)));
+                (lower, dealloc_lists, lift)
+            }
+            None => (String::new(), String::new(), "()".to_string()),
+        };
+
+        let len = if kind == "stream" { ", _: usize" } else { "" };
+        let intrinsics = [
+            ("new", "new", "()".to_string(), " -> u64"),
+            (
+                "read",
+                "start_read",
+                format!("(_: u32, _: *mut u8{len})"),
+                " -> u32",
+            ),
+            (
+                "write",
+                "start_write",
+                format!("(_: u32, _: *mut u8{len})"),
+                " -> u32",
+            ),
+            (
+                "cancel-read",
+                "cancel_read",
+                "(_: u32)".to_string(),
+                " -> u32",
+            ),
+            (
+                "cancel-write",
+                "cancel_write",
+                "(_: u32)".to_string(),
+                " -> u32",
+            ),
+            ("drop-readable", "drop_readable", "(_: u32)".to_string(), ""),
+            ("drop-writable", "drop_writable", "(_: u32)".to_string(), ""),
+        ];
+        let mut imports = String::new();
+        let mut stubs = String::new();
+        for (intrinsic, rust_name, params, result) in intrinsics.iter() {
+            uwriteln!(
+                imports,
+                "#[link_name = \"[{kind}-{intrinsic}-{func_index}]{func}\"]
+                 fn {rust_name}{params}{result};"
+            );
+            uwriteln!(
+                stubs,
+                "#[cfg(not(target_arch = \"wasm32\"))]
+                 unsafe extern \"C\" fn {rust_name}{params}{result} {{ unreachable!() }}"
+            );
+        }
+        let fields = intrinsics
+            .iter()
+            .map(|(_, rust_name, ..)| *rust_name)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(
+            "
+                #[doc(hidden)]
+                pub mod vtable{index} {{
+                    use super::super::*;
+
+                    unsafe fn lower(value: {name}, dst: *mut u8) {{
+                        {lower}
+                    }}
+
+                    unsafe fn dealloc_lists(dst: *mut u8) {{
+                        {dealloc_lists}
+                    }}
+
+                    unsafe fn lift(src: *mut u8) -> {name} {{
+                        {lift}
+                    }}
+
+                    #[cfg(target_arch = \"wasm32\")]
+                    #[link(wasm_import_module = \"{module}\")]
+                    extern \"C\" {{
+                        {imports}
+                    }}
+
+                    {stubs}
+
+                    pub static VTABLE: {async_support}::{camel}Vtable<{name}> = {async_support}::{camel}Vtable {{
+                        layout: unsafe {{ {alloc}::Layout::from_size_align_unchecked({size}, {align}) }},
+                        lower,
+                        dealloc_lists,
+                        lift,
+                        {fields},
+                    }};
+                }}
+
+                impl {camel}Payload for {name} {{
+                    const VTABLE: &'static {async_support}::{camel}Vtable<Self> = &vtable{index}::VTABLE;
+                }}
+            "
+        )
+    }
+
+    /// Returns the owned Rust type for `ty`, or `()` if it's absent.
+    pub fn type_name_owned(&mut self, ty: Option<&Type>) -> String {
+        let prev = mem::take(&mut self.src);
+        self.print_optional_ty(ty, TypeMode::owned());
+        String::from(mem::replace(&mut self.src, prev))
+    }
+
+    fn print_export_sig(&mut self, func: &Function, async_: bool) -> Vec<String> {
         self.src.push_str("(");
-        let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
+        let sig = if async_ {
+            abi::async_wasm_signature(self.resolve, AbiVariant::GuestExport, func)
+        } else {
+            self.resolve.wasm_signature(AbiVariant::GuestExport, func)
+        };
         let mut params = Vec::new();
         for (i, param) in sig.params.iter().enumerate() {
             let name = format!("arg{}", i);
@@ -703,7 +1015,7 @@ macro_rules! {macro_name} {{
 
                     let resource_methods = funcs.remove(&Some(*id)).unwrap_or(Vec::new());
                     let trait_name = format!("{path}::Guest{camel}");
-                    self.generate_stub_impl(&trait_name, "", &resource_methods);
+                    self.generate_stub_impl(interface, &trait_name, "", &resource_methods);
                 }
                 format!("{path}::Guest")
             }
@@ -714,12 +1026,13 @@ macro_rules! {macro_name} {{
         };
 
         if !root_methods.is_empty() || !extra_trait_items.is_empty() {
-            self.generate_stub_impl(&guest_trait, &extra_trait_items, &root_methods);
+            self.generate_stub_impl(interface, &guest_trait, &extra_trait_items, &root_methods);
         }
     }
 
     fn generate_stub_impl(
         &mut self,
+        interface: Option<(InterfaceId, &WorldKey)>,
         trait_name: &str,
         extra_trait_items: &str,
         funcs: &[&Function],
@@ -731,9 +1044,14 @@ macro_rules! {macro_name} {{
             if self.gen.skip.contains(&func.name) {
                 continue;
             }
+            let async_ =
+                self.gen
+                    .is_async(self.resolve, interface.map(|(_, key)| key), func, false);
             let mut sig = FnSig {
+                async_,
                 use_item_name: true,
                 private: true,
+                borrowed_args: self.gen.opts.borrowed_export_args,
                 ..Default::default()
             };
             if let FunctionKind::Method(_) = &func.kind {
@@ -798,7 +1116,12 @@ macro_rules! {macro_name} {{
         // }
     }
 
-    fn print_signature(&mut self, func: &Function, params_owned: bool, sig: &FnSig) -> Vec<String> {
+    pub(super) fn print_signature(
+        &mut self,
+        func: &Function,
+        params_owned: bool,
+        sig: &FnSig,
+    ) -> Vec<String> {
         let params = self.print_docs_and_params(func, params_owned, sig);
         if let FunctionKind::Constructor(_) = &func.kind {
             self.push_str(" -> Self")
@@ -808,7 +1131,7 @@ macro_rules! {macro_name} {{
         params
     }
 
-    fn print_docs_and_params(
+    pub(super) fn print_docs_and_params(
         &mut self,
         func: &Function,
         params_owned: bool,
@@ -879,6 +1202,12 @@ macro_rules! {macro_name} {{
                     Ownership::Borrowing { .. } => TypeOwnershipStyle::Borrowed,
                 }
             };
+            if sig.borrowed_args && self.is_borrowed_export_arg(param) {
+                self.print_borrowed_export_arg(param);
+                self.push_str(",");
+                params.push(name);
+                continue;
+            }
             let mode = self.type_mode_for(param, style, "'_");
             self.print_ty(param, mode);
             self.push_str(",");
@@ -938,6 +1267,48 @@ macro_rules! {macro_name} {{
         }
     }
 
+    /// Returns whether `ty`, the type of a parameter of an exported function,
+    /// is passed as `&str` or `&[T]` with the `borrowed_export_args` option.
+    pub(super) fn is_borrowed_export_arg(&self, ty: &Type) -> bool {
+        match self.dealias(ty) {
+            Type::String => true,
+            Type::Id(id) => {
+                matches!(self.resolve.types[id].kind, TypeDefKind::List(_))
+                    && !self.info(id).has_resource
+            }
+            _ => false,
+        }
+    }
+
+    fn print_borrowed_export_arg(&mut self, ty: &Type) {
+        match self.dealias(ty) {
+            Type::String if self.gen.opts.raw_strings => self.push_str("&[u8]"),
+            Type::String => self.push_str("&str"),
+            Type::Id(id) => {
+                let TypeDefKind::List(element) = &self.resolve.types[id].kind else {
+                    unreachable!()
+                };
+                self.push_str("&[");
+                self.print_ty(element, TypeMode::owned());
+                self.push_str("]");
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the type that `ty` is an alias of, if it's an alias.
+    fn dealias(&self, ty: &Type) -> Type {
+        let Type::Id(id) = *ty else {
+            return *ty;
+        };
+        let id = dealias(self.resolve, id);
+        match self.resolve.types[id].kind {
+            // Aliases of primitive types, such as `type name = string`.
+            TypeDefKind::Type(ty) => ty,
+            _ => Type::Id(id),
+        }
+    }
+
     /// Calculates the `TypeMode` to be used for the `ty` specified.
     ///
     /// This takes a `style` argument which is the requested style of ownership
@@ -1148,6 +1519,45 @@ macro_rules! {macro_name} {{
         }
     }
 
+    /// Returns the path to the Rust type that `id` is remapped to through
+    /// `with`, if it is, along with the path to the type generated for it.
+    pub(super) fn remapped_type(&self, id: TypeId) -> Option<(String, String)> {
+        let name = self.gen.remapped_types.get(&id)?;
+        let path = format!("{}{name}", self.path_to_root());
+        Some((path, self.type_path(id, true)))
+    }
+
+    /// Returns whether `ty` is, or contains, a type remapped through `with`.
+    pub(super) fn has_remapped_type(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Id(id) => {
+                self.gen.remapped_types.contains_key(id) || self.contains_remapped_type(*id)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns whether the contents of the type `id` include a type remapped
+    /// through `with`, in which case `id` can't derive `Copy`.
+    fn contains_remapped_type(&self, id: TypeId) -> bool {
+        match &self.resolve.types[id].kind {
+            TypeDefKind::Record(r) => r.fields.iter().any(|f| self.has_remapped_type(&f.ty)),
+            TypeDefKind::Variant(v) => v
+                .cases
+                .iter()
+                .any(|c| c.ty.as_ref().is_some_and(|ty| self.has_remapped_type(ty))),
+            TypeDefKind::Tuple(t) => t.types.iter().any(|ty| self.has_remapped_type(ty)),
+            TypeDefKind::Result(r) => [&r.ok, &r.err]
+                .into_iter()
+                .flatten()
+                .any(|ty| self.has_remapped_type(ty)),
+            TypeDefKind::Option(ty) | TypeDefKind::List(ty) | TypeDefKind::Type(ty) => {
+                self.has_remapped_type(ty)
+            }
+            _ => false,
+        }
+    }
+
     pub fn type_path(&self, id: TypeId, owned: bool) -> String {
         self.type_path_with_name(
             id,
@@ -1169,6 +1579,19 @@ macro_rules! {macro_name} {{
     }
 
     fn print_tyid(&mut self, id: TypeId, mode: TypeMode) {
+        // Types remapped through `with` are printed as the Rust type they're
+        // remapped to wherever they're used.
+        if let Some((path, _)) = self.remapped_type(id) {
+            if let (TypeOwnershipStyle::OnlyTopBorrowed, Some(lt)) = (mode.style, mode.lifetime) {
+                self.push_str("&");
+                if lt != "'_" {
+                    self.push_str(lt);
+                    self.push_str(" ");
+                }
+            }
+            self.push_str(&path);
+            return;
+        }
         let ty = &self.resolve.types[id];
         if ty.name.is_some() {
             // NB: Most of the heavy lifting of `TypeMode` and what to do here
@@ -1289,20 +1712,129 @@ macro_rules! {macro_name} {{
         result
     }
 
+    /// Prints `serde` implementations for flags which represent them as lists
+    /// of the names of the flags which are set.
+    fn print_flags_serde(&mut self, name: &str, flags: &Flags) {
+        let name = name.to_upper_camel_case();
+        let vec = self.path_to_vec();
+        let string = self.path_to_string();
+        let mut serialize = String::new();
+        let mut deserialize = String::new();
+        let mut names = String::new();
+        for flag in flags.flags.iter() {
+            let constant = flag.name.to_shouty_snake_case();
+            let wit_name = &flag.name;
+            uwriteln!(
+                serialize,
+                "if self.contains({name}::{constant}) {{ seq.serialize_element(\"{wit_name}\")?; }}"
+            );
+            uwriteln!(deserialize, "\"{wit_name}\" => {name}::{constant},");
+            uwrite!(names, "\"{wit_name}\", ");
+        }
+        uwriteln!(
+            self.src,
+            r#"
+                impl serde::Serialize for {name} {{
+                    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {{
+                        use serde::ser::SerializeSeq;
+                        let mut seq = serializer.serialize_seq(Some(self.iter().count()))?;
+                        {serialize}
+                        seq.end()
+                    }}
+                }}
+
+                impl<'de> serde::Deserialize<'de> for {name} {{
+                    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {{
+                        let mut flags = {name}::empty();
+                        for flag in <{vec}<{string}> as serde::Deserialize>::deserialize(deserializer)? {{
+                            flags |= match flag.as_str() {{
+                                {deserialize}
+                                other => return Err(serde::de::Error::unknown_variant(other, &[{names}])),
+                            }};
+                        }}
+                        Ok(flags)
+                    }}
+                }}
+            "#
+        );
+    }
+
+    /// Returns the `serde` traits to derive for the type `id` when printed
+    /// with `lifetime`, if any.
+    fn serde_derives(&self, id: TypeId, lifetime: Option<&str>) -> Vec<String> {
+        if !self.gen.opts.serde || self.info(id).has_resource {
+            return Vec::new();
+        }
+        let mut derives = vec!["serde::Serialize".to_string()];
+        if lifetime.is_none() {
+            derives.push("serde::Deserialize".to_string());
+        }
+        derives
+    }
+
+    /// Returns the derives and attributes which `type_attributes` adds to the
+    /// type `id`.
+    fn type_attributes(&self, id: TypeId) -> (BTreeSet<String>, Vec<String>) {
+        let mut derives = BTreeSet::new();
+        let mut attrs = Vec::new();
+        if self.gen.opts.type_attributes.is_empty() {
+            return (derives, attrs);
+        }
+        // Types in interfaces can be named with or without the version of
+        // their package.
+        let name = self.resolve.types[id].name.as_deref().unwrap_or("");
+        let interfaces = match self.identifier {
+            Identifier::Interface(_, key) => {
+                let interface = self.resolve.name_world_key(key);
+                match interface.split_once('@') {
+                    Some((unversioned, _)) => vec![unversioned.to_string(), interface],
+                    None => vec![interface],
+                }
+            }
+            Identifier::World(_) => Vec::new(),
+        };
+        let paths = match self.identifier {
+            Identifier::Interface(..) => interfaces.iter().map(|i| format!("{i}/{name}")).collect(),
+            Identifier::World(_) => vec![name.to_string()],
+        };
+        for (pattern, value) in self.gen.opts.type_attributes.iter() {
+            let matches = match pattern.strip_suffix('*') {
+                Some(prefix) => paths.iter().any(|p| p.starts_with(prefix)),
+                None => paths.contains(pattern) || interfaces.contains(pattern),
+            };
+            if !matches {
+                continue;
+            }
+            if value.starts_with('#') {
+                attrs.push(value.clone());
+            } else {
+                derives.insert(value.clone());
+            }
+        }
+        (derives, attrs)
+    }
+
     fn print_typedef_record(&mut self, id: TypeId, record: &Record, docs: &Docs) {
         let info = self.info(id);
         // We use a BTree set to make sure we don't have any duplicates and we have a stable order
-        let additional_derives: BTreeSet<String> = self
+        let mut additional_derives: BTreeSet<String> = self
             .gen
             .opts
             .additional_derive_attributes
             .iter()
             .cloned()
             .collect();
+        let (type_derives, attrs) = self.type_attributes(id);
+        additional_derives.extend(type_derives);
         for (name, mode) in self.modes_of(id) {
             self.rustdoc(docs);
+            for attr in attrs.iter() {
+                self.push_str(&format!("{attr}\n"));
+            }
             let mut derives = additional_derives.clone();
-            if info.is_copy() {
+            let serde = self.serde_derives(id, mode.lifetime);
+            derives.extend(serde.iter().cloned());
+            if info.is_copy() && !self.contains_remapped_type(id) {
                 self.push_str("#[repr(C)]\n");
                 derives.extend(["Copy", "Clone"].into_iter().map(|s| s.to_string()));
             } else if info.is_clone() {
@@ -1318,6 +1850,9 @@ macro_rules! {macro_name} {{
             self.push_str(" {\n");
             for field in record.fields.iter() {
                 self.rustdoc(&field.docs);
+                if !serde.is_empty() && to_rust_ident(&field.name) != field.name {
+                    self.push_str(&format!("#[serde(rename = \"{}\")]\n", field.name));
+                }
                 self.push_str("pub ");
                 self.push_str(&to_rust_ident(&field.name));
                 self.push_str(": ");
@@ -1377,10 +1912,14 @@ macro_rules! {macro_name} {{
     {
         self.print_rust_enum(
             id,
-            variant
-                .cases
-                .iter()
-                .map(|c| (c.name.to_upper_camel_case(), &c.docs, c.ty.as_ref())),
+            variant.cases.iter().map(|c| {
+                (
+                    c.name.to_upper_camel_case(),
+                    &c.name,
+                    &c.docs,
+                    c.ty.as_ref(),
+                )
+            }),
             docs,
         );
     }
@@ -1388,24 +1927,31 @@ macro_rules! {macro_name} {{
     fn print_rust_enum<'b>(
         &mut self,
         id: TypeId,
-        cases: impl IntoIterator<Item = (String, &'b Docs, Option<&'b Type>)> + Clone,
+        cases: impl IntoIterator<Item = (String, &'b String, &'b Docs, Option<&'b Type>)> + Clone,
         docs: &Docs,
     ) where
         Self: Sized,
     {
         let info = self.info(id);
         // We use a BTree set to make sure we don't have any duplicates and have a stable order
-        let additional_derives: BTreeSet<String> = self
+        let mut additional_derives: BTreeSet<String> = self
             .gen
             .opts
             .additional_derive_attributes
             .iter()
             .cloned()
             .collect();
+        let (type_derives, attrs) = self.type_attributes(id);
+        additional_derives.extend(type_derives);
         for (name, mode) in self.modes_of(id) {
             self.rustdoc(docs);
+            for attr in attrs.iter() {
+                self.push_str(&format!("{attr}\n"));
+            }
             let mut derives = additional_derives.clone();
-            if info.is_copy() {
+            let serde = self.serde_derives(id, mode.lifetime);
+            derives.extend(serde.iter().cloned());
+            if info.is_copy() && !self.contains_remapped_type(id) {
                 derives.extend(["Copy", "Clone"].into_iter().map(|s| s.to_string()));
             } else if info.is_clone() {
                 derives.insert("Clone".to_string());
@@ -1418,8 +1964,11 @@ macro_rules! {macro_name} {{
             self.push_str(&format!("pub enum {name}"));
             self.print_generics(mode.lifetime);
             self.push_str(" {\n");
-            for (case_name, docs, payload) in cases.clone() {
+            for (case_name, wit_name, docs, payload) in cases.clone() {
                 self.rustdoc(docs);
+                if !serde.is_empty() {
+                    self.push_str(&format!("#[serde(rename = \"{wit_name}\")]\n"));
+                }
                 self.push_str(&case_name);
                 if let Some(ty) = payload {
                     self.push_str("(");
@@ -1437,7 +1986,7 @@ macro_rules! {macro_name} {{
                 cases
                     .clone()
                     .into_iter()
-                    .map(|(name, _docs, ty)| (name, ty)),
+                    .map(|(name, _wit_name, _docs, ty)| (name, ty)),
             );
 
             if info.error {
@@ -1544,8 +2093,9 @@ macro_rules! {macro_name} {{
         let info = self.info(id);
 
         let name = to_upper_camel_case(name);
+        let (type_derives, type_attrs) = self.type_attributes(id);
         self.rustdoc(docs);
-        for attr in attrs {
+        for attr in attrs.iter().chain(&type_attrs) {
             self.push_str(&format!("{}\n", attr));
         }
         self.push_str("#[repr(");
@@ -1559,6 +2109,9 @@ macro_rules! {macro_name} {{
             .iter()
             .cloned()
             .collect();
+        derives.extend(type_derives);
+        let serde = self.serde_derives(id, None);
+        derives.extend(serde.iter().cloned());
         derives.extend(
             ["Clone", "Copy", "PartialEq", "Eq", "PartialOrd", "Ord"]
                 .into_iter()
@@ -1570,6 +2123,9 @@ macro_rules! {macro_name} {{
         self.push_str(&format!("pub enum {name} {{\n"));
         for case in enum_.cases.iter() {
             self.rustdoc(&case.docs);
+            if !serde.is_empty() {
+                self.push_str(&format!("#[serde(rename = \"{}\")]\n", case.name));
+            }
             self.push_str(&case_attr(case));
             self.push_str(&case.name.to_upper_camel_case());
             self.push_str(",\n");
@@ -1863,6 +2419,14 @@ macro_rules! {macro_name} {{
         self.path_from_runtime_module(RuntimeItem::StdAllocModule, "alloc")
     }
 
+    pub fn path_to_async_support(&self) -> String {
+        format!("{}::async_support", self.gen.runtime_path())
+    }
+
+    pub fn path_to_host(&self) -> String {
+        format!("{}::host", self.gen.runtime_path())
+    }
+
     fn path_from_runtime_module(
         &mut self,
         item: RuntimeItem,
@@ -1924,7 +2488,7 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
         } else {
             let module = match self.identifier {
                 Identifier::Interface(_, key) => self.resolve.name_world_key(key),
-                Identifier::World(_) => unimplemented!("resource exports from worlds"),
+                Identifier::World(_) => unreachable!("worlds only import types"),
             };
             let box_path = self.path_to_box();
             uwriteln!(
@@ -2096,15 +2660,35 @@ impl<'a> {camel}Borrow<'a>{{
         }
     }
 
-    fn type_flags(&mut self, _id: TypeId, name: &str, flags: &Flags, docs: &Docs) {
+    fn type_flags(&mut self, id: TypeId, name: &str, flags: &Flags, docs: &Docs) {
         self.src.push_str(&format!(
             "{bitflags}::bitflags! {{\n",
             bitflags = self.gen.bitflags_path()
         ));
         self.rustdoc(docs);
+        let (type_derives, attrs) = self.type_attributes(id);
+        for attr in attrs {
+            self.src.push_str(&format!("{attr}\n"));
+        }
+        let mut derives = vec![
+            "PartialEq",
+            "Eq",
+            "PartialOrd",
+            "Ord",
+            "Hash",
+            "Debug",
+            "Clone",
+            "Copy",
+        ];
+        for derive in type_derives.iter() {
+            if !derives.contains(&derive.as_str()) {
+                derives.push(derive);
+            }
+        }
         let repr = RustFlagsRepr::new(flags);
         self.src.push_str(&format!(
-            "#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]\npub struct {}: {repr} {{\n",
+            "#[derive({})]\npub struct {}: {repr} {{\n",
+            derives.join(", "),
             name.to_upper_camel_case(),
         ));
         for (i, flag) in flags.flags.iter().enumerate() {
@@ -2117,6 +2701,10 @@ impl<'a> {camel}Borrow<'a>{{
         }
         self.src.push_str("}\n");
         self.src.push_str("}\n");
+
+        if self.gen.opts.serde {
+            self.print_flags_serde(name, flags);
+        }
     }
 
     fn type_variant(&mut self, id: TypeId, _name: &str, variant: &Variant, docs: &Docs) {
@@ -2265,18 +2853,30 @@ impl<'a, 'b> wit_bindgen_core::AnonymousTypeGenerator<'a> for AnonTypeGenerator<
     }
 
     fn anonymous_type_future(&mut self, _id: TypeId, ty: &Option<Type>, _docs: &Docs) {
-        self.interface.push_str("Future<");
-        self.interface.print_optional_ty(ty.as_ref(), self.mode);
+        let async_support = self.interface.path_to_async_support();
+        self.interface
+            .push_str(&format!("{async_support}::FutureReader<"));
+        self.interface
+            .print_optional_ty(ty.as_ref(), TypeMode::owned());
         self.interface.push_str(">");
     }
 
     fn anonymous_type_stream(&mut self, _id: TypeId, stream: &Stream, _docs: &Docs) {
-        self.interface.push_str("Stream<");
+        let async_support = self.interface.path_to_async_support();
         self.interface
-            .print_optional_ty(stream.element.as_ref(), self.mode);
-        self.interface.push_str(",");
+            .push_str(&format!("{async_support}::StreamReader<"));
         self.interface
-            .print_optional_ty(stream.end.as_ref(), self.mode);
+            .print_optional_ty(stream.element.as_ref(), TypeMode::owned());
         self.interface.push_str(">");
     }
 }
+
+/// Returns whether `id` is a `future` or a `stream`, along with its payload.
+fn future_or_stream_payload(resolve: &Resolve, id: TypeId) -> (&'static str, Option<&Type>) {
+    match &resolve.types[id].kind {
+        TypeDefKind::Future(ty) => ("future", ty.as_ref()),
+        // Streams with an end type are rejected in `validate`.
+        TypeDefKind::Stream(stream) => ("stream", stream.element.as_ref()),
+        _ => unreachable!(),
+    }
+}
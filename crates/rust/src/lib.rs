@@ -1,4 +1,4 @@
-use crate::interface::InterfaceGenerator;
+use crate::interface::{InterfaceGenerator, PayloadVtables};
 use anyhow::{bail, Result};
 use heck::*;
 use indexmap::IndexSet;
@@ -13,6 +13,7 @@ use wit_bindgen_core::{
 };
 
 mod bindgen;
+mod host;
 mod interface;
 
 struct InterfaceName {
@@ -46,6 +47,13 @@ struct RustWasm {
     export_macros: Vec<(String, String)>,
     /// Interface names to how they should be generated
     with: GenerationConfiguration,
+    /// Vtables for futures and streams used by functions in the root module.
+    payload_vtables: PayloadVtables,
+    /// Why host bindings can't be generated for the world, if they can't.
+    host_error: Option<String>,
+    /// Types remapped through `with` to existing Rust types, along with the
+    /// name the Rust type is imported as at the root of the bindings.
+    remapped_types: HashMap<TypeId, String>,
 }
 
 #[derive(Default)]
@@ -117,6 +125,19 @@ fn parse_with(s: &str) -> Result<(String, WithOption), String> {
     Ok((k.to_string(), v))
 }
 
+#[cfg(feature = "clap")]
+fn parse_type_attribute(s: &str) -> Result<(String, String), String> {
+    let (k, v) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected string of form `<key>=<value>`; got `{s}`"))?;
+    Ok((k.to_string(), v.to_string()))
+}
+
+#[cfg(feature = "clap")]
+fn parse_async(s: &str) -> Result<AsyncConfig, String> {
+    s.parse()
+}
+
 #[derive(Default, Debug, Clone)]
 #[cfg_attr(feature = "clap", derive(clap::Args))]
 pub struct Opts {
@@ -136,6 +157,15 @@ pub struct Opts {
     #[cfg_attr(feature = "clap", arg(long))]
     pub raw_strings: bool,
 
+    /// If true, exported functions receive top-level `string` and `list<T>`
+    /// arguments as `&str` and `&[T]` borrowed for the duration of the call,
+    /// rather than taking ownership of them. The bindings free their memory
+    /// once the call returns.
+    ///
+    /// Lists of types containing resources are still passed by value.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub borrowed_export_args: bool,
+
     /// Names of functions to skip generating bindings for.
     #[cfg_attr(feature = "clap", arg(long))]
     pub skip: Vec<String>,
@@ -173,6 +203,22 @@ pub struct Opts {
     #[cfg_attr(feature = "clap", arg(long))]
     pub runtime_path: Option<String>,
 
+    /// The optional path to a type implementing `Allocator` from the runtime
+    /// which allocates the memory the bindings use to lower values.
+    ///
+    /// This covers the temporary buffers for arguments to imports, the memory
+    /// for results of exports released in `post-return`, and the module's
+    /// `cabi_realloc` export, which these bindings then define. Only one set
+    /// of bindings in a component may set this option as a result.
+    ///
+    /// Lifted lists and strings are owned by Rust values using the global
+    /// allocator, so when this option is set they're copied out of memory
+    /// from the allocator when they're lifted, and lists and strings returned
+    /// from exports are copied into it. Without this option no copies are
+    /// made.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub allocator: Option<String>,
+
     /// The optional path to the bitflags crate to use.
     ///
     /// This defaults to `wit_bindgen::bitflags`.
@@ -186,8 +232,36 @@ pub struct Opts {
     #[cfg_attr(feature = "clap", arg(long = "additional_derive_attribute", short = 'd', default_values_t = Vec::<String>::new()))]
     pub additional_derive_attributes: Vec<String>,
 
+    /// Additional derives and attributes for specific types.
+    ///
+    /// Argument must be of the form `k=v` where `k` names the types and `v`
+    /// is either the path of a trait to derive or an attribute such as
+    /// `#[non_exhaustive]`. This option can be passed multiple times.
+    ///
+    /// Types are named `<interface>/<type>`, for example
+    /// `wasi:http/types/method`, or just `<type>` when defined in a world.
+    /// Naming an interface applies to all of its types and a trailing `*`
+    /// matches any suffix. These apply to records, variants, enums and flags.
+    #[cfg_attr(feature = "clap", arg(long = "type-attribute", value_parser = parse_type_attribute))]
+    pub type_attributes: Vec<(String, String)>,
+
+    /// Generate `serde::Serialize` and `serde::Deserialize` implementations
+    /// for records, variants, enums and flags which use the WIT names of
+    /// fields and cases. Flags are serialized as lists of the names of the
+    /// flags which are set.
+    ///
+    /// Types containing resources are skipped, and types borrowing their
+    /// contents only implement `Serialize`. The crate using the bindings must
+    /// depend on `serde` with its `derive` feature enabled.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub serde: bool,
+
     /// Remapping of interface names to rust module names.
     ///
+    /// Keys of the form `<interface>/<type>` instead remap a single type to an
+    /// existing Rust type implementing `LiftLower` from the runtime, as well
+    /// as `Clone` and `Debug`, which is used in its place wherever it's used.
+    ///
     /// Argument must be of the form `k=v` and this option can be passed
     /// multiple times or one option can be comma separated, for example
     /// `k1=v1,k2=v2`.
@@ -201,6 +275,9 @@ pub struct Opts {
 
     /// Add the specified suffix to the name of the custome section containing
     /// the component type.
+    ///
+    /// This section isn't emitted for worlds using `future` or `stream`
+    /// types, see `async_`.
     #[cfg_attr(feature = "clap", arg(long))]
     pub type_section_suffix: Option<String>,
 
@@ -234,6 +311,38 @@ pub struct Opts {
     /// library-based usage of `generate!` prone to breakage.
     #[cfg_attr(feature = "clap", arg(long))]
     pub disable_custom_section_link_helpers: bool,
+
+    /// Determines which functions to lift or lower using the component model
+    /// async ABI, generating `async fn`s for them.
+    ///
+    /// Valid values include:
+    ///
+    /// - `none`: no functions are async, the default.
+    ///
+    /// - `all`: all imported and exported functions are async.
+    ///
+    /// - a comma-separated list of functions prefixed with either `import:`
+    ///   or `export:`, for example `import:foo,export:my:pkg/iface#bar`.
+    ///   Functions in interfaces are named `<interface>#<function>` and a
+    ///   trailing `*` matches any suffix.
+    ///
+    /// Note that `wit-component` can't yet encode `future` and `stream`
+    /// types, so no component type custom section is emitted for worlds
+    /// using them. Such modules must be turned into components with the
+    /// world passed to `wit-component` separately.
+    #[cfg_attr(feature = "clap", arg(long = "async", value_parser = parse_async, default_value = "none"))]
+    pub async_: AsyncConfig,
+
+    /// Generate bindings for a host which instantiates the component's core
+    /// wasm module itself, rather than for the guest.
+    ///
+    /// Imported interfaces get a `Host` trait for the embedder to implement
+    /// along with a `dispatch` function to call it with the guest's core wasm
+    /// arguments, and exported functions are called through a
+    /// `host::Instance` providing access to the guest's memory and exports.
+    /// Resources, futures, streams and async functions aren't supported.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub host: bool,
 }
 
 impl Opts {
@@ -271,6 +380,7 @@ impl RustWasm {
             return_pointer_area_size: 0,
             return_pointer_area_align: 0,
             needs_runtime_module: false,
+            payload_vtables: PayloadVtables::default(),
         }
     }
 
@@ -309,6 +419,33 @@ impl RustWasm {
         }
     }
 
+    /// Returns whether `func`, either imported or exported within the
+    /// interface named `interface`, should use the async ABI.
+    fn is_async(
+        &self,
+        resolve: &Resolve,
+        interface: Option<&WorldKey>,
+        func: &Function,
+        is_import: bool,
+    ) -> bool {
+        let (imports, exports) = match &self.opts.async_ {
+            AsyncConfig::None => return false,
+            AsyncConfig::All => return true,
+            AsyncConfig::Some { imports, exports } => (imports, exports),
+        };
+        let name = match interface {
+            Some(key) => format!("{}#{}", resolve.name_world_key(key), func.name),
+            None => func.name.clone(),
+        };
+        let patterns = if is_import { imports } else { exports };
+        patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => *pattern == name,
+            })
+    }
+
     fn runtime_path(&self) -> &str {
         self.opts
             .runtime_path
@@ -748,6 +885,43 @@ macro_rules! __export_{world_name}_impl {{
         section_suffix: &str,
         func_name: Option<&str>,
     ) {
+        if let Some(func_name) = func_name {
+            let rt = self.runtime_path().to_string();
+            uwriteln!(
+                self.src,
+                "
+                #[inline(never)]
+                #[doc(hidden)]
+                pub fn {func_name}() {{
+                    {rt}::maybe_link_cabi_realloc();
+                }}
+            ",
+            );
+        }
+
+        // `wit-component` can't yet encode `future` and `stream` types, so
+        // worlds using them don't get a type section and must be described to
+        // tooling by other means.
+        let mut live = LiveTypes::default();
+        live.add_world(resolve, world_id);
+        if live.iter().any(|id| {
+            matches!(
+                resolve.types[id].kind,
+                TypeDefKind::Future(_) | TypeDefKind::Stream(_)
+            )
+        }) {
+            uwriteln!(
+                self.src,
+                "
+                // No `component-type` custom section is emitted here since
+                // this world uses `future` or `stream` types, which
+                // `wit-component` can't encode yet. The world must be passed
+                // to `wit-component` separately instead.
+                "
+            );
+            return;
+        }
+
         self.src.push_str("\n#[cfg(target_arch = \"wasm32\")]\n");
 
         // The custom section name here must start with "component-type" but
@@ -818,24 +992,86 @@ macro_rules! __export_{world_name}_impl {{
 
         self.src.push_str("\";\n");
         self.src.set_indent(old_indent);
+    }
 
-        if let Some(func_name) = func_name {
-            let rt = self.runtime_path().to_string();
-            uwriteln!(
-                self.src,
-                "
-                #[inline(never)]
-                #[doc(hidden)]
-                pub fn {func_name}() {{
-                    {rt}::maybe_link_cabi_realloc();
-                }}
-            ",
-            );
+    /// Checks that the types remapped through `with` are ones which can be
+    /// converted to and from the Rust types they're remapped to.
+    fn check_remapped_types(&self, resolve: &Resolve) -> Result<()> {
+        let mut ids = self.remapped_types.keys().copied().collect::<Vec<_>>();
+        ids.sort_by_key(|id| id.index());
+        for id in ids {
+            let ty = &resolve.types[id];
+            let name = ty.name.as_deref().unwrap();
+            match ty.kind {
+                TypeDefKind::Record(_)
+                | TypeDefKind::Variant(_)
+                | TypeDefKind::Enum(_)
+                | TypeDefKind::Flags(_) => {}
+                _ => bail!(
+                    "type `{name}` can't be remapped with `with`: \
+                     only records, variants, enums and flags can be remapped"
+                ),
+            }
+            let info = self.types.get(id);
+            if info.has_resource {
+                bail!("type `{name}` can't be remapped with `with` since it contains resources");
+            }
+            if info.has_list && matches!(self.opts.ownership, Ownership::Borrowing { .. }) {
+                bail!(
+                    "type `{name}` can't be remapped with `with` since it contains \
+                     lists and borrowing ownership is used"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Formats the generated bindings, adds them to `files` and checks that
+    /// all `with` remappings were used.
+    fn finish_file(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) -> Result<()> {
+        let name = &resolve.worlds[world].name;
+        let mut src = mem::take(&mut self.src);
+        if self.opts.format {
+            let syntax_tree = syn::parse_file(src.as_str()).unwrap();
+            *src.as_mut_string() = prettyplease::unparse(&syntax_tree);
+        }
+
+        let module_name = name.to_snake_case();
+        files.push(&format!("{module_name}.rs"), src.as_bytes());
+
+        let remapped_keys = self
+            .with
+            .iter()
+            .map(|(k, _)| k)
+            .cloned()
+            .collect::<HashSet<String>>();
+
+        let mut unused_keys = remapped_keys
+            .difference(&self.generated_interfaces)
+            .collect::<Vec<&String>>();
+
+        unused_keys.sort();
+
+        if !unused_keys.is_empty() {
+            bail!("unused remappings provided via `with`: {unused_keys:?}");
         }
+
+        Ok(())
     }
 }
 
 impl WorldGenerator for RustWasm {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        let mut live = LiveTypes::default();
+        live.add_world(resolve, world);
+        for id in live.iter() {
+            if let TypeDefKind::Stream(Stream { end: Some(_), .. }) = &resolve.types[id].kind {
+                bail!("streams with an end type are not supported");
+            }
+        }
+        Ok(())
+    }
+
     fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
         wit_bindgen_core::generated_preamble(&mut self.src, env!("CARGO_PKG_VERSION"));
 
@@ -848,6 +1084,9 @@ impl WorldGenerator for RustWasm {
         if self.opts.raw_strings {
             uwriteln!(self.src, "//   * raw_strings");
         }
+        if self.opts.borrowed_export_args {
+            uwriteln!(self.src, "//   * borrowed_export_args");
+        }
         if !self.opts.skip.is_empty() {
             uwriteln!(self.src, "//   * skip: {:?}", self.opts.skip);
         }
@@ -861,6 +1100,12 @@ impl WorldGenerator for RustWasm {
                 self.opts.additional_derive_attributes
             );
         }
+        for (k, v) in self.opts.type_attributes.iter() {
+            uwriteln!(self.src, "//   * type attribute {k:?} = {v:?}");
+        }
+        if self.opts.serde {
+            uwriteln!(self.src, "//   * serde");
+        }
         for (k, v) in self.opts.with.iter() {
             uwriteln!(self.src, "//   * with {k:?} = {v}");
         }
@@ -876,8 +1121,24 @@ impl WorldGenerator for RustWasm {
         if self.opts.pub_export_macro {
             uwriteln!(self.src, "//   * pub-export-macro");
         }
+        if !matches!(self.opts.async_, AsyncConfig::None) {
+            uwriteln!(self.src, "//   * async: {}", self.opts.async_);
+        }
+        if self.opts.host {
+            uwriteln!(self.src, "//   * host");
+        }
+        if let Some(allocator) = &self.opts.allocator {
+            uwriteln!(self.src, "//   * allocator: {allocator}");
+        }
         self.types.analyze(resolve);
         self.world = Some(world);
+        if self.opts.host {
+            self.host_error = if matches!(self.opts.async_, AsyncConfig::None) {
+                host::unsupported(resolve, world)
+            } else {
+                Some("host bindings don't support async functions".to_string())
+            };
+        }
 
         let world = &resolve.worlds[world];
         // Specify that all imports local to the world's package should be generated
@@ -896,6 +1157,39 @@ impl WorldGenerator for RustWasm {
             self.with.insert(k.clone(), v.clone().into());
         }
         self.with.generate_by_default = self.opts.generate_all;
+
+        // Keys of the form `<interface>/<type>` remap a single type of an
+        // interface, rather than the whole interface, to an existing Rust type.
+        // All interfaces are considered, not just those the world lists, so
+        // that keys are resolved the same way as for whole interfaces.
+        for (id, _) in resolve.interfaces.iter() {
+            let Some(interface) = resolve.id_of(id) else {
+                continue;
+            };
+            let names = match interface.split_once('@') {
+                Some((unversioned, _)) => vec![unversioned.to_string(), interface.clone()],
+                None => vec![interface],
+            };
+            for (name, ty) in resolve.interfaces[id].types.iter() {
+                for interface in names.iter() {
+                    let key = format!("{interface}/{name}");
+                    let Some(InterfaceGeneration::Remap(path)) = self.with.map.get(&key) else {
+                        continue;
+                    };
+                    let name = format!("__with_name{}", self.with_name_counter);
+                    self.with_name_counter += 1;
+                    uwriteln!(self.src, "use {path} as {name};");
+                    self.with.map.remove(&key);
+                    self.remapped_types.insert(*ty, name);
+                }
+            }
+        }
+        if self.opts.host && !self.remapped_types.is_empty() && self.host_error.is_none() {
+            self.host_error = Some("host bindings don't support types remapped with `with`".into());
+        }
+        if self.opts.host && self.opts.allocator.is_some() && self.host_error.is_none() {
+            self.host_error = Some("host bindings don't support a custom allocator".into());
+        }
     }
 
     fn import_interface(
@@ -919,7 +1213,12 @@ impl WorldGenerator for RustWasm {
         }
         gen.types(id);
 
-        gen.generate_imports(resolve.interfaces[id].functions.values());
+        let funcs = resolve.interfaces[id].functions.values();
+        if gen.gen.opts.host {
+            gen.generate_host_imports(funcs);
+        } else {
+            gen.generate_imports(funcs);
+        }
 
         gen.finish_append_submodule(&snake, module_path);
 
@@ -937,7 +1236,12 @@ impl WorldGenerator for RustWasm {
 
         let mut gen = self.interface(Identifier::World(world), Some("$root"), resolve, true);
 
-        gen.generate_imports(funcs.iter().map(|(_, func)| *func));
+        let funcs = funcs.iter().map(|(_, func)| *func);
+        if gen.gen.opts.host {
+            gen.generate_host_imports(funcs);
+        } else {
+            gen.generate_imports(funcs);
+        }
 
         let src = gen.finish();
         self.src.push_str(&src);
@@ -957,6 +1261,11 @@ impl WorldGenerator for RustWasm {
             return Ok(());
         }
         gen.types(id);
+        if gen.gen.opts.host {
+            gen.generate_host_exports(resolve.interfaces[id].functions.values());
+            gen.finish_append_submodule(&snake, module_path);
+            return Ok(());
+        }
         let macro_name =
             gen.generate_exports(Some((id, name)), resolve.interfaces[id].functions.values())?;
         gen.finish_append_submodule(&snake, module_path);
@@ -981,6 +1290,12 @@ impl WorldGenerator for RustWasm {
         _files: &mut Files,
     ) -> Result<()> {
         let mut gen = self.interface(Identifier::World(world), None, resolve, false);
+        if gen.gen.opts.host {
+            gen.generate_host_exports(funcs.iter().map(|f| f.1));
+            let src = gen.finish();
+            self.src.push_str(&src);
+            return Ok(());
+        }
         let macro_name = gen.generate_exports(None, funcs.iter().map(|f| f.1))?;
         let src = gen.finish();
         self.src.push_str(&src);
@@ -1020,6 +1335,10 @@ impl WorldGenerator for RustWasm {
     }
 
     fn finish(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) -> Result<()> {
+        if let Some(error) = &self.host_error {
+            bail!("{error}");
+        }
+        self.check_remapped_types(resolve)?;
         let name = &resolve.worlds[world].name;
 
         let imports = mem::take(&mut self.import_modules);
@@ -1027,7 +1346,15 @@ impl WorldGenerator for RustWasm {
         let exports = mem::take(&mut self.export_modules);
         self.emit_modules(exports);
 
+        let payload_vtables = mem::take(&mut self.payload_vtables);
+        let async_support = format!("{}::async_support", self.runtime_path());
+        payload_vtables.finish(&mut self.src, &async_support);
         self.finish_runtime_module();
+        if self.opts.host {
+            // Host bindings aren't compiled into the guest, so there's no
+            // export macro and no custom section describing the world.
+            return self.finish_file(resolve, world, files);
+        }
         self.finish_export_macro(resolve, world);
 
         // This is a bit tricky, but we sometimes want to "split" the `world` in
@@ -1091,37 +1418,34 @@ impl WorldGenerator for RustWasm {
             },
         );
 
-        if self.opts.stubs {
-            self.src.push_str("\n#[derive(Debug)]\npub struct Stub;\n");
-        }
-
-        let mut src = mem::take(&mut self.src);
-        if self.opts.format {
-            let syntax_tree = syn::parse_file(src.as_str()).unwrap();
-            *src.as_mut_string() = prettyplease::unparse(&syntax_tree);
+        if let Some(allocator) = &self.opts.allocator {
+            // This strong definition takes precedence over the weak one from
+            // the runtime, so that everything the canonical ABI allocates
+            // comes from the allocator.
+            let rt = self.runtime_path().to_string();
+            uwriteln!(
+                self.src,
+                "
+                #[doc(hidden)]
+                #[allow(dead_code)]
+                #[cfg_attr(target_arch = \"wasm32\", export_name = \"cabi_realloc\")]
+                pub unsafe extern \"C\" fn __cabi_realloc(
+                    old_ptr: *mut u8,
+                    old_len: usize,
+                    align: usize,
+                    new_len: usize,
+                ) -> *mut u8 {{
+                    {rt}::cabi_realloc_with::<{allocator}>(old_ptr, old_len, align, new_len)
+                }}
+                ",
+            );
         }
 
-        let module_name = name.to_snake_case();
-        files.push(&format!("{module_name}.rs"), src.as_bytes());
-
-        let remapped_keys = self
-            .with
-            .iter()
-            .map(|(k, _)| k)
-            .cloned()
-            .collect::<HashSet<String>>();
-
-        let mut unused_keys = remapped_keys
-            .difference(&self.generated_interfaces)
-            .collect::<Vec<&String>>();
-
-        unused_keys.sort();
-
-        if !unused_keys.is_empty() {
-            bail!("unused remappings provided via `with`: {unused_keys:?}");
+        if self.opts.stubs {
+            self.src.push_str("\n#[derive(Debug)]\npub struct Stub;\n");
         }
 
-        Ok(())
+        self.finish_file(resolve, world, files)
     }
 }
 
@@ -1244,6 +1568,68 @@ impl From<WithOption> for InterfaceGeneration {
     }
 }
 
+/// Which functions to lift or lower using the component model async ABI.
+#[derive(Default, Debug, Clone)]
+pub enum AsyncConfig {
+    /// No functions are async.
+    #[default]
+    None,
+
+    /// Only the imported and exported functions matching these patterns are
+    /// async.
+    Some {
+        imports: Vec<String>,
+        exports: Vec<String>,
+    },
+
+    /// All functions are async.
+    All,
+}
+
+impl FromStr for AsyncConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "all" => Ok(Self::All),
+            _ => {
+                let mut imports = Vec::new();
+                let mut exports = Vec::new();
+                for item in s.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+                    match item.split_once(':') {
+                        Some(("import", name)) => imports.push(name.to_string()),
+                        Some(("export", name)) => exports.push(name.to_string()),
+                        _ => {
+                            return Err(format!(
+                                "unrecognized async function: `{item}`; \
+                                 expected `none`, `all`, or a list of \
+                                 `import:<name>` and `export:<name>`"
+                            ))
+                        }
+                    }
+                }
+                Ok(Self::Some { imports, exports })
+            }
+        }
+    }
+}
+
+impl fmt::Display for AsyncConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsyncConfig::None => f.write_str("none"),
+            AsyncConfig::All => f.write_str("all"),
+            AsyncConfig::Some { imports, exports } => {
+                let imports = imports.iter().map(|name| format!("import:{name}"));
+                let exports = exports.iter().map(|name| format!("export:{name}"));
+                let items = imports.chain(exports).collect::<Vec<_>>();
+                f.write_str(&items.join(","))
+            }
+        }
+    }
+}
+
 #[derive(Default)]
 struct FnSig {
     async_: bool,
@@ -1253,6 +1639,9 @@ struct FnSig {
     generics: Option<String>,
     self_arg: Option<String>,
     self_is_first_param: bool,
+    /// Whether `string` and `list<T>` parameters are taken as `&str` and
+    /// `&[T]`, for exports with `borrowed_export_args`.
+    borrowed_args: bool,
 }
 
 pub fn to_rust_ident(name: &str) -> String {
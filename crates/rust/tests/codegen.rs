@@ -30,6 +30,7 @@ mod codegen_tests {
                         },
                         stubs,
                         export_prefix: "[borrowed]",
+                        borrowed_export_args,
                         generate_all
                     });
 
@@ -45,6 +46,22 @@ mod codegen_tests {
                         },
                         stubs,
                         export_prefix: "[duplicate]",
+                        serde: true,
+                        generate_all
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod async_ {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        async: true,
+                        stubs,
+                        export_prefix: "[async]",
+                        borrowed_export_args,
+                        allocator: "crate::custom_allocator::Counting",
                         generate_all
                     });
 
@@ -363,6 +380,136 @@ mod custom_derives {
     export!(Component);
 }
 
+mod custom_type_attributes {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            interface blah {
+                resource res;
+
+                record point {
+                    x: u32,
+                    y: u32,
+                }
+
+                record with-res {
+                    r: res,
+                }
+
+                variant shape {
+                    circle(f32),
+                    square(u32),
+                }
+
+                flags perms {
+                    read,
+                    write,
+                }
+
+                bar: func(p: point, r: with-res, s: shape, f: perms);
+            }
+
+            world baz {
+                import blah;
+            }
+        ",
+        type_attributes: {
+            "my:inline/blah/point": [Hash, PartialEq, Eq, serde::Serialize],
+            "my:inline/blah/shape": [PartialEq, #[non_exhaustive]],
+            "my:inline/blah/po*": [serde::Deserialize],
+            "my:inline/blah/perms": [#[doc(alias = "permissions")]],
+            "my:inline/blah": [#[allow(dead_code)]],
+        },
+    });
+
+    use my::inline::blah::{Point, Shape};
+
+    #[allow(dead_code)]
+    fn check() {
+        fn hash<T: std::hash::Hash + Eq + serde::Serialize + serde::de::DeserializeOwned>() {}
+        hash::<Point>();
+
+        // `WithRes` contains a resource so it can't derive the traits above,
+        // and `Shape` contains a float so it can't derive `Eq`.
+        let _ = Shape::Square(1) == Shape::Circle(1.0);
+        match Shape::Square(1) {
+            Shape::Circle(_) | Shape::Square(_) => {}
+        }
+    }
+}
+
+mod serde_impls {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            interface blah {
+                resource res;
+
+                record point {
+                    x-coord: u32,
+                    %type: string,
+                }
+
+                // Contains a resource, so no impls are generated.
+                record with-res {
+                    r: res,
+                }
+
+                variant shape {
+                    circle(f32),
+                    unit-square,
+                }
+
+                enum color {
+                    light-red,
+                    blue,
+                }
+
+                flags perms {
+                    read,
+                    write-all,
+                }
+
+                bar: func(p: point, r: with-res, s: shape, c: color, f: perms);
+            }
+
+            world baz {
+                import blah;
+            }
+        ",
+        serde: true,
+    });
+
+    use my::inline::blah::{Color, Perms, Point, Shape};
+
+    fn round_trip<T>(value: &T, json: &str)
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + std::fmt::Debug,
+    {
+        assert_eq!(serde_json::to_string(value).unwrap(), json);
+        let value2 = serde_json::from_str::<T>(json).unwrap();
+        assert_eq!(format!("{value:?}"), format!("{value2:?}"));
+    }
+
+    #[test]
+    fn wit_names() {
+        let point = Point {
+            x_coord: 1,
+            type_: "a".to_string(),
+        };
+        round_trip(&point, r#"{"x-coord":1,"type":"a"}"#);
+        round_trip(&Shape::Circle(1.5), r#"{"circle":1.5}"#);
+        round_trip(&Shape::UnitSquare, r#""unit-square""#);
+        round_trip(&Color::LightRed, r#""light-red""#);
+        round_trip(&(Perms::READ | Perms::WRITE_ALL), r#"["read","write-all"]"#);
+        round_trip(&Perms::empty(), "[]");
+        round_trip(&Some(point), r#"{"x-coord":1,"type":"a"}"#);
+        assert!(serde_json::from_str::<Perms>(r#"["execute"]"#).is_err());
+    }
+}
+
 mod with {
     wit_bindgen::generate!({
         inline: "
@@ -417,6 +564,317 @@ mod with {
     }
 }
 
+mod with_types {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline@0.1.0;
+
+            interface types {
+                record uuid {
+                    high: u64,
+                    low: u64,
+                }
+
+                enum color { red, green }
+
+                record tagged {
+                    id: uuid,
+                    name: string,
+                }
+            }
+
+            interface api {
+                use types.{uuid, color, tagged};
+
+                lookup: func(id: uuid, color: color) -> uuid;
+                describe: func(t: tagged) -> string;
+                ids: func() -> list<uuid>;
+                find: func(ids: list<uuid>, id: option<uuid>) -> option<tuple<uuid, color>>;
+            }
+
+            world uses {
+                import api;
+                export api;
+            }
+        ",
+        with: {
+            "my:inline/types/uuid": Id,
+            "my:inline/types@0.1.0/color": Color,
+        },
+        generate_all,
+    });
+
+    use exports::my::inline::api::Guest;
+    use my::inline::types;
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub struct Id(u128);
+
+    impl wit_bindgen::rt::LiftLower<types::Uuid> for Id {
+        fn lift(wit: types::Uuid) -> Self {
+            Id((u128::from(wit.high) << 64) | u128::from(wit.low))
+        }
+
+        fn lower(self) -> types::Uuid {
+            types::Uuid {
+                high: (self.0 >> 64) as u64,
+                low: self.0 as u64,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub enum Color {
+        Red,
+        Green,
+    }
+
+    impl wit_bindgen::rt::LiftLower<types::Color> for Color {
+        fn lift(wit: types::Color) -> Self {
+            match wit {
+                types::Color::Red => Color::Red,
+                types::Color::Green => Color::Green,
+            }
+        }
+
+        fn lower(self) -> types::Color {
+            match self {
+                Color::Red => types::Color::Red,
+                Color::Green => types::Color::Green,
+            }
+        }
+    }
+
+    struct Component;
+
+    export!(Component);
+
+    impl Guest for Component {
+        fn lookup(id: Id, color: Color) -> Id {
+            match color {
+                Color::Red => id,
+                Color::Green => my::inline::api::lookup(id, color),
+            }
+        }
+
+        fn describe(t: types::Tagged) -> String {
+            my::inline::api::describe(&t)
+        }
+
+        fn ids() -> Vec<Id> {
+            my::inline::api::ids()
+        }
+
+        fn find(ids: Vec<Id>, id: Option<Id>) -> Option<(Id, Color)> {
+            match id {
+                Some(id) => ids
+                    .into_iter()
+                    .find(|i| *i == id)
+                    .map(|i| (i, Color::Green)),
+                None => my::inline::api::find(&ids, None),
+            }
+        }
+    }
+
+    #[test]
+    fn lookup_round_trips() {
+        let id = Id((1 << 64) | 2);
+        let lowered = wit_bindgen::rt::LiftLower::<types::Uuid>::lower(id);
+        assert_eq!((lowered.high, lowered.low), (1, 2));
+
+        let ret = unsafe {
+            exports::my::inline::api::_export_lookup_cabi::<Component>(
+                lowered.high as i64,
+                lowered.low as i64,
+                types::Color::Red as i32,
+            )
+        };
+        let (high, low) = unsafe { (*ret.cast::<u64>(), *ret.add(8).cast::<u64>()) };
+        let lifted: Id = wit_bindgen::rt::LiftLower::lift(types::Uuid { high, low });
+        assert_eq!(lifted, id);
+    }
+
+    #[test]
+    fn nested_round_trips() {
+        let ids = vec![
+            types::Uuid { high: 1, low: 2 },
+            types::Uuid { high: 3, low: 4 },
+        ];
+        let ret = unsafe {
+            exports::my::inline::api::_export_find_cabi::<Component>(
+                ids.as_ptr().cast_mut().cast(),
+                ids.len(),
+                1,
+                3,
+                4,
+            )
+        };
+        // The list of ids was handed over to the export, which freed it.
+        std::mem::forget(ids);
+        let (tag, high, low, color) = unsafe {
+            (
+                *ret.cast::<u8>(),
+                *ret.add(8).cast::<u64>(),
+                *ret.add(16).cast::<u64>(),
+                *ret.add(24).cast::<u8>(),
+            )
+        };
+        assert_eq!((tag, high, low), (1, 3, 4));
+        assert_eq!(color, types::Color::Green as u8);
+    }
+}
+
+mod borrowed_export_args {
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            world borrowed {
+                type bytes = list<u8>;
+
+                export process: func(name: string, data: bytes, names: list<string>) -> u64;
+            }
+        ",
+        borrowed_export_args,
+    });
+
+    struct Component;
+
+    export!(Component);
+
+    impl Guest for Component {
+        fn process(name: &str, data: &[u8], names: &[String]) -> u64 {
+            let sum = data.iter().map(|b| u64::from(*b)).sum::<u64>();
+            (name.len() + names.len()) as u64 * 1000 + sum
+        }
+    }
+
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        static LIVE: Cell<isize> = const { Cell::new(0) };
+    }
+
+    /// Counts the allocations made on the current thread which haven't been
+    /// released yet, so tests running concurrently don't interfere.
+    struct Counting;
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = LIVE.try_with(|live| live.set(live.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = LIVE.try_with(|live| live.set(live.get() - 1));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn args_are_borrowed_and_freed() {
+        let before = LIVE.with(Cell::get);
+
+        // Buffers are handed over the same way the host does, and the
+        // bindings take ownership of them.
+        let name: &mut str = Box::leak(Box::from("name"));
+        let data: &mut [u8] = Box::leak(Box::from([1, 2, 3]));
+        assert_eq!(LIVE.with(Cell::get), before + 2);
+
+        let ret = unsafe {
+            _export_process_cabi::<Component>(
+                name.as_mut_ptr(),
+                name.len(),
+                data.as_mut_ptr(),
+                data.len(),
+                std::ptr::NonNull::<u8>::dangling().as_ptr(),
+                0,
+            )
+        };
+        assert_eq!(ret, 4006);
+        assert_eq!(LIVE.with(Cell::get), before);
+    }
+}
+
+mod custom_allocator {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    wit_bindgen::generate!({
+        inline: "
+            package my:inline;
+
+            world allocated {
+                import log: func(lines: list<list<string>>);
+
+                export names: func() -> list<string>;
+                export consume: func(name: string, data: list<u16>) -> u32;
+            }
+        ",
+        allocator: "crate::custom_allocator::Counting",
+    });
+
+    static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+    /// Counts the allocations which haven't been released yet.
+    pub struct Counting;
+
+    unsafe impl wit_bindgen::rt::Allocator for Counting {
+        unsafe fn alloc(layout: Layout) -> *mut u8 {
+            LIVE.fetch_add(1, Relaxed);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(ptr: *mut u8, layout: Layout) {
+            LIVE.fetch_sub(1, Relaxed);
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    struct Component;
+
+    export!(Component);
+
+    impl Guest for Component {
+        fn names() -> Vec<String> {
+            log(&[vec!["names".to_string()]]);
+            vec!["a".to_string(), "bc".to_string()]
+        }
+
+        fn consume(name: String, data: Vec<u16>) -> u32 {
+            assert_eq!(LIVE.load(Relaxed), 0);
+            name.len() as u32 * 1000 + data.iter().map(|d| u32::from(*d)).sum::<u32>()
+        }
+    }
+
+    /// Allocates `bytes` with `cabi_realloc`, as the host does for the
+    /// arguments of exports.
+    unsafe fn realloc(bytes: &[u8], align: usize) -> *mut u8 {
+        let ptr = __cabi_realloc(std::ptr::null_mut(), 0, align, bytes.len());
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+        ptr
+    }
+
+    #[test]
+    fn export_args_use_the_allocator() {
+        let data = [1u16, 2, 3];
+        unsafe {
+            let name = realloc(b"name", 1);
+            let data = realloc(std::slice::from_raw_parts(data.as_ptr().cast(), 6), 2);
+            assert_eq!(LIVE.load(Relaxed), 2);
+
+            // Lifted arguments are copied out of memory from the allocator,
+            // which is released before the export is called.
+            assert_eq!(_export_consume_cabi::<Component>(name, 4, data, 3), 4006);
+        }
+        assert_eq!(LIVE.load(Relaxed), 0);
+    }
+}
+
 mod with_and_resources {
     wit_bindgen::generate!({
         inline: "
@@ -588,3 +1046,326 @@ mod generate_custom_section_link_helpers {
         disable_custom_section_link_helpers: true,
     });
 }
+
+#[allow(unused)]
+mod async_some {
+    wit_bindgen::generate!({
+        inline: r#"
+            package a:b;
+
+            interface i {
+                f: func(a: list<string>) -> list<u32>;
+                g: func(a: u32, b: u32, c: u32, d: u32, e: u32) -> string;
+            }
+
+            world test {
+                import i;
+                export i;
+                export h: func(x: string) -> string;
+                export j: func();
+            }
+        "#,
+        async: {
+            imports: ["a:b/i#f"],
+            exports: ["a:b/i#*", "h"],
+        },
+    });
+
+    struct Component;
+
+    impl exports::a::b::i::Guest for Component {
+        async fn f(a: Vec<String>) -> Vec<u32> {
+            a.iter().map(|s| s.len() as u32).collect()
+        }
+
+        async fn g(a: u32, b: u32, c: u32, d: u32, e: u32) -> String {
+            a::b::i::g(a, b, c, d, e)
+        }
+    }
+
+    impl Guest for Component {
+        async fn h(x: String) -> String {
+            let lens = a::b::i::f(&[x]).await;
+            format!("{lens:?}")
+        }
+
+        fn j() {
+            wit_bindgen::block_on(async {
+                a::b::i::f(&[]).await;
+            });
+        }
+    }
+
+    export!(Component);
+}
+
+#[allow(unused)]
+mod futures_and_streams {
+    use futures::{SinkExt, StreamExt};
+
+    wit_bindgen::generate!({
+        inline: r#"
+            package a:b;
+
+            interface i {
+                record point { x: u32, label: string }
+                resource thing;
+
+                read-all: func(s: stream<u8>) -> list<u8>;
+                points: func() -> stream<point>;
+                labels: func(f: future<string>) -> future<list<point>>;
+                ready: func(f: future) -> future<u32>;
+                things: func(s: stream<thing>);
+            }
+
+            world test {
+                import i;
+                export i;
+                export count: func(s: stream<string>) -> future<u32>;
+            }
+        "#,
+        async: true,
+    });
+
+    struct Component;
+
+    impl exports::a::b::i::Guest for Component {
+        type Thing = Thing;
+
+        async fn read_all(mut s: wit_bindgen::rt::async_support::StreamReader<u8>) -> Vec<u8> {
+            let mut all = Vec::new();
+            while let Some(bytes) = s.next().await {
+                all.extend(bytes);
+            }
+            all
+        }
+
+        async fn points() -> wit_bindgen::rt::async_support::StreamReader<exports::a::b::i::Point> {
+            let (mut tx, rx) = exports::a::b::i::wit_stream::new();
+            wit_bindgen::spawn(async move {
+                let points = a::b::i::points().await.next().await.unwrap_or_default();
+                let points = points
+                    .into_iter()
+                    .map(|a::b::i::Point { x, label }| exports::a::b::i::Point { x, label })
+                    .collect();
+                let _ = tx.send(points).await;
+            });
+            rx
+        }
+
+        async fn labels(
+            f: wit_bindgen::rt::async_support::FutureReader<String>,
+        ) -> wit_bindgen::rt::async_support::FutureReader<Vec<exports::a::b::i::Point>> {
+            let (tx, rx) = exports::a::b::i::wit_future::new();
+            let label = f.await.unwrap_or_default();
+            wit_bindgen::spawn(async move {
+                let _ = tx
+                    .write(vec![exports::a::b::i::Point { x: 0, label }])
+                    .await;
+            });
+            rx
+        }
+
+        async fn ready(
+            f: wit_bindgen::rt::async_support::FutureReader<()>,
+        ) -> wit_bindgen::rt::async_support::FutureReader<u32> {
+            a::b::i::ready(f).await
+        }
+
+        async fn things(s: wit_bindgen::rt::async_support::StreamReader<exports::a::b::i::Thing>) {
+            drop(s);
+        }
+    }
+
+    impl Guest for Component {
+        async fn count(
+            s: wit_bindgen::rt::async_support::StreamReader<String>,
+        ) -> wit_bindgen::rt::async_support::FutureReader<u32> {
+            let (tx, rx) = wit_future::new();
+            wit_bindgen::spawn(async move {
+                let count = s
+                    .map(|strings| strings.len() as u32)
+                    .collect::<Vec<_>>()
+                    .await;
+                let _ = tx.write(count.into_iter().sum()).await;
+            });
+            rx
+        }
+    }
+
+    struct Thing;
+
+    impl exports::a::b::i::GuestThing for Thing {}
+
+    export!(Component);
+}
+
+#[allow(unused)]
+mod shared_payloads {
+    wit_bindgen::generate!({
+        inline: r#"
+            package a:b;
+
+            interface i {
+                first: func(f: future<u32>, s: stream<u8>);
+                second: func(s: stream<u8>, f: future<u32>) -> future<u32>;
+            }
+
+            world test {
+                import i;
+            }
+        "#,
+        async: true,
+    });
+
+    // Both functions share a single vtable per payload type, so the ends
+    // created here may be passed to either of them.
+    async fn forward() {
+        use a::b::i::{first, second, wit_future, wit_stream};
+
+        let (_, f) = wit_future::new::<u32>();
+        let (_, s) = wit_stream::new::<u8>();
+        let f = second(s, f).await;
+        let (_, s) = wit_stream::new::<u8>();
+        first(f, s).await;
+    }
+}
+
+mod host {
+    use wit_bindgen::rt::host::{Instance, InvalidValue, Val};
+
+    wit_bindgen::generate!({
+        inline: r#"
+            package a:b;
+
+            interface i {
+                record point { x: u32, label: string }
+                enum color { red, green }
+                flags perms { read, write }
+                variant shape { circle(f64), square(point), none }
+
+                echo: func(s: string) -> string;
+                many: func(
+                    a: u8, b: s16, c: u64, d: f32, e: char, f: bool,
+                    g: list<point>, h: option<color>, i: result<perms, string>,
+                    j: shape, k: tuple<u32, s64>, l: list<u8>, m: list<s8>,
+                    n: u32, o: u32, p: u32, q: u32,
+                ) -> (a: list<shape>, b: option<string>);
+            }
+
+            world test {
+                import i;
+                export i;
+                import greet: func(name: string) -> string;
+                export run: func(n: u32) -> u32;
+            }
+        "#,
+        host: true,
+    });
+
+    /// A stand-in for a core wasm instance of a guest exporting `a:b/i` and
+    /// `run`.
+    #[derive(Default)]
+    struct Guest {
+        memory: Vec<u8>,
+        post_returns: u32,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Trap {
+        Invalid(InvalidValue),
+        OutOfBounds,
+    }
+
+    impl From<InvalidValue> for Trap {
+        fn from(e: InvalidValue) -> Trap {
+            Trap::Invalid(e)
+        }
+    }
+
+    impl Instance for Guest {
+        type Error = Trap;
+
+        fn call(&mut self, name: &str, args: &[Val]) -> Result<Vec<Val>, Trap> {
+            match name {
+                "cabi_realloc" => {
+                    let align = args[2].unwrap_i32() as usize;
+                    let len = args[3].unwrap_i32() as usize;
+                    let ptr = self.memory.len().next_multiple_of(align);
+                    self.memory.resize(ptr + len, 0);
+                    Ok(vec![Val::I32(ptr as i32)])
+                }
+                "a:b/i#echo" => {
+                    let ret = self.realloc(0, 0, 4, 8)?;
+                    self.store(ret, 0, args[0].unwrap_i32())?;
+                    self.store(ret, 4, args[1].unwrap_i32())?;
+                    Ok(vec![Val::I32(ret)])
+                }
+                "cabi_post_a:b/i#echo" => {
+                    self.post_returns += 1;
+                    Ok(Vec::new())
+                }
+                "run" => Ok(vec![Val::I32(args[0].unwrap_i32() * 2)]),
+                _ => panic!("unknown export {name}"),
+            }
+        }
+
+        fn read(&mut self, address: usize, dst: &mut [u8]) -> Result<(), Trap> {
+            let src = self
+                .memory
+                .get(address..address + dst.len())
+                .ok_or(Trap::OutOfBounds)?;
+            dst.copy_from_slice(src);
+            Ok(())
+        }
+
+        fn write(&mut self, address: usize, src: &[u8]) -> Result<(), Trap> {
+            self.memory
+                .get_mut(address..address + src.len())
+                .ok_or(Trap::OutOfBounds)?
+                .copy_from_slice(src);
+            Ok(())
+        }
+    }
+
+    struct MyHost;
+
+    impl Host for MyHost {
+        fn greet(&mut self, name: String) -> String {
+            format!("hello {name}")
+        }
+    }
+
+    #[test]
+    fn call_exports() {
+        let mut guest = Guest::default();
+        assert_eq!(run(&mut guest, 21), Ok(42));
+        assert_eq!(
+            exports::a::b::i::echo(&mut guest, "echo"),
+            Ok("echo".to_string())
+        );
+        assert_eq!(guest.post_returns, 1);
+    }
+
+    #[test]
+    fn dispatch_imports() {
+        let mut guest = Guest::default();
+        let name = guest.realloc(0, 0, 1, 5).unwrap();
+        guest.write_bytes(name, b"world").unwrap();
+        let ret = guest.realloc(0, 0, 4, 8).unwrap();
+
+        let args = [Val::I32(name), Val::I32(5), Val::I32(ret)];
+        let results = dispatch(&mut guest, &mut MyHost, "greet", &args);
+        assert_eq!(results, Some(Ok(Vec::new())));
+        let ptr = guest.load::<i32>(ret, 0).unwrap();
+        let len = guest.load::<i32>(ret, 4).unwrap();
+        assert_eq!(guest.read_bytes(ptr, len).unwrap(), b"hello world");
+
+        assert_eq!(dispatch(&mut guest, &mut MyHost, "nope", &[]), None);
+
+        // Invalid UTF-8 is an error rather than undefined behavior.
+        guest.write_bytes(name, &[0xff]).unwrap();
+        let results = dispatch(&mut guest, &mut MyHost, "greet", &args);
+        assert!(matches!(results, Some(Err(Trap::Invalid(_)))));
+    }
+}
@@ -0,0 +1,874 @@
+use crate::{flags_repr, func_name, int_type, InterfaceGenerator};
+use std::fmt::Write as _;
+use std::mem;
+use wit_bindgen_core::abi::{Bindgen, Bitcast, Instruction, WasmType};
+use wit_bindgen_core::{dealias, uwriteln, wit_parser::*, Direction, Ns, Source};
+
+pub(super) struct FunctionBindgen<'a, 'b> {
+    pub gen: &'b mut InterfaceGenerator<'a>,
+    /// The function `CallInterface` calls, or for methods the name of the
+    /// method called on the first argument.
+    pub callee: String,
+    /// Whether this is an imported constructor, which initializes `self`
+    /// with the handle it lifts rather than returning it.
+    pub constructor: bool,
+    pub params: Vec<String>,
+    pub locals: Ns,
+    pub src: Source,
+    block_storage: Vec<Source>,
+    blocks: Vec<(String, Vec<String>)>,
+    payloads: Vec<String>,
+    /// The names of the elements and base pointers of the lists being lowered
+    /// or lifted, innermost last.
+    iter_elems: Vec<String>,
+    iter_bases: Vec<String>,
+    /// The arena an import's temporary allocations are made in, which is
+    /// freed when it returns.
+    pub arena: String,
+    pub needs_arena: bool,
+}
+
+impl<'a, 'b> FunctionBindgen<'a, 'b> {
+    pub(super) fn new(
+        gen: &'b mut InterfaceGenerator<'a>,
+        mut locals: Ns,
+    ) -> FunctionBindgen<'a, 'b> {
+        let arena = locals.tmp("arena");
+        FunctionBindgen {
+            gen,
+            callee: String::new(),
+            constructor: false,
+            params: Vec::new(),
+            locals,
+            src: Source::default(),
+            block_storage: Vec::new(),
+            blocks: Vec::new(),
+            payloads: Vec::new(),
+            iter_elems: Vec::new(),
+            iter_bases: Vec::new(),
+            arena,
+            needs_arena: false,
+        }
+    }
+
+    fn in_import(&self) -> bool {
+        self.gen.direction == Direction::Import
+    }
+
+    /// Binds `op` to a new constant so that it's only evaluated once, and
+    /// returns its name.
+    fn bind(&mut self, name: &str, op: &str) -> String {
+        let tmp = self.locals.tmp(name);
+        uwriteln!(self.src, "let {tmp} = {op}");
+        tmp
+    }
+
+    fn load(&mut self, ty: &str, offset: i32, operands: &[String]) -> String {
+        self.bind(
+            "load",
+            &format!("witLoad({} + {offset}, as: {ty}.self)", operands[0]),
+        )
+    }
+
+    fn store(&mut self, value: &str, offset: i32, operands: &[String]) {
+        uwriteln!(self.src, "witStore({} + {offset}, {value})", operands[1]);
+    }
+
+    /// Returns an expression allocating `size` bytes aligned to `align`,
+    /// which are freed when an import returns, and are freed by the
+    /// post-return function of an export.
+    fn alloc(&mut self, size: &str, align: usize) -> String {
+        if self.in_import() {
+            self.needs_arena = true;
+            format!("{}.alloc({size}, {align})", self.arena)
+        } else {
+            format!("witAlloc({size}, {align})")
+        }
+    }
+
+    /// Declares a variable for each of the core wasm `types` which the
+    /// results of a variant's cases are assigned to.
+    fn declare_results(&mut self, types: &[WasmType]) -> Vec<String> {
+        types
+            .iter()
+            .map(|ty| {
+                let tmp = self.locals.tmp("variant");
+                uwriteln!(self.src, "var {tmp}: {} = 0", wasm_type(*ty));
+                tmp
+            })
+            .collect()
+    }
+
+    /// Writes the case of a `switch` lowering a case of a variant, whose
+    /// payload is bound to `payload` if it has one.
+    fn lower_case(
+        &mut self,
+        pattern: &str,
+        payload: Option<&str>,
+        block: &str,
+        ops: &[String],
+        vars: &[String],
+    ) {
+        match payload {
+            Some(payload) => uwriteln!(self.src, "case {pattern}(let {payload}):"),
+            None => uwriteln!(self.src, "case {pattern}:"),
+        }
+        self.src.push_str(block);
+        for (var, op) in vars.iter().zip(ops) {
+            uwriteln!(self.src, "{var} = {op}");
+        }
+        if block.is_empty() && vars.is_empty() {
+            self.src.push_str("break\n");
+        }
+    }
+
+    /// Writes the case of a `switch` lifting a case of a variant, which
+    /// evaluates `block` and assigns `value` to `result`.
+    fn lift_case(&mut self, discriminant: usize, block: &str, result: &str, value: &str) {
+        uwriteln!(self.src, "case {discriminant}:");
+        self.src.push_str(block);
+        uwriteln!(self.src, "{result} = {value}");
+    }
+
+    fn type_name(&self, ty: &Type) -> String {
+        self.gen.type_name(ty)
+    }
+
+    /// Returns the argument list of a call passing `operands` to the
+    /// parameters of `func`, skipping the first if it's a method.
+    fn args(&self, func: &Function, operands: &[String]) -> String {
+        let params = match func.kind {
+            FunctionKind::Method(_) => &func.params[1..],
+            _ => &func.params[..],
+        };
+        params
+            .iter()
+            .zip(operands)
+            .map(|((name, _), op)| format!("{}: {op}", func_name(name)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Bindgen for FunctionBindgen<'_, '_> {
+    type Operand = String;
+
+    fn sizes(&self) -> &SizeAlign {
+        &self.gen.gen.sizes
+    }
+
+    fn push_block(&mut self) {
+        let prev = mem::take(&mut self.src);
+        self.block_storage.push(prev);
+    }
+
+    fn finish_block(&mut self, operands: &mut Vec<String>) {
+        let to_restore = self.block_storage.pop().unwrap();
+        let src = mem::replace(&mut self.src, to_restore);
+        self.blocks.push((src.into(), mem::take(operands)));
+    }
+
+    fn return_pointer(&mut self, size: usize, align: usize) -> String {
+        // Imports allocate their return area in their arena, because exports
+        // need their return area to be live until the post-return call.
+        if self.in_import() {
+            let alloc = self.alloc(&size.to_string(), align);
+            self.bind("retArea", &alloc)
+        } else {
+            self.gen.gen.return_pointer_area_size = self.gen.gen.return_pointer_area_size.max(size);
+            self.gen.gen.return_pointer_area_align =
+                self.gen.gen.return_pointer_area_align.max(align);
+            "witRetArea".to_string()
+        }
+    }
+
+    fn is_list_canonical(&self, _resolve: &Resolve, ty: &Type) -> bool {
+        // Only arrays of numbers have the same layout as the canonical ABI;
+        // `Bool` and `Unicode.Scalar` are validated.
+        matches!(
+            ty,
+            Type::U8
+                | Type::S8
+                | Type::U16
+                | Type::S16
+                | Type::U32
+                | Type::S32
+                | Type::U64
+                | Type::S64
+                | Type::F32
+                | Type::F64
+        )
+    }
+
+    fn emit(
+        &mut self,
+        resolve: &Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<String>,
+        results: &mut Vec<String>,
+    ) {
+        match inst {
+            Instruction::GetArg { nth } => results.push(self.params[*nth].clone()),
+            Instruction::I32Const { val } => results.push(val.to_string()),
+            Instruction::ConstZero { tys } => {
+                for ty in tys.iter() {
+                    results.push(format!("{}(0)", wasm_type(*ty)));
+                }
+            }
+
+            Instruction::U8FromI32 => {
+                results.push(format!("UInt8(truncatingIfNeeded: {})", operands[0]))
+            }
+            Instruction::S8FromI32 => {
+                results.push(format!("Int8(truncatingIfNeeded: {})", operands[0]))
+            }
+            Instruction::U16FromI32 => {
+                results.push(format!("UInt16(truncatingIfNeeded: {})", operands[0]))
+            }
+            Instruction::S16FromI32 => {
+                results.push(format!("Int16(truncatingIfNeeded: {})", operands[0]))
+            }
+            Instruction::U32FromI32 => results.push(format!("UInt32(bitPattern: {})", operands[0])),
+            Instruction::U64FromI64 => results.push(format!("UInt64(bitPattern: {})", operands[0])),
+            Instruction::S32FromI32 | Instruction::S64FromI64 => results.push(operands[0].clone()),
+
+            Instruction::I32FromU8
+            | Instruction::I32FromS8
+            | Instruction::I32FromU16
+            | Instruction::I32FromS16 => results.push(format!("Int32({})", operands[0])),
+            Instruction::I32FromU32 => results.push(format!("Int32(bitPattern: {})", operands[0])),
+            Instruction::I64FromU64 => results.push(format!("Int64(bitPattern: {})", operands[0])),
+            Instruction::I32FromS32 | Instruction::I64FromS64 => results.push(operands[0].clone()),
+
+            Instruction::CoreF32FromF32
+            | Instruction::CoreF64FromF64
+            | Instruction::F32FromCoreF32
+            | Instruction::F64FromCoreF64 => {
+                results.push(operands[0].clone());
+            }
+
+            Instruction::CharFromI32 => results.push(format!(
+                "Unicode.Scalar(UInt32(bitPattern: {}))!",
+                operands[0]
+            )),
+            Instruction::I32FromChar => {
+                results.push(format!("Int32(bitPattern: {}.value)", operands[0]))
+            }
+            Instruction::BoolFromI32 => results.push(format!("({} != 0)", operands[0])),
+            Instruction::I32FromBool => results.push(format!("Int32({} ? 1 : 0)", operands[0])),
+
+            Instruction::Bitcasts { casts } => {
+                for (cast, op) in casts.iter().zip(operands) {
+                    results.push(perform_cast(op, cast));
+                }
+            }
+
+            Instruction::RecordLower { record, .. } => {
+                if record.fields.is_empty() {
+                    return;
+                }
+                let op = self.bind("record", &operands[0]);
+                for field in record.fields.iter() {
+                    results.push(format!("{op}.{}", func_name(&field.name)));
+                }
+            }
+            Instruction::RecordLift { record, ty, .. } => {
+                let name = self.gen.qualified_name(*ty);
+                let fields = record
+                    .fields
+                    .iter()
+                    .zip(operands.iter())
+                    .map(|(field, op)| format!("{}: {op}", func_name(&field.name)))
+                    .collect::<Vec<_>>();
+                results.push(format!("{name}({})", fields.join(", ")));
+            }
+
+            Instruction::TupleLower { tuple, .. } => match tuple.types.len() {
+                0 => {}
+                // Tuples of one value are represented by the value itself.
+                1 => results.push(operands[0].clone()),
+                n => {
+                    let op = self.bind("tuple", &operands[0]);
+                    results.extend((0..n).map(|i| format!("{op}.{i}")));
+                }
+            },
+            Instruction::TupleLift { .. } => match &operands[..] {
+                [op] => results.push(op.clone()),
+                ops => results.push(format!("({})", ops.join(", "))),
+            },
+
+            Instruction::HandleLower { handle, .. } => {
+                let op = &operands[0];
+                let resource = self.gen.resource(handle_resource(handle));
+                match (resource.direction, handle) {
+                    (Direction::Import, Handle::Own(_)) => {
+                        results.push(format!("{op}.takeHandle()"))
+                    }
+                    (Direction::Import, Handle::Borrow(_)) => results.push(format!("{op}.handle")),
+                    // Each handle to an exported resource holds a reference
+                    // to its object, which its destructor releases.
+                    (Direction::Export, _) => {
+                        results.push(format!("{}(witRep({op}))", resource.new))
+                    }
+                }
+            }
+            Instruction::HandleLift { handle, .. } => {
+                let op = &operands[0];
+                let id = dealias(resolve, handle_resource(handle));
+                let name = self.gen.qualified_name(id);
+                let resource = self.gen.resource(id).clone();
+                match (resource.direction, handle) {
+                    // Imported constructors initialize themselves with the
+                    // handle they return.
+                    (Direction::Import, Handle::Own(_))
+                        if self.constructor && self.block_storage.is_empty() =>
+                    {
+                        results.push(op.clone())
+                    }
+                    (Direction::Import, Handle::Own(_)) => {
+                        results.push(format!("{name}(handle: {op})"))
+                    }
+                    (Direction::Import, Handle::Borrow(_)) => {
+                        results.push(format!("{name}(borrowing: {op})"))
+                    }
+                    // The `self` of an exported method is passed as a pointer
+                    // rather than an `Int32`.
+                    (Direction::Export, Handle::Borrow(_)) => results.push(format!(
+                        "witObject(Int32(truncatingIfNeeded: {op}), as: {name}.self)"
+                    )),
+                    // An owned handle to an exported resource is dropped once
+                    // its object is referenced, which releases the reference
+                    // the handle held.
+                    (Direction::Export, Handle::Own(_)) => {
+                        let handle = self.bind("handle", op);
+                        let object = self.bind(
+                            "object",
+                            &format!("witObject({}({handle}), as: {name}.self)", resource.rep),
+                        );
+                        uwriteln!(self.src, "{}({handle})", resource.drop);
+                        results.push(object);
+                    }
+                }
+            }
+
+            Instruction::FlagsLower { flags, .. } => {
+                let op = &operands[0];
+                match flags.repr() {
+                    FlagsRepr::U32(0) => {}
+                    FlagsRepr::U32(1) => results.push(format!("Int32(bitPattern: {op}.rawValue)")),
+                    FlagsRepr::U32(n) if n > 4 => {
+                        let words = self.bind("words", &format!("{op}.rawValue.words"));
+                        for i in 0..n {
+                            results.push(format!("Int32(bitPattern: {words}[{i}])"));
+                        }
+                    }
+                    FlagsRepr::U32(n) => {
+                        let bits = self.bind("bits", &format!("{op}.rawValue"));
+                        for i in 0..n {
+                            results
+                                .push(format!("Int32(truncatingIfNeeded: {bits} >> {})", 32 * i));
+                        }
+                    }
+                    FlagsRepr::U8 | FlagsRepr::U16 => results.push(format!("Int32({op}.rawValue)")),
+                }
+            }
+            Instruction::FlagsLift { flags, ty, .. } => {
+                let name = self.gen.qualified_name(*ty);
+                let bits = match flags.repr() {
+                    FlagsRepr::U32(0) => "0".to_string(),
+                    FlagsRepr::U32(1) => format!("UInt32(bitPattern: {})", operands[0]),
+                    FlagsRepr::U32(n) if n > 4 => {
+                        let words = operands
+                            .iter()
+                            .map(|op| format!("UInt32(bitPattern: {op})"))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        format!("WitFlags(words: [{words}])")
+                    }
+                    FlagsRepr::U32(_) => {
+                        let repr = flags_repr(flags);
+                        operands
+                            .iter()
+                            .enumerate()
+                            .map(|(i, op)| {
+                                format!("{repr}(UInt32(bitPattern: {op})) << {}", 32 * i)
+                            })
+                            .collect::<Vec<_>>()
+                            .join(" | ")
+                    }
+                    FlagsRepr::U8 => format!("UInt8(truncatingIfNeeded: {})", operands[0]),
+                    FlagsRepr::U16 => format!("UInt16(truncatingIfNeeded: {})", operands[0]),
+                };
+                results.push(format!("{name}(rawValue: {bits})"));
+            }
+
+            Instruction::VariantPayloadName => {
+                let name = self.locals.tmp("payload");
+                results.push(name.clone());
+                self.payloads.push(name);
+            }
+
+            Instruction::VariantLower {
+                variant,
+                results: result_types,
+                ..
+            } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+                let payloads = self
+                    .payloads
+                    .drain(self.payloads.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+
+                let vars = self.declare_results(result_types);
+                uwriteln!(self.src, "switch {} {{", operands[0]);
+                for ((case, (block, block_results)), payload) in
+                    variant.cases.iter().zip(blocks).zip(payloads)
+                {
+                    let pattern = format!(".{}", func_name(&case.name));
+                    let payload = case.ty.as_ref().map(|_| payload.as_str());
+                    self.lower_case(&pattern, payload, &block, &block_results, &vars);
+                }
+                self.src.push_str("}\n");
+                results.extend(vars);
+            }
+
+            Instruction::VariantLift { variant, ty, .. } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+
+                let name = self.gen.qualified_name(*ty);
+                let result = self.locals.tmp("variant");
+                uwriteln!(self.src, "let {result}: {name}");
+                uwriteln!(self.src, "switch {} {{", operands[0]);
+                for (i, (case, (block, block_results))) in
+                    variant.cases.iter().zip(blocks).enumerate()
+                {
+                    let case_name = func_name(&case.name);
+                    let value = match &block_results[..] {
+                        [] => format!(".{case_name}"),
+                        [value] => format!(".{case_name}({value})"),
+                        _ => unreachable!(),
+                    };
+                    self.lift_case(i, &block, &result, &value);
+                }
+                self.src
+                    .push_str("default:\nfatalError(\"invalid variant discriminant\")\n}\n");
+                results.push(result);
+            }
+
+            Instruction::EnumLower { enum_, .. } => match enum_.tag() {
+                Int::U8 | Int::U16 => results.push(format!("Int32({}.rawValue)", operands[0])),
+                Int::U32 => results.push(format!("Int32(bitPattern: {}.rawValue)", operands[0])),
+                Int::U64 => unreachable!(),
+            },
+            Instruction::EnumLift { enum_, ty, .. } => {
+                let name = self.gen.qualified_name(*ty);
+                let tag = int_type(enum_.tag());
+                results.push(format!(
+                    "{name}(rawValue: {tag}(truncatingIfNeeded: {}))!",
+                    operands[0]
+                ));
+            }
+
+            Instruction::OptionLower {
+                results: result_types,
+                ..
+            } => {
+                let (some, some_results) = self.blocks.pop().unwrap();
+                let (none, none_results) = self.blocks.pop().unwrap();
+                let some_payload = self.payloads.pop().unwrap();
+                let _none_payload = self.payloads.pop().unwrap();
+
+                let vars = self.declare_results(result_types);
+                uwriteln!(self.src, "switch {} {{", operands[0]);
+                self.lower_case(".some", Some(&some_payload), &some, &some_results, &vars);
+                self.lower_case(".none", None, &none, &none_results, &vars);
+                self.src.push_str("}\n");
+                results.extend(vars);
+            }
+
+            Instruction::OptionLift { ty, .. } => {
+                let (some, some_results) = self.blocks.pop().unwrap();
+                let (none, none_results) = self.blocks.pop().unwrap();
+                assert!(none_results.is_empty());
+                assert_eq!(some_results.len(), 1);
+
+                let name = self.gen.type_name(&Type::Id(*ty));
+                let result = self.locals.tmp("option");
+                uwriteln!(self.src, "let {result}: {name}");
+                uwriteln!(self.src, "switch {} {{", operands[0]);
+                self.lift_case(0, &none, &result, "nil");
+                // `.some` is explicit so that the payload of a nested option
+                // isn't taken as the outer option.
+                let value = format!(".some({})", some_results[0]);
+                self.lift_case(1, &some, &result, &value);
+                self.src
+                    .push_str("default:\nfatalError(\"invalid option discriminant\")\n}\n");
+                results.push(result);
+            }
+
+            Instruction::ResultLower {
+                results: result_types,
+                result,
+                ..
+            } => {
+                let (err, err_results) = self.blocks.pop().unwrap();
+                let (ok, ok_results) = self.blocks.pop().unwrap();
+                let err_payload = self.payloads.pop().unwrap();
+                let ok_payload = self.payloads.pop().unwrap();
+
+                let vars = self.declare_results(result_types);
+                uwriteln!(self.src, "switch {} {{", operands[0]);
+                let ok_payload = result.ok.as_ref().map(|_| ok_payload.as_str());
+                self.lower_case(".ok", ok_payload, &ok, &ok_results, &vars);
+                let err_payload = result.err.as_ref().map(|_| err_payload.as_str());
+                self.lower_case(".err", err_payload, &err, &err_results, &vars);
+                self.src.push_str("}\n");
+                results.extend(vars);
+            }
+
+            Instruction::ResultLift { ty, .. } => {
+                let (err, err_results) = self.blocks.pop().unwrap();
+                let (ok, ok_results) = self.blocks.pop().unwrap();
+                let name = self.gen.type_name(&Type::Id(*ty));
+                let result = self.locals.tmp("result");
+                uwriteln!(self.src, "let {result}: {name}");
+                uwriteln!(self.src, "switch {} {{", operands[0]);
+                let ok_value = format!(".ok({})", ok_results.first().map_or("()", |s| s));
+                self.lift_case(0, &ok, &result, &ok_value);
+                let err_value = format!(".err({})", err_results.first().map_or("()", |s| s));
+                self.lift_case(1, &err, &result, &err_value);
+                self.src
+                    .push_str("default:\nfatalError(\"invalid result discriminant\")\n}\n");
+                results.push(result);
+            }
+
+            Instruction::ListCanonLower { .. } | Instruction::StringLower { .. } => {
+                let ptr = self.locals.tmp("ptr");
+                let len = self.locals.tmp("len");
+                let lower = if self.in_import() {
+                    self.needs_arena = true;
+                    format!("{}.lower({})", self.arena, operands[0])
+                } else if let Instruction::StringLower { .. } = inst {
+                    format!("witLowerString({})", operands[0])
+                } else {
+                    format!("witLowerList({})", operands[0])
+                };
+                uwriteln!(self.src, "let ({ptr}, {len}) = {lower}");
+                results.push(ptr);
+                results.push(len);
+            }
+
+            Instruction::ListLower { element, .. } => {
+                let (body, body_results) = self.blocks.pop().unwrap();
+                assert!(body_results.is_empty());
+                let elem = self.iter_elems.pop().unwrap();
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.gen.sizes.align(element).align_wasm32();
+
+                let list = self.bind("list", &operands[0]);
+                let alloc = self.alloc(&format!("{list}.count * {size}"), align);
+                let ptr = self.bind("ptr", &alloc);
+                let index = self.locals.tmp("i");
+                uwriteln!(self.src, "for ({index}, {elem}) in {list}.enumerated() {{");
+                uwriteln!(self.src, "let {base} = {ptr} + {index} * {size}");
+                self.src.push_str(&body);
+                self.src.push_str("}\n");
+                results.push(ptr);
+                results.push(format!("{list}.count"));
+            }
+
+            Instruction::ListCanonLift { element, .. } => {
+                let ty = self.type_name(element);
+                results.push(format!(
+                    "witLiftList({}, {}, as: {ty}.self)",
+                    operands[0], operands[1]
+                ));
+            }
+            Instruction::StringLift => {
+                results.push(format!("witLiftString({}, {})", operands[0], operands[1]));
+            }
+
+            Instruction::ListLift { element, .. } => {
+                let (body, body_results) = self.blocks.pop().unwrap();
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let ty = self.type_name(element);
+
+                let ptr = self.bind("ptr", &operands[0]);
+                let len = self.bind("len", &operands[1]);
+                let list = self.locals.tmp("list");
+                let index = self.locals.tmp("i");
+                uwriteln!(self.src, "var {list}: [{ty}] = []");
+                uwriteln!(self.src, "{list}.reserveCapacity({len})");
+                uwriteln!(self.src, "for {index} in 0..<{len} {{");
+                uwriteln!(self.src, "let {base} = {ptr} + {index} * {size}");
+                self.src.push_str(&body);
+                uwriteln!(self.src, "{list}.append({})", body_results[0]);
+                self.src.push_str("}\n");
+                uwriteln!(self.src, "witFree({ptr}, {len} * {size})");
+                results.push(list);
+            }
+
+            Instruction::IterElem { .. } => {
+                let elem = self.locals.tmp("elem");
+                self.iter_elems.push(elem.clone());
+                results.push(elem);
+            }
+            Instruction::IterBasePointer => {
+                let base = self.locals.tmp("base");
+                self.iter_bases.push(base.clone());
+                results.push(base);
+            }
+
+            Instruction::CallWasm { name, sig } => {
+                let module = self.gen.module.clone().unwrap();
+                let params = sig
+                    .params
+                    .iter()
+                    .map(|ty| wasm_type(*ty))
+                    .collect::<Vec<_>>();
+                let result = match &sig.results[..] {
+                    [] => None,
+                    [ty] => Some(wasm_type(*ty)),
+                    _ => unreachable!("multiple results are returned through a return pointer"),
+                };
+                let import = self.gen.gen.declare_extern(&module, name, &params, result);
+                let call = format!("{import}({})", operands.join(", "));
+                if sig.results.is_empty() {
+                    uwriteln!(self.src, "{call}");
+                } else {
+                    results.push(self.bind("ret", &call));
+                }
+            }
+
+            Instruction::CallInterface { func, .. } => {
+                let call = match func.kind {
+                    FunctionKind::Method(_) => format!(
+                        "{}.{}({})",
+                        operands[0],
+                        self.callee,
+                        self.args(func, &operands[1..])
+                    ),
+                    _ => format!("{}({})", self.callee, self.args(func, operands)),
+                };
+                if let Some(result) = self.gen.error_result(func) {
+                    // Catch the error the implementation throws as the
+                    // `result` it represents.
+                    let ty = self.type_name(func.results.iter_types().next().unwrap());
+                    let err = self.gen.optional_type_name(result.err.as_ref());
+                    let ret = self.locals.tmp("ret");
+                    uwriteln!(self.src, "let {ret}: {ty}");
+                    uwriteln!(self.src, "do throws(WitError<{err}>) {{");
+                    if result.ok.is_some() {
+                        uwriteln!(self.src, "{ret} = .ok(try {call})");
+                    } else {
+                        uwriteln!(self.src, "try {call}");
+                        uwriteln!(self.src, "{ret} = .ok(())");
+                    }
+                    self.src.push_str("} catch {\n");
+                    uwriteln!(self.src, "{ret} = .err(error.payload)");
+                    self.src.push_str("}\n");
+                    results.push(ret);
+                    return;
+                }
+                match func.results.len() {
+                    0 => uwriteln!(self.src, "{call}"),
+                    1 => results.push(self.bind("ret", &call)),
+                    n => {
+                        let ret = self.bind("ret", &call);
+                        results.extend((0..n).map(|i| format!("{ret}.{i}")));
+                    }
+                }
+            }
+
+            Instruction::Return { amt, func } => {
+                if !self.in_import() {
+                    assert!(*amt <= 1);
+                    if *amt == 1 {
+                        uwriteln!(self.src, "return {}", operands[0]);
+                    }
+                } else if self.constructor {
+                    uwriteln!(self.src, "self.init(handle: {})", operands[0]);
+                } else if let Some(result) = self.gen.error_result(func) {
+                    // Throw the error of the lifted `result`.
+                    let value = self.locals.tmp("value");
+                    let error = self.locals.tmp("error");
+                    uwriteln!(self.src, "switch {} {{", operands[0]);
+                    if result.ok.is_some() {
+                        uwriteln!(self.src, "case .ok(let {value}):");
+                        uwriteln!(self.src, "return {value}");
+                    } else {
+                        self.src.push_str("case .ok:\nreturn\n");
+                    }
+                    if result.err.is_some() {
+                        uwriteln!(self.src, "case .err(let {error}):");
+                        uwriteln!(self.src, "throw WitError({error})");
+                    } else {
+                        self.src.push_str("case .err:\nthrow WitError(())\n");
+                    }
+                    self.src.push_str("}\n");
+                } else {
+                    match operands.len() {
+                        0 => {}
+                        1 => uwriteln!(self.src, "return {}", operands[0]),
+                        _ => uwriteln!(self.src, "return ({})", operands.join(", ")),
+                    }
+                }
+            }
+
+            Instruction::I32Load { offset } => results.push(self.load("Int32", *offset, operands)),
+            Instruction::I64Load { offset } => results.push(self.load("Int64", *offset, operands)),
+            Instruction::F32Load { offset } => results.push(self.load("Float", *offset, operands)),
+            Instruction::F64Load { offset } => results.push(self.load("Double", *offset, operands)),
+            Instruction::PointerLoad { offset } | Instruction::LengthLoad { offset } => {
+                let load = self.load("UInt32", *offset, operands);
+                results.push(format!("Int({load})"));
+            }
+            Instruction::I32Load8U { offset } => {
+                let load = self.load("UInt8", *offset, operands);
+                results.push(format!("Int32({load})"));
+            }
+            Instruction::I32Load8S { offset } => {
+                let load = self.load("Int8", *offset, operands);
+                results.push(format!("Int32({load})"));
+            }
+            Instruction::I32Load16U { offset } => {
+                let load = self.load("UInt16", *offset, operands);
+                results.push(format!("Int32({load})"));
+            }
+            Instruction::I32Load16S { offset } => {
+                let load = self.load("Int16", *offset, operands);
+                results.push(format!("Int32({load})"));
+            }
+
+            Instruction::I32Store { offset }
+            | Instruction::I64Store { offset }
+            | Instruction::F32Store { offset }
+            | Instruction::F64Store { offset } => {
+                let value = operands[0].clone();
+                self.store(&value, *offset, operands)
+            }
+            Instruction::I32Store8 { offset } => {
+                let value = format!("UInt8(truncatingIfNeeded: {})", operands[0]);
+                self.store(&value, *offset, operands)
+            }
+            Instruction::I32Store16 { offset } => {
+                let value = format!("UInt16(truncatingIfNeeded: {})", operands[0]);
+                self.store(&value, *offset, operands)
+            }
+            Instruction::PointerStore { offset } | Instruction::LengthStore { offset } => {
+                let value = format!("UInt32(truncatingIfNeeded: {})", operands[0]);
+                self.store(&value, *offset, operands)
+            }
+
+            Instruction::Malloc { .. } => unreachable!(),
+
+            Instruction::GuestDeallocate { size, .. } => {
+                uwriteln!(self.src, "witFree({}, {size})", operands[0]);
+            }
+            Instruction::GuestDeallocateString => {
+                uwriteln!(self.src, "witFree({}, {})", operands[0], operands[1]);
+            }
+            Instruction::GuestDeallocateVariant { blocks } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - blocks..)
+                    .collect::<Vec<_>>();
+
+                uwriteln!(self.src, "switch {} {{", operands[0]);
+                for (i, (block, results)) in blocks.into_iter().enumerate() {
+                    assert!(results.is_empty());
+                    uwriteln!(self.src, "case {i}:");
+                    if block.is_empty() {
+                        self.src.push_str("break\n");
+                    } else {
+                        self.src.push_str(&block);
+                    }
+                }
+                self.src
+                    .push_str("default:\nfatalError(\"invalid variant discriminant\")\n}\n");
+            }
+            Instruction::GuestDeallocateList { element } => {
+                let (body, results) = self.blocks.pop().unwrap();
+                assert!(results.is_empty());
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+
+                let ptr = self.bind("ptr", &operands[0]);
+                let len = self.bind("len", &operands[1]);
+                if !body.trim().is_empty() {
+                    let index = self.locals.tmp("i");
+                    uwriteln!(self.src, "for {index} in 0..<{len} {{");
+                    uwriteln!(self.src, "let {base} = {ptr} + {index} * {size}");
+                    self.src.push_str(&body);
+                    self.src.push_str("}\n");
+                }
+                uwriteln!(self.src, "witFree({ptr}, {len} * {size})");
+            }
+
+            Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. }
+            | Instruction::Flush { .. } => {
+                unreachable!("async functions are never requested by this generator")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+        }
+    }
+}
+
+fn handle_resource(handle: &Handle) -> TypeId {
+    let (Handle::Own(resource) | Handle::Borrow(resource)) = handle;
+    *resource
+}
+
+fn perform_cast(op: &str, cast: &Bitcast) -> String {
+    match cast {
+        Bitcast::I32ToF32 => format!("Float(bitPattern: UInt32(bitPattern: {op}))"),
+        Bitcast::I64ToF64 => format!("Double(bitPattern: UInt64(bitPattern: {op}))"),
+        Bitcast::F32ToI32 => format!("Int32(bitPattern: {op}.bitPattern)"),
+        Bitcast::F64ToI64 => format!("Int64(bitPattern: {op}.bitPattern)"),
+        Bitcast::I64ToF32 => format!("Float(bitPattern: UInt32(truncatingIfNeeded: {op}))"),
+        Bitcast::F32ToI64 => format!("Int64({op}.bitPattern)"),
+        Bitcast::I32ToI64 => format!("Int64({op})"),
+        Bitcast::I64ToI32 => format!("Int32(truncatingIfNeeded: {op})"),
+        // P64 is represented as `Int64`, and both pointers and lengths as
+        // `Int`, so these are no-ops.
+        Bitcast::I64ToP64 | Bitcast::P64ToI64 | Bitcast::PToL | Bitcast::LToP => op.to_string(),
+        Bitcast::P64ToP | Bitcast::I64ToL => format!("Int(truncatingIfNeeded: {op})"),
+        Bitcast::PToP64 | Bitcast::LToI64 => format!("Int64({op})"),
+        Bitcast::I32ToP | Bitcast::I32ToL => format!("Int({op})"),
+        Bitcast::PToI32 | Bitcast::LToI32 => format!("Int32(truncatingIfNeeded: {op})"),
+        Bitcast::None => op.to_string(),
+        Bitcast::Sequence(sequence) => {
+            let [first, second] = &**sequence;
+            let inner = perform_cast(op, first);
+            perform_cast(&inner, second)
+        }
+    }
+}
+
+pub(super) fn wasm_type(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 => "Int32",
+        WasmType::I64 => "Int64",
+        WasmType::F32 => "Float",
+        WasmType::F64 => "Double",
+        WasmType::Pointer | WasmType::Length => "Int",
+        WasmType::PointerOrI64 => "Int64",
+    }
+}
@@ -0,0 +1,1245 @@
+mod bindgen;
+
+use anyhow::Result;
+use bindgen::{wasm_type, FunctionBindgen};
+use heck::*;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::mem;
+use wit_bindgen_c::component_type_object;
+use wit_bindgen_core::abi::{self, AbiVariant, LiftLower};
+use wit_bindgen_core::{
+    dealias, uwriteln, wit_parser::*, AnonymousTypeGenerator, Direction, Files,
+    InterfaceGenerator as _, Ns, Source, WorldGenerator,
+};
+use wit_component::StringEncoding;
+
+/// Support code placed at the top of every generated file.
+const RUNTIME: &str = include_str!("runtime.swift");
+
+/// The names `RUNTIME` declares and generated code refers to, which
+/// parameters mustn't shadow.
+const RUNTIME_DECLS: &[&str] = &[
+    "cabi_realloc",
+    "witAlloc",
+    "witFree",
+    "witLoad",
+    "witStore",
+    "witLowerList",
+    "witLowerString",
+    "witLiftList",
+    "witLiftString",
+    "witRep",
+    "witObject",
+    "witRelease",
+    "witRetArea",
+    "fatalError",
+    "self",
+];
+
+/// The types generated code refers to, which generated types mustn't shadow.
+const RESERVED_TYPES: &[&str] = &[
+    "Any",
+    "AnyObject",
+    "Array",
+    "Bool",
+    "Double",
+    "Error",
+    "Float",
+    "Int",
+    "Int8",
+    "Int16",
+    "Int32",
+    "Int64",
+    "MemoryLayout",
+    "Never",
+    "Optional",
+    "Protocol",
+    "Self",
+    "String",
+    "Swift",
+    "Type",
+    "UInt",
+    "UInt8",
+    "UInt16",
+    "UInt32",
+    "UInt64",
+    "UInt128",
+    "Unicode",
+    "Void",
+    "WitArena",
+    "WitError",
+    "WitFlags",
+    "WitLargeFlags",
+    "WitResult",
+];
+
+#[derive(Default)]
+struct Swift {
+    opts: Opts,
+    world: String,
+    sizes: SizeAlign,
+    src: Source,
+    /// The bindings for the functions and types the world itself imports,
+    /// which are all placed in one container.
+    world_src: Source,
+    world_container: String,
+    /// The `@_extern` declarations of the core wasm functions the bindings
+    /// import.
+    externs: Source,
+    /// The `@_expose` definitions of the core wasm functions the bindings
+    /// export.
+    exports: Source,
+    /// The names of the top-level functions in `externs` and `exports`.
+    fn_names: Ns,
+    /// The names of the top-level containers and protocols.
+    type_names: Ns,
+    return_pointer_area_size: usize,
+    return_pointer_area_align: usize,
+
+    /// The container each interface's bindings are placed in, which changes
+    /// to the exported container once an interface which is both imported
+    /// and exported is exported.
+    containers: HashMap<InterfaceId, String>,
+    /// Whether each resource is imported or exported, and the core wasm
+    /// functions which manage its handles, which change in the same way as
+    /// `containers`.
+    resources: HashMap<TypeId, Resource>,
+}
+
+#[derive(Clone)]
+struct Resource {
+    direction: Direction,
+    /// The `[resource-drop]` import.
+    drop: String,
+    /// The `[resource-new]` and `[resource-rep]` imports of an exported
+    /// resource.
+    new: String,
+    rep: String,
+}
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct Opts {
+    /// Skip generating an object file which contains type information for the
+    /// world that is being generated.
+    #[cfg_attr(feature = "clap", arg(long, default_value_t = false))]
+    pub no_object_file: bool,
+
+    /// Add the specified suffix to the name of the custom section containing
+    /// the component type.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub type_section_suffix: Option<String>,
+}
+
+impl Opts {
+    pub fn build(&self) -> Box<dyn WorldGenerator> {
+        Box::new(Swift {
+            opts: self.clone(),
+            ..Swift::default()
+        })
+    }
+}
+
+impl WorldGenerator for Swift {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        wit_bindgen_core::reject_futures_and_streams(resolve, world, "Swift")
+    }
+
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+        self.world = resolve.worlds[world].name.clone();
+        self.sizes.fill(resolve);
+        for name in RESERVED_TYPES {
+            self.type_names.insert(name).unwrap();
+        }
+        self.world_container = self.type_names.tmp(&self.world.to_upper_camel_case());
+    }
+
+    fn import_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let container = self.container_name(resolve, name, Direction::Import);
+        self.containers.insert(id, container.clone());
+        let module = resolve.name_world_key(name);
+        let mut gen = self.interface(resolve, container, Direction::Import, Some(module));
+        gen.funcs = resolve.interfaces[id].functions.values().collect();
+        gen.types(id);
+        gen.import_funcs();
+        gen.finish();
+        Ok(())
+    }
+
+    fn import_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) {
+        let container = self.world_container.clone();
+        let mut gen = self.interface(
+            resolve,
+            container,
+            Direction::Import,
+            Some("$root".to_string()),
+        );
+        gen.funcs = funcs.iter().map(|(_, f)| *f).collect();
+        gen.import_funcs();
+        gen.finish();
+    }
+
+    fn import_types(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        types: &[(&str, TypeId)],
+        _files: &mut Files,
+    ) {
+        let container = self.world_container.clone();
+        let mut gen = self.interface(
+            resolve,
+            container,
+            Direction::Import,
+            Some("$root".to_string()),
+        );
+        for (name, id) in types {
+            gen.define_type(name, *id);
+        }
+        gen.finish();
+    }
+
+    fn export_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let container = self.container_name(resolve, name, Direction::Export);
+        self.containers.insert(id, container.clone());
+        let module = resolve.name_world_key(name);
+        let mut gen = self.interface(resolve, container, Direction::Export, Some(module));
+        gen.funcs = resolve.interfaces[id].functions.values().collect();
+        gen.types(id);
+        gen.export_funcs();
+        gen.finish();
+        Ok(())
+    }
+
+    fn export_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) -> Result<()> {
+        let container = self
+            .type_names
+            .tmp(&format!("Exports{}", self.world.to_upper_camel_case()));
+        let mut gen = self.interface(resolve, container, Direction::Export, None);
+        gen.funcs = funcs.iter().map(|(_, f)| *f).collect();
+        gen.export_funcs();
+        gen.finish();
+        Ok(())
+    }
+
+    fn finish(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
+        let name = self.world.to_upper_camel_case();
+        let snake = self.world.to_snake_case();
+        let version = env!("CARGO_PKG_VERSION");
+
+        let mut src = Source::default();
+        wit_bindgen_core::generated_preamble(&mut src, version);
+        src.push_str("\n");
+        src.as_mut_string().push_str(RUNTIME);
+
+        if !self.world_src.as_str().is_empty() {
+            let world_src = mem::take(&mut self.world_src);
+            src.push_str(&format!("\nenum {} {{\n", self.world_container));
+            src.push_str(world_src.as_str().trim_start_matches('\n'));
+            src.push_str("}\n");
+        }
+        src.push_str(self.src.as_str());
+        src.push_str(self.externs.as_str());
+        src.push_str(self.exports.as_str());
+
+        // Declare a statically-allocated return area, if needed. Only exports
+        // use this since imports allocate their return area in their arena.
+        if self.return_pointer_area_size > 0 {
+            uwriteln!(
+                src,
+                "\nlet witRetArea = witAlloc({}, {})",
+                self.return_pointer_area_size,
+                self.return_pointer_area_align,
+            );
+        }
+
+        if !self.opts.no_object_file {
+            let linking_symbol = component_type_object::linking_symbol(&self.world);
+            src.push_str(&format!(
+                "
+                    // Ensure that the {snake}_component_type.o object is linked in
+                    @_extern(c, \"{linking_symbol}\")
+                    func {linking_symbol}()
+
+                    @_expose(wasm, \"{linking_symbol}_public_use_in_this_compilation_unit\")
+                    func {linking_symbol}_public_use_in_this_compilation_unit() {{
+                        {linking_symbol}()
+                    }}
+                ",
+            ));
+        }
+
+        files.push(&format!("{name}.swift"), src.as_bytes());
+        if !self.opts.no_object_file {
+            files.push(
+                &format!("{snake}_component_type.o"),
+                component_type_object::object(
+                    resolve,
+                    id,
+                    &self.world,
+                    StringEncoding::UTF8,
+                    self.opts.type_section_suffix.as_deref(),
+                )?
+                .as_slice(),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Swift {
+    fn interface<'a>(
+        &'a mut self,
+        resolve: &'a Resolve,
+        container: String,
+        direction: Direction,
+        module: Option<String>,
+    ) -> InterfaceGenerator<'a> {
+        InterfaceGenerator {
+            gen: self,
+            resolve,
+            container,
+            direction,
+            module,
+            funcs: Vec::new(),
+            src: Source::default(),
+            protocols: Source::default(),
+        }
+    }
+
+    /// Returns the name of the top-level container bindings for the
+    /// interface `key` are placed in.
+    fn container_name(
+        &mut self,
+        resolve: &Resolve,
+        key: &WorldKey,
+        direction: Direction,
+    ) -> String {
+        let mut path = String::new();
+        if direction == Direction::Export {
+            path.push_str("Exports");
+        }
+        match key {
+            WorldKey::Name(name) => {
+                path.push_str(&self.world.to_upper_camel_case());
+                path.push_str(&name.to_upper_camel_case());
+            }
+            WorldKey::Interface(id) => {
+                let iface = &resolve.interfaces[*id];
+                let pkg = &resolve.packages[iface.package.unwrap()].name;
+                path.push_str(&pkg.namespace.to_upper_camel_case());
+                path.push_str(&pkg.name.to_upper_camel_case());
+                // Only disambiguate by version if more than one version of
+                // this package is in use.
+                if let Some(version) = &pkg.version {
+                    let versions = resolve
+                        .packages
+                        .iter()
+                        .filter(|(_, p)| {
+                            p.name.namespace == pkg.namespace && p.name.name == pkg.name
+                        })
+                        .count();
+                    if versions > 1 {
+                        let version = version.to_string().replace(['.', '-', '+'], "_");
+                        path.push_str(&format!("V{version}"));
+                    }
+                }
+                path.push_str(&iface.name.as_ref().unwrap().to_upper_camel_case());
+            }
+        }
+        self.type_names.tmp(&path)
+    }
+
+    /// Declares the core wasm import `name` from `module`, returning the name
+    /// of the Swift function it's declared as.
+    fn declare_extern(
+        &mut self,
+        module: &str,
+        name: &str,
+        params: &[&str],
+        result: Option<&str>,
+    ) -> String {
+        let func = self.fn_names.tmp(&format!(
+            "__wasm_import_{}_{}",
+            module.to_snake_case(),
+            name.to_snake_case()
+        ));
+        let params = params
+            .iter()
+            .map(|ty| format!("_: {ty}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let result = result.map_or(String::new(), |ty| format!(" -> {ty}"));
+        uwriteln!(
+            self.externs,
+            "\n@_extern(wasm, module: \"{module}\", name: \"{name}\")"
+        );
+        uwriteln!(self.externs, "func {func}({params}){result}");
+        func
+    }
+}
+
+struct InterfaceGenerator<'a> {
+    gen: &'a mut Swift,
+    resolve: &'a Resolve,
+    /// The container declarations are placed in.
+    container: String,
+    direction: Direction,
+    /// The core wasm module imports come from, or the name of the exported
+    /// interface, which is `None` for functions exported by the world itself.
+    module: Option<String>,
+    /// The functions of the interface, including the methods of resources.
+    funcs: Vec<&'a Function>,
+    src: Source,
+    /// The protocols exported functions and resources conform to, which are
+    /// declared after the container.
+    protocols: Source,
+}
+
+impl<'a> InterfaceGenerator<'a> {
+    fn finish(self) {
+        // Exported containers are extended with the implementations of the
+        // exports, so they're declared even if they're otherwise empty.
+        let exports = self.direction == Direction::Export
+            && (!self.funcs.is_empty() || !self.protocols.as_str().is_empty());
+        if self.container == self.gen.world_container {
+            self.gen.world_src.push_str(self.src.as_str());
+        } else if !self.src.as_str().is_empty() || exports {
+            let container = &self.container;
+            self.gen.src.push_str(&format!("\nenum {container} {{\n"));
+            self.gen
+                .src
+                .push_str(self.src.as_str().trim_start_matches('\n'));
+            self.gen.src.push_str("}\n");
+        }
+        self.gen.src.push_str(self.protocols.as_str());
+    }
+
+    /// Returns a namespace for the parameters and locals of a function, which
+    /// mustn't shadow the runtime declarations it refers to.
+    fn locals(&self) -> Ns {
+        let mut ns = Ns::default();
+        for name in RUNTIME_DECLS {
+            ns.insert(name).unwrap();
+        }
+        ns
+    }
+
+    /// Returns the methods, static functions and constructor of the resource
+    /// `id`.
+    fn resource_funcs(&self, id: TypeId) -> Vec<&'a Function> {
+        self.funcs
+            .iter()
+            .copied()
+            .filter(|f| match f.kind {
+                FunctionKind::Freestanding => false,
+                FunctionKind::Method(r)
+                | FunctionKind::Static(r)
+                | FunctionKind::Constructor(r) => r == id,
+            })
+            .collect()
+    }
+
+    /// Defines the class wrapping handles to the imported resource `id`,
+    /// which has the methods `funcs`.
+    fn define_imported_resource(&mut self, id: TypeId, name: &str) {
+        let resource = self.resolve.types[id].name.as_ref().unwrap();
+        let module = self.module.clone().unwrap();
+        let drop = self.gen.declare_extern(
+            &module,
+            &format!("[resource-drop]{resource}"),
+            &["Int32"],
+            None,
+        );
+        self.gen.resources.insert(
+            id,
+            Resource {
+                direction: Direction::Import,
+                drop: drop.clone(),
+                new: String::new(),
+                rep: String::new(),
+            },
+        );
+        self.src.push_str(&format!(
+            "final class {name} {{
+                /// The handle to the resource, which is dropped along with this object unless it's been taken.
+                private(set) var handle: Int32
+                private let owned: Bool
+
+                /// Wraps `handle`, taking ownership of it.
+                init(handle: Int32) {{
+                    self.handle = handle
+                    self.owned = true
+                }}
+
+                /// Wraps `handle` without taking ownership of it, for a borrowed handle.
+                init(borrowing handle: Int32) {{
+                    self.handle = handle
+                    self.owned = false
+                }}
+
+                /// Takes ownership of the handle, which this object then no longer drops.
+                func takeHandle() -> Int32 {{
+                    let handle = self.handle
+                    self.handle = 0
+                    return handle
+                }}
+
+                deinit {{
+                    if owned && handle != 0 {{
+                        {drop}(handle)
+                    }}
+                }}
+            "
+        ));
+        for func in self.resource_funcs(id) {
+            self.src.push_str("\n");
+            self.import_func(func);
+        }
+        self.src.push_str("}\n");
+    }
+
+    /// Declares the protocol the class implementing the exported resource
+    /// `id` conforms to, and the destructor freeing its instances.
+    fn define_exported_resource(&mut self, id: TypeId, name: &str) {
+        let resource = self.resolve.types[id].name.as_ref().unwrap();
+        let module = self.module.clone().unwrap();
+        let export_module = format!("[export]{module}");
+        let new = self.gen.declare_extern(
+            &export_module,
+            &format!("[resource-new]{resource}"),
+            &["Int32"],
+            Some("Int32"),
+        );
+        let rep = self.gen.declare_extern(
+            &export_module,
+            &format!("[resource-rep]{resource}"),
+            &["Int32"],
+            Some("Int32"),
+        );
+        let drop = self.gen.declare_extern(
+            &export_module,
+            &format!("[resource-drop]{resource}"),
+            &["Int32"],
+            None,
+        );
+        self.gen.resources.insert(
+            id,
+            Resource {
+                direction: Direction::Export,
+                drop,
+                new,
+                rep,
+            },
+        );
+
+        let class = format!("{}.{name}", self.container);
+        let protocol = self
+            .gen
+            .type_names
+            .tmp(&format!("{}{name}Protocol", self.container));
+        uwriteln!(
+            self.protocols,
+            "\n/// The resource `{resource}`, which is implemented by the final class `{class}`."
+        );
+        self.protocols
+            .push_str(&format!("protocol {protocol}: AnyObject {{\n"));
+        for func in self.resource_funcs(id) {
+            self.func_docs(func);
+            let sig = self.signature(func, &mut None);
+            uwriteln!(self.protocols, "{sig}");
+        }
+        self.protocols.push_str("}\n");
+        uwriteln!(self.protocols, "\nextension {class}: {protocol} {{}}");
+
+        // Instances are kept alive while there are handles to them, and are
+        // released by the destructor once the last one is dropped.
+        let dtor = self.gen.fn_names.tmp(&format!(
+            "__wasm_export_{}_{}_dtor",
+            module.to_snake_case(),
+            resource.to_snake_case()
+        ));
+        self.gen.exports.push_str(&format!(
+            "
+                @_expose(wasm, \"{module}#[dtor]{resource}\")
+                func {dtor}(_ rep: Int32) {{
+                    witRelease(rep, as: {class}.self)
+                }}
+            "
+        ));
+    }
+
+    fn import_funcs(&mut self) {
+        for func in self.funcs.clone() {
+            if let FunctionKind::Freestanding = func.kind {
+                self.src.push_str("\n");
+                self.import_func(func);
+            }
+        }
+    }
+
+    /// Generates a function calling the import `func`.
+    fn import_func(&mut self, func: &Function) {
+        self.docs(&func.docs);
+        let mut locals = self.locals();
+        let mut params = Some(Vec::new());
+        let sig = self.signature(func, &mut params);
+        let params = params.unwrap();
+        for param in params.iter() {
+            let _ = locals.insert(param);
+        }
+
+        let mut f = FunctionBindgen::new(self, locals);
+        f.params = params;
+        f.constructor = matches!(func.kind, FunctionKind::Constructor(_));
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            func,
+            &mut f,
+            false,
+        );
+        let FunctionBindgen {
+            src: body,
+            needs_arena,
+            arena,
+            ..
+        } = f;
+
+        self.src.push_str(&format!("{sig} {{\n"));
+        if needs_arena {
+            uwriteln!(self.src, "var {arena} = WitArena()");
+            uwriteln!(self.src, "defer {{ {arena}.deallocate() }}");
+        }
+        self.src.push_str(&body);
+        self.src.push_str("}\n");
+    }
+
+    fn export_funcs(&mut self) {
+        let funcs = self
+            .funcs
+            .iter()
+            .copied()
+            .filter(|f| matches!(f.kind, FunctionKind::Freestanding))
+            .collect::<Vec<_>>();
+        if !funcs.is_empty() {
+            let container = self.container.clone();
+            let protocol = self.gen.type_names.tmp(&format!("{container}Protocol"));
+            let exports = match &self.module {
+                Some(module) => format!("`{module}`"),
+                None => format!("the world `{}`", self.gen.world),
+            };
+            uwriteln!(
+                self.protocols,
+                "\n/// The functions implementing the exports of {exports}, which are static methods of `{container}`."
+            );
+            self.protocols
+                .push_str(&format!("protocol {protocol} {{\n"));
+            for func in funcs {
+                self.func_docs(func);
+                let sig = self.signature(func, &mut None);
+                uwriteln!(self.protocols, "{sig}");
+            }
+            self.protocols.push_str("}\n");
+            uwriteln!(self.protocols, "\nextension {container}: {protocol} {{}}");
+        }
+
+        for func in self.funcs.clone() {
+            self.export_func(func);
+        }
+    }
+
+    /// Generates a function which exports `func` by lifting its arguments,
+    /// calling its implementation, and lowering its results.
+    fn export_func(&mut self, func: &Function) {
+        let export_name = func.core_export_name(self.module.as_deref()).into_owned();
+        let shim = self
+            .gen
+            .fn_names
+            .tmp(&format!("__wasm_export_{}", export_name.to_snake_case()));
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
+
+        let callee = match func.kind {
+            FunctionKind::Freestanding => {
+                format!("{}.{}", self.container, func_name(&func.name))
+            }
+            FunctionKind::Method(_) => func_name(func.item_name()),
+            FunctionKind::Static(id) => {
+                format!(
+                    "{}.{}",
+                    self.qualified_name(id),
+                    func_name(func.item_name())
+                )
+            }
+            FunctionKind::Constructor(id) => self.qualified_name(id),
+        };
+
+        let mut locals = self.locals();
+        let params = sig
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| (locals.tmp(&format!("arg{i}")), wasm_type(*ty)))
+            .collect::<Vec<_>>();
+        let mut f = FunctionBindgen::new(self, locals);
+        f.callee = callee;
+        f.params = params.iter().map(|(name, _)| name.clone()).collect();
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestExport,
+            LiftLower::LiftArgsLowerResults,
+            func,
+            &mut f,
+            false,
+        );
+        let body = f.src;
+        self.push_export(&shim, &params, &sig.results, &body, &export_name);
+
+        if abi::guest_export_needs_post_return(self.resolve, func) {
+            let mut locals = self.locals();
+            let params = sig
+                .results
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| (locals.tmp(&format!("arg{i}")), wasm_type(*ty)))
+                .collect::<Vec<_>>();
+            let mut f = FunctionBindgen::new(self, locals);
+            f.params = params.iter().map(|(name, _)| name.clone()).collect();
+            abi::post_return(f.gen.resolve, func, &mut f);
+            let body = f.src;
+            let shim = self.gen.fn_names.tmp(&format!("{shim}_post_return"));
+            self.push_export(
+                &shim,
+                &params,
+                &[],
+                &body,
+                &format!("cabi_post_{export_name}"),
+            );
+        }
+    }
+
+    /// Adds the function `func`, taking `params` and returning `results`, to
+    /// the functions exported as `export_name`.
+    fn push_export(
+        &mut self,
+        func: &str,
+        params: &[(String, &str)],
+        results: &[abi::WasmType],
+        body: &str,
+        export_name: &str,
+    ) {
+        let params = params
+            .iter()
+            .map(|(name, ty)| format!("_ {name}: {ty}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let result = match results {
+            [] => String::new(),
+            [ty] => format!(" -> {}", wasm_type(*ty)),
+            _ => unreachable!("multiple results are returned through a return pointer"),
+        };
+        uwriteln!(self.gen.exports, "\n@_expose(wasm, \"{export_name}\")");
+        self.gen
+            .exports
+            .push_str(&format!("func {func}({params}){result} {{\n"));
+        self.gen.exports.push_str(body);
+        self.gen.exports.push_str("}\n");
+    }
+
+    /// Returns the declaration of the Swift function for `func`, collecting
+    /// the names its parameters are bound to in `params` if it's given.
+    fn signature(&self, func: &Function, params: &mut Option<Vec<String>>) -> String {
+        let mut decl = String::new();
+        match func.kind {
+            FunctionKind::Freestanding | FunctionKind::Static(_) => {
+                decl.push_str(&format!("static func {}", self.func_name(func)));
+            }
+            FunctionKind::Method(_) => {
+                decl.push_str(&format!("func {}", self.func_name(func)));
+            }
+            FunctionKind::Constructor(_) => match params {
+                Some(_) => decl.push_str("convenience init"),
+                None => decl.push_str("init"),
+            },
+        }
+
+        let mut names = Ns::default();
+        for name in RUNTIME_DECLS {
+            names.insert(name).unwrap();
+        }
+        let mut decls = Vec::new();
+        for (i, (name, ty)) in func.params.iter().enumerate() {
+            if i == 0 && matches!(func.kind, FunctionKind::Method(_)) {
+                if let Some(params) = params {
+                    params.push("self".to_string());
+                }
+                continue;
+            }
+            let label = func_name(name);
+            let ty = self.type_name(ty);
+            match params {
+                Some(params) => {
+                    let local = swift_ident(&names.tmp(&name.to_lower_camel_case()));
+                    if local == label {
+                        decls.push(format!("{label}: {ty}"));
+                    } else {
+                        decls.push(format!("{label} {local}: {ty}"));
+                    }
+                    params.push(local);
+                }
+                None => decls.push(format!("{label}: {ty}")),
+            }
+        }
+        decl.push_str(&format!("({})", decls.join(", ")));
+
+        if let FunctionKind::Constructor(_) = func.kind {
+            return decl;
+        }
+        if let Some(result) = self.error_result(func) {
+            let err = self.optional_type_name(result.err.as_ref());
+            decl.push_str(&format!(" throws(WitError<{err}>)"));
+            if let Some(ok) = &result.ok {
+                decl.push_str(&format!(" -> {}", self.type_name(ok)));
+            }
+            return decl;
+        }
+        let types = func.results.iter_types().collect::<Vec<_>>();
+        match types.as_slice() {
+            [] => {}
+            [ty] => decl.push_str(&format!(" -> {}", self.type_name(ty))),
+            types => {
+                let types = types
+                    .iter()
+                    .map(|ty| self.type_name(ty))
+                    .collect::<Vec<_>>();
+                decl.push_str(&format!(" -> ({})", types.join(", ")));
+            }
+        }
+        decl
+    }
+
+    /// Returns the `result` `func` returns if that's its only result, in
+    /// which case its error is thrown rather than returned.
+    fn error_result(&self, func: &Function) -> Option<&'a Result_> {
+        if let FunctionKind::Constructor(_) = func.kind {
+            return None;
+        }
+        let types = func.results.iter_types().collect::<Vec<_>>();
+        let [Type::Id(id)] = types.as_slice() else {
+            return None;
+        };
+        match &self.resolve.types[dealias(self.resolve, *id)].kind {
+            TypeDefKind::Result(result) => Some(result),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the Swift function for `func`, which is a method
+    /// of its resource's class unless it's freestanding.
+    fn func_name(&self, func: &Function) -> String {
+        match func.kind {
+            FunctionKind::Freestanding => func_name(&func.name),
+            FunctionKind::Constructor(_) => "init".to_string(),
+            FunctionKind::Method(_) | FunctionKind::Static(_) => {
+                let name = func.item_name().to_lower_camel_case();
+                match name.as_str() {
+                    "handle" | "owned" | "takeHandle" | "init" | "deinit" => format!("{name}_"),
+                    _ => swift_ident(&name),
+                }
+            }
+        }
+    }
+
+    fn docs(&mut self, docs: &Docs) {
+        if let Some(docs) = &docs.contents {
+            for line in docs.trim().lines() {
+                uwriteln!(self.src, "/// {}", line.trim());
+            }
+        }
+    }
+
+    fn func_docs(&mut self, func: &Function) {
+        if let Some(docs) = &func.docs.contents {
+            for line in docs.trim().lines() {
+                uwriteln!(self.protocols, "/// {}", line.trim());
+            }
+        }
+    }
+
+    fn type_name(&self, ty: &Type) -> String {
+        match ty {
+            Type::Bool => "Bool".to_string(),
+            Type::U8 => "UInt8".to_string(),
+            Type::S8 => "Int8".to_string(),
+            Type::U16 => "UInt16".to_string(),
+            Type::S16 => "Int16".to_string(),
+            Type::U32 => "UInt32".to_string(),
+            Type::S32 => "Int32".to_string(),
+            Type::U64 => "UInt64".to_string(),
+            Type::S64 => "Int64".to_string(),
+            Type::F32 => "Float".to_string(),
+            Type::F64 => "Double".to_string(),
+            Type::Char => "Unicode.Scalar".to_string(),
+            Type::String => "String".to_string(),
+            Type::Id(id) => {
+                if self.resolve.types[*id].name.is_some() {
+                    self.qualified_name(*id)
+                } else {
+                    self.anonymous_type_name(*id)
+                }
+            }
+        }
+    }
+
+    fn optional_type_name(&self, ty: Option<&Type>) -> String {
+        ty.map_or("Void".to_string(), |ty| self.type_name(ty))
+    }
+
+    /// Returns the Swift type of the anonymous type `id`.
+    fn anonymous_type_name(&self, id: TypeId) -> String {
+        let mut printer = TypePrinter {
+            gen: self,
+            name: String::new(),
+        };
+        printer.define_anonymous_type(id);
+        printer.name
+    }
+
+    /// Returns the name of the named type `id`, qualified by its container so
+    /// it can be used anywhere in the generated file.
+    fn qualified_name(&self, id: TypeId) -> String {
+        let ty = &self.resolve.types[id];
+        let name = type_ident(ty.name.as_ref().unwrap());
+        match ty.owner {
+            TypeOwner::Interface(iface) => format!("{}.{name}", self.gen.containers[&iface]),
+            TypeOwner::World(_) => format!("{}.{name}", self.gen.world_container),
+            TypeOwner::None => unreachable!(),
+        }
+    }
+
+    fn resource(&self, id: TypeId) -> &Resource {
+        &self.gen.resources[&dealias(self.resolve, id)]
+    }
+
+    fn type_alias_to(&mut self, name: &str, docs: &Docs, ty: String) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        uwriteln!(self.src, "typealias {} = {ty}", type_ident(name));
+    }
+}
+
+impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
+    fn resolve(&self) -> &'a Resolve {
+        self.resolve
+    }
+
+    fn type_record(&mut self, _id: TypeId, name: &str, record: &Record, docs: &Docs) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        self.src
+            .push_str(&format!("struct {} {{\n", type_ident(name)));
+        for field in record.fields.iter() {
+            self.docs(&field.docs);
+            let ty = self.type_name(&field.ty);
+            uwriteln!(self.src, "var {}: {ty}", func_name(&field.name));
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_resource(&mut self, id: TypeId, name: &str, docs: &Docs) {
+        let name = type_ident(name);
+        match self.direction {
+            Direction::Import => {
+                self.src.push_str("\n");
+                self.docs(docs);
+                self.define_imported_resource(id, &name);
+            }
+            Direction::Export => self.define_exported_resource(id, &name),
+        }
+    }
+
+    fn type_flags(&mut self, _id: TypeId, name: &str, flags: &Flags, docs: &Docs) {
+        let name = type_ident(name);
+        let repr = flags_repr(flags);
+        self.src.push_str("\n");
+        self.docs(docs);
+        match flags.repr() {
+            FlagsRepr::U32(n) if n > 4 => {
+                self.src
+                    .push_str(&format!("struct {name}: WitLargeFlags {{\n"));
+                uwriteln!(self.src, "static let wordCount = {n}");
+            }
+            _ => self.src.push_str(&format!("struct {name}: OptionSet {{\n")),
+        }
+        uwriteln!(self.src, "let rawValue: {repr}");
+        for (i, flag) in flags.flags.iter().enumerate() {
+            self.docs(&flag.docs);
+            let bit = match flags.repr() {
+                FlagsRepr::U32(n) if n > 4 => format!("WitFlags(wordCount: {n}, bit: {i})"),
+                _ => format!("1 << {i}"),
+            };
+            uwriteln!(
+                self.src,
+                "static let {} = {name}(rawValue: {bit})",
+                func_name(&flag.name)
+            );
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_tuple(&mut self, id: TypeId, name: &str, _tuple: &Tuple, docs: &Docs) {
+        let ty = self.anonymous_type_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_variant(&mut self, _id: TypeId, name: &str, variant: &Variant, docs: &Docs) {
+        self.src.push_str("\n");
+        self.docs(docs);
+        self.src
+            .push_str(&format!("enum {} {{\n", type_ident(name)));
+        for case in variant.cases.iter() {
+            self.docs(&case.docs);
+            let case_name = func_name(&case.name);
+            match &case.ty {
+                Some(ty) => {
+                    let ty = self.type_name(ty);
+                    uwriteln!(self.src, "case {case_name}({ty})");
+                }
+                None => uwriteln!(self.src, "case {case_name}"),
+            }
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_option(&mut self, id: TypeId, name: &str, _payload: &Type, docs: &Docs) {
+        let ty = self.anonymous_type_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_result(&mut self, id: TypeId, name: &str, _result: &Result_, docs: &Docs) {
+        let ty = self.anonymous_type_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_enum(&mut self, _id: TypeId, name: &str, enum_: &Enum, docs: &Docs) {
+        let tag = int_type(enum_.tag());
+        self.src.push_str("\n");
+        self.docs(docs);
+        self.src
+            .push_str(&format!("enum {}: {tag} {{\n", type_ident(name)));
+        for case in enum_.cases.iter() {
+            self.docs(&case.docs);
+            uwriteln!(self.src, "case {}", func_name(&case.name));
+        }
+        self.src.push_str("}\n");
+    }
+
+    fn type_alias(&mut self, _id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        let ty = self.type_name(ty);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_list(&mut self, id: TypeId, name: &str, _ty: &Type, docs: &Docs) {
+        let ty = self.anonymous_type_name(id);
+        self.type_alias_to(name, docs, ty);
+    }
+
+    fn type_builtin(&mut self, _id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        let ty = self.type_name(ty);
+        self.type_alias_to(name, docs, ty);
+    }
+}
+
+/// Prints the Swift type of an anonymous type.
+struct TypePrinter<'a, 'b> {
+    gen: &'b InterfaceGenerator<'a>,
+    name: String,
+}
+
+impl<'a> AnonymousTypeGenerator<'a> for TypePrinter<'a, '_> {
+    fn resolve(&self) -> &'a Resolve {
+        self.gen.resolve
+    }
+
+    fn anonymous_typ_type(&mut self, _id: TypeId, ty: &Type, _docs: &Docs) {
+        self.name = self.gen.type_name(ty);
+    }
+
+    fn anonymous_type_handle(&mut self, _id: TypeId, handle: &Handle, _docs: &Docs) {
+        // Both owned and borrowed handles are represented by the resource's
+        // class.
+        let (Handle::Own(resource) | Handle::Borrow(resource)) = handle;
+        self.name = self
+            .gen
+            .qualified_name(dealias(self.gen.resolve, *resource));
+    }
+
+    fn anonymous_type_tuple(&mut self, _id: TypeId, ty: &Tuple, _docs: &Docs) {
+        // Swift doesn't have tuples of one value, so those are represented by
+        // the value itself.
+        self.name = match ty.types.as_slice() {
+            [ty] => self.gen.type_name(ty),
+            types => {
+                let types = types
+                    .iter()
+                    .map(|ty| self.gen.type_name(ty))
+                    .collect::<Vec<_>>();
+                format!("({})", types.join(", "))
+            }
+        };
+    }
+
+    fn anonymous_type_option(&mut self, _id: TypeId, ty: &Type, _docs: &Docs) {
+        self.name = format!("{}?", self.gen.type_name(ty));
+    }
+
+    fn anonymous_type_result(&mut self, _id: TypeId, ty: &Result_, _docs: &Docs) {
+        let ok = self.gen.optional_type_name(ty.ok.as_ref());
+        let err = self.gen.optional_type_name(ty.err.as_ref());
+        self.name = format!("WitResult<{ok}, {err}>");
+    }
+
+    fn anonymous_type_list(&mut self, _id: TypeId, ty: &Type, _docs: &Docs) {
+        self.name = format!("[{}]", self.gen.type_name(ty));
+    }
+
+    fn anonymous_type_future(&mut self, _id: TypeId, _ty: &Option<Type>, _docs: &Docs) {
+        unreachable!("worlds using futures and streams are rejected in `validate`")
+    }
+
+    fn anonymous_type_stream(&mut self, _id: TypeId, _ty: &Stream, _docs: &Docs) {
+        unreachable!("worlds using futures and streams are rejected in `validate`")
+    }
+}
+
+/// Returns the type of the `rawValue` of `flags`.
+fn flags_repr(flags: &Flags) -> &'static str {
+    match flags.repr() {
+        FlagsRepr::U8 => "UInt8",
+        FlagsRepr::U16 => "UInt16",
+        FlagsRepr::U32(0 | 1) => "UInt32",
+        FlagsRepr::U32(2) => "UInt64",
+        FlagsRepr::U32(3 | 4) => "UInt128",
+        FlagsRepr::U32(_) => "WitFlags",
+    }
+}
+
+fn int_type(int: Int) -> &'static str {
+    match int {
+        Int::U8 => "UInt8",
+        Int::U16 => "UInt16",
+        Int::U32 => "UInt32",
+        Int::U64 => "UInt64",
+    }
+}
+
+/// Returns the name of the type for `name`, which mustn't shadow any type
+/// generated code refers to.
+fn type_ident(name: &str) -> String {
+    let name = name.to_upper_camel_case();
+    if RESERVED_TYPES.contains(&name.as_str()) {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
+
+/// Returns the name of the function, field, enum case or argument label for
+/// `name`.
+fn func_name(name: &str) -> String {
+    swift_ident(&name.to_lower_camel_case())
+}
+
+/// Returns `name` as a Swift identifier, quoting it if it's a keyword.
+pub fn swift_ident(name: &str) -> String {
+    let keywords = [
+        "Any",
+        "Protocol",
+        "Self",
+        "Type",
+        "as",
+        "associatedtype",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "continue",
+        "default",
+        "defer",
+        "deinit",
+        "do",
+        "else",
+        "enum",
+        "extension",
+        "fallthrough",
+        "false",
+        "fileprivate",
+        "for",
+        "func",
+        "guard",
+        "if",
+        "import",
+        "in",
+        "init",
+        "inout",
+        "internal",
+        "is",
+        "let",
+        "nil",
+        "open",
+        "operator",
+        "precedencegroup",
+        "private",
+        "protocol",
+        "public",
+        "repeat",
+        "rethrows",
+        "return",
+        "self",
+        "static",
+        "struct",
+        "subscript",
+        "super",
+        "switch",
+        "throw",
+        "throws",
+        "true",
+        "try",
+        "typealias",
+        "var",
+        "where",
+        "while",
+    ];
+    if keywords.contains(&name) {
+        format!("`{name}`")
+    } else {
+        name.to_string()
+    }
+}
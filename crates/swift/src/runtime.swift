@@ -0,0 +1,181 @@
+import WASILibc
+
+/// A WIT `result` which isn't the result of a function.
+enum WitResult<Ok, Err> {
+    case ok(Ok)
+    case err(Err)
+}
+
+/// The error thrown by a function returning a WIT `result`, which carries
+/// the `result`'s error payload.
+struct WitError<Payload>: Error, @unchecked Sendable {
+    var payload: Payload
+
+    init(_ payload: Payload) {
+        self.payload = payload
+    }
+}
+
+/// The `rawValue` of flags with more than 128 members, with one bit per flag
+/// in 32-bit words.
+struct WitFlags: Equatable {
+    var words: [UInt32]
+
+    init(words: [UInt32]) {
+        self.words = words
+    }
+
+    /// Creates `wordCount` words with only `bit` set.
+    init(wordCount: Int, bit: Int) {
+        words = Array(repeating: 0, count: wordCount)
+        words[bit / 32] = 1 << (bit % 32)
+    }
+}
+
+/// Flags with more than 128 members, which implement `OptionSet` on top of
+/// a `WitFlags` of `wordCount` words.
+protocol WitLargeFlags: OptionSet where RawValue == WitFlags {
+    static var wordCount: Int { get }
+}
+
+extension WitLargeFlags {
+    init() {
+        self.init(rawValue: WitFlags(words: Array(repeating: 0, count: Self.wordCount)))
+    }
+
+    mutating func formUnion(_ other: Self) {
+        self = Self(rawValue: WitFlags(words: zip(rawValue.words, other.rawValue.words).map { $0 | $1 }))
+    }
+
+    mutating func formIntersection(_ other: Self) {
+        self = Self(rawValue: WitFlags(words: zip(rawValue.words, other.rawValue.words).map { $0 & $1 }))
+    }
+
+    mutating func formSymmetricDifference(_ other: Self) {
+        self = Self(rawValue: WitFlags(words: zip(rawValue.words, other.rawValue.words).map { $0 ^ $1 }))
+    }
+}
+
+/// The canonical ABI's allocation function, implemented with `realloc`.
+@_expose(wasm, "cabi_realloc")
+func cabi_realloc(_ oldAddress: Int, _ oldByteCount: Int, _ alignment: Int, _ newByteCount: Int) -> Int {
+    if newByteCount == 0 {
+        return alignment
+    }
+    let old = oldByteCount == 0 ? nil : UnsafeMutableRawPointer(bitPattern: oldAddress)
+    guard let new = realloc(old, newByteCount) else {
+        fatalError("out of memory")
+    }
+    return Int(bitPattern: new)
+}
+
+/// Allocates `byteCount` bytes with `cabi_realloc` and returns their address.
+func witAlloc(_ byteCount: Int, _ alignment: Int) -> Int {
+    return cabi_realloc(0, 0, alignment, byteCount)
+}
+
+/// Frees `byteCount` bytes at `address` which were allocated by
+/// `cabi_realloc`.
+func witFree(_ address: Int, _ byteCount: Int) {
+    if byteCount != 0 {
+        free(UnsafeMutableRawPointer(bitPattern: address))
+    }
+}
+
+func witLoad<T>(_ address: Int, as type: T.Type) -> T {
+    return UnsafeRawPointer(bitPattern: address)!.loadUnaligned(as: type)
+}
+
+func witStore<T>(_ address: Int, _ value: T) {
+    UnsafeMutableRawPointer(bitPattern: address)!.storeBytes(of: value, as: T.self)
+}
+
+/// Copies `list` to memory allocated by `cabi_realloc`, returning its address
+/// and length.
+func witLowerList<T>(_ list: [T]) -> (Int, Int) {
+    let address = witAlloc(list.count * MemoryLayout<T>.stride, MemoryLayout<T>.alignment)
+    list.withUnsafeBytes { bytes in
+        if let base = bytes.baseAddress {
+            UnsafeMutableRawPointer(bitPattern: address)!.copyMemory(from: base, byteCount: bytes.count)
+        }
+    }
+    return (address, list.count)
+}
+
+/// Copies the UTF-8 encoding of `string` to memory allocated by
+/// `cabi_realloc`, returning its address and length.
+func witLowerString(_ string: String) -> (Int, Int) {
+    return witLowerList(Array(string.utf8))
+}
+
+/// Copies the `count` values at `address` into an array, and frees them.
+func witLiftList<T>(_ address: Int, _ count: Int, as type: T.Type) -> [T] {
+    if count == 0 {
+        return []
+    }
+    let list = Array(UnsafeBufferPointer(start: UnsafePointer<T>(bitPattern: address), count: count))
+    witFree(address, count * MemoryLayout<T>.stride)
+    return list
+}
+
+/// Decodes the `count` bytes of UTF-8 at `address` into a string, and frees
+/// them.
+func witLiftString(_ address: Int, _ count: Int) -> String {
+    let bytes = UnsafeRawBufferPointer(start: UnsafeRawPointer(bitPattern: address), count: count)
+    let string = String(decoding: bytes, as: UTF8.self)
+    witFree(address, count)
+    return string
+}
+
+/// Returns the representation of a new handle to `object`, which keeps it
+/// alive until `witRelease` is called with the representation.
+func witRep<T: AnyObject>(_ object: T) -> Int32 {
+    return Int32(truncatingIfNeeded: Int(bitPattern: Unmanaged.passRetained(object).toOpaque()))
+}
+
+/// Returns the object `rep` is the representation of.
+func witObject<T: AnyObject>(_ rep: Int32, as type: T.Type) -> T {
+    return Unmanaged<T>.fromOpaque(UnsafeRawPointer(bitPattern: Int(rep))!).takeUnretainedValue()
+}
+
+/// Releases the object `rep` is the representation of, once the last handle
+/// to it has been dropped.
+func witRelease<T: AnyObject>(_ rep: Int32, as type: T.Type) {
+    Unmanaged<T>.fromOpaque(UnsafeRawPointer(bitPattern: Int(rep))!).release()
+}
+
+/// The memory an import's arguments are lowered into, which is freed once it
+/// returns.
+struct WitArena {
+    private var addresses: [Int] = []
+
+    mutating func alloc(_ byteCount: Int, _ alignment: Int) -> Int {
+        let address = witAlloc(byteCount, alignment)
+        if byteCount != 0 {
+            addresses.append(address)
+        }
+        return address
+    }
+
+    mutating func lower<T>(_ list: [T]) -> (Int, Int) {
+        let (address, count) = witLowerList(list)
+        if count != 0 {
+            addresses.append(address)
+        }
+        return (address, count)
+    }
+
+    mutating func lower(_ string: String) -> (Int, Int) {
+        let (address, count) = witLowerString(string)
+        if count != 0 {
+            addresses.append(address)
+        }
+        return (address, count)
+    }
+
+    func deallocate() {
+        for address in addresses {
+            witFree(address, 1)
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use heck::*;
+use std::path::Path;
+use std::process::Command;
+
+macro_rules! codegen_test {
+    ($id:ident $name:tt $test:tt) => {
+        #[test]
+        fn $id() {
+            test_helpers::run_world_codegen_test(
+                "guest-swift",
+                $test.as_ref(),
+                |resolve, world, files| {
+                    wit_bindgen_swift::Opts::default()
+                        .build()
+                        .generate(resolve, world, files)
+                        .unwrap()
+                },
+                verify,
+            );
+        }
+    };
+}
+
+test_helpers::codegen_tests!();
+
+fn verify(dir: &Path, name: &str) {
+    // Exports are implemented by the component's own sources, so only parse
+    // the generated file rather than compiling it.
+    let mut cmd = Command::new("swiftc");
+    cmd.arg("-parse");
+    cmd.arg(dir.join(format!("{}.swift", name.to_upper_camel_case())));
+    test_helpers::run_command(&mut cmd);
+}
+
+#[test]
+fn flags_with_more_than_128_members() {
+    let dir = test_helpers::test_directory("codegen", "guest-swift", "flags-huge");
+    let flags = (0..160)
+        .map(|i| format!("b{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let wit = dir.join("flags-huge.wit");
+    std::fs::write(
+        &wit,
+        format!(
+            "
+                package foo:foo;
+
+                interface huge-flags {{
+                    flags flag160 {{ {flags} }}
+
+                    roundtrip-flag160: func(x: flag160) -> flag160;
+                    roundtrip-many: func(x: list<flag160>) -> option<flag160>;
+                }}
+
+                world the-flags {{
+                    import huge-flags;
+                    export huge-flags;
+                }}
+            "
+        ),
+    )
+    .unwrap();
+    test_helpers::run_world_codegen_test(
+        "guest-swift",
+        &wit,
+        |resolve, world, files| {
+            wit_bindgen_swift::Opts::default()
+                .build()
+                .generate(resolve, world, files)
+                .unwrap()
+        },
+        verify,
+    );
+}
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use heck::{ToLowerCamelCase, ToShoutySnakeCase, ToUpperCamelCase};
+use indexmap::IndexMap;
 use std::{
     collections::{HashMap, HashSet},
     fmt::Write,
@@ -10,9 +11,9 @@ use wit_bindgen_core::{
     abi::{self, AbiVariant, Bindgen, Bitcast, Instruction, LiftLower, WasmType},
     uwrite, uwriteln,
     wit_parser::{
-        Docs, Enum, Flags, FlagsRepr, Function, FunctionKind, Int, InterfaceId, Record, Resolve,
-        Result_, SizeAlign, Tuple, Type, TypeDef, TypeDefKind, TypeId, TypeOwner, Variant, WorldId,
-        WorldKey,
+        Docs, Enum, Flags, FlagsRepr, Function, FunctionKind, Handle, Int, InterfaceId, Record,
+        Resolve, Result_, SizeAlign, Tuple, Type, TypeDef, TypeDefKind, TypeId, TypeOwner, Variant,
+        WorldId, WorldKey,
     },
     Direction, Files, InterfaceGenerator as _, Ns, Source, WorldGenerator,
 };
@@ -49,6 +50,21 @@ struct InterfaceFragment {
     stub: String,
 }
 
+struct ResourceInfo {
+    /// Name of the class the resource is nested in.
+    module: String,
+    name: String,
+    docs: Docs,
+    direction: Direction,
+}
+
+impl ResourceInfo {
+    /// Name of the stub class implementing an exported resource.
+    fn export_impl_name(&self) -> String {
+        format!("{}Impl.{}", self.module, self.name.to_upper_camel_case())
+    }
+}
+
 #[derive(Default)]
 pub struct TeaVmJava {
     opts: Opts,
@@ -58,10 +74,14 @@ pub struct TeaVmJava {
     tuple_counts: HashSet<usize>,
     needs_cleanup: bool,
     needs_result: bool,
+    needs_rep_table: bool,
     interface_fragments: HashMap<String, Vec<InterfaceFragment>>,
     world_fragments: Vec<InterfaceFragment>,
     sizes: SizeAlign,
     interface_names: HashMap<InterfaceId, String>,
+    all_resources: HashMap<TypeId, ResourceInfo>,
+    world_resources: Vec<TypeId>,
+    import_funcs_called: bool,
 }
 
 impl TeaVmJava {
@@ -69,18 +89,28 @@ impl TeaVmJava {
         format!("{}.", self.name)
     }
 
-    fn interface<'a>(&'a mut self, resolve: &'a Resolve, name: &'a str) -> InterfaceGenerator<'a> {
+    fn interface<'a>(
+        &'a mut self,
+        resolve: &'a Resolve,
+        name: &'a str,
+        direction: Direction,
+    ) -> InterfaceGenerator<'a> {
         InterfaceGenerator {
             src: String::new(),
             stub: String::new(),
             gen: self,
             resolve,
             name,
+            direction,
         }
     }
 }
 
 impl WorldGenerator for TeaVmJava {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        wit_bindgen_core::reject_futures_and_streams(resolve, world, "TeaVM Java")
+    }
+
     fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
         self.name = world_name(resolve, world);
         self.sizes.fill(resolve);
@@ -95,11 +125,24 @@ impl WorldGenerator for TeaVmJava {
     ) -> Result<()> {
         let name = interface_name(resolve, key, Direction::Import);
         self.interface_names.insert(id, name.clone());
-        let mut gen = self.interface(resolve, &name);
+        let mut gen = self.interface(resolve, &name, Direction::Import);
         gen.types(id);
 
-        for (_, func) in resolve.interfaces[id].functions.iter() {
-            gen.import(&resolve.name_world_key(key), func);
+        for (resource, funcs) in by_resource(
+            resolve.interfaces[id].functions.values(),
+            interface_resources(resolve, id),
+        ) {
+            if let Some(resource) = resource {
+                gen.start_resource(resource, Some(key));
+            }
+
+            for func in funcs {
+                gen.import(&resolve.name_world_key(key), func);
+            }
+
+            if resource.is_some() {
+                gen.end_resource();
+            }
         }
 
         gen.add_interface_fragment();
@@ -114,11 +157,24 @@ impl WorldGenerator for TeaVmJava {
         funcs: &[(&str, &Function)],
         _files: &mut Files,
     ) {
+        self.import_funcs_called = true;
+
         let name = world_name(resolve, world);
-        let mut gen = self.interface(resolve, &name);
+        let world_resources = self.world_resources.clone();
+        let mut gen = self.interface(resolve, &name, Direction::Import);
 
-        for (_, func) in funcs {
-            gen.import("$root", func);
+        for (resource, funcs) in by_resource(funcs.iter().map(|(_, func)| *func), world_resources) {
+            if let Some(resource) = resource {
+                gen.start_resource(resource, None);
+            }
+
+            for func in funcs {
+                gen.import("$root", func);
+            }
+
+            if resource.is_some() {
+                gen.end_resource();
+            }
         }
 
         gen.add_world_fragment();
@@ -133,11 +189,24 @@ impl WorldGenerator for TeaVmJava {
     ) -> Result<()> {
         let name = interface_name(resolve, key, Direction::Export);
         self.interface_names.insert(id, name.clone());
-        let mut gen = self.interface(resolve, &name);
+        let mut gen = self.interface(resolve, &name, Direction::Export);
         gen.types(id);
 
-        for (_, func) in resolve.interfaces[id].functions.iter() {
-            gen.export(Some(&resolve.name_world_key(key)), func);
+        for (resource, funcs) in by_resource(
+            resolve.interfaces[id].functions.values(),
+            interface_resources(resolve, id),
+        ) {
+            if let Some(resource) = resource {
+                gen.start_resource(resource, Some(key));
+            }
+
+            for func in funcs {
+                gen.export(Some(&resolve.name_world_key(key)), func);
+            }
+
+            if resource.is_some() {
+                gen.end_resource();
+            }
         }
 
         gen.add_interface_fragment();
@@ -152,7 +221,7 @@ impl WorldGenerator for TeaVmJava {
         _files: &mut Files,
     ) -> Result<()> {
         let name = world_name(resolve, world);
-        let mut gen = self.interface(resolve, &name);
+        let mut gen = self.interface(resolve, &name, Direction::Export);
 
         for (_, func) in funcs {
             gen.export(None, func);
@@ -170,13 +239,28 @@ impl WorldGenerator for TeaVmJava {
         _files: &mut Files,
     ) {
         let name = world_name(resolve, world);
-        let mut gen = self.interface(resolve, &name);
+        let mut gen = self.interface(resolve, &name, Direction::Import);
 
         for (ty_name, ty) in types {
             gen.define_type(ty_name, *ty);
         }
 
         gen.add_world_fragment();
+
+        self.world_resources.extend(
+            types
+                .iter()
+                .map(|(_, ty)| *ty)
+                .filter(|ty| matches!(resolve.types[*ty].kind, TypeDefKind::Resource)),
+        );
+    }
+
+    fn finish_imports(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) {
+        // Resources imported by the world itself get their classes from
+        // `import_funcs`, which isn't called if the world imports no functions.
+        if !self.import_funcs_called && !self.world_resources.is_empty() {
+            self.import_funcs(resolve, world, &[], files);
+        }
     }
 
     fn finish(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
@@ -345,6 +429,53 @@ impl WorldGenerator for TeaVmJava {
             );
         }
 
+        if self.needs_rep_table {
+            src.push_str(
+                r#"
+                public static final class RepTable<T> {
+                    private final ArrayList<Object> list = new ArrayList<>();
+                    private int firstVacant = -1;
+
+                    private static final class Vacant {
+                        public final int next;
+
+                        public Vacant(int next) {
+                            this.next = next;
+                        }
+                    }
+
+                    public int add(T value) {
+                        int rep;
+                        if (firstVacant >= 0) {
+                            rep = firstVacant;
+                            firstVacant = ((Vacant) list.get(rep)).next;
+                            list.set(rep, value);
+                        } else {
+                            rep = list.size();
+                            list.add(value);
+                        }
+                        return rep;
+                    }
+
+                    public T get(int rep) {
+                        Object value = list.get(rep);
+                        if (value instanceof Vacant) {
+                            throw new RuntimeException("invalid rep " + rep);
+                        }
+                        return (T) value;
+                    }
+
+                    public T remove(int rep) {
+                        T value = get(rep);
+                        list.set(rep, new Vacant(firstVacant));
+                        firstVacant = rep;
+                        return value;
+                    }
+                }
+                "#,
+            );
+        }
+
         if self.return_area_align > 0 {
             let size = self.return_area_size;
             let align = self.return_area_align;
@@ -444,6 +575,7 @@ struct InterfaceGenerator<'a> {
     gen: &'a mut TeaVmJava,
     resolve: &'a Resolve,
     name: &'a str,
+    direction: Direction,
 }
 
 impl InterfaceGenerator<'_> {
@@ -482,16 +614,20 @@ impl InterfaceGenerator<'_> {
     }
 
     fn import(&mut self, module: &str, func: &Function) {
-        if func.kind != FunctionKind::Freestanding {
-            todo!("resources");
-        }
-
         let mut bindgen = FunctionBindgen::new(
             self,
             &func.name,
+            &func.kind,
             func.params
                 .iter()
-                .map(|(name, _)| name.to_java_ident())
+                .enumerate()
+                .map(|(i, (name, _))| {
+                    if i == 0 && matches!(&func.kind, FunctionKind::Method(_)) {
+                        "this".to_owned()
+                    } else {
+                        name.to_java_ident()
+                    }
+                })
                 .collect(),
         );
 
@@ -501,6 +637,7 @@ impl InterfaceGenerator<'_> {
             LiftLower::LowerArgsLiftResults,
             func,
             &mut bindgen,
+            false,
         );
 
         let src = bindgen.src;
@@ -561,6 +698,7 @@ impl InterfaceGenerator<'_> {
         let mut bindgen = FunctionBindgen::new(
             self,
             &func.name,
+            &func.kind,
             (0..sig.params.len()).map(|i| format!("p{i}")).collect(),
         );
 
@@ -570,10 +708,19 @@ impl InterfaceGenerator<'_> {
             LiftLower::LiftArgsLowerResults,
             func,
             &mut bindgen,
+            false,
         );
 
         assert!(!bindgen.needs_cleanup_list);
 
+        // Borrowed handles to imported resources are dropped once the call
+        // returns, so they're declared up front to be in scope for that.
+        let resource_drops = bindgen
+            .resource_drops
+            .iter()
+            .map(|(ty, name)| format!("{ty} {name} = null;\n"))
+            .collect::<String>();
+
         let src = bindgen.src;
 
         let result_type = match &sig.results[..] {
@@ -600,11 +747,16 @@ impl InterfaceGenerator<'_> {
             r#"
             @Export(name = "{export_name}")
             private static {result_type} wasmExport{camel_name}({params}) {{
-                {src}
+                {resource_drops} {src}
             }}
             "#
         );
 
+        if let FunctionKind::Method(_) = &func.kind {
+            let sig = self.sig_string_with_modifiers(func, false, "public abstract");
+            uwriteln!(self.src, "{sig};");
+        }
+
         if abi::guest_export_needs_post_return(self.resolve, func) {
             let params = sig
                 .results
@@ -620,6 +772,7 @@ impl InterfaceGenerator<'_> {
             let mut bindgen = FunctionBindgen::new(
                 self,
                 "INVALID",
+                &func.kind,
                 (0..sig.results.len()).map(|i| format!("p{i}")).collect(),
             );
 
@@ -714,6 +867,9 @@ impl InterfaceGenerator<'_> {
 
                         format!("{}Result<{ok}, {err}>", self.gen.qualifier())
                     }
+                    TypeDefKind::Handle(Handle::Own(id) | Handle::Borrow(id)) => {
+                        self.type_name_with_qualifier(&Type::Id(*id), qualifier)
+                    }
                     _ => {
                         if let Some(name) = &ty.name {
                             format!(
@@ -788,7 +944,20 @@ impl InterfaceGenerator<'_> {
     }
 
     fn sig_string(&mut self, func: &Function, qualifier: bool) -> String {
-        let name = func.name.to_java_ident();
+        let modifiers = match &func.kind {
+            FunctionKind::Freestanding | FunctionKind::Static(_) => "public static",
+            FunctionKind::Method(_) | FunctionKind::Constructor(_) => "public",
+        };
+        self.sig_string_with_modifiers(func, qualifier, modifiers)
+    }
+
+    fn sig_string_with_modifiers(
+        &mut self,
+        func: &Function,
+        qualifier: bool,
+        modifiers: &str,
+    ) -> String {
+        let name = func.item_name().to_java_ident();
 
         let result_type = match func.results.len() {
             0 => "void".into(),
@@ -809,9 +978,16 @@ impl InterfaceGenerator<'_> {
             }
         };
 
+        let skip = if let FunctionKind::Method(_) = &func.kind {
+            1
+        } else {
+            0
+        };
+
         let params = func
             .params
             .iter()
+            .skip(skip)
             .map(|(name, ty)| {
                 let ty = self.type_name_with_qualifier(ty, qualifier);
                 let name = name.to_java_ident();
@@ -820,7 +996,199 @@ impl InterfaceGenerator<'_> {
             .collect::<Vec<_>>()
             .join(", ");
 
-        format!("public static {result_type} {name}({params})")
+        if let FunctionKind::Constructor(id) = &func.kind {
+            let name = self.gen.all_resources[id].name.to_upper_camel_case();
+            format!("{modifiers} {name}({params})")
+        } else {
+            format!("{modifiers} {result_type} {name}({params})")
+        }
+    }
+
+    /// Generates a flags class with more members than fit in a `long`, which
+    /// is backed by an `int` per 32 flags instead.
+    fn type_flags_words(&mut self, name: &str, flags: &Flags) {
+        let count = flags.repr().count();
+
+        let flags = flags
+            .flags
+            .iter()
+            .enumerate()
+            .map(|(i, flag)| {
+                let flag_name = flag.name.to_shouty_snake_case();
+                let words = (0..count)
+                    .map(|word| {
+                        if word == i / 32 {
+                            format!("1 << {}", i % 32)
+                        } else {
+                            "0".to_owned()
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "public static final {name} {flag_name} = new {name}(new int[] {{ {words} }});"
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        uwrite!(
+            self.src,
+            "
+            public static final class {name} {{
+                public final int[] value;
+
+                public {name}(int[] value) {{
+                    this.value = value;
+                }}
+
+                public {name} or({name} other) {{
+                    int[] value = new int[{count}];
+                    for (int i = 0; i < {count}; ++i) {{
+                        value[i] = this.value[i] | other.value[i];
+                    }}
+                    return new {name}(value);
+                }}
+
+                public {name} and({name} other) {{
+                    int[] value = new int[{count}];
+                    for (int i = 0; i < {count}; ++i) {{
+                        value[i] = this.value[i] & other.value[i];
+                    }}
+                    return new {name}(value);
+                }}
+
+                public boolean contains({name} other) {{
+                    for (int i = 0; i < {count}; ++i) {{
+                        if ((this.value[i] & other.value[i]) != other.value[i]) {{
+                            return false;
+                        }}
+                    }}
+                    return true;
+                }}
+
+                {flags}
+            }}
+            "
+        );
+    }
+
+    fn start_resource(&mut self, id: TypeId, key: Option<&WorldKey>) {
+        let info = &self.gen.all_resources[&id];
+        let name = info.name.clone();
+        let camel = name.to_upper_camel_case();
+        let docs = info.docs.clone();
+        self.print_docs(&docs);
+
+        match self.direction {
+            Direction::Import => {
+                let module = key
+                    .map(|key| self.resolve.name_world_key(key))
+                    .unwrap_or_else(|| "$root".into());
+
+                // Handles are dropped explicitly with `close` rather than from
+                // a finalizer, since a resource may need to outlive the child
+                // resources created from it.
+                uwrite!(
+                    self.src,
+                    r#"
+                    public static final class {camel} implements AutoCloseable {{
+                        public int handle;
+
+                        public static final class Handle {{
+                            public final int handle;
+
+                            public Handle(int handle) {{
+                                this.handle = handle;
+                            }}
+                        }}
+
+                        public {camel}(Handle handle) {{
+                            this.handle = handle.handle;
+                        }}
+
+                        @Import(name = "[resource-drop]{name}", module = "{module}")
+                        private static native void wasmImportResourceDrop(int p0);
+
+                        @Override
+                        public void close() {{
+                            if (handle != 0) {{
+                                wasmImportResourceDrop(handle);
+                                handle = 0;
+                            }}
+                        }}
+                    "#
+                );
+            }
+            Direction::Export => {
+                self.gen.needs_rep_table = true;
+
+                let (module, prefix) = match key {
+                    Some(key) => {
+                        let key = self.resolve.name_world_key(key);
+                        (format!("[export]{key}"), format!("{key}#"))
+                    }
+                    None => ("[export]$root".to_owned(), String::new()),
+                };
+                let qualifier = self.gen.qualifier();
+
+                // The destructor runs once the last handle to a resource is
+                // dropped, and `close` is called again in case it has been
+                // overridden to release anything else the resource owns.
+                uwrite!(
+                    self.src,
+                    r#"
+                    public static abstract class {camel} implements AutoCloseable {{
+                        public static final {qualifier}RepTable<{camel}> repTable = new {qualifier}RepTable<>();
+
+                        public int handle;
+
+                        @Import(name = "[resource-drop]{name}", module = "{module}")
+                        public static native void wasmImportResourceDrop(int p0);
+
+                        @Import(name = "[resource-new]{name}", module = "{module}")
+                        public static native int wasmImportResourceNew(int p0);
+
+                        @Import(name = "[resource-rep]{name}", module = "{module}")
+                        public static native int wasmImportResourceRep(int p0);
+
+                        @Export(name = "{prefix}[dtor]{name}")
+                        private static void wasmExportResourceDtor(int rep) {{
+                            {camel} value = repTable.remove(rep);
+                            value.handle = 0;
+                            value.close();
+                        }}
+
+                        @Override
+                        public void close() {{
+                            if (handle != 0) {{
+                                int handle = this.handle;
+                                this.handle = 0;
+                                wasmImportResourceDrop(handle);
+                            }}
+                        }}
+                    "#
+                );
+
+                if self.gen.opts.generate_stub {
+                    let qualified = self.type_name_with_qualifier(&Type::Id(id), true);
+                    uwrite!(
+                        self.stub,
+                        r#"
+                        public static class {camel} extends {qualified} {{
+                        "#
+                    );
+                }
+            }
+        }
+    }
+
+    fn end_resource(&mut self) {
+        uwriteln!(self.src, "}}");
+
+        if self.direction == Direction::Export && self.gen.opts.generate_stub {
+            uwriteln!(self.stub, "}}");
+        }
     }
 }
 
@@ -889,8 +1257,17 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
     }
 
     fn type_resource(&mut self, id: TypeId, name: &str, docs: &Docs) {
-        _ = (id, name, docs);
-        todo!()
+        // Resource classes are emitted along with their functions in
+        // `start_resource`, so only record what's needed for that here.
+        self.gen.all_resources.insert(
+            id,
+            ResourceInfo {
+                module: self.name.to_owned(),
+                name: name.to_owned(),
+                docs: docs.clone(),
+                direction: self.direction,
+            },
+        );
     }
 
     fn type_flags(&mut self, _id: TypeId, name: &str, flags: &Flags, docs: &Docs) {
@@ -898,13 +1275,11 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
 
         let name = name.to_upper_camel_case();
 
-        let ty = match flags.repr() {
-            FlagsRepr::U8 => "byte",
-            FlagsRepr::U16 => "short",
-            FlagsRepr::U32(1) => "int",
-            FlagsRepr::U32(2) => "long",
-            repr => todo!("flags {repr:?}"),
+        let Some(repr) = flags_repr(flags) else {
+            self.type_flags_words(&name, flags);
+            return;
         };
+        let ty = int_type(repr);
 
         let flags = flags
             .flags
@@ -912,11 +1287,7 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
             .enumerate()
             .map(|(i, flag)| {
                 let flag_name = flag.name.to_shouty_snake_case();
-                let suffix = if matches!(flags.repr(), FlagsRepr::U32(2)) {
-                    "L"
-                } else {
-                    ""
-                };
+                let suffix = if let Int::U64 = repr { "L" } else { "" };
                 format!(
                     "public static final {name} {flag_name} = new {name}(({ty}) (1{suffix} << {i}));"
                 )
@@ -1096,6 +1467,7 @@ struct BlockStorage {
 struct FunctionBindgen<'a, 'b> {
     gen: &'b mut InterfaceGenerator<'a>,
     func_name: &'b str,
+    kind: &'b FunctionKind,
     params: Box<[String]>,
     src: String,
     locals: Ns,
@@ -1104,17 +1476,20 @@ struct FunctionBindgen<'a, 'b> {
     payloads: Vec<String>,
     cleanup: Vec<Cleanup>,
     needs_cleanup_list: bool,
+    resource_drops: Vec<(String, String)>,
 }
 
 impl<'a, 'b> FunctionBindgen<'a, 'b> {
     fn new(
         gen: &'b mut InterfaceGenerator<'a>,
         func_name: &'b str,
+        kind: &'b FunctionKind,
         params: Box<[String]>,
     ) -> FunctionBindgen<'a, 'b> {
         Self {
             gen,
             func_name,
+            kind,
             params,
             src: String::new(),
             locals: Ns::default(),
@@ -1123,6 +1498,7 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
             payloads: Vec::new(),
             cleanup: Vec::new(),
             needs_cleanup_list: false,
+            resource_drops: Vec::new(),
         }
     }
 
@@ -1345,26 +1721,32 @@ impl Bindgen for FunctionBindgen<'_, '_> {
 
             // TODO: checked
             Instruction::FlagsLower { flags, .. } => match flags_repr(flags) {
-                Int::U8 | Int::U16 | Int::U32 => {
+                Some(Int::U8 | Int::U16 | Int::U32) => {
                     results.push(format!("({}).value", operands[0]));
                 }
-                Int::U64 => {
+                Some(Int::U64) => {
                     let op = &operands[0];
                     results.push(format!("(int) (({op}).value & 0xffffffffL)"));
                     results.push(format!("(int) ((({op}).value >>> 32) & 0xffffffffL)"));
                 }
+                None => {
+                    let op = &operands[0];
+                    for i in 0..flags.repr().count() {
+                        results.push(format!("({op}).value[{i}]"));
+                    }
+                }
             },
 
             Instruction::FlagsLift { flags, ty, .. } => match flags_repr(flags) {
-                Int::U8 | Int::U16 | Int::U32 => {
+                Some(repr @ (Int::U8 | Int::U16 | Int::U32)) => {
                     results.push(format!(
                         "new {}(({}) {})",
                         self.gen.type_name(&Type::Id(*ty)),
-                        int_type(flags_repr(flags)),
+                        int_type(repr),
                         operands[0]
                     ));
                 }
-                Int::U64 => {
+                Some(Int::U64) => {
                     results.push(format!(
                         "new {}(((long) ({})) | (((long) ({})) << 32))",
                         self.gen.type_name(&Type::Id(*ty)),
@@ -1372,9 +1754,83 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                         operands[1]
                     ));
                 }
+                None => {
+                    results.push(format!(
+                        "new {}(new int[] {{ {} }})",
+                        self.gen.type_name(&Type::Id(*ty)),
+                        operands.join(", ")
+                    ));
+                }
             },
 
-            Instruction::HandleLower { .. } | Instruction::HandleLift { .. } => todo!(),
+            Instruction::HandleLower { handle, .. } => {
+                let op = &operands[0];
+                let (Handle::Own(resource) | Handle::Borrow(resource)) = handle;
+                let id = dealias(self.gen.resolve, *resource);
+                let direction = self.gen.gen.all_resources[&id].direction;
+                let ty = self.gen.type_name(&Type::Id(id));
+
+                if direction == Direction::Export {
+                    // Exported resources are only registered with the host
+                    // the first time a handle to them is needed.
+                    uwrite!(
+                        self.src,
+                        "
+                        if (({op}).handle == 0) {{
+                            ({op}).handle = {ty}.wasmImportResourceNew({ty}.repTable.add({op}));
+                        }}
+                        "
+                    );
+                }
+
+                let result = self.locals.tmp("handle");
+                uwriteln!(self.src, "int {result} = ({op}).handle;");
+                if let Handle::Own(_) = handle {
+                    uwriteln!(self.src, "({op}).handle = 0;");
+                }
+                results.push(result);
+            }
+
+            Instruction::HandleLift { handle, .. } => {
+                let op = &operands[0];
+                let (Handle::Own(resource) | Handle::Borrow(resource)) = handle;
+                let id = dealias(self.gen.resolve, *resource);
+                let direction = self.gen.gen.all_resources[&id].direction;
+                let ty = self.gen.type_name(&Type::Id(id));
+
+                match (direction, handle) {
+                    (Direction::Import, _)
+                        if self.gen.direction == Direction::Import
+                            && matches!(self.kind, FunctionKind::Constructor(_)) =>
+                    {
+                        uwriteln!(self.src, "this.handle = {op};");
+                        results.push("this".to_owned());
+                    }
+                    (Direction::Import, Handle::Own(_)) => {
+                        results.push(format!("new {ty}(new {ty}.Handle({op}))"));
+                    }
+                    (Direction::Import, Handle::Borrow(_)) => {
+                        let resource = self.locals.tmp("resource");
+                        uwriteln!(self.src, "{resource} = new {ty}(new {ty}.Handle({op}));");
+                        self.resource_drops.push((ty, resource.clone()));
+                        results.push(resource);
+                    }
+                    (Direction::Export, Handle::Own(_)) => {
+                        let resource = self.locals.tmp("resource");
+                        uwrite!(
+                            self.src,
+                            "
+                            {ty} {resource} = {ty}.repTable.get({ty}.wasmImportResourceRep({op}));
+                            {resource}.handle = {op};
+                            "
+                        );
+                        results.push(resource);
+                    }
+                    (Direction::Export, Handle::Borrow(_)) => {
+                        results.push(format!("{ty}.repTable.get({op})"));
+                    }
+                }
+            }
 
             Instruction::RecordLower { record, .. } => {
                 let op = &operands[0];
@@ -1392,6 +1848,21 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 results.push(format!("new {}({ops})", self.gen.type_name(&Type::Id(*ty))));
             }
 
+            Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. }
+            | Instruction::Flush { .. } => {
+                unreachable!("async functions are never requested by this generator")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+
             Instruction::TupleLower { tuple, .. } => {
                 let op = &operands[0];
                 for i in 0..tuple.types.len() {
@@ -1714,7 +2185,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
 
                 let result = match &block_results[..] {
                     [result] => result,
-                    _ => todo!("result count == {}", results.len()),
+                    _ => unreachable!("list elements are lifted to a single value"),
                 };
 
                 uwrite!(
@@ -1809,14 +2280,39 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 };
 
                 let module = self.gen.name;
-                let name = func.name.to_java_ident();
+                let name = func.item_name().to_java_ident();
+
+                let call = match &func.kind {
+                    FunctionKind::Freestanding => {
+                        format!("{module}Impl.{name}({})", operands.join(", "))
+                    }
+                    FunctionKind::Static(id) => {
+                        let impl_name = self.gen.gen.all_resources[id].export_impl_name();
+                        format!("{impl_name}.{name}({})", operands.join(", "))
+                    }
+                    FunctionKind::Method(_) => {
+                        let target = operands.remove(0);
+                        format!("({target}).{name}({})", operands.join(", "))
+                    }
+                    FunctionKind::Constructor(id) => {
+                        let impl_name = self.gen.gen.all_resources[id].export_impl_name();
+                        format!("new {impl_name}({})", operands.join(", "))
+                    }
+                };
 
-                let args = operands.join(", ");
+                let drops = self
+                    .resource_drops
+                    .iter()
+                    .map(|(_, resource)| {
+                        format!("if ({resource} != null) {{ {resource}.close(); }}\n")
+                    })
+                    .collect::<String>();
 
                 uwrite!(
                     self.src,
                     "
-                    {assignment}{module}Impl.{name}({args});
+                    {assignment}{call};
+                    {drops}
                     {destructure}
                     "
                 );
@@ -1849,6 +2345,10 @@ impl Bindgen for FunctionBindgen<'_, '_> {
 
                 match *amt {
                     0 => (),
+                    // Imported constructors initialize `this` rather than
+                    // returning a value.
+                    1 if self.gen.direction == Direction::Import
+                        && matches!(self.kind, FunctionKind::Constructor(_)) => {}
                     1 => uwriteln!(self.src, "return {};", operands[0]),
                     count => {
                         let results = operands.join(", ");
@@ -2148,13 +2648,15 @@ fn wasm_type(ty: WasmType) -> &'static str {
     }
 }
 
-fn flags_repr(flags: &Flags) -> Int {
+/// Returns the integer type backing `flags`, or `None` if it's instead backed
+/// by an `int[]` holding one element per 32 flags.
+fn flags_repr(flags: &Flags) -> Option<Int> {
     match flags.repr() {
-        FlagsRepr::U8 => Int::U8,
-        FlagsRepr::U16 => Int::U16,
-        FlagsRepr::U32(1) => Int::U32,
-        FlagsRepr::U32(2) => Int::U64,
-        repr => panic!("unimplemented flags {repr:?}"),
+        FlagsRepr::U8 => Some(Int::U8),
+        FlagsRepr::U16 => Some(Int::U16),
+        FlagsRepr::U32(1) => Some(Int::U32),
+        FlagsRepr::U32(2) => Some(Int::U64),
+        FlagsRepr::U32(_) => None,
     }
 }
 
@@ -2254,6 +2756,50 @@ fn interface_name(resolve: &Resolve, name: &WorldKey, direction: Direction) -> S
     )
 }
 
+/// Groups `funcs` by the resource they belong to, if any, including an entry
+/// for each of `resources` even when it has no functions.
+fn by_resource<'a>(
+    funcs: impl Iterator<Item = &'a Function>,
+    resources: impl IntoIterator<Item = TypeId>,
+) -> IndexMap<Option<TypeId>, Vec<&'a Function>> {
+    let mut by_resource = IndexMap::<_, Vec<_>>::new();
+    for func in funcs {
+        by_resource
+            .entry(match &func.kind {
+                FunctionKind::Freestanding => None,
+                FunctionKind::Method(resource)
+                | FunctionKind::Static(resource)
+                | FunctionKind::Constructor(resource) => Some(*resource),
+            })
+            .or_default()
+            .push(func);
+    }
+    for id in resources {
+        by_resource.entry(Some(id)).or_default();
+    }
+    by_resource
+}
+
+fn interface_resources(resolve: &Resolve, id: InterfaceId) -> Vec<TypeId> {
+    resolve.interfaces[id]
+        .types
+        .values()
+        .copied()
+        .filter(|ty| matches!(resolve.types[*ty].kind, TypeDefKind::Resource))
+        .collect()
+}
+
+/// Dereferences any number of `TypeDefKind::Type` aliases to retrieve the
+/// target type.
+fn dealias(resolve: &Resolve, mut id: TypeId) -> TypeId {
+    loop {
+        match &resolve.types[id].kind {
+            TypeDefKind::Type(Type::Id(that_id)) => id = *that_id,
+            _ => break id,
+        }
+    }
+}
+
 fn split_qualified_name(name: &str) -> (String, &str) {
     let tokens = name.split('.').collect::<Vec<_>>();
 
@@ -0,0 +1,1178 @@
+use crate::{
+    case_class_name, flags_repr, func_name, is_primitive, list_element_info, wasm_type,
+    InterfaceGenerator, ToKotlinIdent,
+};
+use heck::ToUpperCamelCase;
+use std::{fmt::Write, mem};
+use wit_bindgen_core::{
+    abi::{Bindgen, Bitcast, Instruction, WasmType},
+    dealias, uwrite, uwriteln,
+    wit_parser::{FunctionKind, Handle, Int, Resolve, Result_, SizeAlign, Type, TypeId, Variant},
+    Direction, Ns,
+};
+
+struct Block {
+    body: String,
+    results: Vec<String>,
+    element: String,
+    base: String,
+}
+
+struct Cleanup {
+    address: String,
+    size: String,
+    align: usize,
+}
+
+struct BlockStorage {
+    body: String,
+    element: String,
+    base: String,
+    cleanup: Vec<Cleanup>,
+}
+
+/// How a case of a variant is lowered and lifted.
+struct Case {
+    /// The class matched by `is` when lowering the case.
+    class: String,
+    /// The expression constructing the case, which is called with the payload
+    /// if it has one.
+    constructor: String,
+    ty: Option<Type>,
+    /// Whether the constructor takes `Unit` when the case has no payload.
+    unit_payload: bool,
+}
+
+pub(super) struct FunctionBindgen<'a, 'b> {
+    pub gen: &'b mut InterfaceGenerator<'a>,
+    func_name: &'b str,
+    kind: &'b FunctionKind,
+    params: Box<[String]>,
+    pub src: String,
+    locals: Ns,
+    block_storage: Vec<BlockStorage>,
+    blocks: Vec<Block>,
+    payloads: Vec<String>,
+    cleanup: Vec<Cleanup>,
+    pub needs_cleanup_list: bool,
+    pub resource_drops: Vec<(String, String)>,
+}
+
+impl<'a, 'b> FunctionBindgen<'a, 'b> {
+    pub fn new(
+        gen: &'b mut InterfaceGenerator<'a>,
+        func_name: &'b str,
+        kind: &'b FunctionKind,
+        params: Box<[String]>,
+    ) -> FunctionBindgen<'a, 'b> {
+        // Locals would shadow the parameters the ABI still refers to, so the
+        // parameters' names are reserved.
+        let mut locals = Ns::default();
+        for param in params.iter() {
+            locals.insert(param).unwrap();
+        }
+        locals.insert("cleanupList").unwrap();
+
+        Self {
+            gen,
+            func_name,
+            kind,
+            params,
+            src: String::new(),
+            locals,
+            block_storage: Vec::new(),
+            blocks: Vec::new(),
+            payloads: Vec::new(),
+            cleanup: Vec::new(),
+            needs_cleanup_list: false,
+            resource_drops: Vec::new(),
+        }
+    }
+
+    fn variant_cases(&mut self, ty: TypeId, variant: &Variant) -> Vec<Case> {
+        let name = self.gen.type_name(&Type::Id(ty));
+        let variant_name = name.rsplit('.').next().unwrap().to_owned();
+        variant
+            .cases
+            .iter()
+            .map(|case| {
+                let class = format!("{name}.{}", case_class_name(&variant_name, &case.name));
+                Case {
+                    constructor: class.clone(),
+                    class,
+                    ty: case.ty,
+                    unit_payload: false,
+                }
+            })
+            .collect()
+    }
+
+    fn result_cases(&mut self, result: &Result_) -> Vec<Case> {
+        let qualifier = self.gen.gen.qualifier();
+        [("Ok", result.ok), ("Err", result.err)]
+            .into_iter()
+            .map(|(case, ty)| Case {
+                class: format!("{qualifier}Result.{case}"),
+                constructor: format!("{qualifier}Result.{case}"),
+                ty,
+                unit_payload: true,
+            })
+            .collect()
+    }
+
+    fn lower_variant(
+        &mut self,
+        cases: &[Case],
+        lowered_types: &[WasmType],
+        op: &str,
+        results: &mut Vec<String>,
+    ) {
+        let blocks = self
+            .blocks
+            .drain(self.blocks.len() - cases.len()..)
+            .collect::<Vec<_>>();
+
+        let payloads = self
+            .payloads
+            .drain(self.payloads.len() - cases.len()..)
+            .collect::<Vec<_>>();
+
+        let lowered = lowered_types
+            .iter()
+            .map(|_| self.locals.tmp("lowered"))
+            .collect::<Vec<_>>();
+
+        results.extend(lowered.iter().cloned());
+
+        let declarations = lowered
+            .iter()
+            .zip(lowered_types)
+            .map(|(lowered, ty)| format!("var {lowered}: {} = {}", wasm_type(*ty), zero(*ty)))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let variant = self.locals.tmp("variant");
+
+        let cases = cases
+            .iter()
+            .zip(blocks)
+            .zip(payloads)
+            .map(|((case, Block { body, results, .. }), payload)| {
+                let payload = if case.ty.is_some() {
+                    format!("val {payload} = {variant}.value")
+                } else {
+                    String::new()
+                };
+
+                let assignments = lowered
+                    .iter()
+                    .zip(&results)
+                    .map(|(lowered, result)| format!("{lowered} = {result}\n"))
+                    .collect::<Vec<_>>()
+                    .concat();
+
+                format!(
+                    "is {} -> {{
+                         {payload}
+                         {body}
+                         {assignments}
+                     }}",
+                    case.class
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        uwrite!(
+            self.src,
+            "
+            {declarations}
+
+            when (val {variant} = {op}) {{
+                {cases}
+            }}
+            "
+        );
+    }
+
+    fn lift_variant(&mut self, ty: &Type, cases: &[Case], op: &str, results: &mut Vec<String>) {
+        let blocks = self
+            .blocks
+            .drain(self.blocks.len() - cases.len()..)
+            .collect::<Vec<_>>();
+
+        let ty = self.gen.type_name(ty);
+        let lifted = self.locals.tmp("lifted");
+
+        let cases = cases
+            .iter()
+            .zip(blocks)
+            .enumerate()
+            .map(|(i, (case, Block { body, results, .. }))| {
+                let value = if case.ty.is_some() {
+                    format!("{}({})", case.constructor, results[0])
+                } else if case.unit_payload {
+                    format!("{}(Unit)", case.constructor)
+                } else {
+                    case.constructor.clone()
+                };
+
+                format!(
+                    "{i} -> {{
+                         {body}
+                         {value}
+                     }}"
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        uwrite!(
+            self.src,
+            r#"
+            val {lifted}: {ty} = when ({op}) {{
+                {cases}
+
+                else -> throw AssertionError("invalid discriminant: " + ({op}))
+            }}
+            "#
+        );
+
+        results.push(lifted);
+    }
+}
+
+impl Bindgen for FunctionBindgen<'_, '_> {
+    type Operand = String;
+
+    fn emit(
+        &mut self,
+        _resolve: &Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<String>,
+        results: &mut Vec<String>,
+    ) {
+        match inst {
+            Instruction::GetArg { nth } => results.push(self.params[*nth].clone()),
+            Instruction::I32Const { val } => results.push(val.to_string()),
+            Instruction::ConstZero { tys } => {
+                results.extend(tys.iter().map(|ty| zero(*ty).to_owned()))
+            }
+
+            Instruction::U8FromI32 | Instruction::S8FromI32 => {
+                results.push(format!("({}).toByte()", operands[0]))
+            }
+            Instruction::U16FromI32 | Instruction::S16FromI32 => {
+                results.push(format!("({}).toShort()", operands[0]))
+            }
+
+            Instruction::I32FromU8 => results.push(format!("(({}).toInt() and 0xFF)", operands[0])),
+            Instruction::I32FromU16 => {
+                results.push(format!("(({}).toInt() and 0xFFFF)", operands[0]))
+            }
+
+            Instruction::I32FromS8 | Instruction::I32FromS16 => {
+                results.push(format!("({}).toInt()", operands[0]))
+            }
+
+            Instruction::CharFromI32
+            | Instruction::I32FromChar
+            | Instruction::U32FromI32
+            | Instruction::S32FromI32
+            | Instruction::S64FromI64
+            | Instruction::U64FromI64
+            | Instruction::I32FromU32
+            | Instruction::I32FromS32
+            | Instruction::I64FromS64
+            | Instruction::I64FromU64
+            | Instruction::CoreF32FromF32
+            | Instruction::CoreF64FromF64
+            | Instruction::F32FromCoreF32
+            | Instruction::F64FromCoreF64 => results.push(operands[0].clone()),
+
+            Instruction::Bitcasts { casts } => results.extend(
+                casts
+                    .iter()
+                    .zip(operands)
+                    .map(|(cast, op)| perform_cast(op, cast)),
+            ),
+
+            Instruction::I32FromBool => {
+                results.push(format!("(if ({}) 1 else 0)", operands[0]));
+            }
+            Instruction::BoolFromI32 => results.push(format!("({} != 0)", operands[0])),
+
+            Instruction::FlagsLower { flags, .. } => match flags_repr(flags) {
+                Some(Int::U8 | Int::U16 | Int::U32) => {
+                    results.push(format!("({}).value", operands[0]));
+                }
+                Some(Int::U64) => {
+                    let op = &operands[0];
+                    results.push(format!("(({op}).value and 0xffffffffL).toInt()"));
+                    results.push(format!("((({op}).value ushr 32) and 0xffffffffL).toInt()"));
+                }
+                None => {
+                    let op = &operands[0];
+                    for i in 0..flags.repr().count() {
+                        results.push(format!("({op}).value[{i}]"));
+                    }
+                }
+            },
+
+            Instruction::FlagsLift { flags, ty, .. } => match flags_repr(flags) {
+                Some(Int::U8 | Int::U16 | Int::U32) => {
+                    results.push(format!(
+                        "{}({})",
+                        self.gen.type_name(&Type::Id(*ty)),
+                        operands[0]
+                    ));
+                }
+                Some(Int::U64) => {
+                    results.push(format!(
+                        "{}((({}).toLong() and 0xffffffffL) or (({}).toLong() shl 32))",
+                        self.gen.type_name(&Type::Id(*ty)),
+                        operands[0],
+                        operands[1]
+                    ));
+                }
+                None => {
+                    results.push(format!(
+                        "{}(intArrayOf({}))",
+                        self.gen.type_name(&Type::Id(*ty)),
+                        operands.join(", ")
+                    ));
+                }
+            },
+
+            Instruction::HandleLower { handle, .. } => {
+                let op = &operands[0];
+                let (Handle::Own(resource) | Handle::Borrow(resource)) = handle;
+                let id = dealias(self.gen.resolve, *resource);
+                let info = &self.gen.gen.all_resources[&id];
+                let direction = info.direction;
+                let new = info.intrinsic_name("New");
+                let ty = self.gen.type_name(&Type::Id(id));
+
+                if direction == Direction::Export {
+                    // Exported resources are only registered with the host
+                    // the first time a handle to them is needed.
+                    uwrite!(
+                        self.src,
+                        "
+                        if (({op}).handle == 0) {{
+                            ({op}).handle = {new}({ty}.repTable.add({op}))
+                        }}
+                        "
+                    );
+                }
+
+                let result = self.locals.tmp("handle");
+                uwriteln!(self.src, "val {result} = ({op}).handle");
+                if let Handle::Own(_) = handle {
+                    uwriteln!(self.src, "({op}).handle = 0");
+                }
+                results.push(result);
+            }
+
+            Instruction::HandleLift { handle, .. } => {
+                let op = &operands[0];
+                let (Handle::Own(resource) | Handle::Borrow(resource)) = handle;
+                let id = dealias(self.gen.resolve, *resource);
+                let info = &self.gen.gen.all_resources[&id];
+                let direction = info.direction;
+                let rep = info.intrinsic_name("Rep");
+                let ty = self.gen.type_name(&Type::Id(id));
+
+                match (direction, handle) {
+                    (Direction::Import, _)
+                        if self.gen.direction == Direction::Import
+                            && matches!(self.kind, FunctionKind::Constructor(_)) =>
+                    {
+                        uwriteln!(self.src, "this.handle = {op}");
+                        results.push("this".to_owned());
+                    }
+                    (Direction::Import, Handle::Own(_)) => {
+                        results.push(format!("{ty}({ty}.Handle({op}))"));
+                    }
+                    (Direction::Import, Handle::Borrow(_)) => {
+                        let resource = self.locals.tmp("resource");
+                        uwriteln!(self.src, "{resource} = {ty}({ty}.Handle({op}))");
+                        self.resource_drops.push((ty, resource.clone()));
+                        results.push(format!("{resource}!!"));
+                    }
+                    (Direction::Export, Handle::Own(_)) => {
+                        let resource = self.locals.tmp("resource");
+                        uwrite!(
+                            self.src,
+                            "
+                            val {resource} = {ty}.repTable.get({rep}({op}))
+                            {resource}.handle = {op}
+                            "
+                        );
+                        results.push(resource);
+                    }
+                    (Direction::Export, Handle::Borrow(_)) => {
+                        results.push(format!("{ty}.repTable.get({op})"));
+                    }
+                }
+            }
+
+            Instruction::RecordLower { record, .. } => {
+                let op = &operands[0];
+                for field in record.fields.iter() {
+                    results.push(format!("({op}).{}", field.name.to_kotlin_ident()));
+                }
+            }
+            Instruction::RecordLift { ty, .. } | Instruction::TupleLift { ty, .. } => {
+                if operands.is_empty() && matches!(inst, Instruction::TupleLift { .. }) {
+                    results.push("Unit".into());
+                } else {
+                    let ops = operands.join(", ");
+                    results.push(format!("{}({ops})", self.gen.type_name(&Type::Id(*ty))));
+                }
+            }
+
+            Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. }
+            | Instruction::Flush { .. } => {
+                unreachable!("async functions are never requested by this generator")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+
+            Instruction::TupleLower { tuple, .. } => {
+                let op = &operands[0];
+                for i in 0..tuple.types.len() {
+                    results.push(format!("({op}).f{i}"));
+                }
+            }
+
+            Instruction::VariantPayloadName => {
+                let payload = self.locals.tmp("payload");
+                results.push(payload.clone());
+                self.payloads.push(payload);
+            }
+
+            Instruction::VariantLower {
+                variant,
+                ty,
+                results: lowered_types,
+                ..
+            } => {
+                let cases = self.variant_cases(*ty, variant);
+                self.lower_variant(&cases, lowered_types, &operands[0], results)
+            }
+
+            Instruction::VariantLift { variant, ty, .. } => {
+                let cases = self.variant_cases(*ty, variant);
+                self.lift_variant(&Type::Id(*ty), &cases, &operands[0], results)
+            }
+
+            Instruction::OptionLower {
+                results: lowered_types,
+                payload,
+                ..
+            } => {
+                let some = self.blocks.pop().unwrap();
+                let none = self.blocks.pop().unwrap();
+                let some_payload = self.payloads.pop().unwrap();
+                let _none_payload = self.payloads.pop().unwrap();
+
+                let lowered = lowered_types
+                    .iter()
+                    .map(|_| self.locals.tmp("lowered"))
+                    .collect::<Vec<_>>();
+
+                results.extend(lowered.iter().cloned());
+
+                let declarations = lowered
+                    .iter()
+                    .zip(lowered_types.iter())
+                    .map(|(lowered, ty)| {
+                        format!("var {lowered}: {} = {}", wasm_type(*ty), zero(*ty))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                let option = self.locals.tmp("option");
+
+                let assignments = |results: &[String]| {
+                    lowered
+                        .iter()
+                        .zip(results)
+                        .map(|(lowered, result)| format!("{lowered} = {result}\n"))
+                        .collect::<Vec<_>>()
+                        .concat()
+                };
+
+                let value = if self.gen.is_option(payload) {
+                    format!("{option}.value")
+                } else {
+                    option.clone()
+                };
+                let none_assignments = assignments(&none.results);
+                let some_assignments = assignments(&some.results);
+                let none = none.body;
+                let some = some.body;
+
+                uwrite!(
+                    self.src,
+                    "
+                    {declarations}
+
+                    val {option} = {}
+                    if ({option} == null) {{
+                        {none}
+                        {none_assignments}
+                    }} else {{
+                        val {some_payload} = {value}
+                        {some}
+                        {some_assignments}
+                    }}
+                    ",
+                    operands[0]
+                );
+            }
+
+            Instruction::OptionLift { payload, ty } => {
+                let some = self.blocks.pop().unwrap();
+                let _none = self.blocks.pop().unwrap();
+
+                let ty = self.gen.type_name(&Type::Id(*ty));
+                let lifted = self.locals.tmp("lifted");
+                let op = &operands[0];
+
+                let payload = if self.gen.is_option(payload) {
+                    format!("{}Some({})", self.gen.gen.qualifier(), some.results[0])
+                } else {
+                    some.results[0].clone()
+                };
+
+                let some = some.body;
+
+                uwrite!(
+                    self.src,
+                    r#"
+                    val {lifted}: {ty} = when ({op}) {{
+                        0 -> null
+
+                        1 -> {{
+                            {some}
+                            {payload}
+                        }}
+
+                        else -> throw AssertionError("invalid discriminant: " + ({op}))
+                    }}
+                    "#
+                );
+
+                results.push(lifted);
+            }
+
+            Instruction::ResultLower {
+                results: lowered_types,
+                result,
+                ..
+            } => {
+                let cases = self.result_cases(result);
+                self.lower_variant(&cases, lowered_types, &operands[0], results)
+            }
+
+            Instruction::ResultLift { result, ty } => {
+                let cases = self.result_cases(result);
+                self.lift_variant(&Type::Id(*ty), &cases, &operands[0], results)
+            }
+
+            Instruction::EnumLower { .. } => results.push(format!("({}).ordinal", operands[0])),
+
+            Instruction::EnumLift { ty, .. } => results.push(format!(
+                "{}.values()[{}]",
+                self.gen.type_name(&Type::Id(*ty)),
+                operands[0]
+            )),
+
+            Instruction::ListCanonLower { element, realloc } => {
+                let op = &operands[0];
+                let (size, ty, _) = list_element_info(element);
+
+                // Note that we can only reliably use `Address.ofData` for elements with alignment <= 4 because as
+                // of this writing TeaVM does not guarantee 64 bit items are aligned on 8 byte boundaries.
+                if realloc.is_none() && size <= 4 {
+                    results.push(format!("Address.ofData({op}).toInt()"));
+                } else {
+                    let address = self.locals.tmp("address");
+
+                    uwrite!(
+                        self.src,
+                        "
+                        val {address} = Memory.malloc({size} * ({op}).size, {size})
+                        Memory.put{ty}s({address}, {op}, 0, ({op}).size)
+                        "
+                    );
+
+                    if realloc.is_none() {
+                        self.cleanup.push(Cleanup {
+                            address: format!("{address}.toInt()"),
+                            size: format!("{size} * ({op}).size"),
+                            align: size,
+                        });
+                    }
+
+                    results.push(format!("{address}.toInt()"));
+                }
+                results.push(format!("({op}).size"));
+            }
+
+            Instruction::ListCanonLift { element, .. } => {
+                let (size, ty, array_ty) = list_element_info(element);
+                let array = self.locals.tmp("array");
+                let address = &operands[0];
+                let length = &operands[1];
+
+                uwrite!(
+                    self.src,
+                    "
+                    val {array} = {array_ty}({length})
+                    Memory.get{ty}s(Address.fromInt({address}), {array}, 0, {array}.size)
+                    Memory.free(Address.fromInt({address}), {size} * ({length}), {size})
+                    "
+                );
+
+                results.push(array);
+            }
+
+            Instruction::StringLower { realloc } => {
+                let op = &operands[0];
+                let bytes = self.locals.tmp("bytes");
+                uwriteln!(self.src, "val {bytes} = ({op}).toByteArray(Charsets.UTF_8)");
+
+                if realloc.is_none() {
+                    results.push(format!("Address.ofData({bytes}).toInt()"));
+                } else {
+                    let address = self.locals.tmp("address");
+
+                    uwrite!(
+                        self.src,
+                        "
+                        val {address} = Memory.malloc({bytes}.size, 1)
+                        Memory.putBytes({address}, {bytes}, 0, {bytes}.size)
+                        "
+                    );
+
+                    results.push(format!("{address}.toInt()"));
+                }
+                results.push(format!("{bytes}.size"));
+            }
+
+            Instruction::StringLift { .. } => {
+                let bytes = self.locals.tmp("bytes");
+                let address = &operands[0];
+                let length = &operands[1];
+
+                uwrite!(
+                    self.src,
+                    "
+                    val {bytes} = ByteArray({length})
+                    Memory.getBytes(Address.fromInt({address}), {bytes}, 0, {length})
+                    Memory.free(Address.fromInt({address}), {length}, 1)
+                    "
+                );
+
+                results.push(format!("String({bytes}, Charsets.UTF_8)"));
+            }
+
+            Instruction::ListLower { element, realloc } => {
+                let Block {
+                    body,
+                    results: block_results,
+                    element: block_element,
+                    base,
+                } = self.blocks.pop().unwrap();
+                assert!(block_results.is_empty());
+
+                let op = &operands[0];
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.gen.sizes.align(element).align_wasm32();
+                let address = self.locals.tmp("address");
+                let index = self.locals.tmp("index");
+
+                uwrite!(
+                    self.src,
+                    "
+                    val {address} = Memory.malloc(({op}).size * {size}, {align}).toInt()
+                    for ({index} in 0 until ({op}).size) {{
+                        val {block_element} = ({op})[{index}]
+                        val {base} = {address} + ({index} * {size})
+                        {body}
+                    }}
+                    "
+                );
+
+                if realloc.is_none() {
+                    self.cleanup.push(Cleanup {
+                        address: address.clone(),
+                        size: format!("({op}).size * {size}"),
+                        align,
+                    });
+                }
+
+                results.push(address);
+                results.push(format!("({op}).size"));
+            }
+
+            Instruction::ListLift { element, .. } => {
+                let Block {
+                    body,
+                    results: block_results,
+                    base,
+                    ..
+                } = self.blocks.pop().unwrap();
+                let address = &operands[0];
+                let length = &operands[1];
+                let array = self.locals.tmp("array");
+                let ty = self.gen.type_name(element);
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.gen.sizes.align(element).align_wasm32();
+                let index = self.locals.tmp("index");
+
+                let result = match &block_results[..] {
+                    [result] => result,
+                    _ => unreachable!("list elements are lifted to a single value"),
+                };
+
+                uwrite!(
+                    self.src,
+                    "
+                    val {array} = ArrayList<{ty}>({length})
+                    for ({index} in 0 until ({length})) {{
+                        val {base} = ({address}) + ({index} * {size})
+                        {body}
+                        {array}.add({result})
+                    }}
+                    Memory.free(Address.fromInt({address}), ({length}) * {size}, {align})
+                    "
+                );
+
+                results.push(array);
+            }
+
+            Instruction::IterElem { .. } => {
+                results.push(self.block_storage.last().unwrap().element.clone())
+            }
+
+            Instruction::IterBasePointer => {
+                results.push(self.block_storage.last().unwrap().base.clone())
+            }
+
+            Instruction::CallWasm { sig, .. } => {
+                let assignment = match &sig.results[..] {
+                    [_] => {
+                        let result = self.locals.tmp("result");
+                        let assignment = format!("val {result} = ");
+                        results.push(result);
+                        assignment
+                    }
+
+                    [] => String::new(),
+
+                    _ => unreachable!(),
+                };
+
+                let func_name = self.func_name.to_upper_camel_case();
+
+                let operands = operands.join(", ");
+
+                uwriteln!(self.src, "{assignment}wasmImport{func_name}({operands})");
+            }
+
+            Instruction::CallInterface { func, .. } => {
+                let (assignment, destructure) = match func.results.len() {
+                    0 => (String::new(), String::new()),
+                    1 => {
+                        let ty = self
+                            .gen
+                            .type_name(func.results.iter_types().next().unwrap());
+                        let result = self.locals.tmp("result");
+                        let assignment = format!("val {result}: {ty} = ");
+                        results.push(result);
+                        (assignment, String::new())
+                    }
+                    _ => {
+                        let result = self.locals.tmp("result");
+                        let assignment = format!("val {result} = ");
+
+                        let destructure = (0..func.results.len())
+                            .map(|index| {
+                                let my_result = self.locals.tmp("result");
+                                let assignment = format!("val {my_result} = {result}.f{index}");
+                                results.push(my_result);
+                                assignment
+                            })
+                            .collect::<Vec<_>>()
+                            .join("\n");
+
+                        (assignment, destructure)
+                    }
+                };
+
+                let module = self.gen.name;
+                let name = func_name(func);
+
+                let call = match &func.kind {
+                    FunctionKind::Freestanding => {
+                        format!("{module}Impl.{name}({})", operands.join(", "))
+                    }
+                    FunctionKind::Static(id) => {
+                        let impl_name = self.gen.gen.all_resources[id].export_impl_name();
+                        format!("{impl_name}.{name}({})", operands.join(", "))
+                    }
+                    FunctionKind::Method(_) => {
+                        let target = operands.remove(0);
+                        format!("({target}).{name}({})", operands.join(", "))
+                    }
+                    FunctionKind::Constructor(id) => {
+                        let impl_name = self.gen.gen.all_resources[id].export_impl_name();
+                        format!("{impl_name}({})", operands.join(", "))
+                    }
+                };
+
+                let drops = self
+                    .resource_drops
+                    .iter()
+                    .map(|(_, resource)| format!("{resource}?.close()\n"))
+                    .collect::<String>();
+
+                uwrite!(
+                    self.src,
+                    "
+                    {assignment}{call}
+                    {drops}
+                    {destructure}
+                    "
+                );
+            }
+
+            Instruction::Return { amt, .. } => {
+                for Cleanup {
+                    address,
+                    size,
+                    align,
+                } in &self.cleanup
+                {
+                    uwriteln!(
+                        self.src,
+                        "Memory.free(Address.fromInt({address}), {size}, {align})"
+                    );
+                }
+
+                if self.needs_cleanup_list {
+                    uwrite!(
+                        self.src,
+                        "
+                        for (cleanup in cleanupList) {{
+                            Memory.free(Address.fromInt(cleanup.address), cleanup.size, cleanup.align)
+                        }}
+                        "
+                    );
+                }
+
+                match *amt {
+                    0 => (),
+                    // Imported constructors initialize `this` rather than
+                    // returning a value.
+                    1 if self.gen.direction == Direction::Import
+                        && matches!(self.kind, FunctionKind::Constructor(_)) => {}
+                    1 => uwriteln!(self.src, "return {}", operands[0]),
+                    count => {
+                        let results = operands.join(", ");
+                        uwriteln!(
+                            self.src,
+                            "return {}Tuple{count}({results})",
+                            self.gen.gen.qualifier()
+                        )
+                    }
+                }
+            }
+
+            Instruction::I32Load { offset }
+            | Instruction::PointerLoad { offset }
+            | Instruction::LengthLoad { offset } => results.push(format!(
+                "Address.fromInt(({}) + {offset}).getInt()",
+                operands[0]
+            )),
+
+            Instruction::I32Load8U { offset } => results.push(format!(
+                "(Address.fromInt(({}) + {offset}).getByte().toInt() and 0xFF)",
+                operands[0]
+            )),
+
+            Instruction::I32Load8S { offset } => results.push(format!(
+                "Address.fromInt(({}) + {offset}).getByte().toInt()",
+                operands[0]
+            )),
+
+            Instruction::I32Load16U { offset } => results.push(format!(
+                "(Address.fromInt(({}) + {offset}).getShort().toInt() and 0xFFFF)",
+                operands[0]
+            )),
+
+            Instruction::I32Load16S { offset } => results.push(format!(
+                "Address.fromInt(({}) + {offset}).getShort().toInt()",
+                operands[0]
+            )),
+
+            Instruction::I64Load { offset } => results.push(format!(
+                "Address.fromInt(({}) + {offset}).getLong()",
+                operands[0]
+            )),
+
+            Instruction::F32Load { offset } => results.push(format!(
+                "Address.fromInt(({}) + {offset}).getFloat()",
+                operands[0]
+            )),
+
+            Instruction::F64Load { offset } => results.push(format!(
+                "Address.fromInt(({}) + {offset}).getDouble()",
+                operands[0]
+            )),
+
+            Instruction::I32Store { offset }
+            | Instruction::PointerStore { offset }
+            | Instruction::LengthStore { offset } => uwriteln!(
+                self.src,
+                "Address.fromInt(({}) + {offset}).putInt({})",
+                operands[1],
+                operands[0]
+            ),
+
+            Instruction::I32Store8 { offset } => uwriteln!(
+                self.src,
+                "Address.fromInt(({}) + {offset}).putByte(({}).toByte())",
+                operands[1],
+                operands[0]
+            ),
+
+            Instruction::I32Store16 { offset } => uwriteln!(
+                self.src,
+                "Address.fromInt(({}) + {offset}).putShort(({}).toShort())",
+                operands[1],
+                operands[0]
+            ),
+
+            Instruction::I64Store { offset } => uwriteln!(
+                self.src,
+                "Address.fromInt(({}) + {offset}).putLong({})",
+                operands[1],
+                operands[0]
+            ),
+
+            Instruction::F32Store { offset } => uwriteln!(
+                self.src,
+                "Address.fromInt(({}) + {offset}).putFloat({})",
+                operands[1],
+                operands[0]
+            ),
+
+            Instruction::F64Store { offset } => uwriteln!(
+                self.src,
+                "Address.fromInt(({}) + {offset}).putDouble({})",
+                operands[1],
+                operands[0]
+            ),
+
+            Instruction::Malloc { size, align, .. } => {
+                let address = self.locals.tmp("address");
+                uwriteln!(
+                    self.src,
+                    "val {address} = Memory.malloc({size}, {align}).toInt()"
+                );
+                results.push(address);
+            }
+
+            Instruction::GuestDeallocate { size, align } => {
+                uwriteln!(
+                    self.src,
+                    "Memory.free(Address.fromInt({}), {size}, {align})",
+                    operands[0]
+                )
+            }
+
+            Instruction::GuestDeallocateString => uwriteln!(
+                self.src,
+                "Memory.free(Address.fromInt({}), {}, 1)",
+                operands[0],
+                operands[1]
+            ),
+
+            Instruction::GuestDeallocateVariant { blocks } => {
+                let cases = self
+                    .blocks
+                    .drain(self.blocks.len() - blocks..)
+                    .enumerate()
+                    .map(|(i, Block { body, results, .. })| {
+                        assert!(results.is_empty());
+
+                        format!(
+                            "{i} -> {{
+                                 {body}
+                             }}"
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                let op = &operands[0];
+
+                uwrite!(
+                    self.src,
+                    "
+                    when ({op}) {{
+                        {cases}
+                    }}
+                    "
+                );
+            }
+
+            Instruction::GuestDeallocateList { element } => {
+                let Block {
+                    body,
+                    results,
+                    base,
+                    ..
+                } = self.blocks.pop().unwrap();
+                assert!(results.is_empty());
+
+                let address = &operands[0];
+                let length = &operands[1];
+
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.gen.sizes.align(element).align_wasm32();
+
+                if !body.trim().is_empty() {
+                    let index = self.locals.tmp("index");
+
+                    uwrite!(
+                        self.src,
+                        "
+                        for ({index} in 0 until ({length})) {{
+                            val {base} = ({address}) + ({index} * {size})
+                            {body}
+                        }}
+                        "
+                    );
+                }
+
+                uwriteln!(
+                    self.src,
+                    "Memory.free(Address.fromInt({address}), ({length}) * {size}, {align})"
+                );
+            }
+        }
+    }
+
+    fn return_pointer(&mut self, size: usize, align: usize) -> String {
+        self.gen.gen.return_area_size = self.gen.gen.return_area_size.max(size);
+        self.gen.gen.return_area_align = self.gen.gen.return_area_align.max(align);
+        format!("{}RETURN_AREA", self.gen.gen.qualifier())
+    }
+
+    fn push_block(&mut self) {
+        self.block_storage.push(BlockStorage {
+            body: mem::take(&mut self.src),
+            element: self.locals.tmp("element"),
+            base: self.locals.tmp("base"),
+            cleanup: mem::take(&mut self.cleanup),
+        });
+    }
+
+    fn finish_block(&mut self, operands: &mut Vec<String>) {
+        let BlockStorage {
+            body,
+            element,
+            base,
+            cleanup,
+        } = self.block_storage.pop().unwrap();
+
+        if !self.cleanup.is_empty() {
+            self.needs_cleanup_list = true;
+
+            for Cleanup {
+                address,
+                size,
+                align,
+            } in &self.cleanup
+            {
+                uwriteln!(
+                    self.src,
+                    "cleanupList.add({}Cleanup({address}, {size}, {align}))",
+                    self.gen.gen.qualifier()
+                );
+            }
+        }
+
+        self.cleanup = cleanup;
+
+        self.blocks.push(Block {
+            body: mem::replace(&mut self.src, body),
+            results: mem::take(operands),
+            element,
+            base,
+        });
+    }
+
+    fn sizes(&self) -> &SizeAlign {
+        &self.gen.gen.sizes
+    }
+
+    fn is_list_canonical(&self, _resolve: &Resolve, element: &Type) -> bool {
+        is_primitive(element)
+    }
+}
+
+fn perform_cast(op: &str, cast: &Bitcast) -> String {
+    match cast {
+        Bitcast::I32ToF32 => format!("Float.fromBits({op})"),
+        Bitcast::I64ToF32 => format!("Float.fromBits(({op}).toInt())"),
+        Bitcast::F32ToI32 => format!("({op}).toRawBits()"),
+        Bitcast::F32ToI64 => format!("({op}).toRawBits().toLong()"),
+        Bitcast::I64ToF64 => format!("Double.fromBits({op})"),
+        Bitcast::F64ToI64 => format!("({op}).toRawBits()"),
+        Bitcast::I32ToI64 => format!("({op}).toLong()"),
+        Bitcast::I64ToI32 => format!("({op}).toInt()"),
+        Bitcast::I64ToP64 => op.to_owned(),
+        Bitcast::P64ToI64 => op.to_owned(),
+        Bitcast::LToI64 | Bitcast::PToP64 => format!("({op}).toLong()"),
+        Bitcast::I64ToL | Bitcast::P64ToP => format!("({op}).toInt()"),
+        Bitcast::I32ToP
+        | Bitcast::PToI32
+        | Bitcast::I32ToL
+        | Bitcast::LToI32
+        | Bitcast::LToP
+        | Bitcast::PToL
+        | Bitcast::None => op.to_owned(),
+
+        Bitcast::Sequence(sequence) => {
+            let [first, second] = &**sequence;
+            perform_cast(&perform_cast(op, first), second)
+        }
+    }
+}
+
+fn zero(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 => "0",
+        WasmType::I64 => "0L",
+        WasmType::F32 => "0.0f",
+        WasmType::F64 => "0.0",
+        WasmType::Pointer => "0",
+        WasmType::PointerOrI64 => "0L",
+        WasmType::Length => "0",
+    }
+}
@@ -0,0 +1,1525 @@
+//! Kotlin bindings generator for guests compiled to JVM bytecode with
+//! `kotlinc` and then to wasm with TeaVM, through TeaVM's `@Import` and
+//! `@Export` annotations.
+//!
+//! Kotlin/Wasm isn't targeted as it only exposes linear memory through
+//! allocations scoped to a block, while the canonical ABI needs memory which
+//! outlives a call, such as the results of exports freed in `post-return`.
+//! TeaVM instead provides `Address` and `Memory.malloc`.
+
+mod bindgen;
+
+use anyhow::Result;
+use bindgen::FunctionBindgen;
+use heck::{ToLowerCamelCase, ToShoutySnakeCase, ToUpperCamelCase};
+use indexmap::IndexMap;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write,
+    iter, mem,
+    ops::Deref,
+};
+use wit_bindgen_core::{
+    abi::{self, AbiVariant, LiftLower, WasmType},
+    uwrite, uwriteln,
+    wit_parser::{
+        Docs, Enum, Flags, FlagsRepr, Function, FunctionKind, Handle, Int, InterfaceId, Record,
+        Resolve, Result_, SizeAlign, Tuple, Type, TypeDefKind, TypeId, TypeOwner, Variant, WorldId,
+        WorldKey,
+    },
+    Direction, Files, InterfaceGenerator as _, Source, WorldGenerator,
+};
+
+const IMPORTS: &str = "\
+import org.teavm.interop.Address
+import org.teavm.interop.Export
+import org.teavm.interop.Import
+import org.teavm.interop.Memory\
+";
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct Opts {
+    /// Whether or not to generate a stub object for exported functions
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub generate_stub: bool,
+}
+
+impl Opts {
+    pub fn build(&self) -> Box<dyn WorldGenerator> {
+        Box::new(TeaVmKotlin {
+            opts: self.clone(),
+            ..TeaVmKotlin::default()
+        })
+    }
+}
+
+struct InterfaceFragment {
+    src: String,
+    stub: String,
+}
+
+struct ResourceInfo {
+    /// Name of the object the resource is nested in.
+    module: String,
+    name: String,
+    docs: Docs,
+    direction: Direction,
+}
+
+impl ResourceInfo {
+    /// Name of the stub class implementing an exported resource.
+    fn export_impl_name(&self) -> String {
+        format!("{}Impl.{}", self.module, self.name.to_upper_camel_case())
+    }
+
+    /// Name of the `[resource-{kind}]` intrinsic for the resource, which is
+    /// declared in the object the resource is nested in.
+    fn intrinsic_name(&self, kind: &str) -> String {
+        format!(
+            "{}.wasmImportResource{kind}{}",
+            self.module,
+            self.name.to_upper_camel_case()
+        )
+    }
+}
+
+#[derive(Default)]
+pub struct TeaVmKotlin {
+    opts: Opts,
+    name: String,
+    return_area_size: usize,
+    return_area_align: usize,
+    tuple_counts: BTreeSet<usize>,
+    needs_cleanup: bool,
+    needs_result: bool,
+    needs_some: bool,
+    needs_rep_table: bool,
+    interface_fragments: HashMap<String, Vec<InterfaceFragment>>,
+    world_fragments: Vec<InterfaceFragment>,
+    sizes: SizeAlign,
+    interface_names: HashMap<InterfaceId, String>,
+    all_resources: HashMap<TypeId, ResourceInfo>,
+    world_resources: Vec<TypeId>,
+    import_funcs_called: bool,
+}
+
+impl TeaVmKotlin {
+    fn qualifier(&self) -> String {
+        format!("{}.", self.name)
+    }
+
+    fn interface<'a>(
+        &'a mut self,
+        resolve: &'a Resolve,
+        name: &'a str,
+        direction: Direction,
+    ) -> InterfaceGenerator<'a> {
+        InterfaceGenerator {
+            src: String::new(),
+            statics: String::new(),
+            companion: String::new(),
+            stub: String::new(),
+            stub_companion: String::new(),
+            gen: self,
+            resolve,
+            name,
+            direction,
+        }
+    }
+}
+
+impl WorldGenerator for TeaVmKotlin {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        wit_bindgen_core::reject_futures_and_streams(resolve, world, "TeaVM Kotlin")
+    }
+
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+        self.name = world_name(resolve, world);
+        self.sizes.fill(resolve);
+    }
+
+    fn import_interface(
+        &mut self,
+        resolve: &Resolve,
+        key: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let name = interface_name(resolve, key, Direction::Import);
+        self.interface_names.insert(id, name.clone());
+        let mut gen = self.interface(resolve, &name, Direction::Import);
+        gen.types(id);
+
+        for (resource, funcs) in by_resource(
+            resolve.interfaces[id].functions.values(),
+            interface_resources(resolve, id),
+        ) {
+            if let Some(resource) = resource {
+                gen.start_resource(resource, Some(key), &funcs);
+            }
+
+            for func in funcs {
+                gen.import(&resolve.name_world_key(key), func);
+            }
+
+            if resource.is_some() {
+                gen.end_resource();
+            }
+        }
+
+        gen.add_interface_fragment();
+
+        Ok(())
+    }
+
+    fn import_funcs(
+        &mut self,
+        resolve: &Resolve,
+        world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) {
+        self.import_funcs_called = true;
+
+        let name = world_name(resolve, world);
+        let world_resources = self.world_resources.clone();
+        let mut gen = self.interface(resolve, &name, Direction::Import);
+
+        for (resource, funcs) in by_resource(funcs.iter().map(|(_, func)| *func), world_resources) {
+            if let Some(resource) = resource {
+                gen.start_resource(resource, None, &funcs);
+            }
+
+            for func in funcs {
+                gen.import("$root", func);
+            }
+
+            if resource.is_some() {
+                gen.end_resource();
+            }
+        }
+
+        gen.add_world_fragment();
+    }
+
+    fn export_interface(
+        &mut self,
+        resolve: &Resolve,
+        key: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let name = interface_name(resolve, key, Direction::Export);
+        self.interface_names.insert(id, name.clone());
+        let mut gen = self.interface(resolve, &name, Direction::Export);
+        gen.types(id);
+
+        for (resource, funcs) in by_resource(
+            resolve.interfaces[id].functions.values(),
+            interface_resources(resolve, id),
+        ) {
+            if let Some(resource) = resource {
+                gen.start_resource(resource, Some(key), &funcs);
+            }
+
+            for func in funcs {
+                gen.export(Some(&resolve.name_world_key(key)), func);
+            }
+
+            if resource.is_some() {
+                gen.end_resource();
+            }
+        }
+
+        gen.add_interface_fragment();
+        Ok(())
+    }
+
+    fn export_funcs(
+        &mut self,
+        resolve: &Resolve,
+        world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) -> Result<()> {
+        let name = world_name(resolve, world);
+        let mut gen = self.interface(resolve, &name, Direction::Export);
+
+        for (_, func) in funcs {
+            gen.export(None, func);
+        }
+
+        gen.add_world_fragment();
+        Ok(())
+    }
+
+    fn import_types(
+        &mut self,
+        resolve: &Resolve,
+        world: WorldId,
+        types: &[(&str, TypeId)],
+        _files: &mut Files,
+    ) {
+        let name = world_name(resolve, world);
+        let mut gen = self.interface(resolve, &name, Direction::Import);
+
+        for (ty_name, ty) in types {
+            gen.define_type(ty_name, *ty);
+        }
+
+        gen.add_world_fragment();
+
+        self.world_resources.extend(
+            types
+                .iter()
+                .map(|(_, ty)| *ty)
+                .filter(|ty| matches!(resolve.types[*ty].kind, TypeDefKind::Resource)),
+        );
+    }
+
+    fn finish_imports(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) {
+        // Resources imported by the world itself get their classes from
+        // `import_funcs`, which isn't called if the world imports no functions.
+        if !self.import_funcs_called && !self.world_resources.is_empty() {
+            self.import_funcs(resolve, world, &[], files);
+        }
+    }
+
+    fn finish(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
+        let name = world_name(resolve, id);
+        let (package, name) = split_qualified_name(&name);
+
+        let mut src = Source::default();
+        let version = env!("CARGO_PKG_VERSION");
+        wit_bindgen_core::generated_preamble(&mut src, version);
+
+        uwrite!(
+            src,
+            "package {package}
+
+             {IMPORTS}
+             import org.teavm.interop.CustomSection
+
+             object {name} {{
+            "
+        );
+
+        src.push_str(
+            &self
+                .world_fragments
+                .iter()
+                .map(|f| f.src.deref())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+
+        let mut producers = wasm_metadata::Producers::empty();
+        producers.add(
+            "processed-by",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+        );
+
+        let component_type = wit_component::metadata::encode(
+            resolve,
+            id,
+            wit_component::StringEncoding::UTF8,
+            Some(&producers),
+        )
+        .unwrap();
+
+        let component_type = component_type
+            .into_iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<_>>()
+            .concat();
+
+        uwriteln!(
+            src,
+            r#"
+            @field:CustomSection(name = "component-type:{name}")
+            private const val __WIT_BINDGEN_COMPONENT_TYPE: String = "{component_type}"
+            "#
+        );
+
+        for &count in &self.tuple_counts {
+            let type_params = (0..count)
+                .map(|index| format!("out T{index}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let fields = (0..count)
+                .map(|index| format!("val f{index}: T{index}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            uwriteln!(
+                src,
+                "
+                data class Tuple{count}<{type_params}>({fields})
+                "
+            );
+        }
+
+        if self.needs_result {
+            src.push_str(
+                "
+                sealed class Result<out Ok, out Err> {
+                    data class Ok<out Ok>(val value: Ok) : Result<Ok, Nothing>()
+
+                    data class Err<out Err>(val value: Err) : Result<Nothing, Err>()
+                }
+                ",
+            )
+        }
+
+        // Nested options can't both be represented with `null`, so the outer
+        // one wraps its payload instead.
+        if self.needs_some {
+            src.push_str(
+                "
+                data class Some<out T>(val value: T)
+                ",
+            )
+        }
+
+        if self.needs_cleanup {
+            src.push_str(
+                "
+                class Cleanup(val address: Int, val size: Int, val align: Int)
+                ",
+            );
+        }
+
+        if self.needs_rep_table {
+            src.push_str(
+                r#"
+                class RepTable<T : Any> {
+                    private val list = ArrayList<Any>()
+                    private var firstVacant = -1
+
+                    private class Vacant(val next: Int)
+
+                    fun add(value: T): Int {
+                        val rep: Int
+                        if (firstVacant >= 0) {
+                            rep = firstVacant
+                            firstVacant = (list[rep] as Vacant).next
+                            list[rep] = value
+                        } else {
+                            rep = list.size
+                            list.add(value)
+                        }
+                        return rep
+                    }
+
+                    @Suppress("UNCHECKED_CAST")
+                    fun get(rep: Int): T {
+                        val value = list[rep]
+                        if (value is Vacant) {
+                            throw RuntimeException("invalid rep " + rep)
+                        }
+                        return value as T
+                    }
+
+                    fun remove(rep: Int): T {
+                        val value = get(rep)
+                        list[rep] = Vacant(firstVacant)
+                        firstVacant = rep
+                        return value
+                    }
+                }
+                "#,
+            );
+        }
+
+        if self.return_area_align > 0 {
+            let size = self.return_area_size;
+            let align = self.return_area_align;
+
+            uwriteln!(
+                src,
+                "@JvmField
+                 val RETURN_AREA: Int = Memory.malloc({size}, {align}).toInt()",
+            );
+        }
+
+        src.push_str("}\n");
+
+        let directory = package.replace('.', "/");
+        files.push(&format!("{directory}/{name}.kt"), indent(&src).as_bytes());
+
+        let generate_stub =
+            |package: &str, name, fragments: &[InterfaceFragment], files: &mut Files| {
+                let b = fragments
+                    .iter()
+                    .map(|f| f.stub.deref())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                let mut body = Source::default();
+                wit_bindgen_core::generated_preamble(&mut body, version);
+                uwriteln!(
+                    &mut body,
+                    "package {package}
+
+                 object {name} {{
+                     {b}
+                 }}
+                "
+                );
+
+                let directory = package.replace('.', "/");
+                files.push(&format!("{directory}/{name}.kt"), indent(&body).as_bytes());
+            };
+
+        if self.opts.generate_stub {
+            generate_stub(
+                &package,
+                format!("{name}Impl"),
+                &self.world_fragments,
+                files,
+            );
+        }
+
+        for (name, fragments) in &self.interface_fragments {
+            let (package, name) = split_qualified_name(name);
+
+            let b = fragments
+                .iter()
+                .map(|f| f.src.deref())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            let mut body = Source::default();
+            wit_bindgen_core::generated_preamble(&mut body, version);
+            uwriteln!(
+                &mut body,
+                "package {package}
+
+                 {IMPORTS}
+
+                 object {name} {{
+                     {b}
+                 }}
+                "
+            );
+
+            let directory = package.replace('.', "/");
+            files.push(&format!("{directory}/{name}.kt"), indent(&body).as_bytes());
+
+            if self.opts.generate_stub {
+                generate_stub(&package, format!("{name}Impl"), fragments, files);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct InterfaceGenerator<'a> {
+    src: String,
+    /// Members which must be declared in the interface's object itself rather
+    /// than in a resource class, such as the `@Import` and `@Export`
+    /// functions, which TeaVM requires to be static.
+    statics: String,
+    /// Static functions of the imported resource being generated.
+    companion: String,
+    stub: String,
+    /// Static functions of the exported resource whose stub is being
+    /// generated.
+    stub_companion: String,
+    gen: &'a mut TeaVmKotlin,
+    resolve: &'a Resolve,
+    name: &'a str,
+    direction: Direction,
+}
+
+impl InterfaceGenerator<'_> {
+    fn qualifier(&self, when: bool, owner: &TypeOwner) -> String {
+        if let TypeOwner::Interface(id) = owner {
+            if let Some(name) = self.gen.interface_names.get(id) {
+                if name != self.name {
+                    return format!("{name}.");
+                }
+            }
+        }
+
+        if when {
+            format!("{}.", self.name)
+        } else {
+            String::new()
+        }
+    }
+
+    fn add_interface_fragment(self) {
+        self.gen
+            .interface_fragments
+            .entry(self.name.to_owned())
+            .or_default()
+            .push(InterfaceFragment {
+                src: self.src + &self.statics,
+                stub: self.stub,
+            });
+    }
+
+    fn add_world_fragment(self) {
+        self.gen.world_fragments.push(InterfaceFragment {
+            src: self.src + &self.statics,
+            stub: self.stub,
+        });
+    }
+
+    fn import(&mut self, module: &str, func: &Function) {
+        let mut bindgen = FunctionBindgen::new(
+            self,
+            &func.name,
+            &func.kind,
+            func.params
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| {
+                    if i == 0 && matches!(&func.kind, FunctionKind::Method(_)) {
+                        "this".to_owned()
+                    } else {
+                        name.to_kotlin_ident()
+                    }
+                })
+                .collect(),
+        );
+
+        abi::call(
+            bindgen.gen.resolve,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            func,
+            &mut bindgen,
+            false,
+        );
+
+        let src = bindgen.src;
+
+        let cleanup_list = if bindgen.needs_cleanup_list {
+            self.gen.needs_cleanup = true;
+
+            format!(
+                "val cleanupList = ArrayList<{}Cleanup>()\n",
+                self.gen.qualifier()
+            )
+        } else {
+            String::new()
+        };
+
+        let name = &func.name;
+
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestImport, func);
+
+        let result_type = match &sig.results[..] {
+            [] => String::new(),
+            [result] => format!(": {}", wasm_type(*result)),
+            _ => unreachable!(),
+        };
+
+        let camel_name = func.name.to_upper_camel_case();
+
+        let params = sig
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let ty = wasm_type(*param);
+                format!("p{i}: {ty}")
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        uwrite!(
+            self.statics,
+            r#"
+            @JvmStatic
+            @Import(name = "{name}", module = "{module}")
+            private external fun wasmImport{camel_name}({params}){result_type}
+            "#
+        );
+
+        let sig = match &func.kind {
+            FunctionKind::Constructor(_) => {
+                format!("{} : this(Handle(0))", self.sig_string(func, false))
+            }
+            _ => self.sig_string(func, false),
+        };
+
+        let dst = match &func.kind {
+            FunctionKind::Static(_) => &mut self.companion,
+            _ => &mut self.src,
+        };
+        uwrite!(
+            dst,
+            "
+            {sig} {{
+                {cleanup_list} {src}
+            }}
+            "
+        );
+    }
+
+    fn export(&mut self, interface_name: Option<&str>, func: &Function) {
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
+
+        let export_name = func.core_export_name(interface_name);
+
+        let mut bindgen = FunctionBindgen::new(
+            self,
+            &func.name,
+            &func.kind,
+            (0..sig.params.len()).map(|i| format!("p{i}")).collect(),
+        );
+
+        abi::call(
+            bindgen.gen.resolve,
+            AbiVariant::GuestExport,
+            LiftLower::LiftArgsLowerResults,
+            func,
+            &mut bindgen,
+            false,
+        );
+
+        assert!(!bindgen.needs_cleanup_list);
+
+        // Borrowed handles to imported resources are dropped once the call
+        // returns, so they're declared up front to be in scope for that.
+        let resource_drops = bindgen
+            .resource_drops
+            .iter()
+            .map(|(ty, name)| format!("var {name}: {ty}? = null\n"))
+            .collect::<String>();
+
+        let src = bindgen.src;
+
+        let result_type = match &sig.results[..] {
+            [] => String::new(),
+            [result] => format!(": {}", wasm_type(*result)),
+            _ => unreachable!(),
+        };
+
+        let camel_name = func.name.to_upper_camel_case();
+
+        let params = sig
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let ty = wasm_type(*param);
+                format!("p{i}: {ty}")
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        uwrite!(
+            self.statics,
+            r#"
+            @JvmStatic
+            @Export(name = "{export_name}")
+            private fun wasmExport{camel_name}({params}){result_type} {{
+                {resource_drops} {src}
+            }}
+            "#
+        );
+
+        if let FunctionKind::Method(_) = &func.kind {
+            let sig = self.sig_string(func, false);
+            uwriteln!(self.src, "abstract {sig}");
+        }
+
+        if abi::guest_export_needs_post_return(self.resolve, func) {
+            let params = sig
+                .results
+                .iter()
+                .enumerate()
+                .map(|(i, param)| {
+                    let ty = wasm_type(*param);
+                    format!("p{i}: {ty}")
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let mut bindgen = FunctionBindgen::new(
+                self,
+                "INVALID",
+                &func.kind,
+                (0..sig.results.len()).map(|i| format!("p{i}")).collect(),
+            );
+
+            abi::post_return(bindgen.gen.resolve, func, &mut bindgen);
+
+            let src = bindgen.src;
+
+            uwrite!(
+                self.statics,
+                r#"
+                @JvmStatic
+                @Export(name = "cabi_post_{export_name}")
+                private fun wasmExport{camel_name}PostReturn({params}) {{
+                    {src}
+                }}
+                "#
+            );
+        }
+
+        if self.gen.opts.generate_stub {
+            let sig = self.sig_string(func, true);
+
+            let (sig, dst) = match &func.kind {
+                FunctionKind::Freestanding => (sig, &mut self.stub),
+                FunctionKind::Method(_) => (format!("override {sig}"), &mut self.stub),
+                FunctionKind::Static(_) => (sig, &mut self.stub_companion),
+                FunctionKind::Constructor(_) => (format!("{sig} : super()"), &mut self.stub),
+            };
+
+            uwrite!(
+                dst,
+                r#"
+                {sig} {{
+                    TODO()
+                }}
+                "#
+            );
+        }
+    }
+
+    fn type_name(&mut self, ty: &Type) -> String {
+        self.type_name_with_qualifier(ty, false)
+    }
+
+    fn type_name_with_qualifier(&mut self, ty: &Type, qualifier: bool) -> String {
+        match ty {
+            Type::Bool => "Boolean".into(),
+            Type::U8 | Type::S8 => "Byte".into(),
+            Type::U16 | Type::S16 => "Short".into(),
+            Type::U32 | Type::S32 | Type::Char => "Int".into(),
+            Type::U64 | Type::S64 => "Long".into(),
+            Type::F32 => "Float".into(),
+            Type::F64 => "Double".into(),
+            Type::String => "String".into(),
+            Type::Id(id) => {
+                let ty = &self.resolve.types[*id];
+                match &ty.kind {
+                    TypeDefKind::Type(ty) => self.type_name_with_qualifier(ty, qualifier),
+                    TypeDefKind::List(ty) => {
+                        if is_primitive(ty) {
+                            list_element_info(ty).2.into()
+                        } else {
+                            format!("List<{}>", self.type_name_with_qualifier(ty, qualifier))
+                        }
+                    }
+                    TypeDefKind::Tuple(tuple) => {
+                        let count = tuple.types.len();
+                        if count == 0 {
+                            return "Unit".into();
+                        }
+                        self.gen.tuple_counts.insert(count);
+
+                        let params = tuple
+                            .types
+                            .iter()
+                            .map(|ty| self.type_name_with_qualifier(ty, qualifier))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+
+                        format!("{}Tuple{count}<{params}>", self.gen.qualifier())
+                    }
+                    TypeDefKind::Option(payload) => {
+                        let name = self.type_name_with_qualifier(payload, qualifier);
+                        if self.is_option(payload) {
+                            self.gen.needs_some = true;
+                            format!("{}Some<{name}>?", self.gen.qualifier())
+                        } else {
+                            format!("{name}?")
+                        }
+                    }
+                    TypeDefKind::Result(result) => {
+                        self.gen.needs_result = true;
+                        let mut name = |ty: &Option<Type>| {
+                            ty.as_ref()
+                                .map(|ty| self.type_name_with_qualifier(ty, qualifier))
+                                .unwrap_or_else(|| "Unit".into())
+                        };
+                        let ok = name(&result.ok);
+                        let err = name(&result.err);
+
+                        format!("{}Result<{ok}, {err}>", self.gen.qualifier())
+                    }
+                    TypeDefKind::Handle(Handle::Own(id) | Handle::Borrow(id)) => {
+                        self.type_name_with_qualifier(&Type::Id(*id), qualifier)
+                    }
+                    _ => {
+                        if let Some(name) = &ty.name {
+                            format!(
+                                "{}{}",
+                                self.qualifier(qualifier, &ty.owner),
+                                name.to_upper_camel_case()
+                            )
+                        } else {
+                            unreachable!()
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns whether `ty` is an `option`, and so is already nullable.
+    fn is_option(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Id(id) => match &self.resolve.types[*id].kind {
+                TypeDefKind::Type(ty) => self.is_option(ty),
+                TypeDefKind::Option(_) => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn print_docs(&mut self, docs: &Docs) {
+        if let Some(docs) = &docs.contents {
+            let lines = docs
+                .trim()
+                .lines()
+                .map(|line| format!("* {line}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            uwrite!(
+                self.src,
+                "
+                /**
+                 {lines}
+                 */
+                "
+            )
+        }
+    }
+
+    fn sig_string(&mut self, func: &Function, qualifier: bool) -> String {
+        let name = func_name(func);
+
+        let result_type = match func.results.len() {
+            0 => String::new(),
+            1 => format!(
+                ": {}",
+                self.type_name_with_qualifier(func.results.iter_types().next().unwrap(), qualifier)
+            ),
+            count => {
+                self.gen.tuple_counts.insert(count);
+                format!(
+                    ": {}Tuple{count}<{}>",
+                    self.gen.qualifier(),
+                    func.results
+                        .iter_types()
+                        .map(|ty| self.type_name_with_qualifier(ty, qualifier))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }
+        };
+
+        let skip = if let FunctionKind::Method(_) = &func.kind {
+            1
+        } else {
+            0
+        };
+
+        let params = func
+            .params
+            .iter()
+            .skip(skip)
+            .map(|(name, ty)| {
+                let ty = self.type_name_with_qualifier(ty, qualifier);
+                let name = name.to_kotlin_ident();
+                format!("{name}: {ty}")
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if let FunctionKind::Constructor(_) = &func.kind {
+            format!("constructor({params})")
+        } else {
+            format!("fun {name}({params}){result_type}")
+        }
+    }
+
+    /// Generates a flags class with more members than fit in a `Long`, which
+    /// is backed by an `Int` per 32 flags instead.
+    fn type_flags_words(&mut self, name: &str, flags: &Flags) {
+        let count = flags.repr().count();
+
+        let flags = flags
+            .flags
+            .iter()
+            .enumerate()
+            .map(|(i, flag)| {
+                let flag_name = flag.name.to_shouty_snake_case();
+                let words = (0..count)
+                    .map(|word| {
+                        if word == i / 32 {
+                            format!("1 shl {}", i % 32)
+                        } else {
+                            "0".to_owned()
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("@JvmField val {flag_name} = {name}(intArrayOf({words}))")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        uwrite!(
+            self.src,
+            "
+            class {name}(val value: IntArray) {{
+                infix fun or(other: {name}): {name} {{
+                    return {name}(IntArray({count}) {{ value[it] or other.value[it] }})
+                }}
+
+                infix fun and(other: {name}): {name} {{
+                    return {name}(IntArray({count}) {{ value[it] and other.value[it] }})
+                }}
+
+                operator fun contains(other: {name}): Boolean {{
+                    for (i in 0 until {count}) {{
+                        if ((value[i] and other.value[i]) != other.value[i]) {{
+                            return false
+                        }}
+                    }}
+                    return true
+                }}
+
+                companion object {{
+                    {flags}
+                }}
+            }}
+            "
+        );
+    }
+
+    fn start_resource(&mut self, id: TypeId, key: Option<&WorldKey>, funcs: &[&Function]) {
+        let info = &self.gen.all_resources[&id];
+        let name = info.name.clone();
+        let camel = name.to_upper_camel_case();
+        let docs = info.docs.clone();
+        self.print_docs(&docs);
+
+        match self.direction {
+            Direction::Import => {
+                let module = key
+                    .map(|key| self.resolve.name_world_key(key))
+                    .unwrap_or_else(|| "$root".into());
+
+                uwrite!(
+                    self.statics,
+                    r#"
+                    @JvmStatic
+                    @Import(name = "[resource-drop]{name}", module = "{module}")
+                    external fun wasmImportResourceDrop{camel}(p0: Int)
+                    "#
+                );
+
+                // Handles are dropped explicitly with `close` rather than from
+                // a finalizer, since a resource may need to outlive the child
+                // resources created from it.
+                uwrite!(
+                    self.src,
+                    "
+                    class {camel}(handle: Handle) : AutoCloseable {{
+                        var handle: Int = handle.handle
+
+                        class Handle(val handle: Int)
+
+                        override fun close() {{
+                            if (handle != 0) {{
+                                wasmImportResourceDrop{camel}(handle)
+                                handle = 0
+                            }}
+                        }}
+                    "
+                );
+            }
+            Direction::Export => {
+                self.gen.needs_rep_table = true;
+
+                let (module, prefix) = match key {
+                    Some(key) => {
+                        let key = self.resolve.name_world_key(key);
+                        (format!("[export]{key}"), format!("{key}#"))
+                    }
+                    None => ("[export]$root".to_owned(), String::new()),
+                };
+                let qualifier = self.gen.qualifier();
+
+                // The destructor runs once the last handle to a resource is
+                // dropped, and `close` is called again in case it has been
+                // overridden to release anything else the resource owns.
+                uwrite!(
+                    self.statics,
+                    r#"
+                    @JvmStatic
+                    @Import(name = "[resource-drop]{name}", module = "{module}")
+                    external fun wasmImportResourceDrop{camel}(p0: Int)
+
+                    @JvmStatic
+                    @Import(name = "[resource-new]{name}", module = "{module}")
+                    external fun wasmImportResourceNew{camel}(p0: Int): Int
+
+                    @JvmStatic
+                    @Import(name = "[resource-rep]{name}", module = "{module}")
+                    external fun wasmImportResourceRep{camel}(p0: Int): Int
+
+                    @JvmStatic
+                    @Export(name = "{prefix}[dtor]{name}")
+                    private fun wasmExportResourceDtor{camel}(rep: Int) {{
+                        val value = {camel}.repTable.remove(rep)
+                        value.handle = 0
+                        value.close()
+                    }}
+                    "#
+                );
+
+                uwrite!(
+                    self.src,
+                    "
+                    abstract class {camel} : AutoCloseable {{
+                        var handle: Int = 0
+
+                        override fun close() {{
+                            if (handle != 0) {{
+                                val handle = this.handle
+                                this.handle = 0
+                                wasmImportResourceDrop{camel}(handle)
+                            }}
+                        }}
+
+                        companion object {{
+                            @JvmField
+                            val repTable = {qualifier}RepTable<{camel}>()
+                        }}
+                    "
+                );
+
+                if self.gen.opts.generate_stub {
+                    let qualified = self.type_name_with_qualifier(&Type::Id(id), true);
+                    // Without a constructor the class gets a primary one, which
+                    // has to initialize the superclass itself.
+                    let init = if funcs
+                        .iter()
+                        .any(|func| matches!(func.kind, FunctionKind::Constructor(_)))
+                    {
+                        ""
+                    } else {
+                        "()"
+                    };
+                    uwrite!(
+                        self.stub,
+                        "
+                        class {camel} : {qualified}{init} {{
+                        "
+                    );
+                }
+            }
+        }
+    }
+
+    fn end_resource(&mut self) {
+        let companion = mem::take(&mut self.companion);
+        if !companion.is_empty() {
+            uwriteln!(self.src, "companion object {{ {companion} }}");
+        }
+        uwriteln!(self.src, "}}");
+
+        if self.direction == Direction::Export && self.gen.opts.generate_stub {
+            let companion = mem::take(&mut self.stub_companion);
+            if !companion.is_empty() {
+                uwriteln!(self.stub, "companion object {{ {companion} }}");
+            }
+            uwriteln!(self.stub, "}}");
+        }
+    }
+}
+
+impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
+    fn resolve(&self) -> &'a Resolve {
+        self.resolve
+    }
+
+    fn type_record(&mut self, _id: TypeId, name: &str, record: &Record, docs: &Docs) {
+        self.print_docs(docs);
+
+        let name = name.to_upper_camel_case();
+
+        let fields = record
+            .fields
+            .iter()
+            .map(|field| {
+                format!(
+                    "val {}: {}",
+                    field.name.to_kotlin_ident(),
+                    self.type_name(&field.ty)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        uwrite!(
+            self.src,
+            "
+            data class {name}({fields})
+            "
+        );
+    }
+
+    fn type_resource(&mut self, id: TypeId, name: &str, docs: &Docs) {
+        // Resource classes are emitted along with their functions in
+        // `start_resource`, so only record what's needed for that here.
+        self.gen.all_resources.insert(
+            id,
+            ResourceInfo {
+                module: self.name.to_owned(),
+                name: name.to_owned(),
+                docs: docs.clone(),
+                direction: self.direction,
+            },
+        );
+    }
+
+    fn type_flags(&mut self, _id: TypeId, name: &str, flags: &Flags, docs: &Docs) {
+        self.print_docs(docs);
+
+        let name = name.to_upper_camel_case();
+
+        let Some(repr) = flags_repr(flags) else {
+            self.type_flags_words(&name, flags);
+            return;
+        };
+        let (ty, one) = match repr {
+            Int::U64 => ("Long", "1L"),
+            _ => ("Int", "1"),
+        };
+
+        let flags = flags
+            .flags
+            .iter()
+            .enumerate()
+            .map(|(i, flag)| {
+                let flag_name = flag.name.to_shouty_snake_case();
+                format!("@JvmField val {flag_name} = {name}({one} shl {i})")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        uwrite!(
+            self.src,
+            "
+            data class {name}(val value: {ty}) {{
+                infix fun or(other: {name}): {name} = {name}(value or other.value)
+
+                infix fun and(other: {name}): {name} = {name}(value and other.value)
+
+                operator fun contains(other: {name}): Boolean = (value and other.value) == other.value
+
+                companion object {{
+                    {flags}
+                }}
+            }}
+            "
+        );
+    }
+
+    fn type_tuple(&mut self, id: TypeId, _name: &str, _tuple: &Tuple, _docs: &Docs) {
+        self.type_name(&Type::Id(id));
+    }
+
+    fn type_variant(&mut self, _id: TypeId, name: &str, variant: &Variant, docs: &Docs) {
+        self.print_docs(docs);
+
+        let name = name.to_upper_camel_case();
+
+        let cases = variant
+            .cases
+            .iter()
+            .map(|case| {
+                let case_name = case_class_name(&name, &case.name);
+                if let Some(ty) = &case.ty {
+                    let ty = self.type_name(ty);
+                    format!("data class {case_name}(val value: {ty}) : {name}()")
+                } else {
+                    format!("object {case_name} : {name}()")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        uwrite!(
+            self.src,
+            "
+            sealed class {name} {{
+                {cases}
+            }}
+            "
+        );
+    }
+
+    fn type_option(&mut self, id: TypeId, _name: &str, _payload: &Type, _docs: &Docs) {
+        self.type_name(&Type::Id(id));
+    }
+
+    fn type_result(&mut self, id: TypeId, _name: &str, _result: &Result_, _docs: &Docs) {
+        self.type_name(&Type::Id(id));
+    }
+
+    fn type_enum(&mut self, _id: TypeId, name: &str, enum_: &Enum, docs: &Docs) {
+        self.print_docs(docs);
+
+        let name = name.to_upper_camel_case();
+
+        let cases = enum_
+            .cases
+            .iter()
+            .map(|case| case.name.to_shouty_snake_case())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        uwrite!(
+            self.src,
+            "
+            enum class {name} {{
+                {cases}
+            }}
+            "
+        );
+    }
+
+    fn type_alias(&mut self, id: TypeId, _name: &str, _ty: &Type, _docs: &Docs) {
+        self.type_name(&Type::Id(id));
+    }
+
+    fn type_list(&mut self, id: TypeId, _name: &str, _ty: &Type, _docs: &Docs) {
+        self.type_name(&Type::Id(id));
+    }
+
+    fn type_builtin(&mut self, id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        self.type_alias(id, name, ty, docs)
+    }
+}
+
+fn wasm_type(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 => "Int",
+        WasmType::I64 => "Long",
+        WasmType::F32 => "Float",
+        WasmType::F64 => "Double",
+        WasmType::Pointer => "Int",
+        WasmType::PointerOrI64 => "Long",
+        WasmType::Length => "Int",
+    }
+}
+
+/// Returns the integer type backing `flags`, or `None` if it's instead backed
+/// by an `IntArray` holding one element per 32 flags.
+fn flags_repr(flags: &Flags) -> Option<Int> {
+    match flags.repr() {
+        FlagsRepr::U8 => Some(Int::U8),
+        FlagsRepr::U16 => Some(Int::U16),
+        FlagsRepr::U32(1) => Some(Int::U32),
+        FlagsRepr::U32(2) => Some(Int::U64),
+        FlagsRepr::U32(_) => None,
+    }
+}
+
+/// Returns the size of a list's primitive elements, the suffix of the
+/// `Memory` functions copying them, and the array type they're lifted into.
+fn list_element_info(ty: &Type) -> (usize, &'static str, &'static str) {
+    match ty {
+        Type::U8 | Type::S8 => (1, "Byte", "ByteArray"),
+        Type::U16 | Type::S16 => (2, "Short", "ShortArray"),
+        Type::U32 | Type::S32 => (4, "Int", "IntArray"),
+        Type::U64 | Type::S64 => (8, "Long", "LongArray"),
+        Type::F32 => (4, "Float", "FloatArray"),
+        Type::F64 => (8, "Double", "DoubleArray"),
+        _ => unreachable!(),
+    }
+}
+
+fn indent(code: &str) -> String {
+    let mut indented = String::with_capacity(code.len());
+    let mut indent = 0;
+    let mut was_empty = false;
+    for line in code.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            if was_empty {
+                continue;
+            }
+            was_empty = true;
+        } else {
+            was_empty = false;
+        }
+
+        if trimmed.starts_with('}') {
+            indent -= 1;
+        }
+        if !trimmed.is_empty() {
+            indented.extend(iter::repeat_n(' ', indent * 4));
+        }
+        indented.push_str(trimmed);
+        if trimmed.ends_with('{') {
+            indent += 1;
+        }
+        indented.push('\n');
+    }
+    indented
+}
+
+fn is_primitive(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::U8
+            | Type::S8
+            | Type::U16
+            | Type::S16
+            | Type::U32
+            | Type::S32
+            | Type::U64
+            | Type::S64
+            | Type::F32
+            | Type::F64
+    )
+}
+
+fn world_name(resolve: &Resolve, world: WorldId) -> String {
+    format!(
+        "wit.worlds.{}",
+        resolve.worlds[world].name.to_upper_camel_case()
+    )
+}
+
+fn interface_name(resolve: &Resolve, name: &WorldKey, direction: Direction) -> String {
+    let pkg = match name {
+        WorldKey::Name(_) => None,
+        WorldKey::Interface(id) => {
+            let pkg = resolve.interfaces[*id].package.unwrap();
+            Some(resolve.packages[pkg].name.clone())
+        }
+    };
+
+    let name = match name {
+        WorldKey::Name(name) => name,
+        WorldKey::Interface(id) => resolve.interfaces[*id].name.as_ref().unwrap(),
+    }
+    .to_upper_camel_case();
+
+    format!(
+        "wit.{}.{}{name}",
+        match direction {
+            Direction::Import => "imports",
+            Direction::Export => "exports",
+        },
+        if let Some(name) = &pkg {
+            format!(
+                "{}.{}.",
+                name.namespace.to_kotlin_ident(),
+                name.name.to_kotlin_ident()
+            )
+        } else {
+            String::new()
+        }
+    )
+}
+
+/// Returns the name of the Kotlin function for `func`, renaming the methods
+/// of resources which would clash with the members every resource class has.
+fn func_name(func: &Function) -> String {
+    let name = func.item_name().to_kotlin_ident();
+    match (&func.kind, name.as_str()) {
+        (FunctionKind::Method(_) | FunctionKind::Static(_), "close" | "handle" | "repTable") => {
+            format!("{name}_")
+        }
+        _ => name,
+    }
+}
+
+/// Returns the name of the class of the case `case` of the variant `variant`,
+/// which can't be the same as the variant's own name.
+fn case_class_name(variant: &str, case: &str) -> String {
+    let name = case.to_upper_camel_case();
+    if name == variant {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
+
+/// Groups `funcs` by the resource they belong to, if any, including an entry
+/// for each of `resources` even when it has no functions.
+fn by_resource<'a>(
+    funcs: impl Iterator<Item = &'a Function>,
+    resources: impl IntoIterator<Item = TypeId>,
+) -> IndexMap<Option<TypeId>, Vec<&'a Function>> {
+    let mut by_resource = IndexMap::<_, Vec<_>>::new();
+    for func in funcs {
+        by_resource
+            .entry(match &func.kind {
+                FunctionKind::Freestanding => None,
+                FunctionKind::Method(resource)
+                | FunctionKind::Static(resource)
+                | FunctionKind::Constructor(resource) => Some(*resource),
+            })
+            .or_default()
+            .push(func);
+    }
+    for id in resources {
+        by_resource.entry(Some(id)).or_default();
+    }
+    by_resource
+}
+
+fn interface_resources(resolve: &Resolve, id: InterfaceId) -> Vec<TypeId> {
+    resolve.interfaces[id]
+        .types
+        .values()
+        .copied()
+        .filter(|ty| matches!(resolve.types[*ty].kind, TypeDefKind::Resource))
+        .collect()
+}
+
+fn split_qualified_name(name: &str) -> (String, &str) {
+    let tokens = name.split('.').collect::<Vec<_>>();
+
+    let package = tokens
+        .iter()
+        .copied()
+        .take(tokens.len() - 1)
+        .collect::<Vec<_>>()
+        .join(".");
+
+    let name = tokens.last().unwrap();
+
+    (package, name)
+}
+
+trait ToKotlinIdent: ToOwned {
+    fn to_kotlin_ident(&self) -> Self::Owned;
+}
+
+impl ToKotlinIdent for str {
+    fn to_kotlin_ident(&self) -> String {
+        // Escape Kotlin's hard keywords
+        // Source: https://kotlinlang.org/docs/keyword-reference.html#hard-keywords
+        match self {
+            "as" | "break" | "class" | "continue" | "do" | "else" | "false" | "for" | "fun"
+            | "if" | "in" | "interface" | "is" | "null" | "object" | "package" | "return"
+            | "super" | "this" | "throw" | "true" | "try" | "typealias" | "typeof" | "val"
+            | "var" | "when" | "while" => format!("{self}_"),
+            _ => self.to_lower_camel_case(),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+macro_rules! codegen_test {
+    // todo: disambiguate conflicting class names and then remove the following lines:
+    (same_names5 $name:tt $test:tt) => {};
+    (multiversion $name:tt $test:tt) => {};
+
+    ($id:ident $name:tt $test:tt) => {
+        #[test]
+        fn $id() {
+            test_helpers::run_world_codegen_test(
+                "guest-teavm-kotlin",
+                $test.as_ref(),
+                |resolve, world, files| {
+                    wit_bindgen_teavm_kotlin::Opts {
+                        generate_stub: true,
+                    }
+                    .build()
+                    .generate(resolve, world, files)
+                    .unwrap()
+                },
+                verify,
+            )
+        }
+    };
+}
+test_helpers::codegen_tests!();
+
+fn verify(dir: &Path, _name: &str) {
+    // Derived from `test_helpers::test_directory`
+    const DEPTH_FROM_TARGET_DIR: u32 = 3;
+
+    let base_dir = {
+        let mut dir = dir.to_owned();
+        for _ in 0..DEPTH_FROM_TARGET_DIR {
+            dir.pop();
+        }
+        dir
+    };
+
+    let teavm_interop_jar = base_dir.join("teavm-interop-0.2.8.jar");
+
+    if !teavm_interop_jar.is_file() {
+        panic!("please run ci/download-teavm.sh prior to running the Kotlin tests")
+    }
+
+    let mut files = Vec::new();
+    find_kotlin_files(&dir.join("wit"), &mut files);
+
+    let mut cmd = Command::new("kotlinc");
+    cmd.arg("-cp")
+        .arg(&teavm_interop_jar)
+        .arg("-d")
+        .arg(dir.join("classes"));
+
+    for file in files {
+        cmd.arg(file);
+    }
+
+    test_helpers::run_command(&mut cmd);
+}
+
+fn find_kotlin_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        for entry in fs::read_dir(path).unwrap() {
+            find_kotlin_files(&entry.unwrap().path(), files);
+        }
+    } else if let Some("kt") = path.extension().and_then(|ext| ext.to_str()) {
+        files.push(path.to_owned());
+    }
+}
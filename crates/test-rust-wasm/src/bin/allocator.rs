@@ -0,0 +1,3 @@
+include!("../../../../tests/runtime/allocator/wasm.rs");
+
+fn main() {}
@@ -0,0 +1,941 @@
+use crate::{error_name, field_name, flags_bits, int_type, uses, InterfaceGenerator};
+use std::fmt::Write as _;
+use std::mem;
+use wit_bindgen_core::abi::{Bindgen, Bitcast, Instruction, WasmType};
+use wit_bindgen_core::{dealias, uwriteln, wit_parser::*, Direction, Ns, Source};
+
+pub(super) struct FunctionBindgen<'a, 'b> {
+    pub gen: &'b mut InterfaceGenerator<'a>,
+    /// The function `CallInterface` calls.
+    pub callee: String,
+    /// For exported constructors, the handle type whose `new` function is
+    /// passed the value `callee` returns.
+    pub constructor: Option<String>,
+    pub params: Vec<String>,
+    pub locals: Ns,
+    pub src: Source,
+    block_storage: Vec<Source>,
+    blocks: Vec<(String, Vec<String>)>,
+    payloads: Vec<String>,
+    /// The names of the elements and base pointers of the lists being lowered
+    /// or lifted, innermost last.
+    iter_elems: Vec<String>,
+    iter_bases: Vec<String>,
+    /// The arena an import's temporary allocations are made in, which is
+    /// freed when it returns.
+    pub arena: String,
+    pub needs_arena: bool,
+    pub ret_area: String,
+    pub import_return_pointer_area_size: usize,
+    pub import_return_pointer_area_align: usize,
+}
+
+impl<'a, 'b> FunctionBindgen<'a, 'b> {
+    pub(super) fn new(
+        gen: &'b mut InterfaceGenerator<'a>,
+        mut locals: Ns,
+    ) -> FunctionBindgen<'a, 'b> {
+        let arena = locals.tmp("arena");
+        let ret_area = locals.tmp("ret_area");
+        FunctionBindgen {
+            gen,
+            callee: String::new(),
+            constructor: None,
+            params: Vec::new(),
+            locals,
+            src: Source::default(),
+            block_storage: Vec::new(),
+            blocks: Vec::new(),
+            payloads: Vec::new(),
+            iter_elems: Vec::new(),
+            iter_bases: Vec::new(),
+            arena,
+            needs_arena: false,
+            ret_area,
+            import_return_pointer_area_size: 0,
+            import_return_pointer_area_align: 0,
+        }
+    }
+
+    fn in_import(&self) -> bool {
+        self.gen.direction == Direction::Import
+    }
+
+    /// Binds `op` to a new constant so that it's only evaluated once, and
+    /// returns its name.
+    fn bind(&mut self, name: &str, op: &str) -> String {
+        let tmp = self.locals.tmp(name);
+        uwriteln!(self.src, "const {tmp} = {op};");
+        tmp
+    }
+
+    fn load(&mut self, ty: &str, mem_ty: &str, offset: i32, operands: &[String]) -> String {
+        let tmp = self.locals.tmp("load");
+        uwriteln!(
+            self.src,
+            "const {tmp}: {ty} = @as(*align(1) const {mem_ty}, @ptrFromInt({} + {offset})).*;",
+            operands[0]
+        );
+        tmp
+    }
+
+    fn store(&mut self, mem_ty: &str, value: &str, offset: i32, operands: &[String]) {
+        uwriteln!(
+            self.src,
+            "@as(*align(1) {mem_ty}, @ptrFromInt({} + {offset})).* = {value};",
+            operands[1]
+        );
+    }
+
+    /// Returns an expression allocating `size` bytes aligned to `align`,
+    /// which are freed when an import returns, and are freed by the
+    /// post-return function of an export.
+    fn alloc(&mut self, size: &str, align: usize) -> String {
+        if self.in_import() {
+            self.needs_arena = true;
+            format!(
+                "@intFromPtr(({}.allocator().alignedAlloc(u8, {align}, {size}) catch @panic(\"out of memory\")).ptr)",
+                self.arena
+            )
+        } else {
+            format!("witAlloc({size}, {align})")
+        }
+    }
+
+    /// Declares a variable for each of the core wasm `types` which the
+    /// results of a variant's cases are assigned to.
+    fn declare_results(&mut self, types: &[WasmType]) -> Vec<String> {
+        types
+            .iter()
+            .map(|ty| {
+                let tmp = self.locals.tmp("variant");
+                uwriteln!(self.src, "var {tmp}: {} = 0;", wasm_type(*ty));
+                tmp
+            })
+            .collect()
+    }
+
+    /// Writes the prong of a `switch` lowering a case of a variant, whose
+    /// payload is captured as `payload` if it's used.
+    fn lower_case(
+        &mut self,
+        pattern: &str,
+        payload: &str,
+        block: &str,
+        ops: &[String],
+        vars: &[String],
+    ) {
+        let mut body = block.to_string();
+        for (var, op) in vars.iter().zip(ops) {
+            uwriteln!(body, "{var} = {op};");
+        }
+        if uses(&body, payload) {
+            uwriteln!(self.src, "{pattern} => |{payload}| {{");
+        } else {
+            uwriteln!(self.src, "{pattern} => {{");
+        }
+        self.src.push_str(&body);
+        self.src.push_str("},\n");
+    }
+
+    /// Returns an expression which evaluates `block` and then `value`.
+    fn lift_case(&mut self, block: &str, value: &str) -> String {
+        if block.is_empty() {
+            return value.to_string();
+        }
+        let label = self.locals.tmp("blk");
+        format!("{label}: {{\n{block}break :{label} {value};\n}}")
+    }
+
+    fn type_name(&self, ty: &Type) -> String {
+        self.gen.type_name(ty)
+    }
+}
+
+impl Bindgen for FunctionBindgen<'_, '_> {
+    type Operand = String;
+
+    fn sizes(&self) -> &SizeAlign {
+        &self.gen.gen.sizes
+    }
+
+    fn push_block(&mut self) {
+        let prev = mem::take(&mut self.src);
+        self.block_storage.push(prev);
+    }
+
+    fn finish_block(&mut self, operands: &mut Vec<String>) {
+        let to_restore = self.block_storage.pop().unwrap();
+        let src = mem::replace(&mut self.src, to_restore);
+        self.blocks.push((src.into(), mem::take(operands)));
+    }
+
+    fn return_pointer(&mut self, size: usize, align: usize) -> String {
+        // Use a stack-based return area for imports, because exports need
+        // their return area to be live until the post-return call.
+        if self.in_import() {
+            self.import_return_pointer_area_size = self.import_return_pointer_area_size.max(size);
+            self.import_return_pointer_area_align =
+                self.import_return_pointer_area_align.max(align);
+            format!("@intFromPtr(&{})", self.ret_area)
+        } else {
+            self.gen.gen.return_pointer_area_size = self.gen.gen.return_pointer_area_size.max(size);
+            self.gen.gen.return_pointer_area_align =
+                self.gen.gen.return_pointer_area_align.max(align);
+            "@intFromPtr(&RET_AREA)".to_string()
+        }
+    }
+
+    fn is_list_canonical(&self, _resolve: &Resolve, ty: &Type) -> bool {
+        // Only slices of numbers have the same layout as the canonical ABI;
+        // `bool` and `char` are validated.
+        matches!(
+            ty,
+            Type::U8
+                | Type::S8
+                | Type::U16
+                | Type::S16
+                | Type::U32
+                | Type::S32
+                | Type::U64
+                | Type::S64
+                | Type::F32
+                | Type::F64
+        )
+    }
+
+    fn emit(
+        &mut self,
+        resolve: &Resolve,
+        inst: &Instruction<'_>,
+        operands: &mut Vec<String>,
+        results: &mut Vec<String>,
+    ) {
+        match inst {
+            Instruction::GetArg { nth } => results.push(self.params[*nth].clone()),
+            Instruction::I32Const { val } => results.push(val.to_string()),
+            Instruction::ConstZero { tys } => {
+                for ty in tys.iter() {
+                    results.push(format!("@as({}, 0)", wasm_type(*ty)));
+                }
+            }
+
+            Instruction::U8FromI32 => results.push(format!(
+                "@as(u8, @truncate(@as(u32, @bitCast({}))))",
+                operands[0]
+            )),
+            Instruction::S8FromI32 => results.push(format!("@as(i8, @truncate({}))", operands[0])),
+            Instruction::U16FromI32 => results.push(format!(
+                "@as(u16, @truncate(@as(u32, @bitCast({}))))",
+                operands[0]
+            )),
+            Instruction::S16FromI32 => {
+                results.push(format!("@as(i16, @truncate({}))", operands[0]))
+            }
+            Instruction::U32FromI32 => results.push(format!("@as(u32, @bitCast({}))", operands[0])),
+            Instruction::U64FromI64 => results.push(format!("@as(u64, @bitCast({}))", operands[0])),
+            Instruction::S32FromI32 | Instruction::S64FromI64 => results.push(operands[0].clone()),
+
+            Instruction::I32FromU8
+            | Instruction::I32FromS8
+            | Instruction::I32FromU16
+            | Instruction::I32FromS16
+            | Instruction::I32FromChar => results.push(format!("@as(i32, {})", operands[0])),
+            Instruction::I32FromU32 => results.push(format!("@as(i32, @bitCast({}))", operands[0])),
+            Instruction::I64FromU64 => results.push(format!("@as(i64, @bitCast({}))", operands[0])),
+            Instruction::I32FromS32 | Instruction::I64FromS64 => results.push(operands[0].clone()),
+
+            Instruction::CoreF32FromF32
+            | Instruction::CoreF64FromF64
+            | Instruction::F32FromCoreF32
+            | Instruction::F64FromCoreF64 => {
+                results.push(operands[0].clone());
+            }
+
+            Instruction::CharFromI32 => {
+                results.push(format!("@as(u21, @intCast({}))", operands[0]))
+            }
+            Instruction::BoolFromI32 => results.push(format!("({} != 0)", operands[0])),
+            Instruction::I32FromBool => {
+                results.push(format!("@as(i32, @intFromBool({}))", operands[0]))
+            }
+
+            Instruction::Bitcasts { casts } => {
+                for (cast, op) in casts.iter().zip(operands) {
+                    results.push(perform_cast(op, cast));
+                }
+            }
+
+            Instruction::RecordLower { record, .. } => {
+                if record.fields.is_empty() {
+                    uwriteln!(self.src, "_ = {};", operands[0]);
+                    return;
+                }
+                let op = self.bind("record", &operands[0]);
+                for field in record.fields.iter() {
+                    results.push(format!("{op}.{}", field_name(&field.name)));
+                }
+            }
+            Instruction::RecordLift { record, ty, .. } => {
+                let name = self.gen.qualified_name(*ty);
+                let fields = record
+                    .fields
+                    .iter()
+                    .zip(operands.iter())
+                    .map(|(field, op)| format!(".{} = {op}", field_name(&field.name)))
+                    .collect::<Vec<_>>();
+                if fields.is_empty() {
+                    results.push(format!("{name}{{}}"));
+                } else {
+                    results.push(format!("{name}{{ {} }}", fields.join(", ")));
+                }
+            }
+
+            Instruction::TupleLower { tuple, .. } => {
+                if tuple.types.is_empty() {
+                    uwriteln!(self.src, "_ = {};", operands[0]);
+                    return;
+                }
+                let op = self.bind("tuple", &operands[0]);
+                for i in 0..tuple.types.len() {
+                    results.push(format!("{op}[{i}]"));
+                }
+            }
+            Instruction::TupleLift { ty, .. } => {
+                let name = self.gen.type_id_name(*ty, true);
+                results.push(format!("@as({name}, .{{ {} }})", operands.join(", ")));
+            }
+
+            Instruction::HandleLower { .. } => results.push(format!("{}.handle", operands[0])),
+            Instruction::HandleLift { handle, .. } => {
+                let (Handle::Own(resource) | Handle::Borrow(resource)) = handle;
+                let resource = dealias(resolve, *resource);
+                let name = self.gen.qualified_name(resource);
+                let op = &operands[0];
+                match (self.gen.resource_direction(resource), handle) {
+                    (Direction::Export, Handle::Borrow(_)) => results.push(format!(
+                        "@as(*{name}.Rep, @ptrFromInt(@as(u32, @bitCast({op}))))"
+                    )),
+                    // Borrows passed to exports must be dropped before they
+                    // return, which can only be done at the top level.
+                    (Direction::Import, Handle::Borrow(_))
+                        if !self.in_import() && self.block_storage.is_empty() =>
+                    {
+                        let borrow = self.bind("borrow", &format!("{name}{{ .handle = {op} }}"));
+                        uwriteln!(self.src, "defer {borrow}.deinit();");
+                        results.push(borrow);
+                    }
+                    _ => results.push(format!("{name}{{ .handle = {op} }}")),
+                }
+            }
+
+            Instruction::FlagsLower { flags, .. } => {
+                let op = &operands[0];
+                match flags.repr() {
+                    FlagsRepr::U32(0) => uwriteln!(self.src, "_ = {op};"),
+                    FlagsRepr::U32(1) => results.push(format!("@as(i32, @bitCast({op}))")),
+                    FlagsRepr::U32(n) => {
+                        let bits = self.locals.tmp("bits");
+                        uwriteln!(
+                            self.src,
+                            "const {bits}: u{} = @bitCast({op});",
+                            flags_bits(flags)
+                        );
+                        for i in 0..n {
+                            results.push(format!(
+                                "@as(i32, @bitCast(@as(u32, @truncate({bits} >> {}))))",
+                                32 * i
+                            ));
+                        }
+                    }
+                    _ => {
+                        let bits = flags_bits(flags);
+                        results.push(format!("@as(i32, @as(u{bits}, @bitCast({op})))"));
+                    }
+                }
+            }
+            Instruction::FlagsLift { flags, ty, .. } => {
+                let name = self.gen.qualified_name(*ty);
+                let bits = flags_bits(flags);
+                match flags.repr() {
+                    FlagsRepr::U32(0) => results.push(format!("{name}{{}}")),
+                    FlagsRepr::U32(1) => {
+                        results.push(format!("@as({name}, @bitCast({}))", operands[0]))
+                    }
+                    FlagsRepr::U32(_) => {
+                        let parts = operands
+                            .iter()
+                            .enumerate()
+                            .map(|(i, op)| {
+                                format!("(@as(u{bits}, @as(u32, @bitCast({op}))) << {})", 32 * i)
+                            })
+                            .collect::<Vec<_>>();
+                        results.push(format!("@as({name}, @bitCast({}))", parts.join(" | ")));
+                    }
+                    _ => results.push(format!(
+                        "@as({name}, @bitCast(@as(u{bits}, @truncate(@as(u32, @bitCast({}))))))",
+                        operands[0]
+                    )),
+                }
+            }
+
+            Instruction::VariantPayloadName => {
+                let name = self.locals.tmp("payload");
+                results.push(name.clone());
+                self.payloads.push(name);
+            }
+
+            Instruction::VariantLower {
+                variant,
+                results: result_types,
+                ..
+            } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+                let payloads = self
+                    .payloads
+                    .drain(self.payloads.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+
+                let vars = self.declare_results(result_types);
+                uwriteln!(self.src, "switch ({}) {{", operands[0]);
+                for ((case, (block, block_results)), payload) in
+                    variant.cases.iter().zip(blocks).zip(payloads)
+                {
+                    let pattern = format!(".{}", field_name(&case.name));
+                    self.lower_case(&pattern, &payload, &block, &block_results, &vars);
+                }
+                self.src.push_str("}\n");
+                results.extend(vars);
+            }
+
+            Instruction::VariantLift { variant, ty, .. } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+
+                let name = self.gen.qualified_name(*ty);
+                let mut prongs = Vec::new();
+                for (i, (case, (block, block_results))) in
+                    variant.cases.iter().zip(blocks).enumerate()
+                {
+                    let value = match &block_results[..] {
+                        [] => "{}",
+                        [value] => value,
+                        _ => unreachable!(),
+                    };
+                    let value = format!(".{{ .{} = {value} }}", field_name(&case.name));
+                    prongs.push(format!("{i} => {},", self.lift_case(&block, &value)));
+                }
+                let result = self.locals.tmp("variant");
+                uwriteln!(
+                    self.src,
+                    "const {result}: {name} = switch ({}) {{",
+                    operands[0]
+                );
+                for prong in prongs {
+                    uwriteln!(self.src, "{prong}");
+                }
+                self.src.push_str("else => unreachable,\n};\n");
+                results.push(result);
+            }
+
+            Instruction::EnumLower { .. } => {
+                results.push(format!("@as(i32, @intFromEnum({}))", operands[0]))
+            }
+            Instruction::EnumLift { enum_, ty, .. } => {
+                let name = self.gen.qualified_name(*ty);
+                let tag = int_type(enum_.tag());
+                results.push(format!(
+                    "@as({name}, @enumFromInt(@as({tag}, @intCast({}))))",
+                    operands[0]
+                ));
+            }
+
+            Instruction::OptionLower {
+                results: result_types,
+                ..
+            } => {
+                let (mut some, some_results) = self.blocks.pop().unwrap();
+                let (mut none, none_results) = self.blocks.pop().unwrap();
+                let some_payload = self.payloads.pop().unwrap();
+                let _none_payload = self.payloads.pop().unwrap();
+
+                let vars = self.declare_results(result_types);
+                for (var, op) in vars.iter().zip(&some_results) {
+                    uwriteln!(some, "{var} = {op};");
+                }
+                for (var, op) in vars.iter().zip(&none_results) {
+                    uwriteln!(none, "{var} = {op};");
+                }
+                let capture = if uses(&some, &some_payload) {
+                    some_payload
+                } else {
+                    "_".to_string()
+                };
+                uwriteln!(self.src, "if ({}) |{capture}| {{", operands[0]);
+                self.src.push_str(&some);
+                self.src.push_str("} else {\n");
+                self.src.push_str(&none);
+                self.src.push_str("}\n");
+                results.extend(vars);
+            }
+
+            Instruction::OptionLift { payload, ty } => {
+                let (some, some_results) = self.blocks.pop().unwrap();
+                let (none, none_results) = self.blocks.pop().unwrap();
+                assert!(none_results.is_empty());
+                assert_eq!(some_results.len(), 1);
+
+                // A `null` payload of a nested option needs to be typed so
+                // that it isn't taken as the outer option's `null`.
+                let mut value = some_results[0].clone();
+                if let Type::Id(id) = payload {
+                    if let TypeDefKind::Option(_) = resolve.types[dealias(resolve, *id)].kind {
+                        value = format!("@as({}, {value})", self.type_name(payload));
+                    }
+                }
+                let none = self.lift_case(&none, "null");
+                let some = self.lift_case(&some, &value);
+                let name = self.gen.type_id_name(*ty, true);
+                let result = self.locals.tmp("option");
+                uwriteln!(
+                    self.src,
+                    "const {result}: {name} = switch ({}) {{",
+                    operands[0]
+                );
+                uwriteln!(self.src, "0 => {none},");
+                uwriteln!(self.src, "1 => {some},");
+                self.src.push_str("else => unreachable,\n};\n");
+                results.push(result);
+            }
+
+            Instruction::ResultLower {
+                results: result_types,
+                ..
+            } => {
+                let (err, err_results) = self.blocks.pop().unwrap();
+                let (ok, ok_results) = self.blocks.pop().unwrap();
+                let err_payload = self.payloads.pop().unwrap();
+                let ok_payload = self.payloads.pop().unwrap();
+
+                let vars = self.declare_results(result_types);
+                uwriteln!(self.src, "switch ({}) {{", operands[0]);
+                self.lower_case(".ok", &ok_payload, &ok, &ok_results, &vars);
+                self.lower_case(".err", &err_payload, &err, &err_results, &vars);
+                self.src.push_str("}\n");
+                results.extend(vars);
+            }
+
+            Instruction::ResultLift { ty, .. } => {
+                let (err, err_results) = self.blocks.pop().unwrap();
+                let (ok, ok_results) = self.blocks.pop().unwrap();
+                let ok_value = format!(".{{ .ok = {} }}", ok_results.first().map_or("{}", |s| s));
+                let err_value =
+                    format!(".{{ .err = {} }}", err_results.first().map_or("{}", |s| s));
+                let ok = self.lift_case(&ok, &ok_value);
+                let err = self.lift_case(&err, &err_value);
+                let name = self.gen.type_id_name(*ty, true);
+                let result = self.locals.tmp("result");
+                uwriteln!(
+                    self.src,
+                    "const {result}: {name} = switch ({}) {{",
+                    operands[0]
+                );
+                uwriteln!(self.src, "0 => {ok},");
+                uwriteln!(self.src, "1 => {err},");
+                self.src.push_str("else => unreachable,\n};\n");
+                results.push(result);
+            }
+
+            Instruction::ListCanonLower { element, realloc } => {
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.gen.sizes.align(element).align_wasm32();
+                self.lower_canonical_list(&operands[0], size, align, realloc.is_some(), results);
+            }
+            Instruction::StringLower { realloc } => {
+                self.lower_canonical_list(&operands[0], 1, 1, realloc.is_some(), results);
+            }
+
+            Instruction::ListLower { element, .. } => {
+                let (body, body_results) = self.blocks.pop().unwrap();
+                assert!(body_results.is_empty());
+                let elem = self.iter_elems.pop().unwrap();
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.gen.sizes.align(element).align_wasm32();
+
+                let list = self.bind("list", &operands[0]);
+                let alloc = self.alloc(&format!("{list}.len * {size}"), align);
+                let ptr = self.bind("ptr", &alloc);
+                let index = self.locals.tmp("i");
+                let elem = if uses(&body, &elem) {
+                    elem
+                } else {
+                    "_".to_string()
+                };
+                if uses(&body, &base) {
+                    uwriteln!(self.src, "for ({list}, 0..) |{elem}, {index}| {{");
+                    uwriteln!(self.src, "const {base} = {ptr} + {index} * {size};");
+                } else {
+                    uwriteln!(self.src, "for ({list}) |{elem}| {{");
+                }
+                self.src.push_str(&body);
+                self.src.push_str("}\n");
+                results.push(ptr);
+                results.push(format!("{list}.len"));
+            }
+
+            Instruction::ListCanonLift { element, .. } => {
+                let ty = self.type_name(element);
+                results.push(format!("witSlice({ty}, {}, {})", operands[0], operands[1]));
+            }
+            Instruction::StringLift => {
+                results.push(format!("witSlice(u8, {}, {})", operands[0], operands[1]));
+            }
+
+            Instruction::ListLift { element, .. } => {
+                let (body, body_results) = self.blocks.pop().unwrap();
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.gen.sizes.align(element).align_wasm32();
+                let ty = self.type_name(element);
+
+                let ptr = self.bind("ptr", &operands[0]);
+                let len = self.bind("len", &operands[1]);
+                let list = self.bind(
+                    "list",
+                    &format!("allocator.alloc({ty}, {len}) catch @panic(\"out of memory\")"),
+                );
+                let elem = self.locals.tmp("elem");
+                let index = self.locals.tmp("i");
+                if uses(&body, &base) {
+                    uwriteln!(self.src, "for ({list}, 0..) |*{elem}, {index}| {{");
+                    uwriteln!(self.src, "const {base} = {ptr} + {index} * {size};");
+                } else {
+                    uwriteln!(self.src, "for ({list}) |*{elem}| {{");
+                }
+                self.src.push_str(&body);
+                uwriteln!(self.src, "{elem}.* = {};", body_results[0]);
+                self.src.push_str("}\n");
+                uwriteln!(self.src, "witFree({ptr}, {len} * {size}, {align});");
+                results.push(list);
+            }
+
+            Instruction::IterElem { .. } => {
+                let elem = self.locals.tmp("elem");
+                self.iter_elems.push(elem.clone());
+                results.push(elem);
+            }
+            Instruction::IterBasePointer => {
+                let base = self.locals.tmp("base");
+                self.iter_bases.push(base.clone());
+                results.push(base);
+            }
+
+            Instruction::CallWasm { name, sig } => {
+                let module = self.gen.module.as_deref().unwrap();
+                let params = sig
+                    .params
+                    .iter()
+                    .map(|ty| wasm_type(*ty))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let ret = match &sig.results[..] {
+                    [] => "void",
+                    [ty] => wasm_type(*ty),
+                    _ => unreachable!("multiple results are returned through a return pointer"),
+                };
+                let import = self.locals.tmp("import");
+                uwriteln!(
+                    self.src,
+                    "const {import} = @extern(*const fn ({params}) callconv(.C) {ret}, .{{ .library_name = \"{module}\", .name = \"{name}\" }});"
+                );
+                let call = format!("{import}({})", operands.join(", "));
+                if sig.results.is_empty() {
+                    uwriteln!(self.src, "{call};");
+                } else {
+                    results.push(self.bind("ret", &call));
+                }
+            }
+
+            Instruction::CallInterface { func, .. } => {
+                let mut call = format!("{}({})", self.callee, operands.join(", "));
+                if let Some(resource) = &self.constructor {
+                    call = format!("{resource}.new({call})");
+                }
+                if let Some((result, enum_)) = self.gen.error_result(func) {
+                    // Convert the error union the implementation returns to
+                    // the `result` it represents.
+                    let ty = self.type_name(func.results.iter_types().next().unwrap());
+                    let value = self.locals.tmp("value");
+                    let err = self.locals.tmp("err");
+                    let ok = if result.ok.is_some() {
+                        format!("|{value}| .{{ .ok = {value} }}")
+                    } else {
+                        "|_| .{ .ok = {} }".to_string()
+                    };
+                    let ret = self.locals.tmp("ret");
+                    uwriteln!(
+                        self.src,
+                        "const {ret}: {ty} = if ({call}) {ok} else |{err}| .{{ .err = switch ({err}) {{"
+                    );
+                    let TypeDefKind::Enum(enum_) = &resolve.types[enum_].kind else {
+                        unreachable!()
+                    };
+                    for case in enum_.cases.iter() {
+                        uwriteln!(
+                            self.src,
+                            "error.{} => .{},",
+                            error_name(&case.name),
+                            field_name(&case.name)
+                        );
+                    }
+                    self.src.push_str("} };\n");
+                    results.push(ret);
+                    return;
+                }
+                match func.results.len() {
+                    0 => uwriteln!(self.src, "{call};"),
+                    1 => results.push(self.bind("ret", &call)),
+                    n => {
+                        let ret = self.bind("ret", &call);
+                        results.extend((0..n).map(|i| format!("{ret}[{i}]")));
+                    }
+                }
+            }
+
+            Instruction::Return { amt, func } => {
+                if !self.in_import() {
+                    assert!(*amt <= 1);
+                    if *amt == 1 {
+                        uwriteln!(self.src, "return {};", operands[0]);
+                    }
+                } else if let Some((result, enum_)) = self.gen.error_result(func) {
+                    // Convert the lifted `result` to the error union the
+                    // function returns.
+                    let value = self.locals.tmp("value");
+                    let err = self.locals.tmp("err");
+                    let ok = if result.ok.is_some() {
+                        format!("|{value}| {value}")
+                    } else {
+                        "{}".to_string()
+                    };
+                    uwriteln!(self.src, "return switch ({}) {{", operands[0]);
+                    if result.ok.is_some() {
+                        uwriteln!(self.src, ".ok => {ok},");
+                    } else {
+                        uwriteln!(self.src, ".ok => {{}},");
+                    }
+                    uwriteln!(self.src, ".err => |{err}| switch ({err}) {{");
+                    let TypeDefKind::Enum(enum_) = &resolve.types[enum_].kind else {
+                        unreachable!()
+                    };
+                    for case in enum_.cases.iter() {
+                        uwriteln!(
+                            self.src,
+                            ".{} => error.{},",
+                            field_name(&case.name),
+                            error_name(&case.name)
+                        );
+                    }
+                    self.src.push_str("},\n};\n");
+                } else {
+                    match operands.len() {
+                        0 => {}
+                        1 => uwriteln!(self.src, "return {};", operands[0]),
+                        _ => uwriteln!(self.src, "return .{{ {} }};", operands.join(", ")),
+                    }
+                }
+            }
+
+            Instruction::I32Load { offset } => {
+                results.push(self.load("i32", "i32", *offset, operands))
+            }
+            Instruction::I64Load { offset } => {
+                results.push(self.load("i64", "i64", *offset, operands))
+            }
+            Instruction::F32Load { offset } => {
+                results.push(self.load("f32", "f32", *offset, operands))
+            }
+            Instruction::F64Load { offset } => {
+                results.push(self.load("f64", "f64", *offset, operands))
+            }
+            Instruction::PointerLoad { offset } | Instruction::LengthLoad { offset } => {
+                results.push(self.load("usize", "u32", *offset, operands))
+            }
+            Instruction::I32Load8U { offset } => {
+                results.push(self.load("i32", "u8", *offset, operands))
+            }
+            Instruction::I32Load8S { offset } => {
+                results.push(self.load("i32", "i8", *offset, operands))
+            }
+            Instruction::I32Load16U { offset } => {
+                results.push(self.load("i32", "u16", *offset, operands))
+            }
+            Instruction::I32Load16S { offset } => {
+                results.push(self.load("i32", "i16", *offset, operands))
+            }
+
+            Instruction::I32Store { offset } => self.store("i32", &operands[0], *offset, operands),
+            Instruction::I64Store { offset } => self.store("i64", &operands[0], *offset, operands),
+            Instruction::F32Store { offset } => self.store("f32", &operands[0], *offset, operands),
+            Instruction::F64Store { offset } => self.store("f64", &operands[0], *offset, operands),
+            Instruction::I32Store8 { offset } => {
+                let value = format!("@as(u8, @truncate(@as(u32, @bitCast({}))))", operands[0]);
+                self.store("u8", &value, *offset, operands)
+            }
+            Instruction::I32Store16 { offset } => {
+                let value = format!("@as(u16, @truncate(@as(u32, @bitCast({}))))", operands[0]);
+                self.store("u16", &value, *offset, operands)
+            }
+            Instruction::PointerStore { offset } | Instruction::LengthStore { offset } => {
+                let value = format!("@as(u32, @intCast({}))", operands[0]);
+                self.store("u32", &value, *offset, operands)
+            }
+
+            Instruction::Malloc { .. } => unreachable!(),
+
+            Instruction::GuestDeallocate { size, align } => {
+                uwriteln!(self.src, "witFree({}, {size}, {align});", operands[0]);
+            }
+            Instruction::GuestDeallocateString => {
+                uwriteln!(self.src, "witFree({}, {}, 1);", operands[0], operands[1]);
+            }
+            Instruction::GuestDeallocateVariant { blocks } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - blocks..)
+                    .collect::<Vec<_>>();
+
+                uwriteln!(self.src, "switch ({}) {{", operands[0]);
+                for (i, (block, results)) in blocks.into_iter().enumerate() {
+                    assert!(results.is_empty());
+                    if block.is_empty() {
+                        uwriteln!(self.src, "{i} => {{}},");
+                    } else {
+                        uwriteln!(self.src, "{i} => {{");
+                        self.src.push_str(&block);
+                        self.src.push_str("},\n");
+                    }
+                }
+                self.src.push_str("else => unreachable,\n}\n");
+            }
+            Instruction::GuestDeallocateList { element } => {
+                let (body, results) = self.blocks.pop().unwrap();
+                assert!(results.is_empty());
+                let base = self.iter_bases.pop().unwrap();
+                let size = self.gen.gen.sizes.size(element).size_wasm32();
+                let align = self.gen.gen.sizes.align(element).align_wasm32();
+
+                let ptr = self.bind("ptr", &operands[0]);
+                let len = self.bind("len", &operands[1]);
+                if !body.trim().is_empty() {
+                    let index = self.locals.tmp("i");
+                    if uses(&body, &base) {
+                        uwriteln!(self.src, "for (0..{len}) |{index}| {{");
+                        uwriteln!(self.src, "const {base} = {ptr} + {index} * {size};");
+                    } else {
+                        uwriteln!(self.src, "for (0..{len}) |_| {{");
+                    }
+                    self.src.push_str(&body);
+                    self.src.push_str("}\n");
+                }
+                uwriteln!(self.src, "witFree({ptr}, {len} * {size}, {align});");
+            }
+
+            Instruction::AsyncMalloc { .. }
+            | Instruction::AsyncCallWasm { .. }
+            | Instruction::AsyncPostCallInterface { .. }
+            | Instruction::AsyncCallReturn { .. }
+            | Instruction::Flush { .. } => {
+                unreachable!("async functions are never requested by this generator")
+            }
+
+            Instruction::FutureLower { .. }
+            | Instruction::FutureLift { .. }
+            | Instruction::StreamLower { .. }
+            | Instruction::StreamLift { .. } => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+        }
+    }
+}
+
+impl FunctionBindgen<'_, '_> {
+    /// Lowers the slice `op`, whose elements are `size` bytes and have the
+    /// same layout as the canonical ABI.
+    ///
+    /// Imports pass a pointer to the slice itself, while exports copy it to
+    /// memory the post-return function frees, since implementations may
+    /// return slices which weren't allocated with `allocator`.
+    fn lower_canonical_list(
+        &mut self,
+        op: &str,
+        size: usize,
+        align: usize,
+        copy: bool,
+        results: &mut Vec<String>,
+    ) {
+        let list = self.bind("list", op);
+        if copy {
+            let alloc = self.alloc(&format!("{list}.len * {size}"), align);
+            let ptr = self.bind("ptr", &alloc);
+            uwriteln!(
+                self.src,
+                "@memcpy(@as([*]u8, @ptrFromInt({ptr}))[0 .. {list}.len * {size}], std.mem.sliceAsBytes({list}));"
+            );
+            results.push(ptr);
+        } else {
+            results.push(format!("@intFromPtr({list}.ptr)"));
+        }
+        results.push(format!("{list}.len"));
+    }
+}
+
+fn perform_cast(op: &str, cast: &Bitcast) -> String {
+    match cast {
+        Bitcast::I32ToF32 | Bitcast::I64ToF64 | Bitcast::F32ToI32 | Bitcast::F64ToI64 => {
+            let ty = match cast {
+                Bitcast::I32ToF32 => "f32",
+                Bitcast::I64ToF64 => "f64",
+                Bitcast::F32ToI32 => "i32",
+                _ => "i64",
+            };
+            format!("@as({ty}, @bitCast({op}))")
+        }
+        Bitcast::I64ToF32 => format!("@as(f32, @bitCast(@as(i32, @truncate({op}))))"),
+        Bitcast::F32ToI64 => format!("@as(i64, @as(u32, @bitCast({op})))"),
+        Bitcast::I32ToI64 => format!("@as(i64, {op})"),
+        Bitcast::I64ToI32 => format!("@as(i32, @truncate({op}))"),
+        // P64 is represented as `i64`, and both pointers and lengths as
+        // `usize`, so these are no-ops.
+        Bitcast::I64ToP64 | Bitcast::P64ToI64 | Bitcast::PToL | Bitcast::LToP => op.to_string(),
+        Bitcast::P64ToP | Bitcast::I64ToL => format!("@as(usize, @intCast({op}))"),
+        Bitcast::PToP64 | Bitcast::LToI64 => format!("@as(i64, @intCast({op}))"),
+        Bitcast::I32ToP | Bitcast::I32ToL => format!("@as(usize, @as(u32, @bitCast({op})))"),
+        Bitcast::PToI32 | Bitcast::LToI32 => {
+            format!("@as(i32, @bitCast(@as(u32, @intCast({op}))))")
+        }
+        Bitcast::None => op.to_string(),
+        Bitcast::Sequence(sequence) => {
+            let [first, second] = &**sequence;
+            let inner = perform_cast(op, first);
+            perform_cast(&inner, second)
+        }
+    }
+}
+
+pub(super) fn wasm_type(ty: WasmType) -> &'static str {
+    match ty {
+        WasmType::I32 => "i32",
+        WasmType::I64 => "i64",
+        WasmType::F32 => "f32",
+        WasmType::F64 => "f64",
+        WasmType::Pointer | WasmType::Length => "usize",
+        WasmType::PointerOrI64 => "i64",
+    }
+}
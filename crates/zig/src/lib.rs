@@ -0,0 +1,1079 @@
+mod bindgen;
+
+use anyhow::Result;
+use bindgen::{wasm_type, FunctionBindgen};
+use heck::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use wit_bindgen_c::component_type_object;
+use wit_bindgen_core::abi::{self, AbiVariant, LiftLower};
+use wit_bindgen_core::{
+    dealias, uwriteln, wit_parser::*, Direction, Files, Ns, Source, WorldGenerator,
+};
+use wit_component::StringEncoding;
+
+/// Support code placed at the top of every generated file.
+const RUNTIME: &str = include_str!("runtime.zig");
+
+/// The top-level declarations `RUNTIME` makes, which generated names must
+/// neither collide with nor shadow.
+const RUNTIME_DECLS: &[&str] = &[
+    "std",
+    "root",
+    "bindings",
+    "allocator",
+    "WitResult",
+    "cabi_realloc",
+    "witAlloc",
+    "witFree",
+    "witSlice",
+    "RET_AREA",
+];
+
+#[derive(Default)]
+struct Zig {
+    opts: Opts,
+    world: String,
+    sizes: SizeAlign,
+    src: Source,
+    /// The functions exported from the generated file, which are declared at
+    /// its top level so they're always analyzed.
+    exports: Source,
+    /// The name of each function in `exports` and the name it's exported as.
+    export_names: Vec<(String, String)>,
+    export_fn_names: Ns,
+    return_pointer_area_size: usize,
+    return_pointer_area_align: usize,
+
+    /// The container each interface's bindings are placed in, which changes
+    /// to the exported container once an interface which is both imported
+    /// and exported is exported.
+    containers: HashMap<InterfaceId, String>,
+    /// Whether each resource is imported or exported, which changes in the
+    /// same way as `containers`.
+    resources: HashMap<TypeId, Direction>,
+    /// The names declared at the top level of the generated file, since Zig
+    /// doesn't allow parameters or locals to shadow them.
+    top_level: HashSet<String>,
+}
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct Opts {
+    /// Skip generating an object file which contains type information for the
+    /// world that is being generated.
+    #[cfg_attr(feature = "clap", arg(long, default_value_t = false))]
+    pub no_object_file: bool,
+
+    /// Add the specified suffix to the name of the custom section containing
+    /// the component type.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub type_section_suffix: Option<String>,
+}
+
+impl Opts {
+    pub fn build(&self) -> Box<dyn WorldGenerator> {
+        Box::new(Zig {
+            opts: self.clone(),
+            ..Zig::default()
+        })
+    }
+}
+
+impl WorldGenerator for Zig {
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        wit_bindgen_core::reject_futures_and_streams(resolve, world, "Zig")
+    }
+
+    fn preprocess(&mut self, resolve: &Resolve, world: WorldId) {
+        self.world = resolve.worlds[world].name.clone();
+        self.sizes.fill(resolve);
+
+        self.top_level
+            .extend(RUNTIME_DECLS.iter().map(|s| s.to_string()));
+        let world = &resolve.worlds[world];
+        for (key, item) in world.imports.iter() {
+            match item {
+                WorldItem::Function(func) => {
+                    self.top_level.insert(func_name(&func.name));
+                }
+                WorldItem::Type(id) => {
+                    self.top_level.extend(type_decl_names(resolve, *id));
+                }
+                WorldItem::Interface { .. } => {
+                    let container = self.container_name(resolve, key, Direction::Import);
+                    self.top_level.insert(container);
+                }
+            }
+        }
+        for (key, item) in world.exports.iter() {
+            if let WorldItem::Interface { .. } = item {
+                let container = self.container_name(resolve, key, Direction::Export);
+                self.top_level.insert(container);
+            }
+        }
+    }
+
+    fn import_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let container = self.container_name(resolve, name, Direction::Import);
+        self.containers.insert(id, container.clone());
+        let module = resolve.name_world_key(name);
+        let iface = &resolve.interfaces[id];
+        let funcs = iface.functions.values().collect::<Vec<_>>();
+        let mut gen = self.interface(resolve, Some(container), Direction::Import, Some(module));
+        gen.declare(iface.types.values().copied(), &funcs);
+        gen.define_types(iface.types.values().copied(), &funcs);
+        gen.import_funcs(&funcs);
+        gen.finish();
+        Ok(())
+    }
+
+    fn import_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) {
+        let funcs = funcs.iter().map(|(_, f)| *f).collect::<Vec<_>>();
+        let mut gen = self.interface(resolve, None, Direction::Import, Some("$root".to_string()));
+        gen.import_funcs(&funcs);
+        gen.finish();
+    }
+
+    fn import_types(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        types: &[(&str, TypeId)],
+        _files: &mut Files,
+    ) {
+        let mut gen = self.interface(resolve, None, Direction::Import, Some("$root".to_string()));
+        gen.define_types(types.iter().map(|(_, id)| *id), &[]);
+        gen.finish();
+    }
+
+    fn export_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        id: InterfaceId,
+        _files: &mut Files,
+    ) -> Result<()> {
+        let container = self.container_name(resolve, name, Direction::Export);
+        self.containers.insert(id, container.clone());
+        let module = resolve.name_world_key(name);
+        let iface = &resolve.interfaces[id];
+        let funcs = iface.functions.values().collect::<Vec<_>>();
+        let mut gen = self.interface(resolve, Some(container), Direction::Export, Some(module));
+        gen.declare(iface.types.values().copied(), &funcs);
+        gen.define_types(iface.types.values().copied(), &funcs);
+        gen.export_funcs(&funcs);
+        gen.finish();
+        Ok(())
+    }
+
+    fn export_funcs(
+        &mut self,
+        resolve: &Resolve,
+        _world: WorldId,
+        funcs: &[(&str, &Function)],
+        _files: &mut Files,
+    ) -> Result<()> {
+        let funcs = funcs.iter().map(|(_, f)| *f).collect::<Vec<_>>();
+        let mut gen = self.interface(resolve, None, Direction::Export, None);
+        gen.export_funcs(&funcs);
+        gen.finish();
+        Ok(())
+    }
+
+    fn finish(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
+        let snake = self.world.to_snake_case();
+        let version = env!("CARGO_PKG_VERSION");
+
+        let mut src = Source::default();
+        wit_bindgen_core::generated_preamble(&mut src, version);
+        src.as_mut_string().push_str(RUNTIME);
+        src.push_str(self.src.as_str());
+
+        if !self.exports.as_str().is_empty() {
+            src.push_str("\n");
+            src.push_str(self.exports.as_str());
+            src.push_str("\ncomptime {\n");
+            for (func, name) in self.export_names.iter() {
+                uwriteln!(src, "@export({func}, .{{ .name = \"{name}\" }});");
+            }
+            src.push_str("}\n");
+        }
+
+        // Declare a statically-allocated return area, if needed. Only exports
+        // use this since imports allocate their return area on the stack.
+        if self.return_pointer_area_size > 0 {
+            uwriteln!(
+                src,
+                "\nvar RET_AREA: [{}]u8 align({}) = undefined;",
+                self.return_pointer_area_size,
+                self.return_pointer_area_align,
+            );
+        }
+
+        if !self.opts.no_object_file {
+            let linking_symbol = component_type_object::linking_symbol(&self.world);
+            src.push_str(&format!(
+                "
+                    // Ensure that the {snake}_component_type.o object is linked in
+                    extern fn {linking_symbol}() void;
+                    export fn {linking_symbol}_public_use_in_this_compilation_unit() void {{
+                        {linking_symbol}();
+                    }}
+                ",
+            ));
+        }
+
+        files.push(&format!("{snake}.zig"), src.as_bytes());
+        if !self.opts.no_object_file {
+            files.push(
+                &format!("{snake}_component_type.o"),
+                component_type_object::object(
+                    resolve,
+                    id,
+                    &self.world,
+                    StringEncoding::UTF8,
+                    self.opts.type_section_suffix.as_deref(),
+                )?
+                .as_slice(),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Zig {
+    fn interface<'a>(
+        &'a mut self,
+        resolve: &'a Resolve,
+        container: Option<String>,
+        direction: Direction,
+        module: Option<String>,
+    ) -> InterfaceGenerator<'a> {
+        InterfaceGenerator {
+            gen: self,
+            resolve,
+            container,
+            direction,
+            module,
+            src: Source::default(),
+            decls: HashSet::new(),
+        }
+    }
+
+    /// Returns the name of the top-level container bindings for the
+    /// interface `key` are placed in.
+    ///
+    /// These always contain an underscore, so they never collide with the
+    /// names of functions or types.
+    fn container_name(&self, resolve: &Resolve, key: &WorldKey, direction: Direction) -> String {
+        let mut path = Vec::new();
+        if direction == Direction::Export {
+            path.push("exports".to_string());
+        }
+        match key {
+            WorldKey::Name(name) => {
+                path.push(self.world.to_snake_case());
+                path.push(name.to_snake_case());
+            }
+            WorldKey::Interface(id) => {
+                let iface = &resolve.interfaces[*id];
+                let pkg = &resolve.packages[iface.package.unwrap()].name;
+                path.push(pkg.namespace.to_snake_case());
+                path.push(pkg.name.to_snake_case());
+                // Only disambiguate by version if more than one version of
+                // this package is in use.
+                if let Some(version) = &pkg.version {
+                    let versions = resolve
+                        .packages
+                        .iter()
+                        .filter(|(_, p)| {
+                            p.name.namespace == pkg.namespace && p.name.name == pkg.name
+                        })
+                        .count();
+                    if versions > 1 {
+                        let version = version.to_string().replace(['.', '-', '+'], "_");
+                        path.push(format!("v{version}"));
+                    }
+                }
+                path.push(iface.name.as_ref().unwrap().to_snake_case());
+            }
+        }
+        path.join("_")
+    }
+}
+
+struct InterfaceGenerator<'a> {
+    gen: &'a mut Zig,
+    resolve: &'a Resolve,
+    /// The container declarations are placed in, or `None` for the top level
+    /// of the generated file.
+    container: Option<String>,
+    direction: Direction,
+    /// The core wasm module imports come from, or the name of the exported
+    /// interface, which is `None` for functions exported by the world itself.
+    module: Option<String>,
+    src: Source,
+    /// The names declared in `container`.
+    decls: HashSet<String>,
+}
+
+impl InterfaceGenerator<'_> {
+    fn finish(self) {
+        if self.src.as_str().is_empty() {
+            return;
+        }
+        match &self.container {
+            Some(container) => {
+                self.gen
+                    .src
+                    .push_str(&format!("\npub const {container} = struct {{\n"));
+                self.gen
+                    .src
+                    .push_str(self.src.as_str().trim_start_matches('\n'));
+                self.gen.src.push_str("};\n");
+            }
+            None => {
+                self.gen.src.push_str("\n");
+                self.gen
+                    .src
+                    .push_str(self.src.as_str().trim_start_matches('\n'));
+            }
+        }
+    }
+
+    /// Records the names of the types among `types` and the freestanding
+    /// functions among `funcs` as declared in this generator's container.
+    fn declare(&mut self, types: impl Iterator<Item = TypeId>, funcs: &[&Function]) {
+        for id in types {
+            self.decls.extend(type_decl_names(self.resolve, id));
+        }
+        for func in funcs {
+            if let FunctionKind::Freestanding = func.kind {
+                self.decls.insert(func_name(&func.name));
+            }
+        }
+    }
+
+    /// Returns a namespace for the parameters and locals of a function, which
+    /// mustn't shadow any declaration in scope or any of `members`.
+    fn locals(&self, members: &HashSet<String>) -> Ns {
+        let mut ns = Ns::default();
+        for name in self.gen.top_level.iter().chain(&self.decls).chain(members) {
+            let _ = ns.insert(name);
+        }
+        ns
+    }
+
+    /// Defines each of the named `types`, with the methods among `funcs` of
+    /// the resources among them.
+    ///
+    /// Zig declarations are order-independent, so no care needs to be taken
+    /// to define types before their uses.
+    fn define_types(&mut self, types: impl Iterator<Item = TypeId>, funcs: &[&Function]) {
+        for id in types {
+            let ty = &self.resolve.types[id];
+            let name = type_ident(ty.name.as_ref().unwrap());
+            self.src.push_str("\n");
+            self.docs(&ty.docs);
+            match &ty.kind {
+                TypeDefKind::Record(record) => {
+                    self.src
+                        .push_str(&format!("pub const {name} = struct {{\n"));
+                    for field in record.fields.iter() {
+                        self.docs(&field.docs);
+                        let ty = self.type_name(&field.ty);
+                        uwriteln!(self.src, "{}: {ty},", field_name(&field.name));
+                    }
+                    self.src.push_str("};\n");
+                }
+                TypeDefKind::Resource => {
+                    self.gen.resources.insert(id, self.direction);
+                    let methods = funcs
+                        .iter()
+                        .copied()
+                        .filter(|f| match f.kind {
+                            FunctionKind::Freestanding => false,
+                            FunctionKind::Method(r)
+                            | FunctionKind::Static(r)
+                            | FunctionKind::Constructor(r) => r == id,
+                        })
+                        .collect::<Vec<_>>();
+                    match self.direction {
+                        Direction::Import => self.define_imported_resource(id, &name, &methods),
+                        Direction::Export => self.define_exported_resource(id, &name),
+                    }
+                }
+                TypeDefKind::Flags(flags) => {
+                    let bits = flags_bits(flags);
+                    self.src
+                        .push_str(&format!("pub const {name} = packed struct(u{bits}) {{\n"));
+                    for flag in flags.flags.iter() {
+                        self.docs(&flag.docs);
+                        uwriteln!(self.src, "{}: bool = false,", field_name(&flag.name));
+                    }
+                    let padding = bits - flags.flags.len();
+                    if padding > 0 {
+                        uwriteln!(self.src, "_padding: u{padding} = 0,");
+                    }
+                    self.src.push_str("};\n");
+                }
+                TypeDefKind::Variant(variant) => {
+                    self.src
+                        .push_str(&format!("pub const {name} = union(enum) {{\n"));
+                    for case in variant.cases.iter() {
+                        self.docs(&case.docs);
+                        let case_name = field_name(&case.name);
+                        match &case.ty {
+                            Some(ty) => {
+                                let ty = self.type_name(ty);
+                                uwriteln!(self.src, "{case_name}: {ty},");
+                            }
+                            None => uwriteln!(self.src, "{case_name},"),
+                        }
+                    }
+                    self.src.push_str("};\n");
+                }
+                TypeDefKind::Enum(enum_) => {
+                    let tag = int_type(enum_.tag());
+                    self.src
+                        .push_str(&format!("pub const {name} = enum({tag}) {{\n"));
+                    for case in enum_.cases.iter() {
+                        self.docs(&case.docs);
+                        uwriteln!(self.src, "{},", field_name(&case.name));
+                    }
+                    self.src.push_str("};\n");
+
+                    // Functions whose only result is a `result` with this
+                    // enum as its error return an error from this set.
+                    uwriteln!(
+                        self.src,
+                        "\n/// The errors corresponding to the cases of `{name}`."
+                    );
+                    self.src
+                        .push_str(&format!("pub const {name}Error = error{{\n"));
+                    for case in enum_.cases.iter() {
+                        uwriteln!(self.src, "{},", error_name(&case.name));
+                    }
+                    self.src.push_str("};\n");
+                }
+                TypeDefKind::Tuple(_)
+                | TypeDefKind::Option(_)
+                | TypeDefKind::Result(_)
+                | TypeDefKind::List(_)
+                | TypeDefKind::Handle(_) => {
+                    let ty = self.type_id_name(id, false);
+                    uwriteln!(self.src, "pub const {name} = {ty};");
+                }
+                TypeDefKind::Type(t) => {
+                    let ty = self.type_name(t);
+                    uwriteln!(self.src, "pub const {name} = {ty};");
+                }
+                TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {
+                    unreachable!("worlds using futures and streams are rejected in `validate`")
+                }
+                TypeDefKind::Unknown => unreachable!(),
+            }
+        }
+    }
+
+    /// Defines the imported resource `id`, a handle to which has the methods
+    /// `funcs`.
+    fn define_imported_resource(&mut self, id: TypeId, name: &str, funcs: &[&Function]) {
+        let resource = self.resolve.types[id].name.as_ref().unwrap();
+        let module = self.module.clone().unwrap();
+        self.src
+            .push_str(&format!("pub const {name} = struct {{\nhandle: i32,\n"));
+        let mut members = ["handle", "init", "deinit"]
+            .into_iter()
+            .map(String::from)
+            .collect::<HashSet<_>>();
+        for func in funcs {
+            members.insert(self.func_name(func));
+        }
+        for func in funcs {
+            self.src.push_str("\n");
+            self.import_func(func, &members);
+        }
+        self.src.push_str(&format!(
+            "
+                /// Drops this handle to the resource.
+                pub fn deinit(self: {name}) void {{
+                    const resource_drop = @extern(*const fn (i32) callconv(.C) void, .{{ .library_name = \"{module}\", .name = \"[resource-drop]{resource}\" }});
+                    resource_drop(self.handle);
+                }}
+            "
+        ));
+        self.src.push_str("};\n");
+    }
+
+    /// Defines a handle to the exported resource `id`, whose representation
+    /// is implemented in the root source file.
+    fn define_exported_resource(&mut self, id: TypeId, name: &str) {
+        let resource = self.resolve.types[id].name.as_ref().unwrap();
+        let module = self.module.clone().unwrap();
+        let container = self.container.clone().unwrap();
+        self.src.push_str(&format!(
+            "pub const {name} = struct {{
+                    handle: i32,
+
+                    /// The type implementing this resource, which is `{container}.{name}` in the root source file.
+                    pub const Rep = root.{container}.{name};
+
+                    /// Creates a new resource owning `value`, which is moved into memory allocated with `allocator`.
+                    pub fn new(value: Rep) {name} {{
+                        const ptr = allocator.create(Rep) catch @panic(\"out of memory\");
+                        ptr.* = value;
+                        const resource_new = @extern(*const fn (i32) callconv(.C) i32, .{{ .library_name = \"[export]{module}\", .name = \"[resource-new]{resource}\" }});
+                        return .{{ .handle = resource_new(@bitCast(@as(u32, @intCast(@intFromPtr(ptr))))) }};
+                    }}
+
+                    /// Returns the representation of the resource this is a handle to.
+                    pub fn rep(self: {name}) *Rep {{
+                        const resource_rep = @extern(*const fn (i32) callconv(.C) i32, .{{ .library_name = \"[export]{module}\", .name = \"[resource-rep]{resource}\" }});
+                        return @ptrFromInt(@as(u32, @bitCast(resource_rep(self.handle))));
+                    }}
+
+                    /// Drops this handle, which destroys the resource if it's the last one.
+                    pub fn deinit(self: {name}) void {{
+                        const resource_drop = @extern(*const fn (i32) callconv(.C) void, .{{ .library_name = \"[export]{module}\", .name = \"[resource-drop]{resource}\" }});
+                        resource_drop(self.handle);
+                    }}
+                }};
+            "
+        ));
+
+        // The destructor frees the representation, after calling its `deinit`
+        // method if it has one.
+        let rep = format!("{container}.{name}.Rep");
+        let func = self.gen.export_fn_names.tmp(&format!(
+            "__wasm_export_{container}_{}_dtor",
+            resource.to_snake_case()
+        ));
+        let mut locals = self.locals(&HashSet::new());
+        let arg = locals.tmp("arg0");
+        let ptr = locals.tmp("ptr");
+        self.gen.exports.push_str(&format!(
+            "
+                fn {func}({arg}: i32) callconv(.C) void {{
+                    const {ptr}: *{rep} = @ptrFromInt(@as(u32, @bitCast({arg})));
+                    if (@hasDecl({rep}, \"deinit\")) {{
+                        {ptr}.deinit();
+                    }}
+                    allocator.destroy({ptr});
+                }}
+            "
+        ));
+        self.gen
+            .export_names
+            .push((func, format!("{module}#[dtor]{resource}")));
+    }
+
+    fn import_funcs(&mut self, funcs: &[&Function]) {
+        for func in funcs {
+            if let FunctionKind::Freestanding = func.kind {
+                self.src.push_str("\n");
+                self.import_func(func, &HashSet::new());
+            }
+        }
+    }
+
+    /// Generates a function calling the import `func`, which is a member of a
+    /// container that additionally declares `members`.
+    fn import_func(&mut self, func: &Function, members: &HashSet<String>) {
+        self.docs(&func.docs);
+        let mut locals = self.locals(members);
+        let name = self.func_name(func);
+        let params = func
+            .params
+            .iter()
+            .map(|(name, ty)| {
+                let name = locals.tmp(&field_name(name));
+                let ty = self.type_name(ty);
+                (name, ty)
+            })
+            .collect::<Vec<_>>();
+        let ret = self.return_type(func);
+
+        let mut f = FunctionBindgen::new(self, locals);
+        f.params = params.iter().map(|(name, _)| name.clone()).collect();
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            func,
+            &mut f,
+            false,
+        );
+        let FunctionBindgen {
+            src: body,
+            needs_arena,
+            arena,
+            ret_area,
+            import_return_pointer_area_size: ret_area_size,
+            import_return_pointer_area_align: ret_area_align,
+            ..
+        } = f;
+
+        let params_decl = params
+            .iter()
+            .map(|(name, ty)| format!("{name}: {ty}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.src
+            .push_str(&format!("pub fn {name}({params_decl}) {ret} {{\n"));
+        discard_unused(&mut self.src, params.iter().map(|(name, _)| name), &body);
+        if needs_arena {
+            uwriteln!(
+                self.src,
+                "var {arena} = std.heap.ArenaAllocator.init(allocator);"
+            );
+            uwriteln!(self.src, "defer {arena}.deinit();");
+        }
+        if ret_area_size > 0 {
+            uwriteln!(
+                self.src,
+                "var {ret_area}: [{ret_area_size}]u8 align({ret_area_align}) = undefined;"
+            );
+        }
+        self.src.push_str(&body);
+        self.src.push_str("}\n");
+    }
+
+    fn export_funcs(&mut self, funcs: &[&Function]) {
+        for func in funcs {
+            self.export_func(func);
+        }
+    }
+
+    /// Generates a function which exports `func` by lifting its arguments,
+    /// calling its implementation in the root source file, and lowering its
+    /// results.
+    fn export_func(&mut self, func: &Function) {
+        let export_name = func.core_export_name(self.module.as_deref()).into_owned();
+        let shim = self
+            .gen
+            .export_fn_names
+            .tmp(&format!("__wasm_export_{}", export_name.to_snake_case()));
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
+
+        let (callee, constructor) = match func.kind {
+            FunctionKind::Freestanding => {
+                let name = func_name(&func.name);
+                match &self.container {
+                    Some(container) => (format!("root.{container}.{name}"), None),
+                    None => (format!("root.{name}"), None),
+                }
+            }
+            FunctionKind::Method(id) | FunctionKind::Static(id) => {
+                let resource = self.qualified_name(id);
+                let name = func_name(func.item_name());
+                (format!("{resource}.Rep.{name}"), None)
+            }
+            FunctionKind::Constructor(id) => {
+                let resource = self.qualified_name(id);
+                (format!("{resource}.Rep.init"), Some(resource))
+            }
+        };
+
+        let mut locals = self.locals(&HashSet::new());
+        let params = sig
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| (locals.tmp(&format!("arg{i}")), wasm_type(*ty)))
+            .collect::<Vec<_>>();
+        let mut f = FunctionBindgen::new(self, locals);
+        f.callee = callee;
+        f.constructor = constructor;
+        f.params = params.iter().map(|(name, _)| name.clone()).collect();
+        abi::call(
+            f.gen.resolve,
+            AbiVariant::GuestExport,
+            LiftLower::LiftArgsLowerResults,
+            func,
+            &mut f,
+            false,
+        );
+        let body = f.src;
+        self.push_export(&shim, &params, &sig.results, &body, export_name.clone());
+
+        if abi::guest_export_needs_post_return(self.resolve, func) {
+            let mut locals = self.locals(&HashSet::new());
+            let params = sig
+                .results
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| (locals.tmp(&format!("arg{i}")), wasm_type(*ty)))
+                .collect::<Vec<_>>();
+            let mut f = FunctionBindgen::new(self, locals);
+            f.params = params.iter().map(|(name, _)| name.clone()).collect();
+            abi::post_return(f.gen.resolve, func, &mut f);
+            let body = f.src;
+            let shim = self.gen.export_fn_names.tmp(&format!("{shim}_post_return"));
+            self.push_export(
+                &shim,
+                &params,
+                &[],
+                &body,
+                format!("cabi_post_{export_name}"),
+            );
+        }
+    }
+
+    /// Adds the function `func`, taking `params` and returning `results`, to
+    /// the functions exported as `export_name`.
+    fn push_export(
+        &mut self,
+        func: &str,
+        params: &[(String, &str)],
+        results: &[abi::WasmType],
+        body: &str,
+        export_name: String,
+    ) {
+        let params_decl = params
+            .iter()
+            .map(|(name, ty)| format!("{name}: {ty}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let ret = match results {
+            [] => "void",
+            [ty] => wasm_type(*ty),
+            _ => unreachable!("multiple results are returned through a return pointer"),
+        };
+        let mut src = Source::default();
+        src.push_str(&format!(
+            "\nfn {func}({params_decl}) callconv(.C) {ret} {{\n"
+        ));
+        discard_unused(&mut src, params.iter().map(|(name, _)| name), body);
+        src.push_str(body);
+        src.push_str("}\n");
+        self.gen.exports.push_str(src.as_str());
+        self.gen.export_names.push((func.to_string(), export_name));
+    }
+
+    fn docs(&mut self, docs: &Docs) {
+        if let Some(docs) = &docs.contents {
+            for line in docs.trim().lines() {
+                uwriteln!(self.src, "/// {}", line.trim());
+            }
+        }
+    }
+
+    /// Returns the name of the Zig function for `func`, which is a method of
+    /// its resource's handle type unless it's freestanding.
+    fn func_name(&self, func: &Function) -> String {
+        match func.kind {
+            FunctionKind::Freestanding => func_name(&func.name),
+            FunctionKind::Constructor(_) => "init".to_string(),
+            FunctionKind::Method(_) | FunctionKind::Static(_) => {
+                let name = func_name(func.item_name());
+                match name.as_str() {
+                    "handle" | "init" | "deinit" => format!("{name}_"),
+                    _ => name,
+                }
+            }
+        }
+    }
+
+    /// Returns the Zig return type of `func`.
+    fn return_type(&self, func: &Function) -> String {
+        if let Some((result, enum_)) = self.error_result(func) {
+            let ok = match &result.ok {
+                Some(ty) => self.type_name(ty),
+                None => "void".to_string(),
+            };
+            return format!("{}Error!{ok}", self.qualified_name(enum_));
+        }
+        let types = func.results.iter_types().collect::<Vec<_>>();
+        match types.as_slice() {
+            [] => "void".to_string(),
+            [ty] => self.type_name(ty),
+            types => tuple_type(types.iter().map(|ty| self.type_name(ty))),
+        }
+    }
+
+    /// Returns the `result` type `func` returns, and the `enum` which is its
+    /// error type, if `func` returns only that and so returns an error union.
+    fn error_result(&self, func: &Function) -> Option<(&Result_, TypeId)> {
+        let types = func.results.iter_types().collect::<Vec<_>>();
+        let [Type::Id(id)] = types.as_slice() else {
+            return None;
+        };
+        let TypeDefKind::Result(result) = &self.resolve.types[dealias(self.resolve, *id)].kind
+        else {
+            return None;
+        };
+        let Some(Type::Id(err)) = result.err else {
+            return None;
+        };
+        let err = dealias(self.resolve, err);
+        match self.resolve.types[err].kind {
+            TypeDefKind::Enum(_) => Some((result, err)),
+            _ => None,
+        }
+    }
+
+    fn type_name(&self, ty: &Type) -> String {
+        match ty {
+            Type::Bool => "bool".to_string(),
+            Type::U8 => "u8".to_string(),
+            Type::S8 => "i8".to_string(),
+            Type::U16 => "u16".to_string(),
+            Type::S16 => "i16".to_string(),
+            Type::U32 => "u32".to_string(),
+            Type::S32 => "i32".to_string(),
+            Type::U64 => "u64".to_string(),
+            Type::S64 => "i64".to_string(),
+            Type::F32 => "f32".to_string(),
+            Type::F64 => "f64".to_string(),
+            Type::Char => "u21".to_string(),
+            Type::String => "[]const u8".to_string(),
+            Type::Id(id) => self.type_id_name(*id, true),
+        }
+    }
+
+    /// Returns the name of the type `id`, which is its qualified name if it's
+    /// named and `use_name` is set.
+    fn type_id_name(&self, id: TypeId, use_name: bool) -> String {
+        let ty = &self.resolve.types[id];
+        if use_name && ty.name.is_some() {
+            return self.qualified_name(id);
+        }
+        match &ty.kind {
+            TypeDefKind::Type(t) => self.type_name(t),
+            TypeDefKind::Handle(handle) => {
+                let (Handle::Own(resource) | Handle::Borrow(resource)) = handle;
+                let resource = dealias(self.resolve, *resource);
+                let name = self.qualified_name(resource);
+                match (self.resource_direction(resource), handle) {
+                    (Direction::Export, Handle::Borrow(_)) => format!("*{name}.Rep"),
+                    _ => name,
+                }
+            }
+            TypeDefKind::Tuple(tuple) => tuple_type(tuple.types.iter().map(|t| self.type_name(t))),
+            TypeDefKind::Option(t) => format!("?{}", self.type_name(t)),
+            TypeDefKind::Result(r) => {
+                let ok =
+                    r.ok.as_ref()
+                        .map_or("void".to_string(), |t| self.type_name(t));
+                let err = r
+                    .err
+                    .as_ref()
+                    .map_or("void".to_string(), |t| self.type_name(t));
+                format!("WitResult({ok}, {err})")
+            }
+            TypeDefKind::List(t) => format!("[]const {}", self.type_name(t)),
+            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {
+                unreachable!("worlds using futures and streams are rejected in `validate`")
+            }
+            TypeDefKind::Record(_)
+            | TypeDefKind::Resource
+            | TypeDefKind::Flags(_)
+            | TypeDefKind::Variant(_)
+            | TypeDefKind::Enum(_)
+            | TypeDefKind::Unknown => unreachable!(),
+        }
+    }
+
+    /// Returns the name of the named type `id`, qualified by its container so
+    /// it can be used anywhere in the generated file.
+    fn qualified_name(&self, id: TypeId) -> String {
+        let ty = &self.resolve.types[id];
+        let name = type_ident(ty.name.as_ref().unwrap());
+        match ty.owner {
+            TypeOwner::Interface(iface) => format!("{}.{name}", self.gen.containers[&iface]),
+            TypeOwner::World(_) => format!("bindings.{name}"),
+            TypeOwner::None => unreachable!(),
+        }
+    }
+
+    fn resource_direction(&self, id: TypeId) -> Direction {
+        self.gen
+            .resources
+            .get(&id)
+            .copied()
+            .unwrap_or(Direction::Import)
+    }
+}
+
+/// Writes a discard of each of `params` which `body` doesn't use, since Zig
+/// rejects unused parameters.
+fn discard_unused<'a>(src: &mut Source, params: impl Iterator<Item = &'a String>, body: &str) {
+    for param in params {
+        if !uses(body, param) {
+            uwriteln!(src, "_ = {param};");
+        }
+    }
+}
+
+/// Returns whether `src` contains the identifier `name`.
+fn uses(src: &str, name: &str) -> bool {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    src.match_indices(name).any(|(i, _)| {
+        let before = src[..i].chars().next_back();
+        let after = src[i + name.len()..].chars().next();
+        !before.is_some_and(|c| is_ident(c) || c == '.') && !after.is_some_and(is_ident)
+    })
+}
+
+/// Returns the names the named type `id` declares.
+fn type_decl_names(resolve: &Resolve, id: TypeId) -> Vec<String> {
+    let ty = &resolve.types[id];
+    let name = type_ident(ty.name.as_ref().unwrap());
+    match ty.kind {
+        TypeDefKind::Enum(_) => vec![format!("{name}Error"), name],
+        _ => vec![name],
+    }
+}
+
+fn tuple_type(types: impl Iterator<Item = String>) -> String {
+    let types = types.collect::<Vec<_>>();
+    if types.is_empty() {
+        "std.meta.Tuple(&.{})".to_string()
+    } else {
+        format!("std.meta.Tuple(&.{{ {} }})", types.join(", "))
+    }
+}
+
+/// Returns the number of bits in the representation of `flags`.
+fn flags_bits(flags: &Flags) -> usize {
+    match flags.repr() {
+        FlagsRepr::U8 => 8,
+        FlagsRepr::U16 => 16,
+        FlagsRepr::U32(n) => 32 * n,
+    }
+}
+
+fn int_type(int: Int) -> &'static str {
+    match int {
+        Int::U8 => "u8",
+        Int::U16 => "u16",
+        Int::U32 => "u32",
+        Int::U64 => "u64",
+    }
+}
+
+fn type_ident(name: &str) -> String {
+    zig_ident(&name.to_upper_camel_case())
+}
+
+fn func_name(name: &str) -> String {
+    zig_ident(&name.to_lower_camel_case())
+}
+
+/// Returns the name of the field, or union or enum case, for `name`.
+fn field_name(name: &str) -> String {
+    zig_ident(&name.to_snake_case())
+}
+
+fn error_name(name: &str) -> String {
+    zig_ident(&name.to_upper_camel_case())
+}
+
+/// Returns `name` as a Zig identifier, quoting it if it's a keyword or the
+/// name of a primitive type.
+pub fn zig_ident(name: &str) -> String {
+    let is_int_type = match name.as_bytes() {
+        [b'i' | b'u', bits @ ..] => !bits.is_empty() && bits.iter().all(u8::is_ascii_digit),
+        _ => false,
+    };
+    let reserved = [
+        "addrspace",
+        "align",
+        "allowzero",
+        "and",
+        "anyframe",
+        "anytype",
+        "asm",
+        "async",
+        "await",
+        "break",
+        "callconv",
+        "catch",
+        "comptime",
+        "const",
+        "continue",
+        "defer",
+        "else",
+        "enum",
+        "errdefer",
+        "error",
+        "export",
+        "extern",
+        "fn",
+        "for",
+        "if",
+        "inline",
+        "linksection",
+        "noalias",
+        "noinline",
+        "nosuspend",
+        "opaque",
+        "or",
+        "orelse",
+        "packed",
+        "pub",
+        "resume",
+        "return",
+        "struct",
+        "suspend",
+        "switch",
+        "test",
+        "threadlocal",
+        "try",
+        "union",
+        "unreachable",
+        "usingnamespace",
+        "var",
+        "volatile",
+        "while",
+        // Primitive types and values
+        "anyerror",
+        "anyopaque",
+        "bool",
+        "c_char",
+        "c_int",
+        "c_long",
+        "c_longdouble",
+        "c_longlong",
+        "c_short",
+        "c_uint",
+        "c_ulong",
+        "c_ulonglong",
+        "c_ushort",
+        "comptime_float",
+        "comptime_int",
+        "f16",
+        "f32",
+        "f64",
+        "f80",
+        "f128",
+        "false",
+        "isize",
+        "noreturn",
+        "null",
+        "true",
+        "type",
+        "undefined",
+        "usize",
+        "void",
+    ];
+    if is_int_type || reserved.contains(&name) {
+        format!("@\"{name}\"")
+    } else {
+        name.to_string()
+    }
+}
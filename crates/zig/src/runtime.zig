@@ -0,0 +1,65 @@
+const std = @import("std");
+const root = @import("root");
+const bindings = @This();
+
+/// The allocator all memory passed between this component and others is
+/// allocated with, which must outlive every value the bindings return.
+///
+/// Lists and strings lifted by the bindings are owned by their receiver and
+/// are freed with `allocator.free`.
+pub var allocator: std.mem.Allocator = std.heap.wasm_allocator;
+
+/// A WIT `result` which isn't mapped to a Zig error union.
+pub fn WitResult(comptime ok_type: type, comptime err_type: type) type {
+    return union(enum) {
+        ok: ok_type,
+        err: err_type,
+    };
+}
+
+/// The canonical ABI's allocation function, implemented with `allocator`.
+export fn cabi_realloc(old_ptr: ?[*]u8, old_len: usize, byte_align: usize, new_len: usize) [*]u8 {
+    const log2_align: u8 = std.math.log2_int(usize, byte_align);
+    const ret_addr = @returnAddress();
+    if (old_ptr) |old_base| {
+        if (old_len != 0) {
+            const old_bytes = old_base[0..old_len];
+            if (allocator.rawResize(old_bytes, log2_align, new_len, ret_addr)) {
+                return old_base;
+            }
+            const new_base = allocator.rawAlloc(new_len, log2_align, ret_addr) orelse @panic("out of memory");
+            const copy_len = @min(old_len, new_len);
+            @memcpy(new_base[0..copy_len], old_bytes[0..copy_len]);
+            allocator.rawFree(old_bytes, log2_align, ret_addr);
+            return new_base;
+        }
+    }
+    if (new_len == 0) {
+        return @ptrFromInt(byte_align);
+    }
+    return allocator.rawAlloc(new_len, log2_align, ret_addr) orelse @panic("out of memory");
+}
+
+/// Allocates `byte_len` bytes with `cabi_realloc` and returns their address.
+fn witAlloc(byte_len: usize, byte_align: usize) usize {
+    return @intFromPtr(cabi_realloc(null, 0, byte_align, byte_len));
+}
+
+/// Frees `byte_len` bytes at `base_addr` which were allocated by
+/// `cabi_realloc`.
+fn witFree(base_addr: usize, byte_len: usize, byte_align: usize) void {
+    if (byte_len == 0) {
+        return;
+    }
+    const base_ptr: [*]u8 = @ptrFromInt(base_addr);
+    allocator.rawFree(base_ptr[0..byte_len], std.math.log2_int(usize, byte_align), @returnAddress());
+}
+
+/// Returns the `item_len` values of type `item_type` at `base_addr` as a slice.
+fn witSlice(comptime item_type: type, base_addr: usize, item_len: usize) []const item_type {
+    if (item_len == 0) {
+        return &.{};
+    }
+    const base_ptr: [*]const item_type = @ptrFromInt(base_addr);
+    return base_ptr[0..item_len];
+}
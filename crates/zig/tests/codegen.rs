@@ -0,0 +1,33 @@
+use heck::*;
+use std::path::Path;
+use std::process::Command;
+
+macro_rules! codegen_test {
+    ($id:ident $name:tt $test:tt) => {
+        #[test]
+        fn $id() {
+            test_helpers::run_world_codegen_test(
+                "guest-zig",
+                $test.as_ref(),
+                |resolve, world, files| {
+                    wit_bindgen_zig::Opts::default()
+                        .build()
+                        .generate(resolve, world, files)
+                        .unwrap()
+                },
+                verify,
+            );
+        }
+    };
+}
+
+test_helpers::codegen_tests!();
+
+fn verify(dir: &Path, name: &str) {
+    // Exports are implemented in the root source file, so only check each
+    // file on its own rather than compiling it.
+    let mut cmd = Command::new("zig");
+    cmd.arg("ast-check");
+    cmd.arg(dir.join(format!("{}.zig", name.to_snake_case())));
+    test_helpers::run_command(&mut cmd);
+}
@@ -1,8 +1,10 @@
 use anyhow::{bail, Context, Error, Result};
 use clap::Parser;
-use std::path::PathBuf;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
 use std::str;
-use wit_bindgen_core::{wit_parser, Files, WorldGenerator};
+use std::time::{Duration, SystemTime};
+use wit_bindgen_core::{compat, trace, wit_parser, Files, WorldGenerator};
 use wit_parser::Resolve;
 
 /// Helper for passing VERSION to opt.
@@ -11,7 +13,7 @@ fn version() -> &'static str {
     option_env!("CARGO_VERSION_INFO").unwrap_or(env!("CARGO_PKG_VERSION"))
 }
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 #[command(version = version())]
 enum Opt {
     /// This generator outputs a Markdown file describing an interface.
@@ -22,6 +24,72 @@ enum Opt {
         #[clap(flatten)]
         args: Common,
     },
+    /// This generator outputs a JSON file describing the canonical ABI of a
+    /// world, for tooling generating bindings outside of `wit-bindgen`.
+    #[cfg(feature = "json")]
+    Json {
+        #[clap(flatten)]
+        opts: wit_bindgen_json::Opts,
+        #[clap(flatten)]
+        args: Common,
+    },
+    /// This generator outputs the canonical ABI instructions of each function
+    /// in a world, for debugging how generators lift and lower them.
+    AbiTrace {
+        /// Lift and lower lists of primitives with the `list-canon-*`
+        /// instructions, as most generators do.
+        #[clap(long)]
+        canonical_lists: bool,
+        #[clap(flatten)]
+        args: Common,
+    },
+    /// Runs each of the generators listed in a configuration file, parsing the
+    /// WIT files they share only once.
+    Generate {
+        /// Path to the configuration file, such as `wit-bindgen.toml`.
+        ///
+        /// The file names the WIT package with a top-level `wit` key, along
+        /// with optional `world`, `features` and `all-features` keys, and lists
+        /// the generators to run in `[[generate]]` tables. Each table names its
+        /// `generator` as the subcommand running it, and optionally its
+        /// `out-dir`, a `world` replacing the top-level one, and `options`
+        /// mapping the generator's flags to their values. Paths are relative
+        /// to the configuration file.
+        #[clap(long, value_name = "PATH")]
+        config: PathBuf,
+
+        /// Indicates that no files are written and instead files are checked if
+        /// they're up-to-date with the source files.
+        #[clap(long)]
+        check: bool,
+    },
+    /// Compares two versions of a WIT package, reporting the changes to each
+    /// world which break components built against the old version, or code
+    /// using the bindings generated for it.
+    Check {
+        /// Location of the old version of the WIT package.
+        #[clap(value_name = "OLD")]
+        old: PathBuf,
+
+        /// Location of the new version of the WIT package.
+        #[clap(value_name = "NEW")]
+        new: PathBuf,
+
+        /// Optionally specified world to compare, otherwise all worlds of the
+        /// old package are compared.
+        #[clap(short, long)]
+        world: Option<String>,
+
+        /// Comma-separated list of features that should be enabled when
+        /// processing WIT files.
+        #[clap(long)]
+        features: Vec<String>,
+
+        /// Whether or not to activate all WIT features when processing WIT
+        /// files.
+        #[clap(long)]
+        all_features: bool,
+    },
     /// Generates bindings for MoonBit guest modules.
     #[cfg(feature = "moonbit")]
     Moonbit {
@@ -38,7 +106,7 @@ enum Opt {
         #[clap(flatten)]
         args: Common,
     },
-    /// Generates bindings for C/CPP guest modules.
+    /// Generates bindings for C guest modules.
     #[cfg(feature = "c")]
     C {
         #[clap(flatten)]
@@ -47,6 +115,51 @@ enum Opt {
         args: Common,
     },
 
+    /// Generates bindings for C++ guest modules.
+    #[cfg(feature = "cpp")]
+    Cpp {
+        #[clap(flatten)]
+        opts: wit_bindgen_cpp::Opts,
+        #[clap(flatten)]
+        args: Common,
+    },
+
+    /// Generates bindings for Zig guest modules.
+    #[cfg(feature = "zig")]
+    Zig {
+        #[clap(flatten)]
+        opts: wit_bindgen_zig::Opts,
+        #[clap(flatten)]
+        args: Common,
+    },
+
+    /// Generates bindings for Swift guest modules.
+    #[cfg(feature = "swift")]
+    Swift {
+        #[clap(flatten)]
+        opts: wit_bindgen_swift::Opts,
+        #[clap(flatten)]
+        args: Common,
+    },
+
+    /// Generates bindings for AssemblyScript guest modules.
+    #[cfg(feature = "assemblyscript")]
+    Assemblyscript {
+        #[clap(flatten)]
+        opts: wit_bindgen_assemblyscript::Opts,
+        #[clap(flatten)]
+        args: Common,
+    },
+
+    /// Generates bindings for Grain guest modules.
+    #[cfg(feature = "grain")]
+    Grain {
+        #[clap(flatten)]
+        opts: wit_bindgen_grain::Opts,
+        #[clap(flatten)]
+        args: Common,
+    },
+
     /// Generates bindings for TeaVM-based Java guest modules.
     #[cfg(feature = "teavm-java")]
     TeavmJava {
@@ -55,6 +168,15 @@ enum Opt {
         #[clap(flatten)]
         args: Common,
     },
+
+    /// Generates bindings for Kotlin guest modules compiled with TeaVM.
+    #[cfg(feature = "teavm-kotlin")]
+    TeavmKotlin {
+        #[clap(flatten)]
+        opts: wit_bindgen_teavm_kotlin::Opts,
+        #[clap(flatten)]
+        args: Common,
+    },
     /// Generates bindings for TinyGo-based Go guest modules.
     #[cfg(feature = "go")]
     TinyGo {
@@ -74,7 +196,7 @@ enum Opt {
     },
 }
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 struct Common {
     /// Where to place output files
     #[clap(long = "out-dir")]
@@ -106,6 +228,13 @@ struct Common {
     #[clap(long)]
     check: bool,
 
+    /// Keeps running, generating the bindings again whenever the WIT files
+    /// change and only writing the files whose contents changed.
+    ///
+    /// Errors in the WIT files are reported and don't stop the watch.
+    #[clap(long, conflicts_with = "check")]
+    watch: bool,
+
     /// Comma-separated list of features that should be enabled when processing
     /// WIT files.
     ///
@@ -121,34 +250,76 @@ struct Common {
 }
 
 fn main() -> Result<()> {
+    let opt = match Opt::parse() {
+        Opt::Check {
+            old,
+            new,
+            world,
+            features,
+            all_features,
+        } => return check(&old, &new, world.as_deref(), &features, all_features),
+        Opt::Generate { config, check } => return generate(&config, check),
+        opt => opt,
+    };
+    let (generator, args) = generator(opt.clone())?;
+    if args.watch {
+        return watch(opt, &args);
+    }
+
     let mut files = Files::default();
-    let (generator, opt) = match Opt::parse() {
+    gen_world(generator, &args, &mut files).map_err(attach_with_context)?;
+    write_files(&files, args.out_dir.as_deref(), args.check)
+}
+
+/// Returns the generator selected by `opt`, along with its common options.
+fn generator(opt: Opt) -> Result<(Box<dyn WorldGenerator>, Common)> {
+    Ok(match opt {
         #[cfg(feature = "markdown")]
         Opt::Markdown { opts, args } => (opts.build(), args),
+        #[cfg(feature = "json")]
+        Opt::Json { opts, args } => (opts.build(), args),
+        Opt::AbiTrace {
+            canonical_lists,
+            args,
+        } => (trace::Opts { canonical_lists }.build(), args),
         #[cfg(feature = "moonbit")]
         Opt::Moonbit { opts, args } => (opts.build(), args),
         #[cfg(feature = "c")]
         Opt::C { opts, args } => (opts.build(), args),
+        #[cfg(feature = "cpp")]
+        Opt::Cpp { opts, args } => (opts.build(), args),
+        #[cfg(feature = "zig")]
+        Opt::Zig { opts, args } => (opts.build(), args),
+        #[cfg(feature = "swift")]
+        Opt::Swift { opts, args } => (opts.build(), args),
+        #[cfg(feature = "assemblyscript")]
+        Opt::Assemblyscript { opts, args } => (opts.build(), args),
+        #[cfg(feature = "grain")]
+        Opt::Grain { opts, args } => (opts.build(), args),
         #[cfg(feature = "rust")]
         Opt::Rust { opts, args } => (opts.build(), args),
         #[cfg(feature = "teavm-java")]
         Opt::TeavmJava { opts, args } => (opts.build(), args),
+        #[cfg(feature = "teavm-kotlin")]
+        Opt::TeavmKotlin { opts, args } => (opts.build(), args),
         #[cfg(feature = "go")]
         Opt::TinyGo { opts, args } => (opts.build(), args),
         #[cfg(feature = "csharp")]
         Opt::CSharp { opts, args } => (opts.build(), args),
-    };
-
-    gen_world(generator, &opt, &mut files).map_err(attach_with_context)?;
+        Opt::Check { .. } => bail!("`check` is not a generator"),
+        Opt::Generate { .. } => bail!("`generate` is not a generator"),
+    })
+}
 
+fn write_files(files: &Files, out_dir: Option<&Path>, check: bool) -> Result<()> {
     for (name, contents) in files.iter() {
-        let dst = match &opt.out_dir {
+        let dst = match out_dir {
             Some(path) => path.join(name),
             None => name.into(),
         };
         eprintln!("Generating {:?}", dst);
 
-        if opt.check {
+        if check {
             let prev = std::fs::read(&dst).with_context(|| format!("failed to read {:?}", dst))?;
             if prev != contents {
                 // The contents differ. If it looks like textual contents, do a
@@ -182,6 +353,112 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// The contents of the file passed to `generate --config`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    wit: PathBuf,
+    world: Option<String>,
+    #[serde(default)]
+    features: Vec<String>,
+    #[serde(default)]
+    all_features: bool,
+    #[serde(default)]
+    generate: Vec<ConfigEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ConfigEntry {
+    generator: String,
+    out_dir: Option<PathBuf>,
+    world: Option<String>,
+    #[serde(default)]
+    options: toml::Table,
+}
+
+fn generate(config: &Path, check: bool) -> Result<()> {
+    let contents =
+        std::fs::read_to_string(config).with_context(|| format!("failed to read {:?}", config))?;
+    let config_dir = config.parent().unwrap_or(Path::new(""));
+    let config: Config =
+        toml::from_str(&contents).with_context(|| format!("failed to parse {:?}", config))?;
+
+    let wit = config_dir.join(&config.wit);
+    let mut resolve = new_resolve(&config.features, config.all_features);
+    let (pkg, _files) = resolve.push_path(&wit)?;
+
+    // All generators are set up before any runs so that a mistake in the
+    // configuration doesn't leave some of the outputs updated.
+    let mut generators = Vec::new();
+    for entry in config.generate.iter() {
+        let args = entry_args(entry, &wit)?;
+        let (generator, _) = Opt::try_parse_from(&args)
+            .map_err(Error::from)
+            .and_then(generator)
+            .with_context(|| format!("invalid generator entry `{}`", entry.generator))?;
+        let world = entry.world.as_ref().or(config.world.as_ref());
+        let world = resolve.select_world(pkg, world.map(|s| s.as_str()))?;
+        generators.push((entry, generator, world));
+    }
+
+    for (entry, mut generator, world) in generators {
+        let mut files = Files::default();
+        generator
+            .generate(&resolve, world, &mut files)
+            .map_err(attach_with_context)
+            .with_context(|| format!("failed to run generator `{}`", entry.generator))?;
+        let out_dir = match &entry.out_dir {
+            Some(out_dir) => config_dir.join(out_dir),
+            None => config_dir.to_path_buf(),
+        };
+        write_files(&files, Some(&out_dir), check)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the command line running the generator of `entry` on `wit`.
+///
+/// The options of each generator are parsed as the flags of its subcommand, so
+/// they're spelled the same as on the command line.
+fn entry_args(entry: &ConfigEntry, wit: &Path) -> Result<Vec<String>> {
+    let mut args = vec!["wit-bindgen".into(), entry.generator.clone()];
+    for (name, value) in entry.options.iter() {
+        if ["out-dir", "world", "check", "features", "all-features"].contains(&name.as_str()) {
+            bail!(
+                "`{name}` isn't an option of `{}`, set it outside of `options` instead",
+                entry.generator
+            );
+        }
+        push_option(&mut args, name, value)
+            .with_context(|| format!("invalid option `{name}` for `{}`", entry.generator))?;
+    }
+    args.push(wit.display().to_string());
+    Ok(args)
+}
+
+/// Appends the command line flags equivalent to setting the option `name` to
+/// `value` in a configuration file.
+fn push_option(args: &mut Vec<String>, name: &str, value: &toml::Value) -> Result<()> {
+    match value {
+        toml::Value::Boolean(true) => args.push(format!("--{name}")),
+        toml::Value::Boolean(false) => {}
+        toml::Value::String(value) => args.push(format!("--{name}={value}")),
+        toml::Value::Integer(value) => args.push(format!("--{name}={value}")),
+        toml::Value::Float(value) => args.push(format!("--{name}={value}")),
+        toml::Value::Array(values) => {
+            for value in values {
+                push_option(args, name, value)?;
+            }
+        }
+        toml::Value::Table(_) | toml::Value::Datetime(_) => {
+            bail!("expected a boolean, string, number or array")
+        }
+    }
+    Ok(())
+}
+
 fn attach_with_context(err: Error) -> Error {
     #[cfg(feature = "rust")]
     if let Some(e) = err.downcast_ref::<wit_bindgen_rust::MissingWith>() {
@@ -198,9 +475,86 @@ fn gen_world(
     opts: &Common,
     files: &mut Files,
 ) -> Result<()> {
+    let mut resolve = new_resolve(&opts.features, opts.all_features);
+    let (pkg, _files) = resolve.push_path(&opts.wit)?;
+    let world = resolve.select_world(pkg, opts.world.as_deref())?;
+    generator.generate(&resolve, world, files)?;
+
+    Ok(())
+}
+
+/// Generates the bindings selected by `opt` again each time the files under
+/// `args.wit` change, never returning unless the bindings can't be written.
+fn watch(opt: Opt, args: &Common) -> Result<()> {
+    loop {
+        let sources = sources(&args.wit);
+
+        // Generators carry state from one run to the next, so a new one is
+        // created for each run.
+        let (generator, _) = generator(opt.clone())?;
+        let mut files = Files::default();
+        match gen_world(generator, args, &mut files).map_err(attach_with_context) {
+            Ok(()) => {
+                let mut changed = Files::default();
+                for (name, contents) in files.iter() {
+                    let dst = match &args.out_dir {
+                        Some(path) => path.join(name),
+                        None => name.into(),
+                    };
+                    if std::fs::read(&dst).ok().as_deref() != Some(contents) {
+                        changed.push(name, contents);
+                    }
+                }
+                write_files(&changed, args.out_dir.as_deref(), false)?;
+            }
+            Err(e) => eprintln!("Error: {e:?}"),
+        }
+
+        eprintln!("Watching {:?} for changes", args.wit);
+        while sources == self::sources(&args.wit) {
+            std::thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
+
+/// Returns the WIT files under `root` along with their modification times and
+/// sizes, which change whenever one of them is edited, added or removed.
+///
+/// Other files are left out, so that writing the bindings next to the WIT
+/// files doesn't cause them to be generated again.
+fn sources(root: &Path) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+    let mut sources = Vec::new();
+    let mut paths = vec![root.to_path_buf()];
+    while let Some(path) = paths.pop() {
+        match std::fs::read_dir(&path) {
+            Ok(entries) => paths.extend(entries.filter_map(|e| Some(e.ok()?.path()))),
+            Err(_) if path != root && !is_wit_source(&path) => {}
+            Err(_) => {
+                let metadata = std::fs::metadata(&path).ok();
+                let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+                let len = metadata.map(|m| m.len()).unwrap_or(0);
+                sources.push((path, modified, len));
+            }
+        }
+    }
+    sources.sort();
+    sources
+}
+
+/// Returns whether `path`, found in a directory of WIT files, is one of the
+/// files `Resolve::push_path` reads, which are either WIT files or packages
+/// encoded in wasm.
+fn is_wit_source(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("wit" | "wasm")
+    )
+}
+
+fn new_resolve(features: &[String], all_features: bool) -> Resolve {
     let mut resolve = Resolve::default();
-    resolve.all_features = opts.all_features;
-    for features in opts.features.iter() {
+    resolve.all_features = all_features;
+    for features in features.iter() {
         for feature in features
             .split(',')
             .flat_map(|s| s.split_whitespace())
@@ -209,10 +563,68 @@ fn gen_world(
             resolve.features.insert(feature.to_string());
         }
     }
-    let (pkg, _files) = resolve.push_path(&opts.wit)?;
-    let world = resolve.select_world(pkg, opts.world.as_deref())?;
-    generator.generate(&resolve, world, files)?;
+    resolve
+}
+
+fn check(
+    old: &Path,
+    new: &Path,
+    world: Option<&str>,
+    features: &[String],
+    all_features: bool,
+) -> Result<()> {
+    let mut old_resolve = new_resolve(features, all_features);
+    let (old_pkg, _files) = old_resolve.push_path(old)?;
+    let mut new_resolve = new_resolve(features, all_features);
+    let (new_pkg, _files) = new_resolve.push_path(new)?;
+
+    let worlds = match world {
+        Some(world) => vec![world.to_string()],
+        None => old_resolve.packages[old_pkg]
+            .worlds
+            .keys()
+            .cloned()
+            .collect(),
+    };
+    let mut breaking = 0;
+    for name in worlds {
+        println!("world `{name}`:");
+        let old_world = old_resolve.select_world(old_pkg, Some(&name))?;
+        let new_world = match new_resolve.select_world(new_pkg, Some(&name)) {
+            Ok(world) => world,
+            Err(_) => {
+                println!("  breaking: world removed");
+                breaking += 1;
+                continue;
+            }
+        };
+        let changes = compat::compare(&old_resolve, old_world, &new_resolve, new_world);
+        if changes.is_empty() {
+            println!("  no changes");
+        }
+        for change in changes {
+            if change.breaking {
+                println!("  breaking: {change}");
+                breaking += 1;
+            } else {
+                println!("  compatible: {change}");
+            }
+            if !change.bindings.is_empty() {
+                println!("    breaks bindings for: {}", change.bindings.join(", "));
+            }
+        }
+    }
+    if world.is_none() {
+        for name in new_resolve.packages[new_pkg].worlds.keys() {
+            if !old_resolve.packages[old_pkg].worlds.contains_key(name) {
+                println!("world `{name}`:\n  compatible: world added");
+            }
+        }
+    }
 
+    if breaking > 0 {
+        bail!("found {breaking} breaking change(s)");
+    }
     Ok(())
 }
 
@@ -221,3 +633,226 @@ fn verify_cli() {
     use clap::CommandFactory;
     Opt::command().debug_assert()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an empty directory for the test `name` to write files into.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("wit-bindgen-cli-{name}-{}", std::process::id()));
+        drop(std::fs::remove_dir_all(&dir));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn option_args(value: toml::Value) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        push_option(&mut args, "opt", &value)?;
+        Ok(args)
+    }
+
+    #[test]
+    fn options_map_to_flags() {
+        assert_eq!(option_args(true.into()).unwrap(), ["--opt"]);
+        assert!(option_args(false.into()).unwrap().is_empty());
+        assert_eq!(option_args("a=b".into()).unwrap(), ["--opt=a=b"]);
+        assert_eq!(option_args(3.into()).unwrap(), ["--opt=3"]);
+        assert_eq!(
+            option_args(vec!["a", "b"].into()).unwrap(),
+            ["--opt=a", "--opt=b"]
+        );
+        assert!(option_args(toml::Table::new().into()).is_err());
+        assert!(option_args(vec![toml::Value::from(toml::Table::new())].into()).is_err());
+    }
+
+    #[test]
+    fn config_parses_into_flags() {
+        let config: Config = toml::from_str(
+            r#"
+            wit = "wit"
+            world = "w"
+            features = ["a"]
+
+            [[generate]]
+            generator = "rust"
+            out-dir = "src"
+            options = { generate-all = true, std-feature = false, with = ["a:b/c=generate", "d:e/f=crate::f"] }
+
+            [[generate]]
+            generator = "c"
+            world = "other"
+
+            [generate.options]
+            autodrop-borrows = "yes"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.world.as_deref(), Some("w"));
+        assert_eq!(config.features, ["a"]);
+        assert!(!config.all_features);
+        assert_eq!(config.generate.len(), 2);
+
+        let rust = &config.generate[0];
+        assert_eq!(rust.out_dir.as_deref(), Some(Path::new("src")));
+        assert_eq!(
+            entry_args(rust, Path::new("wit")).unwrap(),
+            [
+                "wit-bindgen",
+                "rust",
+                "--generate-all",
+                "--with=a:b/c=generate",
+                "--with=d:e/f=crate::f",
+                "wit",
+            ]
+        );
+        let c = &config.generate[1];
+        assert_eq!(c.world.as_deref(), Some("other"));
+        assert_eq!(
+            entry_args(c, Path::new("wit")).unwrap(),
+            ["wit-bindgen", "c", "--autodrop-borrows=yes", "wit"]
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("wit = 'wit'\nout-dir = 'x'").is_err());
+        assert!(toml::from_str::<Config>(
+            "wit = 'wit'\n[[generate]]\ngenerator = 'c'\nfeatures = ['a']"
+        )
+        .is_err());
+
+        // Options handled by `generate` itself can't be set per generator.
+        let config: Config = toml::from_str(
+            "wit = 'wit'\n[[generate]]\ngenerator = 'c'\noptions = { out-dir = 'x' }",
+        )
+        .unwrap();
+        let err = entry_args(&config.generate[0], Path::new("wit")).unwrap_err();
+        assert!(err.to_string().contains("`out-dir` isn't an option of `c`"));
+
+        // Options the generator doesn't have, or generators which don't
+        // exist, are rejected before anything is generated.
+        let dir = test_dir("unknown-keys");
+        std::fs::write(dir.join("a.wit"), "package a:b; world w {}").unwrap();
+        for entry in [
+            "generator = 'abi-trace'\noptions = { frobnicate = true }",
+            "generator = 'frobnicate'",
+            "generator = 'check'",
+        ] {
+            let config = format!(
+                "wit = 'a.wit'\n\
+                 [[generate]]\ngenerator = 'abi-trace'\nout-dir = 'ok'\n\
+                 [[generate]]\n{entry}"
+            );
+            std::fs::write(dir.join("wit-bindgen.toml"), config).unwrap();
+            let err = generate(&dir.join("wit-bindgen.toml"), false).unwrap_err();
+            assert!(
+                format!("{err:#}").contains("invalid generator entry"),
+                "{err:#}"
+            );
+            assert!(!dir.join("ok").exists());
+        }
+    }
+
+    #[test]
+    fn check_reports_stale_output() {
+        let dir = test_dir("check");
+        std::fs::write(
+            dir.join("a.wit"),
+            "package a:b; world w { export f: func(x: list<u8>); }",
+        )
+        .unwrap();
+        let config = dir.join("wit-bindgen.toml");
+        std::fs::write(
+            &config,
+            "wit = 'a.wit'\n\
+             [[generate]]\ngenerator = 'abi-trace'\nout-dir = 'plain'\n\
+             [[generate]]\ngenerator = 'abi-trace'\nout-dir = 'canon'\n\
+             options = { canonical-lists = true }",
+        )
+        .unwrap();
+
+        // Nothing has been generated yet.
+        assert!(generate(&config, true).is_err());
+        generate(&config, false).unwrap();
+        let plain = dir.join("plain/w.abi.txt");
+        let canon = dir.join("canon/w.abi.txt");
+        assert_ne!(
+            std::fs::read(&plain).unwrap(),
+            std::fs::read(&canon).unwrap()
+        );
+        generate(&config, true).unwrap();
+
+        // Stale output is found whichever entry it belongs to, and `--check`
+        // never writes it.
+        for stale in [&plain, &canon] {
+            let contents = std::fs::read(stale).unwrap();
+            std::fs::write(stale, "stale").unwrap();
+            let err = generate(&config, true).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains(&format!("not up to date: {}", stale.display())),
+                "{err:#}"
+            );
+            assert_eq!(std::fs::read(stale).unwrap(), b"stale");
+            std::fs::write(stale, contents).unwrap();
+        }
+        generate(&config, true).unwrap();
+    }
+
+    #[test]
+    fn sources_change_with_wit_files() {
+        let dir = test_dir("sources");
+        std::fs::create_dir_all(dir.join("deps/c")).unwrap();
+        std::fs::write(dir.join("a.wit"), "package a:b;").unwrap();
+        std::fs::write(dir.join("deps/c/c.wit"), "package c:d;").unwrap();
+        let initial = sources(&dir);
+        assert_eq!(
+            initial.iter().map(|s| s.0.clone()).collect::<Vec<_>>(),
+            [dir.join("a.wit"), dir.join("deps/c/c.wit")]
+        );
+
+        // Other files, such as bindings written next to the WIT files, don't
+        // cause the bindings to be generated again.
+        std::fs::write(dir.join("bindings.rs"), "// generated").unwrap();
+        std::fs::write(dir.join("deps/README.md"), "docs").unwrap();
+        assert_eq!(sources(&dir), initial);
+
+        // A change of size.
+        std::fs::write(dir.join("a.wit"), "package a:bc;").unwrap();
+        let resized = sources(&dir);
+        assert_ne!(resized, initial);
+
+        // A change of modification time alone.
+        let file = std::fs::File::options()
+            .write(true)
+            .open(dir.join("a.wit"))
+            .unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        drop(file);
+        let touched = sources(&dir);
+        assert_ne!(touched, resized);
+        assert_eq!(touched[0].2, resized[0].2);
+
+        // New files, including packages encoded in wasm.
+        std::fs::write(dir.join("deps/e.wasm"), "").unwrap();
+        let added = sources(&dir);
+        assert_eq!(added.len(), 3);
+
+        // Deleted files.
+        std::fs::remove_file(dir.join("deps/c/c.wit")).unwrap();
+        let removed = sources(&dir);
+        assert_eq!(
+            removed.iter().map(|s| s.0.clone()).collect::<Vec<_>>(),
+            [dir.join("a.wit"), dir.join("deps/e.wasm")]
+        );
+
+        // A single WIT file is watched whatever its name.
+        let single = dir.join("world.txt");
+        std::fs::write(&single, "package a:b;").unwrap();
+        assert_eq!(sources(&single).len(), 1);
+        std::fs::remove_file(&single).unwrap();
+        assert_eq!(sources(&single), [(single, None, 0)]);
+    }
+}
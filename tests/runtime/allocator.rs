@@ -0,0 +1,35 @@
+use anyhow::Result;
+use wasmtime::Store;
+
+wasmtime::component::bindgen!(in "tests/runtime/allocator");
+
+#[derive(Default)]
+pub struct MyImports;
+
+impl test::allocator::host::Host for MyImports {
+    fn echo(&mut self, lines: Vec<String>) -> Vec<String> {
+        lines
+    }
+}
+
+#[test]
+fn run() -> Result<()> {
+    crate::run_test(
+        "allocator",
+        |linker| Allocator::add_to_linker(linker, |x| &mut x.0),
+        |store, component, linker| Allocator::instantiate(store, component, linker),
+        run_test,
+    )
+}
+
+fn run_test(exports: Allocator, store: &mut Store<crate::Wasi<MyImports>>) -> Result<()> {
+    // Results of exports are released by their `post-return`, which runs
+    // before the next call.
+    assert_eq!(exports.call_names(&mut *store)?, ["a", "bc"]);
+    assert_eq!(exports.call_live_allocations(&mut *store)?, 0);
+
+    let lines = ["x".to_string(), "yz".to_string()];
+    assert_eq!(exports.call_concat(&mut *store, &lines)?, "xyz");
+    assert_eq!(exports.call_live_allocations(&mut *store)?, 0);
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+wit_bindgen::generate!({
+    path: "../../tests/runtime/allocator",
+    allocator: "crate::Counting",
+});
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts the allocations made by the bindings which haven't been released
+/// yet.
+pub struct Counting;
+
+unsafe impl wit_bindgen::rt::Allocator for Counting {
+    unsafe fn alloc(layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(1, Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(1, Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+struct Component;
+
+export!(Component);
+
+impl Guest for Component {
+    fn names() -> Vec<String> {
+        let lines = test::allocator::host::echo(&["a".to_string(), "bc".to_string()]);
+        assert_eq!(lines, ["a", "bc"]);
+        assert_eq!(LIVE.load(Relaxed), 0);
+        lines
+    }
+
+    fn concat(lines: Vec<String>) -> String {
+        assert_eq!(LIVE.load(Relaxed), 0);
+        lines.concat()
+    }
+
+    fn live_allocations() -> u32 {
+        LIVE.load(Relaxed) as u32
+    }
+}
@@ -13,6 +13,7 @@ use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiView};
 use wit_component::{ComponentEncoder, StringEncoding};
 use wit_parser::{Resolve, WorldId, WorldItem};
 
+mod allocator;
 mod flavorful;
 mod lists;
 mod many_arguments;
@@ -190,7 +191,9 @@ fn tests(name: &str, dir_name: &str) -> Result<Vec<PathBuf>> {
             let mut files = Default::default();
             let mut opts = wit_bindgen_c::Opts::default();
             if let Some(path) = path.file_name().and_then(|s| s.to_str()) {
-                if path.contains("utf16") {
+                if path.contains("compact_utf16") {
+                    opts.string_encoding = wit_component::StringEncoding::CompactUTF16;
+                } else if path.contains("utf16") {
                     opts.string_encoding = wit_component::StringEncoding::UTF16;
                 }
             }
@@ -0,0 +1,45 @@
+#include <assert.h>
+#include <strings.h>
+#include <stdlib.h>
+#include <string.h>
+#include <stdio.h>
+
+void assert_str(strings_string_t* str, const char16_t* expected) {
+  size_t expected_len = 0;
+  while (expected[expected_len])
+    expected_len++;
+  assert(strings_string_is_utf16(str));
+  assert(strings_string_code_units(str) == expected_len);
+  assert(memcmp(str->ptr, expected, expected_len * 2) == 0);
+}
+
+void strings_test_imports() {
+  strings_string_t str1;
+  strings_string_set_latin1(&str1, "latin utf16");
+  test_strings_imports_take_basic(&str1);
+
+  strings_string_t str2;
+  strings_string_set_utf16(&str2, u"latin utf16");
+  test_strings_imports_take_basic(&str2);
+
+  strings_string_t str3;
+  test_strings_imports_return_unicode(&str3);
+  assert_str(&str3, u"🚀🚀🚀 𠈄𓀀");
+
+  strings_string_t str4;
+  assert(!strings_string_to_latin1(&str4, &str3));
+  strings_string_free(&str3);
+}
+
+void strings_return_empty(strings_string_t *ret) {
+  strings_string_dup_latin1(ret, ""); // Exercise cabi_realloc new_size = 0
+}
+
+void strings_roundtrip(strings_string_t *str, strings_string_t *ret) {
+  assert(strings_string_code_units(str) > 0);
+  // Switch representations to exercise lowering both of them.
+  if (!strings_string_is_utf16(str) || !strings_string_to_latin1(ret, str)) {
+    strings_string_to_utf16(ret, str);
+  }
+  strings_string_free(str);
+}